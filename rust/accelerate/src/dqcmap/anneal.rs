@@ -0,0 +1,318 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
+
+use super::{
+    cif_pairs::CifPairs,
+    ctrl_to_pq::Ctrl2Pq,
+    layout::feedback_weights,
+    metrics::{self, Phase},
+};
+
+/// Temperature schedule of [anneal_layout].  The temperature decays geometrically from
+/// `initial_temperature` at the first iteration to `final_temperature` at the last one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnnealSchedule {
+    pub num_iterations: usize,
+    pub initial_temperature: f64,
+    pub final_temperature: f64,
+}
+
+impl Default for AnnealSchedule {
+    fn default() -> Self {
+        AnnealSchedule {
+            num_iterations: 10_000,
+            initial_temperature: 1.0,
+            final_temperature: 1e-3,
+        }
+    }
+}
+
+impl AnnealSchedule {
+    fn validate(&self) -> PyResult<()> {
+        let (t0, t1) = (self.initial_temperature, self.final_temperature);
+        if !(t0.is_finite() && t1.is_finite() && t1 > 0. && t1 <= t0) {
+            return Err(PyValueError::new_err(format!(
+                "temperatures must be finite with 0 < final_temperature <= initial_temperature, \
+                 got {t0} and {t1}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The temperature at `iteration`.
+    fn temperature(&self, iteration: usize) -> f64 {
+        if self.num_iterations <= 1 {
+            return self.initial_temperature;
+        }
+        let progress = iteration as f64 / (self.num_iterations - 1) as f64;
+        self.initial_temperature
+            * (self.final_temperature / self.initial_temperature).powf(progress)
+    }
+}
+
+/// Refine a complete layout by simulated annealing, with the number of cross-controller
+/// feedbacks of the cif pairs as the energy.
+///
+/// Every iteration proposes to either exchange two logical qubits on different controllers or to
+/// move a logical qubit onto a free physical qubit of another controller within its capacity.
+/// Proposals that do not increase the energy are always accepted, others with probability
+/// `exp(-Δ/T)`.  The proposals only depend on the RNG seeded with `seed`, so runs are
+/// reproducible.  Returns the layout of lowest energy seen, with its energy.
+pub fn anneal_layout(
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    initial_layout: &HashMap<usize, i32>,
+    schedule: AnnealSchedule,
+    seed: Option<u64>,
+) -> PyResult<(HashMap<usize, i32>, usize)> {
+    let _timer = metrics::time_phase(Phase::Layout);
+    schedule.validate()?;
+    let num_logical = initial_layout.len();
+    let weights: Vec<Vec<(usize, usize)>> = feedback_weights(cif_pairs, num_logical)?
+        .into_iter()
+        .map(|partners| partners.into_iter().collect())
+        .collect();
+
+    let controllers = ctrl2pq.controllers();
+    let ctrl_index: HashMap<i32, usize> = controllers
+        .iter()
+        .enumerate()
+        .map(|(index, &ctrl)| (ctrl, index))
+        .collect();
+    let mut physical = vec![0; num_logical];
+    let mut ctrl_of = vec![0; num_logical];
+    // physical qubits of every controller that are still free for moves
+    let mut free: Vec<Vec<i32>> = controllers
+        .iter()
+        .map(|ctrl| {
+            let mut qubits = ctrl2pq.map[ctrl].clone();
+            qubits.sort_unstable();
+            qubits
+        })
+        .collect();
+    let mut room: Vec<usize> = controllers
+        .iter()
+        .map(|&ctrl| {
+            let capacity = ctrl2pq.get_capacity(ctrl).unwrap_or(usize::MAX);
+            ctrl2pq.map[&ctrl].len().min(capacity)
+        })
+        .collect();
+    for logical in 0..num_logical {
+        let Some(&phys) = initial_layout.get(&logical) else {
+            return Err(PyValueError::new_err(format!(
+                "the layout must cover logical qubits 0 to {}, {} is missing",
+                num_logical - 1,
+                logical
+            )));
+        };
+        let Some(ctrl) = ctrl2pq.get_controller_by_qubit(phys) else {
+            return Err(PyValueError::new_err(format!(
+                "physical qubit {phys} is not connected to any controller"
+            )));
+        };
+        let ctrl = ctrl_index[ctrl];
+        let Ok(position) = free[ctrl].binary_search(&phys) else {
+            return Err(PyValueError::new_err(format!(
+                "physical qubit {phys} is assigned more than once"
+            )));
+        };
+        if room[ctrl] == 0 {
+            return Err(PyValueError::new_err(format!(
+                "the layout exceeds the capacity of controller {}",
+                controllers[ctrl]
+            )));
+        }
+        free[ctrl].remove(position);
+        room[ctrl] -= 1;
+        physical[logical] = phys;
+        ctrl_of[logical] = ctrl;
+    }
+
+    // change in energy when `logical` moves to controller `target`, the others staying in place
+    let move_delta = |ctrl_of: &[usize], logical: usize, target: usize| -> isize {
+        weights[logical]
+            .iter()
+            .map(|&(partner, weight)| {
+                let before = (ctrl_of[partner] != ctrl_of[logical]) as isize;
+                let after = (ctrl_of[partner] != target) as isize;
+                weight as isize * (after - before)
+            })
+            .sum()
+    };
+    let mut energy: usize = (0..num_logical)
+        .flat_map(|q| weights[q].iter().map(move |&(p, w)| (q, p, w)))
+        .filter(|&(q, p, _)| q < p && ctrl_of[q] != ctrl_of[p])
+        .map(|(_, _, w)| w)
+        .sum();
+    let mut best = (physical.clone(), energy);
+
+    let mut rng = match seed {
+        Some(seed) => Pcg64Mcg::seed_from_u64(seed),
+        None => Pcg64Mcg::from_entropy(),
+    };
+    if num_logical == 0 || controllers.len() < 2 {
+        return Ok((initial_layout.clone(), energy));
+    }
+    for iteration in 0..schedule.num_iterations {
+        let temperature = schedule.temperature(iteration);
+        let accept = |rng: &mut Pcg64Mcg, delta: isize| {
+            delta <= 0 || rng.gen::<f64>() < (-(delta as f64) / temperature).exp()
+        };
+        let a = rng.gen_range(0..num_logical);
+        if rng.gen_bool(0.5) {
+            // exchange two logical qubits
+            let b = rng.gen_range(0..num_logical);
+            let (ctrl_a, ctrl_b) = (ctrl_of[a], ctrl_of[b]);
+            if ctrl_a == ctrl_b {
+                continue;
+            }
+            let delta_a = move_delta(&ctrl_of, a, ctrl_b);
+            ctrl_of[a] = ctrl_b;
+            let delta = delta_a + move_delta(&ctrl_of, b, ctrl_a);
+            if accept(&mut rng, delta) {
+                ctrl_of[b] = ctrl_a;
+                physical.swap(a, b);
+                energy = (energy as isize + delta) as usize;
+            } else {
+                ctrl_of[a] = ctrl_a;
+                continue;
+            }
+        } else {
+            // move a logical qubit onto a free physical qubit
+            let target = rng.gen_range(0..controllers.len());
+            let source = ctrl_of[a];
+            if target == source || room[target] == 0 || free[target].is_empty() {
+                continue;
+            }
+            let delta = move_delta(&ctrl_of, a, target);
+            if !accept(&mut rng, delta) {
+                continue;
+            }
+            let phys = free[target].remove(0);
+            let position = free[source].partition_point(|&q| q < physical[a]);
+            free[source].insert(position, physical[a]);
+            room[target] -= 1;
+            room[source] += 1;
+            physical[a] = phys;
+            ctrl_of[a] = target;
+            energy = (energy as isize + delta) as usize;
+        }
+        if energy < best.1 {
+            best = (physical.clone(), energy);
+        }
+    }
+    let (physical, energy) = best;
+    Ok((physical.into_iter().enumerate().collect(), energy))
+}
+
+/// Refine a layout by simulated annealing to reduce the number of cross-controller feedbacks.
+/// The GIL is released while annealing.
+///
+/// Args:
+///     cif_pairs (CifPairs): The cif pairs of the circuit on logical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     initial_layout (dict[int, int]): The physical qubit of every logical qubit, e.g. from
+///         ``dqcmap_layout``.
+///     num_iterations (int): The number of proposed moves.
+///     initial_temperature (float): The temperature of the first iteration.
+///     final_temperature (float): The temperature of the last iteration, reached by geometric
+///         cooling.
+///     seed (int): The seed of the RNG proposing and accepting moves.
+///
+/// Returns:
+///     (dict[int, int], int): The layout with the fewest cross-controller feedbacks found and its
+///     number of cross-controller feedbacks.
+///
+/// Raises:
+///     ValueError: if the layout is not a valid assignment onto the qubits of ``ctrl2pq`` or
+///         the temperatures are invalid.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (cif_pairs, ctrl2pq, initial_layout, num_iterations=10_000, initial_temperature=1.0, final_temperature=1e-3, seed=None))]
+pub fn dqcmap_anneal_layout(
+    py: Python,
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    initial_layout: HashMap<usize, i32>,
+    num_iterations: usize,
+    initial_temperature: f64,
+    final_temperature: f64,
+    seed: Option<u64>,
+) -> PyResult<(HashMap<usize, i32>, usize)> {
+    let schedule = AnnealSchedule {
+        num_iterations,
+        initial_temperature,
+        final_temperature,
+    };
+    py.allow_threads(|| anneal_layout(cif_pairs, ctrl2pq, &initial_layout, schedule, seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anneal_layout_removes_cross_feedback() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1, 2]), (1, vec![3, 4, 5])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(
+            1,
+            vec![vec![0, 1], vec![2, 3], vec![0, 2]],
+        )]));
+        // 0 and 2 share a controller, 1 and 3 share the other one: two cross feedbacks
+        let initial = HashMap::from([(0, 0), (1, 3), (2, 1), (3, 4)]);
+
+        let (layout, energy) = anneal_layout(
+            &cif_pairs,
+            &ctrl2pq,
+            &initial,
+            AnnealSchedule::default(),
+            Some(7),
+        )
+        .unwrap();
+        assert_eq!(energy, 1);
+        let ctrl = |q: usize| ctrl2pq.get_controller_by_qubit(layout[&q]).unwrap();
+        let cross = [(0, 1), (2, 3), (0, 2)]
+            .into_iter()
+            .filter(|&(a, b)| ctrl(a) != ctrl(b))
+            .count();
+        assert_eq!(cross, 1);
+        let mut physical: Vec<i32> = layout.values().copied().collect();
+        physical.sort_unstable();
+        physical.dedup();
+        assert_eq!(physical.len(), 4);
+
+        // the same seed gives the same layout
+        let (again, _) = anneal_layout(
+            &cif_pairs,
+            &ctrl2pq,
+            &initial,
+            AnnealSchedule::default(),
+            Some(7),
+        )
+        .unwrap();
+        assert_eq!(again, layout);
+    }
+
+    #[test]
+    fn test_anneal_layout_errors() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::new());
+        let schedule = AnnealSchedule::default();
+        for layout in [
+            HashMap::from([(0, 0), (1, 0)]),
+            HashMap::from([(0, 0), (2, 1)]),
+            HashMap::from([(0, 0), (1, 7)]),
+        ] {
+            assert!(anneal_layout(&cif_pairs, &ctrl2pq, &layout, schedule, None).is_err());
+        }
+        let schedule = AnnealSchedule {
+            final_temperature: 2.,
+            ..schedule
+        };
+        let layout = HashMap::from([(0, 0)]);
+        assert!(anneal_layout(&cif_pairs, &ctrl2pq, &layout, schedule, None).is_err());
+    }
+}
@@ -0,0 +1,517 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::time::Instant;
+
+use hashbrown::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+use super::{
+    cif_pairs::{is_well_formed, CifPairs},
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    front_layer::FrontLayer,
+    metrics::{self, Phase},
+    router::{gate_dependencies, RouterGate, RouterOutput, RoutingResult, SwapList},
+    state::{DqcMapState, DEFAULT_FEEDBACK_WEIGHT},
+};
+
+/// Number of expanded search states after which the search gives up by default.
+pub const DEFAULT_MAX_EXPANSIONS: usize = 100_000;
+
+/// A state of the search, reached by a swap after which every reachable gate is routed.
+struct SearchNode {
+    layout: NLayout,
+    front_layer: FrontLayer,
+    routed: Vec<bool>,
+    /// Number of swaps plus the weighted cross-controller feedbacks of the routed gates.
+    cost: f64,
+    cross_ctrl_feedbacks: f64,
+    parent: Option<usize>,
+    swap: Option<[PhysicalQubit; 2]>,
+    /// Indices of the gates routed right after `swap`, in routing order.
+    routed_gates: Vec<usize>,
+}
+
+/// An entry of the open set, ordered so that the max-heap pops the lowest estimated total cost
+/// first, then the state furthest into the circuit, then the oldest.
+struct OpenEntry {
+    estimate: f64,
+    num_retired: usize,
+    index: usize,
+}
+
+impl PartialEq for OpenEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(self.num_retired.cmp(&other.num_retired))
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+struct AStar<'a> {
+    coupling: &'a CouplingMap,
+    gates: &'a [RouterGate],
+    /// The cif pairs of every gate as conditioned and measured virtual qubits, with their weight.
+    gate_pairs: GatePairs,
+    ctrl2pq: Option<&'a Ctrl2Pq>,
+    feedback_weight: f64,
+}
+
+impl<'a> AStar<'a> {
+    #[inline]
+    fn distance(&self, layout: &NLayout, gate: usize) -> usize {
+        match self.gates[gate].qubits[..] {
+            [a, b] => self.coupling.distance(a.to_phys(layout), b.to_phys(layout)),
+            _ => 1,
+        }
+    }
+
+    #[inline]
+    fn is_cross_controller(&self, layout: &NLayout, pair: [VirtualQubit; 2]) -> bool {
+        let Some(ctrl2pq) = self.ctrl2pq else {
+            return false;
+        };
+        match (
+            ctrl2pq.controller_of(pair[0].to_phys(layout)),
+            ctrl2pq.controller_of(pair[1].to_phys(layout)),
+        ) {
+            (Some(ctrl0), Some(ctrl1)) => ctrl0 != ctrl1,
+            _ => false,
+        }
+    }
+
+    /// Route every gate reachable from `gates` that is executable on the layout of `node`, fixing
+    /// the cost of its cif pairs.
+    fn route_reachable_gates(&self, node: &mut SearchNode, gates: Vec<usize>) {
+        let mut to_visit = VecDeque::from(gates);
+        while let Some(gate) = to_visit.pop_front() {
+            if self.distance(&node.layout, gate) != 1 {
+                continue;
+            }
+            for &(pair, weight) in &self.gate_pairs[gate] {
+                if self.is_cross_controller(&node.layout, pair) {
+                    node.cross_ctrl_feedbacks += weight;
+                    node.cost += self.feedback_weight * weight;
+                }
+            }
+            node.routed[gate] = true;
+            node.routed_gates.push(gate);
+            to_visit.extend(node.front_layer.retire(gate));
+        }
+    }
+
+    /// A lower bound of the cost still to pay from `node`, infinite if a gate can never be routed.
+    ///
+    /// Every swap moves two qubits and brings each of them at most one step closer to its partner,
+    /// so at least half the excess distance of the front layer, and the excess distance of its
+    /// furthest gate, remain to be swapped.  Every cif pair of an unrouted gate that currently
+    /// crosses controllers either ends up crossing them or has one of its qubits moved by a swap,
+    /// which moves pairs weighing at most twice the maximum weight of such pairs per qubit.  A
+    /// swap can serve both purposes, so the larger of the two bounds is taken.
+    fn heuristic(&self, node: &SearchNode) -> f64 {
+        let mut max_excess = 0;
+        let mut total_excess = 0;
+        for &gate in node.front_layer.nodes() {
+            let distance = self.distance(&node.layout, gate);
+            if distance == usize::MAX {
+                return f64::INFINITY;
+            }
+            max_excess = max_excess.max(distance - 1);
+            total_excess += distance - 1;
+        }
+        let distance_bound = (max_excess as f64).max(total_excess as f64 / 2.);
+
+        let mut cross_weight = 0.;
+        let mut weight_per_qubit: HashMap<VirtualQubit, f64> = HashMap::new();
+        for (gate, pairs) in self.gate_pairs.iter().enumerate() {
+            if node.routed[gate] {
+                continue;
+            }
+            for &(pair, weight) in pairs {
+                if self.is_cross_controller(&node.layout, pair) {
+                    cross_weight += weight;
+                    for qubit in pair {
+                        *weight_per_qubit.entry(qubit).or_insert(0.) += weight;
+                    }
+                }
+            }
+        }
+        let feedback_bound = match weight_per_qubit.values().copied().reduce(f64::max) {
+            Some(max_weight) => cross_weight * self.feedback_weight.min(1. / (2. * max_weight)),
+            None => 0.,
+        };
+        distance_bound.max(feedback_bound)
+    }
+}
+
+/// Route `gates` (given in a valid topological order) onto `coupling` like [super::router::route],
+/// but with an A* search for the swap sequence minimizing the number of swaps plus
+/// `feedback_weight` times the number of cross-controller feedbacks of the routed circuit.
+///
+/// Gates are routed as soon as they are executable, so the result is optimal among the swap
+/// sequences doing so.  The search space grows exponentially with the depth of the circuit, so
+/// it is meant for small to medium circuits and fails after `max_expansions` expanded states.
+pub fn route_astar(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    ctrl2pq: Option<&Ctrl2Pq>,
+    cif_pairs: Option<&CifPairs>,
+    feedback_weight: f64,
+    max_expansions: usize,
+) -> PyResult<RouterOutput> {
+    let _timer = metrics::time_phase(Phase::Routing);
+    let front_layer = gate_dependencies(coupling, initial_layout, gates)?;
+    let search = AStar {
+        coupling,
+        gates,
+        gate_pairs: virtual_gate_pairs(initial_layout, gates, cif_pairs),
+        ctrl2pq,
+        feedback_weight,
+    };
+
+    let first_layer = front_layer.nodes().to_vec();
+    let mut root = SearchNode {
+        layout: initial_layout.clone(),
+        front_layer,
+        routed: vec![false; gates.len()],
+        cost: 0.,
+        cross_ctrl_feedbacks: 0.,
+        parent: None,
+        swap: None,
+        routed_gates: Vec::new(),
+    };
+    search.route_reachable_gates(&mut root, first_layer);
+
+    let mut nodes = vec![root];
+    let mut best_cost: HashMap<(Vec<PhysicalQubit>, Vec<usize>), f64> = HashMap::new();
+    let mut open = BinaryHeap::from([OpenEntry {
+        estimate: search.heuristic(&nodes[0]),
+        num_retired: nodes[0].front_layer.num_retired(),
+        index: 0,
+    }]);
+    let mut num_expansions = 0;
+    let goal = loop {
+        let Some(OpenEntry {
+            estimate, index, ..
+        }) = open.pop()
+        else {
+            return Err(PyValueError::new_err(
+                "no swap sequence routes every gate, the coupling map is disconnected",
+            ));
+        };
+        if estimate.is_infinite() {
+            return Err(PyValueError::new_err(
+                "no swap sequence routes every gate, the coupling map is disconnected",
+            ));
+        }
+        let node = &nodes[index];
+        if node.front_layer.is_empty() {
+            break index;
+        }
+        let key = state_key(node);
+        // a cheaper path to the same state was expanded already
+        if best_cost.get(&key).is_some_and(|&cost| cost < node.cost) {
+            continue;
+        }
+        num_expansions += 1;
+        if num_expansions > max_expansions {
+            return Err(PyValueError::new_err(format!(
+                "the A* search exceeded {} expanded states",
+                max_expansions
+            )));
+        }
+
+        let swaps = DqcMapState::candidate_swaps(
+            coupling,
+            node.front_layer.nodes().iter().flat_map(|&gate| {
+                search.gates[gate]
+                    .qubits
+                    .iter()
+                    .map(|qubit| qubit.to_phys(&node.layout))
+            }),
+        );
+        for swap in swaps {
+            let node = &nodes[index];
+            let mut layout = node.layout.clone();
+            layout.swap_physical(swap[0], swap[1]);
+            let mut child = SearchNode {
+                layout,
+                front_layer: node.front_layer.clone(),
+                routed: node.routed.clone(),
+                cost: node.cost + 1.,
+                cross_ctrl_feedbacks: node.cross_ctrl_feedbacks,
+                parent: Some(index),
+                swap: Some(swap),
+                routed_gates: Vec::new(),
+            };
+            let front = child.front_layer.nodes().to_vec();
+            search.route_reachable_gates(&mut child, front);
+            let key = state_key(&child);
+            if best_cost.get(&key).is_some_and(|&cost| cost <= child.cost) {
+                continue;
+            }
+            best_cost.insert(key, child.cost);
+            open.push(OpenEntry {
+                estimate: child.cost + search.heuristic(&child),
+                num_retired: child.front_layer.num_retired(),
+                index: nodes.len(),
+            });
+            nodes.push(child);
+        }
+    };
+
+    // walk back from the goal, attaching every swap to the first gate routed after it
+    let mut path = Vec::new();
+    let mut current = Some(goal);
+    while let Some(index) = current {
+        path.push(index);
+        current = nodes[index].parent;
+    }
+    let mut swaps: SwapList = Vec::new();
+    let mut gate_order = Vec::with_capacity(gates.len());
+    let mut pending_swaps = Vec::new();
+    for &index in path.iter().rev() {
+        let node = &nodes[index];
+        pending_swaps.extend(node.swap);
+        if let Some(&first) = node.routed_gates.first() {
+            let node_id = gates[first].node_id;
+            swaps.extend(pending_swaps.drain(..).map(|swap| (node_id, swap)));
+        }
+        gate_order.extend(node.routed_gates.iter().map(|&gate| gates[gate].node_id));
+    }
+    let goal = &nodes[goal];
+    Ok(RouterOutput {
+        swaps,
+        gate_order,
+        final_layout: goal.layout.clone(),
+        cross_ctrl_feedbacks: (ctrl2pq.is_some() && cif_pairs.is_some())
+            .then_some(goal.cross_ctrl_feedbacks),
+        trace: None,
+    })
+}
+
+/// The cif pairs of every gate as conditioned and measured virtual qubits, with their weight.
+pub(super) type GatePairs = Vec<Vec<([VirtualQubit; 2], f64)>>;
+
+/// The [GatePairs] of `gates`, where `cif_pairs` are on the physical qubits of `initial_layout`.
+/// Malformed pairs and pairs of unknown nodes are ignored.
+pub(super) fn virtual_gate_pairs(
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    cif_pairs: Option<&CifPairs>,
+) -> GatePairs {
+    let mut gate_pairs = vec![Vec::new(); gates.len()];
+    let Some(cif_pairs) = cif_pairs else {
+        return gate_pairs;
+    };
+    let num_physical = initial_layout.iter_physical().len();
+    let gate_by_node: HashMap<usize, usize> = gates
+        .iter()
+        .enumerate()
+        .map(|(index, gate)| (gate.node_id, index))
+        .collect();
+    for (node_id, pairs) in cif_pairs.pairs().iter() {
+        let Some(&gate) = gate_by_node.get(node_id) else {
+            continue;
+        };
+        for (position, pair) in pairs.iter().enumerate() {
+            let in_range = |q: i32| 0 <= q && (q as usize) < num_physical;
+            if is_well_formed(pair) && in_range(pair[0]) && in_range(pair[1]) {
+                gate_pairs[gate].push((
+                    [
+                        PhysicalQubit::new(pair[0] as u32).to_virt(initial_layout),
+                        PhysicalQubit::new(pair[1] as u32).to_virt(initial_layout),
+                    ],
+                    cif_pairs.pair_weight(*node_id, position),
+                ));
+            }
+        }
+    }
+    gate_pairs
+}
+
+/// The layout and the front layer identify a state, as the routed gates are exactly the ones
+/// preceding the front layer.
+fn state_key(node: &SearchNode) -> (Vec<PhysicalQubit>, Vec<usize>) {
+    let mut front = node.front_layer.nodes().to_vec();
+    front.sort_unstable();
+    (
+        node.layout
+            .iter_virtual()
+            .map(|(_, physical)| physical)
+            .collect(),
+        front,
+    )
+}
+
+/// Route a circuit with an A* search for the swap sequence minimizing the number of swaps plus
+/// ``beta`` times the number of cross-controller feedbacks, instead of the greedy choices of
+/// ``dqcmap_routing``.  Gates are routed as soon as they are executable.  The GIL is released
+/// while routing.
+///
+/// The search is exponential in the depth of the circuit and meant for small to medium
+/// circuits.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     initial_layout (NLayout): The initial virtual to physical qubit layout.
+///     gates (list[tuple[int, list[int]]]): The node id and virtual qubits of every gate, in a
+///         valid topological order.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     cif_pairs (CifPairs): The cif pairs of the circuit keyed by node id, on the physical
+///         qubits of ``initial_layout``.
+///     beta (float): The cost of a cross-controller feedback relative to a swap.
+///     max_expansions (int): The number of expanded search states after which to give up.
+///
+/// Returns:
+///     RoutingResult: As for ``dqcmap_routing``.
+///
+/// Raises:
+///     ValueError: If the search exceeds ``max_expansions`` or a gate cannot be routed.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (coupling_map, initial_layout, gates, ctrl2pq=None, cif_pairs=None, beta=DEFAULT_FEEDBACK_WEIGHT, max_expansions=DEFAULT_MAX_EXPANSIONS))]
+pub fn dqcmap_astar_routing(
+    py: Python,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: Vec<(usize, Vec<VirtualQubit>)>,
+    ctrl2pq: Option<Ctrl2Pq>,
+    cif_pairs: Option<CifPairs>,
+    beta: f64,
+    max_expansions: usize,
+) -> PyResult<RoutingResult> {
+    if !(beta.is_finite() && beta >= 0.) {
+        return Err(PyValueError::new_err(format!(
+            "beta must be a non-negative finite number, got {beta}"
+        )));
+    }
+    let gates: Vec<RouterGate> = gates
+        .into_iter()
+        .map(|(node_id, qubits)| RouterGate { node_id, qubits })
+        .collect();
+    let start = Instant::now();
+    let output = py.allow_threads(|| {
+        route_astar(
+            coupling_map,
+            initial_layout,
+            &gates,
+            ctrl2pq.as_ref(),
+            cif_pairs.as_ref(),
+            beta,
+            max_expansions,
+        )
+    })?;
+    let elapsed = start.elapsed();
+    Ok(RoutingResult::new(output, initial_layout, &gates, elapsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::router::{route, RouteOptions};
+
+    fn line(num_qubits: u32) -> CouplingMap {
+        let edges = (0..num_qubits - 1)
+            .map(|q| [PhysicalQubit::new(q), PhysicalQubit::new(q + 1)])
+            .collect();
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    fn gate(node_id: usize, qubits: &[u32]) -> RouterGate {
+        RouterGate {
+            node_id,
+            qubits: qubits.iter().map(|&q| VirtualQubit::new(q)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_astar_never_needs_more_swaps_than_greedy() {
+        let layout = NLayout::generate_trivial_layout(5);
+        let gates = vec![
+            gate(0, &[0, 4]),
+            gate(1, &[1, 3]),
+            gate(2, &[0, 2]),
+            gate(3, &[4, 1]),
+        ];
+        let greedy = route(
+            &line(5),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap();
+        let astar = route_astar(
+            &line(5),
+            &layout,
+            &gates,
+            None,
+            None,
+            0.,
+            DEFAULT_MAX_EXPANSIONS,
+        )
+        .unwrap();
+        assert!(astar.swaps.len() <= greedy.swaps.len());
+        assert_eq!(astar.gate_order.len(), 4);
+        for (node_id, [a, b]) in &astar.swaps {
+            assert!(gates.iter().any(|gate| gate.node_id == *node_id));
+            assert_eq!(line(5).distance(*a, *b), 1);
+        }
+    }
+
+    #[test]
+    fn test_astar_avoids_cross_controller_feedback() {
+        // 0 - 1 - 2 - 3 with controllers {0, 1} and {2, 3}; node 1 measures qubit 0 and
+        // conditions qubit 3 after they interact, which is within a controller only if one of
+        // them takes both swaps
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(0, &[0, 3]), gate(1, &[3])];
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![3, 0]])]));
+        let output = route_astar(
+            &line(4),
+            &layout,
+            &gates,
+            Some(&ctrl2pq),
+            Some(&cif_pairs),
+            10.,
+            DEFAULT_MAX_EXPANSIONS,
+        )
+        .unwrap();
+        assert_eq!(output.cross_ctrl_feedbacks, Some(0.));
+        assert_eq!(output.swaps.len(), 2);
+        assert_eq!(output.gate_order, vec![0, 1]);
+
+        let output = route_astar(
+            &line(4),
+            &layout,
+            &gates,
+            Some(&ctrl2pq),
+            Some(&cif_pairs),
+            0.,
+            1,
+        );
+        assert!(output.is_err());
+    }
+}
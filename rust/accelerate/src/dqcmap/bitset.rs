@@ -0,0 +1,94 @@
+use super::swap::Swap;
+
+/// The largest number of qubits a [QubitBitset] can hold.
+pub const MAX_DENSE_QUBITS: usize = 1024;
+
+const WORDS: usize = MAX_DENSE_QUBITS / u64::BITS as usize;
+
+/// A fixed-size set of the qubits `0..MAX_DENSE_QUBITS`, so that membership of a qubit is a
+/// single bit test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QubitBitset {
+    words: [u64; WORDS],
+}
+
+impl Default for QubitBitset {
+    fn default() -> Self {
+        QubitBitset { words: [0; WORDS] }
+    }
+}
+
+impl QubitBitset {
+    /// The set of `qubits`, `None` if one of them is out of the dense range.
+    pub fn from_qubits<'a, I>(qubits: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a i32>,
+    {
+        let mut bitset = QubitBitset::default();
+        for &qubit in qubits {
+            if !QubitBitset::in_range(qubit) {
+                return None;
+            }
+            bitset.insert(qubit);
+        }
+        Some(bitset)
+    }
+
+    #[inline]
+    pub fn in_range(qubit: i32) -> bool {
+        (0..MAX_DENSE_QUBITS as i32).contains(&qubit)
+    }
+
+    /// Add `qubit`, returning whether it was not in the set yet.  Qubits out of the dense range
+    /// are not added.
+    pub fn insert(&mut self, qubit: i32) -> bool {
+        if !QubitBitset::in_range(qubit) {
+            return false;
+        }
+        let (word, bit) = (qubit as usize / 64, qubit as usize % 64);
+        let absent = self.words[word] & (1 << bit) == 0;
+        self.words[word] |= 1 << bit;
+        absent
+    }
+
+    #[inline]
+    pub fn contains(&self, qubit: i32) -> bool {
+        QubitBitset::in_range(qubit)
+            && self.words[qubit as usize / 64] & (1 << (qubit as usize % 64)) != 0
+    }
+
+    /// Whether the set holds either qubit of `swap`.
+    #[inline]
+    pub fn touches(&self, swap: Swap) -> bool {
+        self.contains(swap[0]) || self.contains(swap[1])
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qubit_bitset() {
+        let bitset = QubitBitset::from_qubits(&[0, 63, 64, 1023, 64]).unwrap();
+        assert_eq!(bitset.len(), 4);
+        assert!(bitset.contains(63) && bitset.contains(64) && bitset.contains(1023));
+        assert!(!bitset.contains(1) && !bitset.contains(-1) && !bitset.contains(1024));
+        assert!(bitset.touches(Swap::new(5, 64).unwrap()));
+        assert!(!bitset.touches(Swap::new(5, 6).unwrap()));
+        assert!(QubitBitset::default().is_empty());
+        assert_eq!(QubitBitset::from_qubits(&[1, 1024]), None);
+        assert_eq!(QubitBitset::from_qubits(&[-1]), None);
+    }
+}
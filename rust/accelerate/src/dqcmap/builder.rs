@@ -0,0 +1,287 @@
+#[cfg(feature = "python")]
+use {super::device::NoiseSource, pyo3::prelude::*};
+
+use super::{
+    cif_pairs::{is_well_formed, CifPairs},
+    clbit_to_ctrl::Clbit2Ctrl,
+    config::DqcMapConfig,
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+    error::MappingError,
+    noise::NoiseModel,
+    state::DqcMapState,
+};
+
+/// Assembles a ``DqcMapState`` and checks that its ``Ctrl2Pq`` and ``CifPairs`` agree before
+/// building it, so that a qubit without a controller is reported where the state is built rather
+/// than as a ``None`` score in the middle of routing.
+///
+/// Every setter returns the builder, so that calls can be chained::
+///
+///     state = (
+///         DqcMapStateBuilder()
+///         .ctrl2pq(ctrl2pq)
+///         .cif_pairs(cif_pairs)
+///         .num_qubits(27)
+///         .build()
+///     )
+///
+/// Malformed cif pairs, which all scores ignore, are not checked.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Default)]
+pub struct DqcMapStateBuilder {
+    ctrl2pq: Option<Ctrl2Pq>,
+    cif_pairs: Option<CifPairs>,
+    num_qubits: Option<usize>,
+    config: Option<DqcMapConfig>,
+    ctrl_topology: Option<CtrlTopology>,
+    noise_model: Option<NoiseModel>,
+    clbit2ctrl: Option<Clbit2Ctrl>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DqcMapStateBuilder {
+    #[new]
+    fn py_new() -> Self {
+        DqcMapStateBuilder::default()
+    }
+
+    /// Set the mapping between controllers and physical qubits, required.
+    #[pyo3(name = "ctrl2pq", text_signature = "(self, ctrl2pq, /)")]
+    fn py_ctrl2pq(mut slf: PyRefMut<Self>, ctrl2pq: Ctrl2Pq) -> PyRefMut<Self> {
+        slf.ctrl2pq = Some(ctrl2pq);
+        slf
+    }
+
+    /// Set the cif pairs of the circuit on physical qubits, required.
+    #[pyo3(name = "cif_pairs", text_signature = "(self, cif_pairs, /)")]
+    fn py_cif_pairs(mut slf: PyRefMut<Self>, cif_pairs: CifPairs) -> PyRefMut<Self> {
+        slf.cif_pairs = Some(cif_pairs);
+        slf
+    }
+
+    /// Set the number of qubits of the device, which all qubits must be below.  Qubits are only
+    /// checked to be non-negative if it is not set.
+    #[pyo3(name = "num_qubits", text_signature = "(self, num_qubits, /)")]
+    fn py_num_qubits(mut slf: PyRefMut<Self>, num_qubits: usize) -> PyRefMut<Self> {
+        slf.num_qubits = Some(num_qubits);
+        slf
+    }
+
+    /// Take the scoring parameters from ``config``, see ``DqcMapState.apply_config``.
+    #[pyo3(name = "config", text_signature = "(self, config, /)")]
+    fn py_config(mut slf: PyRefMut<Self>, config: DqcMapConfig) -> PyRefMut<Self> {
+        slf.config = Some(config);
+        slf
+    }
+
+    /// Set the latency model between controllers.
+    #[pyo3(name = "ctrl_topology", text_signature = "(self, ctrl_topology, /)")]
+    fn py_ctrl_topology(mut slf: PyRefMut<Self>, ctrl_topology: CtrlTopology) -> PyRefMut<Self> {
+        slf.ctrl_topology = Some(ctrl_topology);
+        slf
+    }
+
+    /// Set the error rates of the device, from a ``NoiseModel`` or a ``DeviceProperties``.
+    #[pyo3(name = "noise_model", text_signature = "(self, noise_model, /)")]
+    fn py_noise_model(
+        mut slf: PyRefMut<Self>,
+        noise_model: NoiseSource,
+    ) -> PyResult<PyRefMut<Self>> {
+        slf.noise_model = Some(noise_model.into_noise_model()?);
+        Ok(slf)
+    }
+
+    /// Set the controllers of the clbits.
+    #[pyo3(name = "clbit2ctrl", text_signature = "(self, clbit2ctrl, /)")]
+    fn py_clbit2ctrl(mut slf: PyRefMut<Self>, clbit2ctrl: Clbit2Ctrl) -> PyRefMut<Self> {
+        slf.clbit2ctrl = Some(clbit2ctrl);
+        slf
+    }
+
+    /// Check that ``ctrl2pq`` and ``cif_pairs`` are set and agree.
+    ///
+    /// Raises:
+    ///     DqcMapError: if one of them is missing, a qubit is negative or not below
+    ///         ``num_qubits``, or a qubit of a cif pair is not connected to any controller.
+    #[pyo3(name = "validate", text_signature = "(self, /)")]
+    fn py_validate(&self) -> PyResult<()> {
+        Ok(self.validate()?)
+    }
+
+    /// Validate the inputs and build the state.  The builder can be reused afterwards.
+    ///
+    /// Returns:
+    ///     DqcMapState: A state holding all inputs set on the builder.
+    ///
+    /// Raises:
+    ///     DqcMapError: if the inputs are inconsistent, see ``validate``.
+    #[pyo3(name = "build", text_signature = "(self, /)")]
+    fn py_build(&self) -> PyResult<DqcMapState> {
+        Ok(self.clone().build()?)
+    }
+}
+
+impl DqcMapStateBuilder {
+    pub fn new() -> Self {
+        DqcMapStateBuilder::default()
+    }
+
+    pub fn ctrl2pq(mut self, ctrl2pq: Ctrl2Pq) -> Self {
+        self.ctrl2pq = Some(ctrl2pq);
+        self
+    }
+
+    pub fn cif_pairs(mut self, cif_pairs: CifPairs) -> Self {
+        self.cif_pairs = Some(cif_pairs);
+        self
+    }
+
+    pub fn num_qubits(mut self, num_qubits: usize) -> Self {
+        self.num_qubits = Some(num_qubits);
+        self
+    }
+
+    pub fn config(mut self, config: DqcMapConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn ctrl_topology(mut self, ctrl_topology: CtrlTopology) -> Self {
+        self.ctrl_topology = Some(ctrl_topology);
+        self
+    }
+
+    pub fn noise_model(mut self, noise_model: NoiseModel) -> Self {
+        self.noise_model = Some(noise_model);
+        self
+    }
+
+    pub fn clbit2ctrl(mut self, clbit2ctrl: Clbit2Ctrl) -> Self {
+        self.clbit2ctrl = Some(clbit2ctrl);
+        self
+    }
+
+    /// See [DqcMapStateBuilder::py_validate].  The first inconsistency is reported, the
+    /// controllers being checked in id order before the cif pairs in node order.
+    pub fn validate(&self) -> Result<(), MappingError> {
+        let ctrl2pq = self
+            .ctrl2pq
+            .as_ref()
+            .ok_or(MappingError::MissingInput("ctrl2pq"))?;
+        let cif_pairs = self
+            .cif_pairs
+            .as_ref()
+            .ok_or(MappingError::MissingInput("cif_pairs"))?;
+        check_mapping(ctrl2pq, cif_pairs, self.num_qubits)
+    }
+
+    /// Validate the inputs and build the state, see [DqcMapStateBuilder::py_build].
+    pub fn build(self) -> Result<DqcMapState, MappingError> {
+        self.validate()?;
+        let mut state = DqcMapState::new(self.ctrl2pq, self.cif_pairs);
+        if let Some(config) = self.config.as_ref() {
+            state.apply_config(config)?;
+        }
+        state.ctrl_topology = self.ctrl_topology;
+        state.noise_model = self.noise_model;
+        state.clbit2ctrl = self.clbit2ctrl;
+        Ok(state)
+    }
+}
+
+/// Check that all qubits of `ctrl2pq` and `cif_pairs` are non-negative and below `num_qubits`,
+/// if given, and that both qubits of every well-formed cif pair are connected to a controller.
+pub(super) fn check_mapping(
+    ctrl2pq: &Ctrl2Pq,
+    cif_pairs: &CifPairs,
+    num_qubits: Option<usize>,
+) -> Result<(), MappingError> {
+    let check_range = |qubit: i32| {
+        let in_range =
+            qubit >= 0 && num_qubits.map_or(true, |num_qubits| (qubit as usize) < num_qubits);
+        if in_range {
+            Ok(())
+        } else {
+            Err(MappingError::QubitOutOfRange { qubit, num_qubits })
+        }
+    };
+    for ctrl_id in ctrl2pq.controllers() {
+        for &qubit in ctrl2pq
+            .get_qubits_by_controller(ctrl_id)
+            .unwrap_or_default()
+        {
+            check_range(qubit)?;
+        }
+    }
+    for (&node, pairs) in cif_pairs.pairs() {
+        for pair in pairs.iter().filter(|pair| is_well_formed(pair)) {
+            for &qubit in &pair[..2] {
+                check_range(qubit)?;
+                if ctrl2pq.get_controller_by_qubit(qubit).is_none() {
+                    return Err(MappingError::UnmappedQubit { node, qubit });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::swap::Swap;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn test_state_builder() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(0, vec![vec![0, 2]])]));
+
+        let state = DqcMapStateBuilder::new()
+            .ctrl2pq(ctrl2pq.clone())
+            .cif_pairs(cif_pairs.clone())
+            .num_qubits(4)
+            .config(DqcMapConfig {
+                lookahead_window: 2,
+                ..DqcMapConfig::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(state.lookahead_window, 2);
+        assert_eq!(
+            state.score(Swap::new(1, 2).unwrap(), &[0]).unwrap(),
+            Some(1.)
+        );
+
+        assert_eq!(
+            DqcMapStateBuilder::new()
+                .cif_pairs(cif_pairs.clone())
+                .validate(),
+            Err(MappingError::MissingInput("ctrl2pq"))
+        );
+        assert_eq!(
+            DqcMapStateBuilder::new()
+                .ctrl2pq(ctrl2pq.clone())
+                .cif_pairs(cif_pairs.clone())
+                .num_qubits(3)
+                .validate(),
+            Err(MappingError::QubitOutOfRange {
+                qubit: 3,
+                num_qubits: Some(3)
+            })
+        );
+        let unmapped = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![0, 2]]),
+            (4, vec![vec![1], vec![5, 1]]),
+        ]));
+        assert_eq!(
+            DqcMapStateBuilder::new()
+                .ctrl2pq(ctrl2pq)
+                .cif_pairs(unmapped)
+                .validate(),
+            Err(MappingError::UnmappedQubit { node: 4, qubit: 5 })
+        );
+    }
+}
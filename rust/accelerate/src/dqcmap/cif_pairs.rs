@@ -1,94 +1,1157 @@
-use hashbrown::HashMap;
-use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use std::borrow::Cow;
+#[cfg(feature = "python")]
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::sync::Arc;
 
-#[pyclass(module = "dqcmap._accelerate.dqcmap")]
+use hashbrown::{HashMap, HashSet};
+use im::OrdMap;
+use ndarray::{Array2, ArrayView1};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "python")]
+use {
+    numpy::{AllowTypeChange, IntoPyArray, PyArray2, PyArrayLike1},
+    pyo3::exceptions::PyKeyError,
+    pyo3::prelude::*,
+    pyo3::types::{PyDict, PyList},
+};
+
+use super::{
+    bitset::QubitBitset,
+    ctrl_to_pq::Ctrl2Pq,
+    error::MappingError,
+    memory::{map_bytes, ord_map_bytes, vec_bytes, MemoryStats},
+    metrics,
+    pair_list::PairList,
+    swap::Swap,
+};
+
+/// Whether `pair` is well-formed: two qubits, optionally followed by the clbit holding the
+/// measurement result.
+#[inline]
+pub fn is_well_formed(pair: &[i32]) -> bool {
+    matches!(pair.len(), 2 | 3)
+}
+
+/// `node_ids` sorted and without duplicates, borrowed when it already is, so that membership
+/// tests are binary searches rather than scans of the whole list.
+fn sorted_node_ids(node_ids: &[usize]) -> Cow<'_, [usize]> {
+    if node_ids.windows(2).all(|w| w[0] < w[1]) {
+        return Cow::Borrowed(node_ids);
+    }
+    let mut sorted = node_ids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    Cow::Owned(sorted)
+}
+
+/// The clbit of `pair`, if it carries one.
+#[inline]
+pub fn pair_clbit(pair: &[i32]) -> Option<i32> {
+    pair.get(2).copied()
+}
+
+/// Whether `pair` is well-formed and its qubits are connected to two different controllers.
+pub fn is_cross_controller(pair: &[i32], ctrl2pq: &Ctrl2Pq) -> bool {
+    if !is_well_formed(pair) {
+        return false;
+    }
+    match (
+        ctrl2pq.get_controller_by_qubit(pair[0]),
+        ctrl2pq.get_controller_by_qubit(pair[1]),
+    ) {
+        (Some(ctrl0), Some(ctrl1)) => ctrl0 != ctrl1,
+        _ => false,
+    }
+}
+
+/// The (node id, position in the node's pairs) of every pair a qubit appears in.
+type QubitIndex = HashMap<i32, Arc<Vec<(usize, usize)>>>;
+
+/// The pairs of every node, sorted by node id so that iterating over them, and everything
+/// computed from it, does not depend on the hasher.  The map is persistent: a clone shares all
+/// its nodes, and modifying it only copies the path to the changed entry, so forks of a state
+/// cost O(log n) per modified node rather than a copy of the whole map.
+pub type NodePairs = OrdMap<usize, Arc<PairList>>;
+
+/// The weights of the pairs of every node, in the order of the node's pairs.
+pub type PairWeights = HashMap<usize, Vec<f64>>;
+
+/// Pairs borrowed from their node's flat array, with their node id.
+pub type PairViews<'a> = Vec<(usize, &'a [i32])>;
+
+/// Copies of the pairs with their node id, keyed by the controllers of their qubits.
+#[cfg(feature = "python")]
+type ControllerPairGroups = HashMap<(i32, i32), Vec<(usize, Vec<i32>)>>;
+
+/// Counts of the cif pairs of a circuit by the controllers of their two qubits.
+///
+/// Pairs with a qubit that is not connected to any controller are only counted in
+/// ``total_pairs`` and ``unmapped_pairs``.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedbackStats {
+    pub total_pairs: usize,
+    pub cross_controller_pairs: usize,
+    pub unmapped_pairs: usize,
+    /// Number of pairs between every two controllers, keyed by the (smaller, larger) controller
+    /// ids; intra-controller pairs are keyed by the same id twice.
+    pub per_controller_pair: HashMap<(i32, i32), usize>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl FeedbackStats {
+    #[new]
+    #[pyo3(signature = (total_pairs=0, cross_controller_pairs=0, unmapped_pairs=0, per_controller_pair=HashMap::new()))]
+    fn new(
+        total_pairs: usize,
+        cross_controller_pairs: usize,
+        unmapped_pairs: usize,
+        per_controller_pair: HashMap<(i32, i32), usize>,
+    ) -> Self {
+        FeedbackStats {
+            total_pairs,
+            cross_controller_pairs,
+            unmapped_pairs,
+            per_controller_pair,
+        }
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.total_pairs,
+                self.cross_controller_pairs,
+                self.unmapped_pairs,
+                self.per_controller_pair.clone(),
+            ),
+        )
+            .into_py(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "FeedbackStats(total_pairs={}, cross_controller_pairs={}, unmapped_pairs={})",
+            self.total_pairs, self.cross_controller_pairs, self.unmapped_pairs
+        )
+    }
+}
+
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, module = "dqcmap._accelerate.dqcmap")
+)]
 #[derive(Clone, Debug)]
 pub struct CifPairs {
     // A container storing all cif pairs
     // a cif pair is defined by two qubit indexes, of which one qubit's operation is conditioned on
-    // another
-    pub pairs: HashMap<usize, Vec<Vec<i32>>>,
+    // another, optionally followed by the clbit that carries the measurement result, which is
+    // neither indexed nor changed by swaps
+    // The pairs of every node are stored in flat arrays, without an allocation per pair, so that
+    // millions of pairs fit in memory.
+    // The maps keyed by node are persistent and the others copy-on-write, all of them at the
+    // entry level as well, so that clones share all the pairs that neither of them modifies.
+    pairs: NodePairs,
+    // inverted index from a qubit to the (node id, position in the node's pairs) of every pair
+    // it appears in, with one entry per occurrence
+    qubit_index: Arc<QubitIndex>,
+    // the qubits of the pairs of every node as a bitset, as long as all qubits are below
+    // `MAX_DENSE_QUBITS`, so that finding the pairs of the active nodes touched by a swap only
+    // scans the pairs of the nodes holding one of its qubits
+    node_qubits: Option<OrdMap<usize, QubitBitset>>,
+    // the weight of every pair, in the order of the node's pairs, of the nodes whose pairs do
+    // not all weigh 1, e.g. the number of times a feedback repeats in a loop
+    weights: Arc<PairWeights>,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl CifPairs {
     #[new]
-    fn new(obj: Bound<PyDict>) -> PyResult<Self> {
-        let mut pairs: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
+    #[pyo3(signature = (obj, weights=None))]
+    fn new(obj: Bound<PyDict>, weights: Option<PairWeights>) -> PyResult<Self> {
+        let mut pairs: HashMap<usize, PairList> = HashMap::new();
+        let mut pair: Vec<i32> = Vec::new();
 
         for (py_node_id, part_pairs) in obj.iter() {
             let py_node_id: usize = py_node_id.extract()?;
 
             let py_part_pairs: &PyList = part_pairs.extract()?;
 
-            let mut part_pairs: Vec<Vec<i32>> = Vec::new();
+            let mut part_pairs = PairList::with_capacity(py_part_pairs.len(), 0);
 
             for sublist in py_part_pairs.iter() {
                 let py_sublist: &PyList = sublist.extract()?;
-                let mut vec: Vec<i32> = Vec::new();
+                pair.clear();
                 for item in py_sublist {
                     let val: i32 = item.extract()?;
-                    vec.push(val);
+                    pair.push(val);
                 }
 
-                part_pairs.push(vec);
+                part_pairs.push(&pair);
             }
             pairs.insert(py_node_id, part_pairs);
         }
 
-        Ok(CifPairs { pairs })
+        let mut cif_pairs = CifPairs::from_lists(pairs);
+        for (node_id, node_weights) in weights.into_iter().flatten() {
+            cif_pairs.set_weights(node_id, node_weights)?;
+        }
+        Ok(cif_pairs)
+    }
+
+    /// Serialize the cif pairs to a JSON object mapping node ids to their list of pairs, with the
+    /// node ids sorted so that the output is stable.
+    #[pyo3(name = "to_json", text_signature = "(self, /)")]
+    fn py_to_json(&self) -> PyResult<String> {
+        Ok(self.to_json()?)
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (self.to_map(), self.weights.as_ref().clone()),
+        )
+            .into_py(py)
+    }
+
+    /// Cif pairs are equal if every node has the same pairs, in the same order, with the same
+    /// weights.
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    /// A hash of the pairs and their weights, so that cif pairs can key caches.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The number of nodes with cif pairs.
+    fn __len__(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// The cif pairs of node ``node_id``.
+    ///
+    /// Raises:
+    ///     KeyError: if the node has no cif pairs.
+    fn __getitem__(&self, node_id: usize) -> PyResult<Vec<Vec<i32>>> {
+        self.pairs
+            .get(&node_id)
+            .map(|pairs| pairs.to_vec())
+            .ok_or_else(|| PyKeyError::new_err(node_id))
+    }
+
+    /// The weights of the cif pairs of node ``node_id``, in the order of its pairs.  Pairs weigh
+    /// 1 unless given another weight with the ``weights`` argument of the constructor, e.g. the
+    /// number of times their feedback repeats, which ``DqcMapState.score_weighted`` multiplies
+    /// their cost by.
+    ///
+    /// Raises:
+    ///     KeyError: if the node has no cif pairs.
+    #[pyo3(name = "weights", text_signature = "(self, node_id, /)")]
+    fn py_weights(&self, node_id: usize) -> PyResult<Vec<f64>> {
+        let num_pairs = self
+            .pairs
+            .get(&node_id)
+            .ok_or_else(|| PyKeyError::new_err(node_id))?
+            .len();
+        Ok((0..num_pairs)
+            .map(|position| self.pair_weight(node_id, position))
+            .collect())
+    }
+
+    /// Whether node ``node_id`` has cif pairs.
+    fn __contains__(&self, node_id: usize) -> bool {
+        self.pairs.contains_key(&node_id)
+    }
+
+    /// Iterate over the node ids in increasing order.
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyList::new_bound(py, self.node_ids())
+            .as_any()
+            .iter()?
+            .into_py(py))
+    }
+
+    /// The number of cif pairs whose qubits are connected to two different controllers, every
+    /// pair counting as many times as its weight.
+    #[pyo3(name = "count_cross_controller", text_signature = "(self, ctrl2pq, /)")]
+    fn py_count_cross_controller(&self, ctrl2pq: &Ctrl2Pq) -> f64 {
+        self.count_cross_controller(ctrl2pq)
+    }
+
+    /// Count the cif pairs in total, across controllers and per pair of controllers.
+    #[pyo3(name = "stats", text_signature = "(self, ctrl2pq, /)")]
+    fn py_stats(&self, ctrl2pq: &Ctrl2Pq) -> FeedbackStats {
+        self.stats(ctrl2pq)
+    }
+
+    /// The cif pairs with a qubit connected to controller ``ctrl_id``, in node id order.
+    /// Malformed pairs are left out.
+    ///
+    /// Returns:
+    ///     list[tuple[int, list[int]]]: The ``(node_id, pair)`` of every such pair.
+    #[pyo3(
+        name = "pairs_for_controller",
+        text_signature = "(self, ctrl2pq, ctrl_id, /)"
+    )]
+    fn py_pairs_for_controller(&self, ctrl2pq: &Ctrl2Pq, ctrl_id: i32) -> Vec<(usize, Vec<i32>)> {
+        self.pairs_for_controller(ctrl2pq, ctrl_id)
+            .into_iter()
+            .map(|(node_id, pair)| (node_id, pair.to_vec()))
+            .collect()
+    }
+
+    /// The cif pairs bucketed by the controllers of their two qubits, keyed by
+    /// ``(ctrl_a, ctrl_b)`` with ``ctrl_a <= ctrl_b`` as in ``FeedbackStats``, so that the
+    /// intra-controller pairs of a controller ``c`` are under ``(c, c)``.  Malformed pairs and
+    /// pairs with a qubit that is not connected to any controller are left out.
+    ///
+    /// Returns:
+    ///     dict[tuple[int, int], list[tuple[int, list[int]]]]: The ``(node_id, pair)`` of the
+    ///     pairs of every pair of controllers, in node id order.
+    #[pyo3(
+        name = "group_by_controller_pair",
+        text_signature = "(self, ctrl2pq, /)"
+    )]
+    fn py_group_by_controller_pair(&self, ctrl2pq: &Ctrl2Pq) -> ControllerPairGroups {
+        self.group_by_controller_pair(ctrl2pq)
+            .into_iter()
+            .map(|(ctrls, pairs)| {
+                let pairs = pairs
+                    .into_iter()
+                    .map(|(node_id, pair)| (node_id, pair.to_vec()))
+                    .collect();
+                (ctrls, pairs)
+            })
+            .collect()
+    }
+
+    /// Report the number of pairs and the memory held by the pairs and their indices.
+    ///
+    /// Returns:
+    ///     MemoryStats: The statistics, with the fields of the routing state left at 0.
+    #[pyo3(name = "memory_stats", text_signature = "(self, /)")]
+    fn py_memory_stats(&self) -> MemoryStats {
+        self.memory_stats()
+    }
+
+    /// The number of feedbacks between every two controllers as a ``(num_controllers,
+    /// num_controllers)`` integer array, where entry ``[i, j]`` counts the pairs whose measured
+    /// qubit (the second one) is connected to controller ``ctrl2pq.controllers()[i]`` and whose
+    /// conditioned qubit (the first one) is connected to ``ctrl2pq.controllers()[j]``.  The
+    /// diagonal holds the intra-controller feedbacks.  Malformed pairs and pairs with a qubit
+    /// that is not connected to any controller are not counted.
+    #[pyo3(name = "feedback_matrix", text_signature = "(self, ctrl2pq, /)")]
+    fn py_feedback_matrix<'py>(
+        &self,
+        py: Python<'py>,
+        ctrl2pq: &Ctrl2Pq,
+    ) -> Bound<'py, PyArray2<usize>> {
+        self.feedback_matrix(ctrl2pq).into_pyarray_bound(py)
+    }
+
+    /// Build cif pairs from three equally long integer arrays, where pair ``i`` is
+    /// ``[q0s[i], q1s[i]]`` of node ``node_ids[i]``.  The pairs of a node keep their order in the
+    /// arrays.  ``int64`` arrays are read without copying, other integer arrays are converted.
+    ///
+    /// Raises:
+    ///     ValueError: if the arrays differ in length, a node id is negative or a qubit does not
+    ///         fit in 32 bits.
+    #[staticmethod]
+    #[pyo3(text_signature = "(node_ids, q0s, q1s, /)")]
+    fn from_arrays(
+        node_ids: PyArrayLike1<i64, AllowTypeChange>,
+        q0s: PyArrayLike1<i64, AllowTypeChange>,
+        q1s: PyArrayLike1<i64, AllowTypeChange>,
+    ) -> PyResult<Self> {
+        Ok(CifPairs::from_columns(
+            node_ids.as_array(),
+            q0s.as_array(),
+            q1s.as_array(),
+        )?)
+    }
+
+    /// Cif pairs without any node.  Use a ``CifPairsBuilder`` to fill them while traversing a
+    /// circuit.
+    #[staticmethod]
+    #[pyo3(name = "empty", text_signature = "()")]
+    fn py_empty() -> Self {
+        CifPairs::empty()
+    }
+
+    /// Load cif pairs from the output of ``to_json``.
+    #[staticmethod]
+    #[pyo3(name = "from_json", text_signature = "(json, /)")]
+    fn py_from_json(json: &str) -> PyResult<Self> {
+        Ok(CifPairs::from_json(json)?)
+    }
+
+    /// Combine the cif pairs with the ones of another circuit fragment.  The node ids of
+    /// ``other`` are shifted by ``node_offset``, and a node present in both keeps its own pairs
+    /// followed by the pairs of ``other`` it does not already have.  A pair repeated within
+    /// ``other`` is only added once.
+    ///
+    /// Returns:
+    ///     CifPairs: The merged cif pairs, both operands are left unchanged.
+    ///
+    /// Raises:
+    ///     ValueError: if a shifted node id overflows.
+    #[pyo3(name = "merge", signature = (other, node_offset=0))]
+    fn py_merge(&self, other: &CifPairs, node_offset: usize) -> PyResult<CifPairs> {
+        Ok(self.merge(other, node_offset)?)
+    }
+
+    /// Compare the cif pairs with ``other``, e.g. the pairs of a circuit before and after a
+    /// transformation.  Pairs are compared per node as multisets, ignoring their order.
+    ///
+    /// Returns:
+    ///     (added, removed): The pairs of ``other`` missing from these cif pairs and the pairs of
+    ///     these cif pairs missing from ``other``, keyed by node id.  Both are empty if the cif
+    ///     pairs are equal.
+    #[pyo3(name = "diff", text_signature = "(self, other, /)")]
+    fn py_diff(&self, other: &CifPairs) -> (CifPairs, CifPairs) {
+        self.diff(other)
     }
 }
 
+/// The pairs of `pairs` that `reference` does not have, counting duplicates, without the nodes
+/// for which none is missing.
+fn missing_pairs(pairs: &NodePairs, reference: &NodePairs) -> HashMap<usize, PairList> {
+    let mut missing = HashMap::new();
+    for (&node_id, node_pairs) in pairs {
+        let mut available: HashMap<&[i32], usize> = HashMap::new();
+        for pair in reference
+            .get(&node_id)
+            .into_iter()
+            .flat_map(|pairs| pairs.iter())
+        {
+            *available.entry(pair).or_insert(0) += 1;
+        }
+        let mut node_missing = PairList::new();
+        for pair in node_pairs.iter() {
+            match available.get_mut(pair) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => node_missing.push(pair),
+            }
+        }
+        if !node_missing.is_empty() {
+            missing.insert(node_id, node_missing);
+        }
+    }
+    missing
+}
+
 impl CifPairs {
-    /// Given a swap, return all cif_pairs that contain at least one of the qubit in the swap
-    pub fn get_swap_involved_pairs(
+    pub fn to_json(&self) -> Result<String, MappingError> {
+        let sorted: BTreeMap<&usize, Vec<Vec<i32>>> = self
+            .pairs
+            .iter()
+            .map(|(node, pairs)| (node, pairs.to_vec()))
+            .collect();
+        serde_json::to_string(&sorted).map_err(|err| MappingError::Value(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MappingError> {
+        let pairs: BTreeMap<usize, Vec<Vec<i32>>> = serde_json::from_str(json)
+            .map_err(|err| MappingError::Value(format!("invalid cif pairs JSON: {err}")))?;
+        Ok(CifPairs::from_pairs(pairs.into_iter().collect()))
+    }
+
+    /// See [CifPairs::py_merge].
+    pub fn merge(&self, other: &CifPairs, node_offset: usize) -> Result<CifPairs, MappingError> {
+        let mut merged = self.clone();
+        for (&other_id, other_pairs) in other.pairs.iter() {
+            let node_id = other_id.checked_add(node_offset).ok_or_else(|| {
+                MappingError::Value(format!(
+                    "node id {other_id} shifted by {node_offset} overflows"
+                ))
+            })?;
+            let (mut node_pairs, mut weights) = match self.pairs.get(&node_id) {
+                Some(pairs) => (
+                    PairList::clone(pairs),
+                    (0..pairs.len())
+                        .map(|position| self.pair_weight(node_id, position))
+                        .collect(),
+                ),
+                None => (PairList::new(), Vec::new()),
+            };
+            for (position, pair) in other_pairs.iter().enumerate() {
+                if !node_pairs.iter().any(|existing| existing == pair) {
+                    node_pairs.push(pair);
+                    weights.push(other.pair_weight(other_id, position));
+                }
+            }
+            merged.insert_node_list(node_id, node_pairs);
+            merged.store_weights(node_id, weights);
+        }
+        Ok(merged)
+    }
+
+    pub fn diff(&self, other: &CifPairs) -> (CifPairs, CifPairs) {
+        (
+            CifPairs::from_lists(missing_pairs(&other.pairs, &self.pairs)),
+            CifPairs::from_lists(missing_pairs(&self.pairs, &other.pairs)),
+        )
+    }
+
+    pub fn from_pairs(pairs: HashMap<usize, Vec<Vec<i32>>>) -> Self {
+        CifPairs::from_lists(
+            pairs
+                .into_iter()
+                .map(|(node_id, node_pairs)| (node_id, PairList::from(node_pairs)))
+                .collect(),
+        )
+    }
+
+    /// Cif pairs without any node.
+    pub fn empty() -> Self {
+        CifPairs {
+            pairs: OrdMap::new(),
+            qubit_index: Arc::new(HashMap::new()),
+            node_qubits: Some(OrdMap::new()),
+            weights: Arc::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_lists(pairs: HashMap<usize, PairList>) -> Self {
+        let mut cif_pairs = CifPairs::empty();
+        for (node_id, node_pairs) in pairs {
+            cif_pairs.insert_node_list(node_id, node_pairs);
+        }
+        cif_pairs
+    }
+
+    /// See [CifPairs::from_arrays].
+    pub fn from_columns(
+        node_ids: ArrayView1<i64>,
+        q0s: ArrayView1<i64>,
+        q1s: ArrayView1<i64>,
+    ) -> Result<Self, MappingError> {
+        if node_ids.len() != q0s.len() || node_ids.len() != q1s.len() {
+            return Err(MappingError::Value(format!(
+                "node_ids, q0s and q1s must have the same length, got {}, {} and {}",
+                node_ids.len(),
+                q0s.len(),
+                q1s.len()
+            )));
+        }
+        let to_qubit = |q: i64| {
+            i32::try_from(q)
+                .map_err(|_| MappingError::Value(format!("qubit {q} does not fit in 32 bits")))
+        };
+        let mut pairs: HashMap<usize, PairList> = HashMap::new();
+        for ((&node_id, &q0), &q1) in node_ids.iter().zip(&q0s).zip(&q1s) {
+            let node_id = usize::try_from(node_id)
+                .map_err(|_| MappingError::Value(format!("invalid node id {node_id}")))?;
+            pairs
+                .entry(node_id)
+                .or_default()
+                .push(&[to_qubit(q0)?, to_qubit(q1)?]);
+        }
+        Ok(CifPairs::from_lists(pairs))
+    }
+
+    /// All cif pairs keyed by node id, in increasing node id order.
+    #[inline]
+    pub fn pairs(&self) -> &NodePairs {
+        &self.pairs
+    }
+
+    /// A deep copy of all cif pairs keyed by node id.
+    pub fn to_map(&self) -> HashMap<usize, Vec<Vec<i32>>> {
+        self.pairs
+            .iter()
+            .map(|(&node_id, pairs)| (node_id, pairs.to_vec()))
+            .collect()
+    }
+
+    /// The ids of the nodes with cif pairs, sorted.
+    pub fn node_ids(&self) -> Vec<usize> {
+        self.pairs.keys().copied().collect()
+    }
+
+    /// The weighted number of well-formed pairs whose qubits are connected to two different
+    /// controllers, see [CifPairs::py_count_cross_controller].
+    pub fn count_cross_controller(&self, ctrl2pq: &Ctrl2Pq) -> f64 {
+        self.pairs
+            .keys()
+            .map(|&node_id| self.count_node_cross_controller(node_id, ctrl2pq))
+            .sum()
+    }
+
+    /// Like [CifPairs::count_cross_controller] for the pairs of node `node_id` only.
+    pub fn count_node_cross_controller(&self, node_id: usize, ctrl2pq: &Ctrl2Pq) -> f64 {
+        self.pairs.get(&node_id).map_or(0., |pairs| {
+            pairs
+                .iter()
+                .enumerate()
+                .filter(|(_, pair)| is_cross_controller(pair, ctrl2pq))
+                .map(|(position, _)| self.pair_weight(node_id, position))
+                .sum()
+        })
+    }
+
+    /// See [CifPairs::py_feedback_matrix].
+    pub fn feedback_matrix(&self, ctrl2pq: &Ctrl2Pq) -> Array2<usize> {
+        let controllers = ctrl2pq.controllers();
+        let index: HashMap<i32, usize> = controllers
+            .iter()
+            .enumerate()
+            .map(|(index, &ctrl)| (ctrl, index))
+            .collect();
+        let mut matrix = Array2::zeros((controllers.len(), controllers.len()));
+        for pair in self.pairs.values().flat_map(|pairs| pairs.iter()) {
+            if !is_well_formed(pair) {
+                continue;
+            }
+            if let (Some(ctrl0), Some(ctrl1)) = (
+                ctrl2pq.get_controller_by_qubit(pair[0]),
+                ctrl2pq.get_controller_by_qubit(pair[1]),
+            ) {
+                matrix[[index[ctrl1], index[ctrl0]]] += 1;
+            }
+        }
+        matrix
+    }
+
+    /// The well-formed pairs with a qubit on controller `ctrl_id`, with their node id.
+    pub fn pairs_for_controller(&self, ctrl2pq: &Ctrl2Pq, ctrl_id: i32) -> PairViews<'_> {
+        self.pair_views()
+            .filter(|(_, pair)| {
+                is_well_formed(pair)
+                    && pair[..2]
+                        .iter()
+                        .any(|&q| ctrl2pq.get_controller_by_qubit(q) == Some(&ctrl_id))
+            })
+            .collect()
+    }
+
+    /// The well-formed pairs with their node id, keyed by the (smaller, larger) controller of
+    /// their qubits, see [CifPairs::py_group_by_controller_pair].
+    pub fn group_by_controller_pair(
         &self,
-        swap: &Vec<i32>,
-        active_nodes: &Vec<usize>,
-    ) -> Vec<Vec<i32>> {
-        if swap.len() != 2 {
-            panic!("Swap must contain exactly two elements");
-        }
-
-        let mut involved_pairs = Vec::new();
-        for (py_node_id, node_pairs) in &self.pairs {
-            if active_nodes.contains(py_node_id) {
-                for pair in node_pairs {
-                    if pair.contains(&swap[0]) || pair.contains(&swap[1]) {
-                        involved_pairs.push(pair.clone());
-                    }
+        ctrl2pq: &Ctrl2Pq,
+    ) -> BTreeMap<(i32, i32), PairViews<'_>> {
+        let mut groups: BTreeMap<(i32, i32), PairViews> = BTreeMap::new();
+        for (node_id, pair) in self.pair_views() {
+            if !is_well_formed(pair) {
+                continue;
+            }
+            if let (Some(&ctrl0), Some(&ctrl1)) = (
+                ctrl2pq.get_controller_by_qubit(pair[0]),
+                ctrl2pq.get_controller_by_qubit(pair[1]),
+            ) {
+                groups
+                    .entry((ctrl0.min(ctrl1), ctrl0.max(ctrl1)))
+                    .or_default()
+                    .push((node_id, pair));
+            }
+        }
+        groups
+    }
+
+    /// All pairs with their node id, in node id order.
+    fn pair_views(&self) -> impl Iterator<Item = (usize, &[i32])> + '_ {
+        self.pairs
+            .iter()
+            .flat_map(|(&node_id, pairs)| pairs.iter().map(move |pair| (node_id, pair)))
+    }
+
+    /// Statistics of the well-formed pairs under `ctrl2pq`, see [FeedbackStats].
+    pub fn stats(&self, ctrl2pq: &Ctrl2Pq) -> FeedbackStats {
+        let mut stats = FeedbackStats::default();
+        for pair in self.pairs.values().flat_map(|pairs| pairs.iter()) {
+            if !is_well_formed(pair) {
+                continue;
+            }
+            stats.total_pairs += 1;
+            let (Some(&ctrl0), Some(&ctrl1)) = (
+                ctrl2pq.get_controller_by_qubit(pair[0]),
+                ctrl2pq.get_controller_by_qubit(pair[1]),
+            ) else {
+                stats.unmapped_pairs += 1;
+                continue;
+            };
+            if ctrl0 != ctrl1 {
+                stats.cross_controller_pairs += 1;
+            }
+            *stats
+                .per_controller_pair
+                .entry((ctrl0.min(ctrl1), ctrl0.max(ctrl1)))
+                .or_insert(0) += 1;
+        }
+        stats
+    }
+
+    /// See [CifPairs::py_memory_stats].
+    pub fn memory_stats(&self) -> MemoryStats {
+        let index_entries = self.qubit_index.values().map(|entries| entries.len()).sum();
+        MemoryStats {
+            num_nodes: self.pairs.len(),
+            num_pairs: self.pairs.values().map(|pairs| pairs.len()).sum(),
+            pair_bytes: ord_map_bytes(&self.pairs)
+                + self
+                    .pairs
+                    .values()
+                    .map(|pairs| size_of::<PairList>() + pairs.allocated_bytes())
+                    .sum::<usize>(),
+            index_entries,
+            index_bytes: map_bytes(&self.qubit_index)
+                + self
+                    .qubit_index
+                    .values()
+                    .map(|entries| size_of::<Vec<(usize, usize)>>() + vec_bytes(entries))
+                    .sum::<usize>()
+                + self.node_qubits.as_ref().map_or(0, ord_map_bytes)
+                + map_bytes(&self.weights)
+                + self.weights.values().map(vec_bytes).sum::<usize>(),
+            ..MemoryStats::default()
+        }
+    }
+
+    /// Set the weights of the pairs of a node, in the order of its pairs, see
+    /// [CifPairs::py_weights].
+    pub fn set_weights(&mut self, node_id: usize, weights: Vec<f64>) -> Result<(), MappingError> {
+        let num_pairs = self.pairs.get(&node_id).map_or(0, |pairs| pairs.len());
+        if weights.len() != num_pairs {
+            return Err(MappingError::Value(format!(
+                "node {node_id} has {num_pairs} cif pairs, got {} weights",
+                weights.len()
+            )));
+        }
+        if let Some(weight) = weights
+            .iter()
+            .find(|weight| !(weight.is_finite() && **weight >= 0.))
+        {
+            return Err(MappingError::Value(format!(
+                "pair weights must be finite and non-negative, got {weight}"
+            )));
+        }
+        self.store_weights(node_id, weights);
+        Ok(())
+    }
+
+    /// Store validated weights, only keeping the ones of nodes with a pair not weighing 1.
+    fn store_weights(&mut self, node_id: usize, weights: Vec<f64>) {
+        if weights.iter().all(|&weight| weight == 1.) {
+            if self.weights.contains_key(&node_id) {
+                Arc::make_mut(&mut self.weights).remove(&node_id);
+            }
+        } else {
+            Arc::make_mut(&mut self.weights).insert(node_id, weights);
+        }
+    }
+
+    /// The weight of the pair at `position` of node `node_id`, 1 unless set otherwise.
+    #[inline]
+    pub fn pair_weight(&self, node_id: usize, position: usize) -> f64 {
+        self.weights
+            .get(&node_id)
+            .map_or(1., |weights| weights[position])
+    }
+
+    /// Set the cif pairs of a node, replacing the previous ones and their weights if any.
+    pub fn insert_node(&mut self, node_id: usize, node_pairs: Vec<Vec<i32>>) {
+        self.insert_node_list(node_id, PairList::from(node_pairs));
+    }
+
+    /// Append `pair` to the pairs of node `node_id` with a weight of 1, without rebuilding the
+    /// index entries of the node's other pairs.
+    pub fn add_pair(&mut self, node_id: usize, pair: &[i32]) {
+        let node_pairs = Arc::make_mut(self.pairs.entry(node_id).or_default());
+        let position = node_pairs.len();
+        node_pairs.push(pair);
+        let qubit_index = Arc::make_mut(&mut self.qubit_index);
+        for q in pair.iter().take(2) {
+            Arc::make_mut(qubit_index.entry(*q).or_default()).push((node_id, position));
+        }
+        if self.weights.contains_key(&node_id) {
+            Arc::make_mut(&mut self.weights)
+                .get_mut(&node_id)
+                .unwrap()
+                .push(1.);
+        }
+        self.update_node_qubits(node_id);
+    }
+
+    /// Like [CifPairs::insert_node], with the pairs already in a [PairList].
+    pub fn insert_node_list(&mut self, node_id: usize, node_pairs: PairList) {
+        self.retire_node(node_id);
+        let qubit_index = Arc::make_mut(&mut self.qubit_index);
+        for (position, pair) in node_pairs.iter().enumerate() {
+            for q in pair.iter().take(2) {
+                Arc::make_mut(qubit_index.entry(*q).or_default()).push((node_id, position));
+            }
+        }
+        self.pairs.insert(node_id, Arc::new(node_pairs));
+        self.update_node_qubits(node_id);
+    }
+
+    /// Remove the pairs of a node, typically once its gate has been routed, so that scans of the
+    /// qubit index only visit the remaining work.  Returns the removed pairs, if any.
+    pub fn retire_node(&mut self, node_id: usize) -> Option<Arc<PairList>> {
+        self.retire_nodes(&[node_id]).remove(&node_id)
+    }
+
+    /// Like [CifPairs::retire_node] for many nodes at once, visiting the index of every qubit
+    /// of the removed pairs a single time.  Returns the removed pairs by node id.
+    pub fn retire_nodes(&mut self, node_ids: &[usize]) -> NodePairs {
+        let mut retired = NodePairs::new();
+        if !node_ids
+            .iter()
+            .any(|node_id| self.pairs.contains_key(node_id))
+        {
+            // nothing to remove, avoid unsharing the maps
+            return retired;
+        }
+        for node_id in node_ids {
+            if let Some(node_pairs) = self.pairs.remove(node_id) {
+                retired.insert(*node_id, node_pairs);
+            }
+        }
+        let qubits: HashSet<i32> = retired
+            .values()
+            .flat_map(|node_pairs| node_pairs.iter())
+            .flat_map(|pair| pair.iter().take(2).copied())
+            .collect();
+        let qubit_index = Arc::make_mut(&mut self.qubit_index);
+        for qubit in qubits {
+            let Some(entries) = qubit_index.get_mut(&qubit) else {
+                continue;
+            };
+            Arc::make_mut(entries).retain(|(node_id, _)| !retired.contains_key(node_id));
+            if entries.is_empty() {
+                qubit_index.remove(&qubit);
+            }
+        }
+        if let Some(node_qubits) = self.node_qubits.as_mut() {
+            for node_id in retired.keys() {
+                node_qubits.remove(node_id);
+            }
+        }
+        if retired
+            .keys()
+            .any(|node_id| self.weights.contains_key(node_id))
+        {
+            let weights = Arc::make_mut(&mut self.weights);
+            for node_id in retired.keys() {
+                weights.remove(node_id);
+            }
+        }
+        retired
+    }
+
+    /// Whether the qubits of the pairs of every node are kept as bitsets, i.e. all qubits are
+    /// below [super::bitset::MAX_DENSE_QUBITS].
+    pub fn is_dense(&self) -> bool {
+        self.node_qubits.is_some()
+    }
+
+    /// Recompute the bitset of node `node_id`, dropping all bitsets if one of its qubits is out of
+    /// the dense range.
+    fn update_node_qubits(&mut self, node_id: usize) {
+        if self.node_qubits.is_none() {
+            return;
+        }
+        let qubits = self
+            .pairs
+            .get(&node_id)
+            .into_iter()
+            .flat_map(|pairs| pairs.iter())
+            .flat_map(|pair| pair.iter().take(2));
+        match QubitBitset::from_qubits(qubits) {
+            Some(bitset) => {
+                if let Some(node_qubits) = self.node_qubits.as_mut() {
+                    node_qubits.insert(node_id, bitset);
                 }
             }
+            None => self.node_qubits = None,
         }
+    }
+
+    /// Given a swap, return all cif_pairs that contain at least one of the qubit in the swap,
+    /// sorted by node id and then by position in the node's pairs
+    pub fn get_swap_involved_pairs(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Vec<Vec<i32>>, MappingError> {
+        Ok(self
+            .get_swap_involved_pairs_by_node(swap, active_nodes)?
+            .into_iter()
+            .map(|(_, pair)| pair)
+            .collect())
+    }
+
+    /// Like [CifPairs::get_swap_involved_pairs], with the id of the node every pair belongs to.
+    pub fn get_swap_involved_pairs_by_node(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Vec<(usize, Vec<i32>)>, MappingError> {
+        Ok(self
+            .swap_involved_pair_views(swap, active_nodes)?
+            .into_iter()
+            .map(|(node_id, pair)| (node_id, pair.to_vec()))
+            .collect())
+    }
+
+    /// Like [CifPairs::get_swap_involved_pairs_by_node], borrowing the pairs from their node's
+    /// flat array instead of copying them, for the scoring hot paths.
+    pub fn swap_involved_pair_views(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Vec<(usize, &[i32])>, MappingError> {
+        Ok(self
+            .swap_involved_entries(swap, active_nodes)?
+            .into_iter()
+            .map(|(node_id, position)| (node_id, &self.pairs[&node_id][position]))
+            .collect())
+    }
 
-        involved_pairs
+    /// The sorted (node id, position in the node's pairs) of the pairs of the active nodes that
+    /// hold one of the qubits of `swap`.
+    pub fn swap_involved_entries(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Vec<(usize, usize)>, MappingError> {
+        let active_nodes = sorted_node_ids(active_nodes);
+        let entries = match self.node_qubits.as_ref() {
+            Some(node_qubits) => {
+                let mut entries = Vec::new();
+                for &node_id in active_nodes.iter() {
+                    if !node_qubits
+                        .get(&node_id)
+                        .is_some_and(|bitset| bitset.touches(swap))
+                    {
+                        continue;
+                    }
+                    entries.extend(
+                        self.pairs[&node_id]
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, pair)| pair.iter().take(2).any(|&q| swap.applies_to(q)))
+                            .map(|(position, _)| (node_id, position)),
+                    );
+                }
+                entries
+            }
+            None => {
+                let mut entries: Vec<(usize, usize)> = swap
+                    .iter()
+                    .filter_map(|q| self.qubit_index.get(q))
+                    .flat_map(|entries| entries.iter())
+                    .filter(|(node_id, _)| active_nodes.binary_search(node_id).is_ok())
+                    .copied()
+                    .collect();
+                // a pair holding both qubits of the swap, or the same qubit twice, is indexed
+                // twice
+                entries.sort_unstable();
+                entries.dedup();
+                entries
+            }
+        };
+        metrics::record_pairs_scanned(entries.len());
+        Ok(entries)
     }
 
     /// Apply the selected swap to cif_pairs that are not in gate_order
     /// essentially update corresponding indexes
-    pub fn apply_swap(&mut self, swap: &Vec<i32>, gate_order: &Vec<usize>) {
-        if swap.len() != 2 {
-            panic!("Swap must contain exactly two elements");
-        }
-
-        for (py_node_id, node_pairs) in self.pairs.iter_mut() {
-            if !gate_order.contains(py_node_id) {
-                for pair in node_pairs.iter_mut() {
-                    for q in pair {
-                        if *q == swap[0] {
-                            *q = swap[1];
-                        } else if *q == swap[1] {
-                            *q = swap[0];
-                        }
+    pub fn apply_swap(&mut self, swap: Swap, gate_order: &[usize]) -> Result<(), MappingError> {
+        self.apply_swap_recorded(swap, gate_order)?;
+        Ok(())
+    }
+
+    /// Like [CifPairs::apply_swap], but returns the sorted (node id, position) of the pairs that
+    /// were changed, which [CifPairs::revert_swap] takes to undo the swap.
+    pub fn apply_swap_recorded(
+        &mut self,
+        swap: Swap,
+        gate_order: &[usize],
+    ) -> Result<Vec<(usize, usize)>, MappingError> {
+        let gate_order = sorted_node_ids(gate_order);
+        Ok(self.swap_pairs(swap, |(node_id, _)| {
+            gate_order.binary_search(node_id).is_err()
+        }))
+    }
+
+    /// Undo a swap applied by [CifPairs::apply_swap_recorded], given the pairs it changed.  Swaps
+    /// must be reverted in the reverse order they were applied.
+    pub fn revert_swap(
+        &mut self,
+        swap: Swap,
+        touched: &[(usize, usize)],
+    ) -> Result<(), MappingError> {
+        self.swap_pairs(swap, |entry| touched.binary_search(entry).is_ok());
+        Ok(())
+    }
+
+    /// Exchange the two qubits of `swap` in the pairs selected by `filter`, returning the sorted
+    /// (node id, position) of the pairs that were changed.
+    fn swap_pairs<F>(&mut self, swap: Swap, filter: F) -> Vec<(usize, usize)>
+    where
+        F: Fn(&(usize, usize)) -> bool,
+    {
+        // take the index entries of the selected pairs off both qubits
+        let selected_on = |q: &i32| {
+            self.qubit_index
+                .get(q)
+                .is_some_and(|entries| entries.iter().any(&filter))
+        };
+        if !selected_on(&swap[0]) && !selected_on(&swap[1]) {
+            // nothing changes, avoid unsharing the maps
+            return Vec::new();
+        }
+
+        let qubit_index = Arc::make_mut(&mut self.qubit_index);
+        let mut moved: [Vec<(usize, usize)>; 2] = [Vec::new(), Vec::new()];
+        for (q, moved) in swap.iter().zip(moved.iter_mut()) {
+            if let Some(entries) = qubit_index.get_mut(q) {
+                Arc::make_mut(entries).retain(|entry| {
+                    let selected = filter(entry);
+                    if selected {
+                        moved.push(*entry);
                     }
-                }
+                    !selected
+                });
+            }
+        }
+
+        let mut touched: Vec<(usize, usize)> = moved.iter().flatten().copied().collect();
+        touched.sort_unstable();
+        touched.dedup();
+        for &(node_id, position) in &touched {
+            let pair = &mut Arc::make_mut(self.pairs.get_mut(&node_id).unwrap())[position];
+            for q in pair.iter_mut().take(2) {
+                *q = swap.apply(*q);
+            }
+        }
+
+        let [from_0, from_1] = moved;
+        Arc::make_mut(qubit_index.entry(swap[1]).or_default()).extend(from_0);
+        Arc::make_mut(qubit_index.entry(swap[0]).or_default()).extend(from_1);
+        let mut nodes: Vec<usize> = touched.iter().map(|&(node_id, _)| node_id).collect();
+        nodes.dedup();
+        for node_id in nodes {
+            self.update_node_qubits(node_id);
+        }
+        touched
+    }
+}
+
+// The qubit index and the bitsets are derived from the pairs, so only the pairs and their
+// weights are compared.
+impl PartialEq for CifPairs {
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs == other.pairs && self.weights == other.weights
+    }
+}
+
+// weights are validated to be finite, so equality is reflexive
+impl Eq for CifPairs {}
+
+impl Hash for CifPairs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pairs.hash(state);
+        let mut weights: Vec<(&usize, &Vec<f64>)> = self.weights.iter().collect();
+        weights.sort_unstable_by_key(|&(node_id, _)| node_id);
+        for (node_id, node_weights) in weights {
+            node_id.hash(state);
+            for weight in node_weights {
+                weight.to_bits().hash(state);
             }
         }
     }
 }
 
+// Only the pairs and their weights are serialized, the qubit index is rebuilt on
+// deserialization.  The order of the pairs of every node is kept, so positions recorded in an
+// undo log stay valid.
+impl Serialize for CifPairs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.to_map(), self.weights.as_ref()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CifPairs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (pairs, weights): (HashMap<usize, Vec<Vec<i32>>>, PairWeights) =
+            Deserialize::deserialize(deserializer)?;
+        let mut cif_pairs = CifPairs::from_pairs(pairs);
+        for (node_id, node_weights) in weights {
+            cif_pairs
+                .set_weights(node_id, node_weights)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(cif_pairs)
+    }
+}
+
+/// Collects cif pairs one at a time, e.g. while traversing a circuit, without building a dict
+/// of all of them first.  ``CifPairs`` are frozen, the builder is the mutable side::
+///
+///     builder = CifPairsBuilder()
+///     builder.add_pair(0, 1, 2)
+///     cif_pairs = builder.build()
+#[cfg(feature = "python")]
+#[pyclass(module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug)]
+pub struct CifPairsBuilder {
+    cif_pairs: CifPairs,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CifPairsBuilder {
+    #[new]
+    fn py_new() -> Self {
+        CifPairsBuilder {
+            cif_pairs: CifPairs::empty(),
+        }
+    }
+
+    /// Append the pair of ``q0`` conditioned on ``q1``, optionally through ``clbit``, to the pairs
+    /// of node ``node_id``, which is created if it has none yet.  The pair weighs 1.
+    #[pyo3(
+        name = "add_pair",
+        signature = (node_id, q0, q1, clbit=None),
+        text_signature = "(self, node_id, q0, q1, /, clbit=None)"
+    )]
+    fn py_add_pair(&mut self, node_id: usize, q0: i32, q1: i32, clbit: Option<i32>) {
+        match clbit {
+            Some(clbit) => self.cif_pairs.add_pair(node_id, &[q0, q1, clbit]),
+            None => self.cif_pairs.add_pair(node_id, &[q0, q1]),
+        }
+    }
+
+    /// The pairs added so far.  The builder can be reused afterwards, its later pairs do not
+    /// change the returned ones.
+    ///
+    /// Returns:
+    ///     CifPairs: The pairs added so far.
+    #[pyo3(name = "build", text_signature = "(self, /)")]
+    fn py_build(&self) -> CifPairs {
+        self.cif_pairs.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.cif_pairs.__len__()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,35 +1164,434 @@ mod tests {
         pairs_map.insert(1, vec![vec![1, 2], vec![3, 4]]);
         pairs_map.insert(2, vec![vec![5, 6], vec![1, 6]]);
 
-        let cif_pairs: CifPairs = CifPairs { pairs: pairs_map };
+        let cif_pairs: CifPairs = CifPairs::from_pairs(pairs_map);
 
-        let swap: Vec<i32> = vec![1, 5];
+        let swap = Swap::new(1, 5).unwrap();
         let active_nodes: Vec<usize> = vec![1, 2];
         let active_nodes_2: Vec<usize> = vec![1];
-        let mut result: Vec<Vec<i32>> = cif_pairs.get_swap_involved_pairs(&swap, &active_nodes);
-        let mut result_2: Vec<Vec<i32>> = cif_pairs.get_swap_involved_pairs(&swap, &active_nodes_2);
+        let mut result: Vec<Vec<i32>> = cif_pairs
+            .get_swap_involved_pairs(swap, &active_nodes)
+            .unwrap();
+        let mut result_2: Vec<Vec<i32>> = cif_pairs
+            .get_swap_involved_pairs(swap, &active_nodes_2)
+            .unwrap();
         assert_eq!(
             result.sort(),
             vec![vec![1, 2], vec![5, 6], vec![1, 6]].sort()
         );
         assert_eq!(result_2.sort(), vec![vec![1, 2], vec![1, 6]].sort());
 
-        let swap: Vec<i32> = vec![3, 6];
-        let mut result: Vec<Vec<i32>> = cif_pairs.get_swap_involved_pairs(&swap, &active_nodes);
+        let swap = Swap::new(3, 6).unwrap();
+        let mut result: Vec<Vec<i32>> = cif_pairs
+            .get_swap_involved_pairs(swap, &active_nodes)
+            .unwrap();
         assert_eq!(
             result.sort(),
             vec![vec![3, 4], vec![5, 6], vec![1, 6]].sort()
         );
 
-        let swap: Vec<i32> = vec![7, 8];
-        let result: Vec<Vec<i32>> = cif_pairs.get_swap_involved_pairs(&swap, &active_nodes);
+        let swap = Swap::new(7, 8).unwrap();
+        let result: Vec<Vec<i32>> = cif_pairs
+            .get_swap_involved_pairs(swap, &active_nodes)
+            .unwrap();
         assert!(result.is_empty());
 
-        let invalid_swap: Vec<i32> = vec![1];
-        let result: Result<Vec<Vec<i32>>, Box<dyn std::any::Any + Send>> =
-            std::panic::catch_unwind(|| {
-                cif_pairs.get_swap_involved_pairs(&invalid_swap, &active_nodes)
-            });
-        assert!(result.is_err());
+        // the views borrow the same pairs in (node id, position) order
+        assert_eq!(
+            cif_pairs.swap_involved_entries(Swap::new(3, 6).unwrap(), &active_nodes),
+            Ok(vec![(1, 1), (2, 0), (2, 1)])
+        );
+        assert_eq!(
+            cif_pairs.swap_involved_pair_views(Swap::new(3, 6).unwrap(), &active_nodes),
+            Ok(vec![(1, &[3, 4][..]), (2, &[5, 6][..]), (2, &[1, 6][..])])
+        );
+
+        // nodes are visited in increasing id order whatever the insertion order
+        let reversed =
+            CifPairs::from_pairs((0..50).rev().map(|node| (node, vec![vec![1, 5]])).collect());
+        assert!(reversed.pairs().keys().copied().eq(0..50));
+        assert_eq!(
+            reversed
+                .swap_involved_entries(
+                    Swap::new(1, 5).unwrap(),
+                    &(0..50).rev().collect::<Vec<usize>>()
+                )
+                .unwrap(),
+            (0..50)
+                .map(|node| (node, 0))
+                .collect::<Vec<(usize, usize)>>()
+        );
+
+        assert_eq!(
+            Swap::try_from(&[1][..]),
+            Err(MappingError::InvalidSwap(vec![1]))
+        );
+    }
+
+    #[test]
+    fn test_cif_pairs_stats() {
+        let ctrl2pq = Ctrl2Pq {
+            map: HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]).into(),
+            reverse_map: HashMap::from([(0, 0), (1, 0), (2, 1), (3, 1)]).into(),
+            capacity: HashMap::new().into(),
+        };
+        let mut pairs_map: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
+        pairs_map.insert(1, vec![vec![0, 2], vec![1, 3], vec![0, 1]]);
+        pairs_map.insert(2, vec![vec![3, 0], vec![2, 7], vec![1]]);
+        let cif_pairs: CifPairs = CifPairs::from_pairs(pairs_map);
+
+        assert_eq!(cif_pairs.count_cross_controller(&ctrl2pq), 3.);
+        assert_eq!(cif_pairs.node_ids(), vec![1, 2]);
+        #[cfg(feature = "python")]
+        assert_eq!(cif_pairs.__len__(), 2);
+        #[cfg(feature = "python")]
+        assert!(cif_pairs.__contains__(2));
+        #[cfg(feature = "python")]
+        assert_eq!(cif_pairs.__getitem__(2).unwrap()[0], vec![3, 0]);
+        #[cfg(feature = "python")]
+        assert!(cif_pairs.__getitem__(3).is_err());
+        assert_eq!(
+            cif_pairs.stats(&ctrl2pq),
+            FeedbackStats {
+                total_pairs: 5,
+                cross_controller_pairs: 3,
+                unmapped_pairs: 1,
+                per_controller_pair: HashMap::from([((0, 1), 3), ((0, 0), 1)]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_cif_pairs_by_controller() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (1, vec![vec![0, 1], vec![2, 0, 4]]),
+            (2, vec![vec![3, 2], vec![1, 7], vec![1]]),
+        ]));
+
+        assert_eq!(
+            cif_pairs.pairs_for_controller(&ctrl2pq, 1),
+            vec![(1, &[2, 0, 4][..]), (2, &[3, 2][..])]
+        );
+        // pairs with an unmapped qubit still involve the controller of the other one
+        assert_eq!(cif_pairs.pairs_for_controller(&ctrl2pq, 0).len(), 3);
+        assert!(cif_pairs.pairs_for_controller(&ctrl2pq, 5).is_empty());
+
+        let groups = cif_pairs.group_by_controller_pair(&ctrl2pq);
+        assert_eq!(
+            groups.keys().copied().collect::<Vec<_>>(),
+            vec![(0, 0), (0, 1), (1, 1)]
+        );
+        assert_eq!(groups[&(0, 1)], vec![(1, &[2, 0, 4][..])]);
+        #[cfg(feature = "python")]
+        assert_eq!(
+            cif_pairs.py_group_by_controller_pair(&ctrl2pq)[&(1, 1)],
+            vec![(2, vec![3, 2])]
+        );
+    }
+
+    #[test]
+    fn test_cif_pairs_feedback_matrix() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(5, vec![0, 1]), (2, vec![2])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![2, 0], vec![1, 0, 4]]),
+            (1, vec![vec![2, 1], vec![0, 2], vec![0, 7], vec![1]]),
+        ]));
+        // controller 2 comes first, measured qubits select the row
+        assert_eq!(
+            cif_pairs.feedback_matrix(&ctrl2pq),
+            ndarray::arr2(&[[0, 1], [2, 1]])
+        );
+    }
+
+    #[test]
+    fn test_cif_pairs_from_columns() {
+        let node_ids = ndarray::arr1(&[2, 1, 2]);
+        let cif_pairs = CifPairs::from_columns(
+            node_ids.view(),
+            ndarray::arr1(&[0, 1, 4]).view(),
+            ndarray::arr1(&[3, 2, 5]).view(),
+        )
+        .unwrap();
+        assert_eq!(cif_pairs.pairs()[&1].to_vec(), vec![vec![1, 2]]);
+        assert_eq!(cif_pairs.pairs()[&2].to_vec(), vec![vec![0, 3], vec![4, 5]]);
+
+        let short = ndarray::arr1(&[0, 1]);
+        assert!(CifPairs::from_columns(node_ids.view(), short.view(), short.view()).is_err());
+        let negative = ndarray::arr1(&[-1]);
+        let qubits = ndarray::arr1(&[0]);
+        assert!(CifPairs::from_columns(negative.view(), qubits.view(), qubits.view()).is_err());
+        let large = ndarray::arr1(&[1 << 40]);
+        assert!(CifPairs::from_columns(qubits.view(), large.view(), qubits.view()).is_err());
+    }
+
+    #[test]
+    fn test_cif_pairs_json_round_trip() {
+        let mut pairs_map: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
+        pairs_map.insert(2, vec![vec![5, 6]]);
+        pairs_map.insert(1, vec![vec![1, 2], vec![3, 4]]);
+        let cif_pairs: CifPairs = CifPairs::from_pairs(pairs_map);
+
+        let json = cif_pairs.to_json().unwrap();
+        assert_eq!(json, r#"{"1":[[1,2],[3,4]],"2":[[5,6]]}"#);
+        let loaded = CifPairs::from_json(&json).unwrap();
+        assert_eq!(loaded.pairs(), cif_pairs.pairs());
+        assert_eq!(
+            loaded.get_swap_involved_pairs(Swap::new(1, 5).unwrap(), &[1, 2]),
+            Ok(vec![vec![1, 2], vec![5, 6]])
+        );
+        assert!(CifPairs::from_json(r#"{"1": [[1, "a"]]}"#).is_err());
+    }
+
+    #[test]
+    fn test_cif_pairs_weights() {
+        let mut cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (1, vec![vec![0, 1], vec![2, 3]]),
+            (2, vec![vec![4, 5]]),
+        ]));
+        assert_eq!(cif_pairs.pair_weight(1, 1), 1.);
+        assert!(cif_pairs.set_weights(1, vec![2.]).is_err());
+        assert!(cif_pairs.set_weights(1, vec![2., f64::NAN]).is_err());
+        cif_pairs.set_weights(1, vec![1., 2.5]).unwrap();
+        assert_eq!(cif_pairs.pair_weight(1, 1), 2.5);
+
+        let json = serde_json::to_string(&cif_pairs).unwrap();
+        let loaded: CifPairs = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pair_weight(1, 1), 2.5);
+
+        // the weights follow their pairs into a merge
+        let merged = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 1]])]))
+            .merge(&cif_pairs, 0)
+            .unwrap();
+        assert_eq!(merged.pair_weight(1, 0), 1.);
+        assert_eq!(merged.pair_weight(1, 1), 2.5);
+
+        // and are dropped with them
+        cif_pairs.insert_node(1, vec![vec![0, 1], vec![2, 3]]);
+        assert_eq!(cif_pairs.pair_weight(1, 1), 1.);
+        cif_pairs.set_weights(2, vec![3.]).unwrap();
+        cif_pairs.retire_node(2);
+        assert!(cif_pairs.weights.is_empty());
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_cif_pairs_eq_and_hash() {
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (1, vec![vec![0, 1], vec![2, 3]]),
+            (2, vec![vec![1, 2]]),
+        ]));
+        // the same pairs inserted in another order
+        let mut same = CifPairs::from_pairs(HashMap::from([(2, vec![vec![1, 2]])]));
+        same.insert_node(1, vec![vec![0, 1], vec![2, 3]]);
+        assert!(cif_pairs.__eq__(&same));
+        assert_eq!(cif_pairs.__hash__(), same.__hash__());
+
+        let mut weighted = same.clone();
+        weighted.set_weights(2, vec![3.]).unwrap();
+        assert_ne!(cif_pairs, weighted);
+        assert_ne!(cif_pairs.__hash__(), weighted.__hash__());
+        weighted.set_weights(2, vec![1.]).unwrap();
+        assert_eq!(cif_pairs, weighted);
+
+        let mut swapped = same;
+        swapped.apply_swap(Swap::new(0, 3).unwrap(), &[]).unwrap();
+        assert_ne!(cif_pairs, swapped);
+    }
+
+    #[test]
+    fn test_cif_pairs_merge_diff() {
+        let before = CifPairs::from_pairs(HashMap::from([
+            (1, vec![vec![1, 2], vec![3, 4]]),
+            (2, vec![vec![5, 6]]),
+        ]));
+        let fragment = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![3, 4], vec![7, 8]]),
+            (2, vec![vec![0, 1]]),
+        ]));
+
+        let merged = before.merge(&fragment, 1).unwrap();
+        assert_eq!(
+            merged.to_map(),
+            HashMap::from([
+                (1, vec![vec![1, 2], vec![3, 4], vec![7, 8]]),
+                (2, vec![vec![5, 6]]),
+                (3, vec![vec![0, 1]]),
+            ])
+        );
+        assert_eq!(
+            merged
+                .get_swap_involved_pairs(Swap::new(7, 9).unwrap(), &[1, 3])
+                .unwrap(),
+            vec![vec![7, 8]]
+        );
+        assert_eq!(
+            before
+                .merge(&CifPairs::from_pairs(HashMap::new()), 0)
+                .unwrap()
+                .to_map(),
+            before.to_map()
+        );
+
+        let (added, removed) = before.diff(&merged);
+        assert_eq!(
+            added.to_map(),
+            HashMap::from([(1, vec![vec![7, 8]]), (3, vec![vec![0, 1]])])
+        );
+        assert!(removed.to_map().is_empty());
+        let (added, removed) = merged.diff(&before);
+        assert!(added.to_map().is_empty());
+        assert_eq!(removed.to_map().len(), 2);
+        let (added, removed) = before.diff(&before);
+        assert!(added.to_map().is_empty() && removed.to_map().is_empty());
+
+        // pairs repeated within the fragment are added once, to new nodes as well
+        let repeated = CifPairs::from_pairs(HashMap::from([
+            (1, vec![vec![7, 8], vec![7, 8], vec![1, 2]]),
+            (5, vec![vec![0, 1], vec![0, 1]]),
+        ]));
+        assert_eq!(
+            before.merge(&repeated, 0).unwrap().to_map(),
+            HashMap::from([
+                (1, vec![vec![1, 2], vec![3, 4], vec![7, 8]]),
+                (2, vec![vec![5, 6]]),
+                (5, vec![vec![0, 1]]),
+            ])
+        );
+        assert!(matches!(
+            before.merge(&fragment, usize::MAX),
+            Err(MappingError::Value(_))
+        ));
+    }
+
+    #[test]
+    fn test_cif_pairs_add_pair() {
+        let mut cif_pairs = CifPairs::empty();
+        assert!(cif_pairs.pairs().is_empty());
+        cif_pairs.add_pair(3, &[0, 1]);
+        cif_pairs.add_pair(1, &[2, 3]);
+        cif_pairs.add_pair(3, &[1, 2, 7]);
+        assert_eq!(
+            cif_pairs,
+            CifPairs::from_pairs(HashMap::from([
+                (1, vec![vec![2, 3]]),
+                (3, vec![vec![0, 1], vec![1, 2, 7]]),
+            ]))
+        );
+        let swap = Swap::new(1, 5).unwrap();
+        assert_eq!(
+            cif_pairs.swap_involved_entries(swap, &[3]).unwrap(),
+            vec![(3, 0), (3, 1)]
+        );
+
+        // weighted nodes give the new pair a weight of 1
+        cif_pairs.set_weights(1, vec![2.]).unwrap();
+        cif_pairs.add_pair(1, &[3, 4]);
+        assert_eq!(cif_pairs.pair_weight(1, 0), 2.);
+        assert_eq!(cif_pairs.pair_weight(1, 1), 1.);
+        // qubits beyond the dense range fall back to the sparse lookup
+        cif_pairs.add_pair(1, &[4, 2000]);
+        assert!(!cif_pairs.is_dense());
+    }
+
+    #[test]
+    fn test_apply_swap_updates_index() {
+        let mut pairs_map: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
+        pairs_map.insert(1, vec![vec![1, 2], vec![3, 4]]);
+        pairs_map.insert(2, vec![vec![5, 6], vec![1, 5]]);
+        let mut cif_pairs: CifPairs = CifPairs::from_pairs(pairs_map);
+
+        // node 1 is already routed, so only the pairs of node 2 follow the swap
+        cif_pairs
+            .apply_swap(Swap::new(1, 5).unwrap(), &[1])
+            .unwrap();
+        assert_eq!(cif_pairs.pairs()[&1].to_vec(), vec![vec![1, 2], vec![3, 4]]);
+        assert_eq!(cif_pairs.pairs()[&2].to_vec(), vec![vec![1, 6], vec![5, 1]]);
+
+        cif_pairs
+            .apply_swap(Swap::new(6, 7).unwrap(), &[1])
+            .unwrap();
+        assert_eq!(
+            cif_pairs
+                .get_swap_involved_pairs(Swap::new(1, 7).unwrap(), &[1, 2])
+                .unwrap(),
+            vec![vec![1, 2], vec![1, 7], vec![5, 1]]
+        );
+        assert_eq!(
+            cif_pairs
+                .get_swap_involved_pairs(Swap::new(6, 8).unwrap(), &[1, 2])
+                .unwrap(),
+            Vec::<Vec<i32>>::new()
+        );
+
+        cif_pairs.insert_node(2, vec![vec![8, 9]]);
+        assert_eq!(
+            cif_pairs
+                .get_swap_involved_pairs(Swap::new(1, 8).unwrap(), &[1, 2])
+                .unwrap(),
+            vec![vec![1, 2], vec![8, 9]]
+        );
+
+        // routed nodes may be listed in any order
+        assert!(cif_pairs
+            .apply_swap_recorded(Swap::new(1, 8).unwrap(), &[2, 1, 2])
+            .unwrap()
+            .is_empty());
+        assert!(matches!(sorted_node_ids(&[1, 2, 5]), Cow::Borrowed(_)));
+        assert_eq!(*sorted_node_ids(&[5, 1, 2, 1]), [1, 2, 5]);
+    }
+
+    #[test]
+    fn test_clones_share_pairs() {
+        let cif_pairs = CifPairs::from_pairs(
+            (0..100)
+                .map(|node_id| (node_id, vec![vec![0, 1]]))
+                .collect(),
+        );
+        let mut fork = cif_pairs.clone();
+        fork.apply_swap(Swap::new(1, 2).unwrap(), &(1..100).collect::<Vec<_>>())
+            .unwrap();
+        fork.retire_node(99);
+
+        assert_eq!(fork.pairs()[&0].to_vec(), vec![vec![0, 2]]);
+        assert_eq!(cif_pairs.pairs()[&0].to_vec(), vec![vec![0, 1]]);
+        assert_eq!((fork.pairs().len(), cif_pairs.pairs().len()), (99, 100));
+        assert!((1..99)
+            .all(|node_id| Arc::ptr_eq(&fork.pairs()[&node_id], &cif_pairs.pairs()[&node_id])));
+    }
+
+    #[test]
+    fn test_dense_and_sparse_lookups_agree() {
+        let mut pairs_map: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
+        pairs_map.insert(1, vec![vec![1, 2], vec![3, 1]]);
+        pairs_map.insert(2, vec![vec![5, 6, 0], vec![2, 5]]);
+        let dense = CifPairs::from_pairs(pairs_map.clone());
+        pairs_map.insert(3, vec![vec![2000, 7]]);
+        let mut sparse = CifPairs::from_pairs(pairs_map);
+        assert!(dense.is_dense());
+        assert!(!sparse.is_dense());
+
+        for swap in [(1, 5), (2, 3), (6, 7), (1, 2000)] {
+            let swap = Swap::try_from(swap).unwrap();
+            assert_eq!(
+                dense.get_swap_involved_pairs_by_node(swap, &[2, 1, 2]),
+                sparse.get_swap_involved_pairs_by_node(swap, &[2, 1, 2])
+            );
+        }
+
+        // a swap onto a qubit beyond the dense range drops the bitsets
+        let mut swapped = dense.clone();
+        swapped
+            .apply_swap(Swap::new(1, 1500).unwrap(), &[])
+            .unwrap();
+        assert!(dense.is_dense());
+        assert!(!swapped.is_dense());
+        sparse
+            .apply_swap(Swap::new(1, 1500).unwrap(), &[3])
+            .unwrap();
+        assert_eq!(
+            swapped.get_swap_involved_pairs(Swap::new(1500, 2).unwrap(), &[1, 2]),
+            sparse.get_swap_involved_pairs(Swap::new(1500, 2).unwrap(), &[1, 2])
+        );
     }
 }
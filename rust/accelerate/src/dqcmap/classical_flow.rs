@@ -0,0 +1,195 @@
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use super::{cif_pairs::CifPairs, dag::Instruction, error::MappingError};
+
+/// Tracks which measurements every clbit carries while walking the instructions of a circuit in
+/// order, to derive the cif pairs of the conditioned instructions.
+///
+/// A measurement sets its clbit to the (measured qubit, clbit) it wrote.  A classical
+/// instruction, i.e. one without qubits, computes its clbits from its condition clbits, e.g. an
+/// expression over a register, so they carry the union of the measurements of their inputs.
+#[derive(Clone, Debug)]
+pub struct ClassicalFlow {
+    /// The (measured qubit, clbit written by the measurement) that every clbit depends on.
+    sources: Vec<Vec<(usize, usize)>>,
+}
+
+impl ClassicalFlow {
+    pub fn new(num_clbits: usize) -> Self {
+        ClassicalFlow {
+            sources: vec![Vec::new(); num_clbits],
+        }
+    }
+
+    /// Process the next instruction, with all clbits in range, and return the cif pairs of a
+    /// conditioned instruction on qubits as `[conditioned qubit, measured qubit, clbit]`.
+    ///
+    /// Condition clbits of such an instruction that no measurement reaches contribute no pairs,
+    /// the first of them is returned alongside the pairs of the others.
+    pub fn process(
+        &mut self,
+        is_measure: bool,
+        qubits: &[usize],
+        clbits: &[usize],
+        condition: Option<&[usize]>,
+    ) -> (Option<Vec<Vec<i32>>>, Option<usize>) {
+        let mut pairs = None;
+        let mut unmeasured = None;
+        if let Some(condition) = condition {
+            if qubits.is_empty() {
+                let mut sources: Vec<(usize, usize)> = condition
+                    .iter()
+                    .flat_map(|&clbit| self.sources[clbit].iter().copied())
+                    .collect();
+                sources.sort_unstable();
+                sources.dedup();
+                for &clbit in clbits {
+                    self.sources[clbit].clone_from(&sources);
+                }
+            } else {
+                let mut node_pairs = Vec::new();
+                for &clbit in condition {
+                    if self.sources[clbit].is_empty() {
+                        unmeasured = unmeasured.or(Some(clbit));
+                        continue;
+                    }
+                    for &q in qubits {
+                        node_pairs.extend(self.sources[clbit].iter().map(|&(measured, source)| {
+                            vec![q as i32, measured as i32, source as i32]
+                        }));
+                    }
+                }
+                pairs = Some(node_pairs);
+            }
+        }
+        if is_measure && qubits.len() == clbits.len() {
+            for (&q, &c) in qubits.iter().zip(clbits) {
+                self.sources[c] = vec![(q, c)];
+            }
+        }
+        (pairs, unmeasured)
+    }
+}
+
+/// Derive the cif pairs of a circuit from its instructions, without building its DAG.
+///
+/// A conditioned instruction gets a pair for every qubit it acts on and every measurement its
+/// condition clbits depend on, so that a condition on a register yields the measurements into
+/// all its clbits.  Instructions without qubits that write clbits, such as classical
+/// expressions, pass the measurements of their condition clbits on to the clbits they write.
+/// Condition clbits that no measurement reaches are skipped, keeping the pairs of the other
+/// clbits of the condition.
+///
+/// Args:
+///     instructions (list[tuple[str, list[int], list[int], list[int] | None]]): The name, qubits,
+///         clbits and condition clbits of every instruction in topological order.  The node id
+///         of an instruction is its index.
+///     num_clbits (int): The number of clbits.  Defaults to one more than the largest clbit.
+///
+/// Returns:
+///     CifPairs: The cif pairs ``[conditioned qubit, measured qubit, clbit]`` of every
+///     conditioned instruction, keyed by node id.
+///
+/// Raises:
+///     IndexError: if a clbit is out of range.
+#[cfg_attr(feature = "python", pyfunction(signature = (instructions, num_clbits=None)))]
+pub fn extract_cif_pairs(
+    instructions: Vec<Instruction>,
+    num_clbits: Option<usize>,
+) -> Result<CifPairs, MappingError> {
+    let num_clbits = num_clbits.unwrap_or_else(|| {
+        instructions
+            .iter()
+            .flat_map(|(_, _, clbits, condition)| clbits.iter().chain(condition.iter().flatten()))
+            .max()
+            .map_or(0, |clbit| clbit + 1)
+    });
+    let mut flow = ClassicalFlow::new(num_clbits);
+    let mut pairs = HashMap::new();
+    for (node_id, (name, qubits, clbits, condition)) in instructions.into_iter().enumerate() {
+        if let Some(&clbit) = clbits
+            .iter()
+            .chain(condition.iter().flatten())
+            .find(|&&c| c >= num_clbits)
+        {
+            return Err(MappingError::Index(format!(
+                "clbit index {} is out of range for {} clbits",
+                clbit, num_clbits
+            )));
+        }
+        let (node_pairs, _) =
+            flow.process(name == "measure", &qubits, &clbits, condition.as_deref());
+        if let Some(node_pairs) = node_pairs.filter(|node_pairs| !node_pairs.is_empty()) {
+            pairs.insert(node_id, node_pairs);
+        }
+    }
+    Ok(CifPairs::from_pairs(pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(
+        name: &str,
+        qubits: &[usize],
+        clbits: &[usize],
+        condition: Option<&[usize]>,
+    ) -> Instruction {
+        (
+            name.to_string(),
+            qubits.to_vec(),
+            clbits.to_vec(),
+            condition.map(<[usize]>::to_vec),
+        )
+    }
+
+    #[test]
+    fn test_extract_cif_pairs_through_expressions() {
+        let cif_pairs = extract_cif_pairs(
+            vec![
+                instruction("measure", &[0, 1], &[0, 1], None),
+                // clbit 2 = clbit 0 xor clbit 1
+                instruction("xor", &[], &[2], Some(&[0, 1])),
+                instruction("x", &[2], &[], Some(&[2])),
+                // a condition on the register of clbits 0 and 1
+                instruction("z", &[3], &[], Some(&[0, 1])),
+                // clbit 3 is never measured
+                instruction("y", &[3], &[], Some(&[3])),
+            ],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(cif_pairs.node_ids(), vec![2, 3]);
+        assert_eq!(
+            cif_pairs.pairs()[&2].to_vec(),
+            vec![vec![2, 0, 0], vec![2, 1, 1]]
+        );
+        assert_eq!(
+            cif_pairs.pairs()[&3].to_vec(),
+            vec![vec![3, 0, 0], vec![3, 1, 1]]
+        );
+        assert!(extract_cif_pairs(vec![instruction("x", &[0], &[], Some(&[2]))], Some(2)).is_err());
+    }
+
+    #[test]
+    fn test_extract_cif_pairs_partially_measured_condition() {
+        let cif_pairs = extract_cif_pairs(
+            vec![
+                instruction("measure", &[1], &[1], None),
+                // clbit 0 is never measured, clbit 1 is
+                instruction("x", &[2], &[], Some(&[0, 1])),
+                instruction("x", &[3], &[], Some(&[1, 0])),
+            ],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(cif_pairs.node_ids(), vec![1, 2]);
+        assert_eq!(cif_pairs.pairs()[&1].to_vec(), vec![vec![2, 1, 1]]);
+        assert_eq!(cif_pairs.pairs()[&2].to_vec(), vec![vec![3, 1, 1]]);
+    }
+}
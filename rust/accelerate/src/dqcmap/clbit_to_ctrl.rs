@@ -0,0 +1,117 @@
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::error::MappingError;
+
+/// The controller holding every clbit of a circuit.
+///
+/// With shared classical registers, the result of a measurement may be stored on a controller
+/// other than the ones of the measured and the conditioned qubits, so that a feedback is routed
+/// from the measured qubit to the clbit and on to the conditioned qubit.  Cif pairs carrying
+/// their clbit as a third element are scored accordingly once a ``Clbit2Ctrl`` is set on a
+/// ``DqcMapState``.
+///
+/// Args:
+///     obj (dict[int, list[int]]): The clbits of every controller.
+///
+/// Raises:
+///     ValueError: if a clbit is assigned to more than one controller.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Clbit2Ctrl {
+    // mapping between controller id and the clbits it holds
+    map: HashMap<i32, Vec<i32>>,
+    // mapping between clbit and controller id
+    reverse_map: HashMap<i32, i32>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Clbit2Ctrl {
+    #[new]
+    #[pyo3(text_signature = "(obj, /)")]
+    fn py_new(obj: HashMap<i32, Vec<i32>>) -> PyResult<Self> {
+        Ok(Clbit2Ctrl::new(obj)?)
+    }
+
+    /// The controller holding ``clbit``, ``None`` if it is not assigned.
+    #[pyo3(name = "get_controller_by_clbit", text_signature = "(self, clbit, /)")]
+    fn py_get_controller_by_clbit(&self, clbit: i32) -> Option<i32> {
+        self.get_controller_by_clbit(clbit)
+    }
+
+    /// The clbits held by controller ``ctrl_id``, ``None`` if it is not a controller of this
+    /// mapping.
+    #[pyo3(text_signature = "(self, ctrl_id, /)")]
+    fn get_clbits_by_controller(&self, ctrl_id: i32) -> Option<Vec<i32>> {
+        self.map.get(&ctrl_id).cloned()
+    }
+
+    /// The sorted list of controller ids.
+    #[pyo3(name = "controllers", text_signature = "(self, /)")]
+    fn py_controllers(&self) -> Vec<i32> {
+        self.controllers()
+    }
+
+    /// The number of clbits assigned to a controller.
+    #[pyo3(name = "num_clbits", text_signature = "(self, /)")]
+    fn py_num_clbits(&self) -> usize {
+        self.num_clbits()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (py.get_type_bound::<Self>(), (self.map.clone(),)).into_py(py)
+    }
+}
+
+impl Clbit2Ctrl {
+    pub fn new(obj: HashMap<i32, Vec<i32>>) -> Result<Self, MappingError> {
+        let mut reverse_map = HashMap::new();
+        for (&ctrl_id, clbits) in &obj {
+            for &clbit in clbits {
+                if let Some(other) = reverse_map.insert(clbit, ctrl_id) {
+                    return Err(MappingError::Value(format!(
+                        "clbit {clbit} is assigned to controllers {other} and {ctrl_id}"
+                    )));
+                }
+            }
+        }
+        Ok(Clbit2Ctrl {
+            map: obj,
+            reverse_map,
+        })
+    }
+
+    pub fn controllers(&self) -> Vec<i32> {
+        let mut controllers: Vec<i32> = self.map.keys().copied().collect();
+        controllers.sort_unstable();
+        controllers
+    }
+
+    pub fn num_clbits(&self) -> usize {
+        self.reverse_map.len()
+    }
+
+    #[inline]
+    pub fn get_controller_by_clbit(&self, clbit: i32) -> Option<i32> {
+        self.reverse_map.get(&clbit).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clbit2ctrl() {
+        let clbit2ctrl = Clbit2Ctrl::new(HashMap::from([(0, vec![0, 1]), (2, vec![2])])).unwrap();
+        assert_eq!(clbit2ctrl.get_controller_by_clbit(1), Some(0));
+        assert_eq!(clbit2ctrl.get_controller_by_clbit(2), Some(2));
+        assert_eq!(clbit2ctrl.get_controller_by_clbit(3), None);
+        assert_eq!(clbit2ctrl.controllers(), vec![0, 2]);
+        assert_eq!(clbit2ctrl.num_clbits(), 3);
+        assert!(Clbit2Ctrl::new(HashMap::from([(0, vec![0]), (1, vec![0])])).is_err());
+    }
+}
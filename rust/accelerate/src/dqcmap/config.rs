@@ -0,0 +1,617 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use rand::prelude::*;
+
+use super::{
+    cost_model::cost_model_by_name,
+    ctrl_topology::CtrlTopology,
+    error::MappingError,
+    router::{BeamSearch, RouteOptions},
+    state::{DEFAULT_DISTANCE_WEIGHT, DEFAULT_FEEDBACK_WEIGHT, DEFAULT_LOOKAHEAD_DECAY},
+};
+
+/// Default temperature of the softmax selection.
+pub const DEFAULT_TEMPERATURE: f64 = 1.0;
+
+/// How the router picks among equally scored swaps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Take the smallest of the tied swaps.
+    #[default]
+    First,
+    /// Pick one of the tied swaps at random.
+    Random,
+    /// Sample among all candidate swaps with probability proportional to
+    /// `exp(-score / temperature)`.
+    Softmax,
+}
+
+impl SelectionStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SelectionStrategy::First => "first",
+            SelectionStrategy::Random => "random",
+            SelectionStrategy::Softmax => "softmax",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, MappingError> {
+        match name {
+            "first" => Ok(SelectionStrategy::First),
+            "random" => Ok(SelectionStrategy::Random),
+            "softmax" => Ok(SelectionStrategy::Softmax),
+            _ => Err(MappingError::Value(format!(
+                "unknown selection strategy '{name}', expected 'first', 'random' or 'softmax'"
+            ))),
+        }
+    }
+}
+
+/// How measurement results travel between controllers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeedbackModel {
+    /// Every result is sent from the controller of the measured qubit to the controller of the
+    /// conditioned qubit, at the latency of the link between them.
+    #[default]
+    PointToPoint,
+    /// Every result is broadcast to all controllers at a fixed cost, whichever controllers the
+    /// feedback connects.
+    Broadcast,
+}
+
+impl FeedbackModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeedbackModel::PointToPoint => "point_to_point",
+            FeedbackModel::Broadcast => "broadcast",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, MappingError> {
+        match name {
+            "point_to_point" => Ok(FeedbackModel::PointToPoint),
+            "broadcast" => Ok(FeedbackModel::Broadcast),
+            _ => Err(MappingError::Value(format!(
+                "unknown feedback model '{name}', expected 'point_to_point' or 'broadcast'"
+            ))),
+        }
+    }
+
+    /// The latency of a feedback between `ctrl0` and `ctrl1`, 0 within a controller.  A
+    /// point-to-point transfer takes the latency of their link in `ctrl_topology` and a broadcast
+    /// its `default_latency`, both 1 without a topology.
+    #[inline]
+    pub fn latency(&self, ctrl_topology: Option<&CtrlTopology>, ctrl0: i32, ctrl1: i32) -> f64 {
+        if ctrl0 == ctrl1 {
+            return 0.;
+        }
+        match (self, ctrl_topology) {
+            (FeedbackModel::PointToPoint, Some(topology)) => topology.cost(ctrl0, ctrl1),
+            (FeedbackModel::Broadcast, Some(topology)) => topology.default_latency,
+            (_, None) => 1.,
+        }
+    }
+}
+
+/// The parameters of the scoring heuristics and of the router in one object, accepted by
+/// ``DqcMapState``, ``dqcmap_routing`` and ``route``, so that experiments can sweep them without
+/// touching the call sites.
+///
+/// Args:
+///     feedback_weight (float): The weight β of the change in cross-controller feedbacks.
+///     distance_weight (float): The weight α of the change in distance of the front layer.
+///     lookahead_window (int): The number of upcoming nodes scored by ``score_lookahead``.
+///     lookahead_decay (float): The weight multiplier per step into the lookahead window.
+///     cost_model (str): The name of the heuristic used by ``score_swap``.
+///     selection (str): ``"first"`` to take the smallest of equally scored swaps, ``"random"``
+///         to pick one at random, or ``"softmax"`` to sample among all candidate swaps with
+///         probability proportional to ``exp(-score / temperature)``, lower scores being better.
+///     seed (int): The seed of the RNG of the ``"random"`` and ``"softmax"`` selections, drawn
+///         from the OS if not given.
+///     beam_width (int): The number of swap sequences kept by the beam search of the router.
+///     beam_depth (int): The maximum length of the swap sequences explored by the beam search.
+///         The router picks swaps greedily if both are 1.
+///     temperature (float): The temperature of the ``"softmax"`` selection, higher values
+///         flattening the distribution.
+///     time_decay (float): The decay constant λ weighting the pairs of the ``k``-th active node
+///         by ``exp(-λ k)`` in ``score_weighted``, 0 to weigh all active nodes equally.
+///     feedback_model (str): ``"point_to_point"`` if a feedback is sent between the two
+///         controllers it connects at the latency of their link, or ``"broadcast"`` if every
+///         measurement result is broadcast to all controllers at the fixed ``default_latency`` of
+///         the controller topology.
+///
+/// Raises:
+///     ValueError: if a weight or the time decay is negative or not finite, the lookahead decay
+///         is not in [0, 1], the selection strategy or feedback model is unknown, the beam is
+///         empty, the temperature is not a positive finite number or the softmax selection is
+///         combined with a beam search.
+///     DqcMapError: if the cost model is unknown.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DqcMapConfig {
+    pub feedback_weight: f64,
+    pub distance_weight: f64,
+    pub lookahead_window: usize,
+    pub lookahead_decay: f64,
+    pub cost_model: String,
+    pub selection: SelectionStrategy,
+    pub seed: Option<u64>,
+    pub beam_width: usize,
+    pub beam_depth: usize,
+    pub temperature: f64,
+    pub time_decay: f64,
+    pub feedback_model: FeedbackModel,
+}
+
+impl Default for DqcMapConfig {
+    fn default() -> Self {
+        DqcMapConfig {
+            feedback_weight: DEFAULT_FEEDBACK_WEIGHT,
+            distance_weight: DEFAULT_DISTANCE_WEIGHT,
+            lookahead_window: 0,
+            lookahead_decay: DEFAULT_LOOKAHEAD_DECAY,
+            cost_model: "fidelity_weighted".to_string(),
+            selection: SelectionStrategy::First,
+            seed: None,
+            beam_width: 1,
+            beam_depth: 1,
+            temperature: DEFAULT_TEMPERATURE,
+            time_decay: 0.,
+            feedback_model: FeedbackModel::PointToPoint,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DqcMapConfig {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (feedback_weight=DEFAULT_FEEDBACK_WEIGHT, distance_weight=DEFAULT_DISTANCE_WEIGHT, lookahead_window=0, lookahead_decay=DEFAULT_LOOKAHEAD_DECAY, cost_model="fidelity_weighted", selection="first", seed=None, beam_width=1, beam_depth=1, temperature=DEFAULT_TEMPERATURE, time_decay=0., feedback_model="point_to_point"))]
+    fn py_new(
+        feedback_weight: f64,
+        distance_weight: f64,
+        lookahead_window: usize,
+        lookahead_decay: f64,
+        cost_model: &str,
+        selection: &str,
+        seed: Option<u64>,
+        beam_width: usize,
+        beam_depth: usize,
+        temperature: f64,
+        time_decay: f64,
+        feedback_model: &str,
+    ) -> PyResult<Self> {
+        Ok(DqcMapConfig::new(
+            feedback_weight,
+            distance_weight,
+            lookahead_window,
+            lookahead_decay,
+            cost_model,
+            selection,
+            seed,
+            beam_width,
+            beam_depth,
+            temperature,
+            time_decay,
+            feedback_model,
+        )?)
+    }
+
+    #[getter(feedback_weight)]
+    fn get_feedback_weight(&self) -> f64 {
+        self.feedback_weight
+    }
+
+    #[getter(distance_weight)]
+    fn get_distance_weight(&self) -> f64 {
+        self.distance_weight
+    }
+
+    #[getter(lookahead_window)]
+    fn get_lookahead_window(&self) -> usize {
+        self.lookahead_window
+    }
+
+    #[getter(lookahead_decay)]
+    fn get_lookahead_decay(&self) -> f64 {
+        self.lookahead_decay
+    }
+
+    #[getter(cost_model)]
+    fn get_cost_model(&self) -> &str {
+        &self.cost_model
+    }
+
+    #[getter(seed)]
+    fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    #[getter(beam_width)]
+    fn get_beam_width(&self) -> usize {
+        self.beam_width
+    }
+
+    #[getter(beam_depth)]
+    fn get_beam_depth(&self) -> usize {
+        self.beam_depth
+    }
+
+    #[getter(temperature)]
+    fn get_temperature(&self) -> f64 {
+        self.temperature
+    }
+
+    #[getter(time_decay)]
+    fn get_time_decay(&self) -> f64 {
+        self.time_decay
+    }
+
+    /// The name of the selection strategy.
+    #[getter(selection)]
+    fn get_selection(&self) -> &'static str {
+        self.selection.name()
+    }
+
+    /// The name of the feedback model.
+    #[getter(feedback_model)]
+    fn get_feedback_model(&self) -> &'static str {
+        self.feedback_model.name()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.feedback_weight,
+                self.distance_weight,
+                self.lookahead_window,
+                self.lookahead_decay,
+                self.cost_model.clone(),
+                self.selection.name(),
+                self.seed,
+                self.beam_width,
+                self.beam_depth,
+                self.temperature,
+                self.time_decay,
+                self.feedback_model.name(),
+            ),
+        )
+            .into_py(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DqcMapConfig(feedback_weight={}, distance_weight={}, lookahead_window={}, \
+             lookahead_decay={}, cost_model='{}', selection='{}', seed={}, beam_width={}, \
+             beam_depth={}, temperature={}, time_decay={}, feedback_model='{}')",
+            self.feedback_weight,
+            self.distance_weight,
+            self.lookahead_window,
+            self.lookahead_decay,
+            self.cost_model,
+            self.selection.name(),
+            self.seed
+                .map_or_else(|| "None".to_string(), |seed| seed.to_string()),
+            self.beam_width,
+            self.beam_depth,
+            self.temperature,
+            self.time_decay,
+            self.feedback_model.name()
+        )
+    }
+}
+
+impl DqcMapConfig {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        feedback_weight: f64,
+        distance_weight: f64,
+        lookahead_window: usize,
+        lookahead_decay: f64,
+        cost_model: &str,
+        selection: &str,
+        seed: Option<u64>,
+        beam_width: usize,
+        beam_depth: usize,
+        temperature: f64,
+        time_decay: f64,
+        feedback_model: &str,
+    ) -> Result<Self, MappingError> {
+        for weight in [feedback_weight, distance_weight] {
+            if !(weight.is_finite() && weight >= 0.) {
+                return Err(MappingError::Value(format!(
+                    "objective weights must be non-negative finite numbers, got {weight}"
+                )));
+            }
+        }
+        if !(0.0..=1.0).contains(&lookahead_decay) {
+            return Err(MappingError::Value(format!(
+                "lookahead decay must be in [0, 1], got {lookahead_decay}"
+            )));
+        }
+        if beam_width == 0 || beam_depth == 0 {
+            return Err(MappingError::Value(format!(
+                "beam width and depth must be positive, got {beam_width} and {beam_depth}"
+            )));
+        }
+        if !(temperature.is_finite() && temperature > 0.) {
+            return Err(MappingError::Value(format!(
+                "temperature must be a positive finite number, got {temperature}"
+            )));
+        }
+        if !(time_decay.is_finite() && time_decay >= 0.) {
+            return Err(MappingError::Value(format!(
+                "time decay must be finite and non-negative, got {time_decay}"
+            )));
+        }
+        let selection = SelectionStrategy::from_name(selection)?;
+        if selection == SelectionStrategy::Softmax && (beam_width > 1 || beam_depth > 1) {
+            return Err(MappingError::Value(
+                "the beam search does not support the softmax selection".to_string(),
+            ));
+        }
+        cost_model_by_name(cost_model)?;
+        Ok(DqcMapConfig {
+            feedback_weight,
+            distance_weight,
+            lookahead_window,
+            lookahead_decay,
+            cost_model: cost_model.to_string(),
+            selection,
+            seed,
+            beam_width,
+            beam_depth,
+            temperature,
+            time_decay,
+            feedback_model: FeedbackModel::from_name(feedback_model)?,
+        })
+    }
+
+    /// The seed to hand to the router: `None` to take the first of tied swaps, or the configured
+    /// seed (a random one if unset) to pick among them at random.
+    pub fn router_seed(&self) -> Option<u64> {
+        match self.selection {
+            SelectionStrategy::First => None,
+            SelectionStrategy::Random | SelectionStrategy::Softmax => {
+                Some(self.seed.unwrap_or_else(random))
+            }
+        }
+    }
+
+    /// The temperature of the softmax selection of the router, `None` unless it is selected.
+    pub fn softmax_temperature(&self) -> Option<f64> {
+        (self.selection == SelectionStrategy::Softmax).then_some(self.temperature)
+    }
+
+    /// The beam search of the router, `None` to pick swaps greedily.
+    pub fn beam_search(&self) -> Option<BeamSearch> {
+        (self.beam_width > 1 || self.beam_depth > 1).then_some(BeamSearch {
+            width: self.beam_width,
+            depth: self.beam_depth,
+        })
+    }
+
+    /// The seed, beam search and softmax temperature of the router, without a trace.
+    pub fn route_options(&self) -> RouteOptions {
+        RouteOptions {
+            seed: self.router_seed(),
+            record_trace: false,
+            beam: self.beam_search(),
+            temperature: self.softmax_temperature(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn test_config_validation() {
+        let config = DqcMapConfig::new(
+            0.5,
+            2.,
+            3,
+            0.25,
+            "feedback_count",
+            "random",
+            Some(4),
+            4,
+            2,
+            1.,
+            0.,
+            "point_to_point",
+        )
+        .unwrap();
+        assert_eq!(config.selection, SelectionStrategy::Random);
+        assert_eq!(config.router_seed(), Some(4));
+        assert_eq!(
+            config.beam_search(),
+            Some(BeamSearch { width: 4, depth: 2 })
+        );
+        assert_eq!(DqcMapConfig::default().router_seed(), None);
+        assert_eq!(DqcMapConfig::default().beam_search(), None);
+
+        assert!(DqcMapConfig::new(
+            -1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            1.5,
+            "feedback_count",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "distance",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "best",
+            None,
+            1,
+            1,
+            1.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "first",
+            None,
+            0,
+            1,
+            1.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "softmax",
+            None,
+            1,
+            1,
+            0.,
+            0.,
+            "point_to_point"
+        )
+        .is_err());
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            -1.,
+            "point_to_point"
+        )
+        .is_err());
+
+        let softmax = DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "softmax",
+            Some(2),
+            1,
+            1,
+            0.5,
+            0.,
+            "point_to_point",
+        )
+        .unwrap();
+        assert_eq!(softmax.softmax_temperature(), Some(0.5));
+        assert_eq!(softmax.router_seed(), Some(2));
+        assert_eq!(config.softmax_temperature(), None);
+        assert_eq!(softmax.route_options().temperature, Some(0.5));
+        assert_eq!(softmax.route_options().beam, None);
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "softmax",
+            Some(2),
+            2,
+            1,
+            0.5,
+            0.,
+            "point_to_point",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_feedback_model() {
+        let topology =
+            CtrlTopology::new(HashMap::from([((0, 1), 5.)]), 2., HashMap::new(), None).unwrap();
+        let point_to_point = FeedbackModel::PointToPoint;
+        assert_eq!(point_to_point.latency(Some(&topology), 1, 0), 5.);
+        assert_eq!(point_to_point.latency(Some(&topology), 1, 2), 2.);
+        assert_eq!(point_to_point.latency(None, 1, 2), 1.);
+        let broadcast = FeedbackModel::from_name("broadcast").unwrap();
+        assert_eq!(broadcast.latency(Some(&topology), 1, 0), 2.);
+        assert_eq!(broadcast.latency(Some(&topology), 1, 1), 0.);
+        assert_eq!(broadcast.latency(None, 1, 0), 1.);
+
+        assert_eq!(
+            DqcMapConfig::default().feedback_model,
+            FeedbackModel::PointToPoint
+        );
+        assert!(DqcMapConfig::new(
+            1.,
+            1.,
+            0,
+            0.5,
+            "feedback_count",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            0.,
+            "multicast"
+        )
+        .is_err());
+    }
+}
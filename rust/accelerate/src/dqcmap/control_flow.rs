@@ -0,0 +1,300 @@
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::error::MappingError;
+
+/// The kind of a control-flow block.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BlockKind {
+    /// One branch of an ``if_else``, taken with the given probability.
+    IfElse { probability: f64 },
+    /// The body of a ``while`` loop, executed the given number of times on average.
+    While { expected_iterations: f64 },
+}
+
+impl BlockKind {
+    /// How many times the block runs each time its parent scope runs.
+    #[inline]
+    pub fn multiplicity(&self) -> f64 {
+        match *self {
+            BlockKind::IfElse { probability } => probability,
+            BlockKind::While {
+                expected_iterations,
+            } => expected_iterations,
+        }
+    }
+}
+
+/// The scopes of a [ControlFlowModel] as pickled, as (block kind, multiplicity, parent), with
+/// the scope of every node.
+#[cfg(feature = "python")]
+type PickleState<K> = (Vec<(K, f64, Option<usize>)>, HashMap<usize, usize>);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Scope {
+    kind: BlockKind,
+    parent: Option<usize>,
+    // product of the multiplicities of this scope and all its ancestors
+    multiplicity: f64,
+}
+
+/// Nested ``if_else`` and ``while`` scopes of a dynamic circuit, used to weight cif pairs by how
+/// often their nodes are expected to execute.
+///
+/// Scopes are created with ``add_if_else`` and ``add_while``, optionally inside a parent scope,
+/// and the nodes of a block are attached to its scope with ``assign``.  Nodes outside of any
+/// scope execute exactly once.  A node in a loop body nested in a branch taken half of the time
+/// with 4 expected iterations, for example, has a multiplicity of 2.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlFlowModel {
+    scopes: Vec<Scope>,
+    node_scope: HashMap<usize, usize>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ControlFlowModel {
+    #[new]
+    fn py_new() -> Self {
+        ControlFlowModel::new()
+    }
+
+    /// Add the scope of an ``if_else`` branch taken with ``probability`` and return its id.
+    ///
+    /// Raises:
+    ///     ValueError: if ``probability`` is not in [0, 1].
+    ///     IndexError: if ``parent`` is not a scope id.
+    #[pyo3(name = "add_if_else", signature = (probability, parent=None), text_signature = "(self, probability, /, parent=None)")]
+    fn py_add_if_else(&mut self, probability: f64, parent: Option<usize>) -> PyResult<usize> {
+        Ok(self.add_if_else(probability, parent)?)
+    }
+
+    /// Add the scope of a ``while`` body executed ``expected_iterations`` times on average and
+    /// return its id.
+    ///
+    /// Raises:
+    ///     ValueError: if ``expected_iterations`` is negative or not finite.
+    ///     IndexError: if ``parent`` is not a scope id.
+    #[pyo3(name = "add_while", signature = (expected_iterations, parent=None), text_signature = "(self, expected_iterations, /, parent=None)")]
+    fn py_add_while(&mut self, expected_iterations: f64, parent: Option<usize>) -> PyResult<usize> {
+        Ok(self.add_while(expected_iterations, parent)?)
+    }
+
+    /// Put node ``node_id`` into scope ``scope``, or back at the top level if ``scope`` is
+    /// ``None``.
+    ///
+    /// Raises:
+    ///     IndexError: if ``scope`` is not a scope id.
+    #[pyo3(name = "assign", signature = (node_id, scope), text_signature = "(self, node_id, scope, /)")]
+    fn py_assign(&mut self, node_id: usize, scope: Option<usize>) -> PyResult<()> {
+        Ok(self.assign(node_id, scope)?)
+    }
+
+    /// The innermost scope of node ``node_id``, ``None`` at the top level.
+    #[pyo3(name = "scope_of", text_signature = "(self, node_id, /)")]
+    fn py_scope_of(&self, node_id: usize) -> Option<usize> {
+        self.scope_of(node_id)
+    }
+
+    /// The parent of scope ``scope``, ``None`` for a scope at the top level.
+    #[pyo3(name = "parent", text_signature = "(self, scope, /)")]
+    fn py_parent(&self, scope: usize) -> PyResult<Option<usize>> {
+        Ok(self.parent(scope)?)
+    }
+
+    /// Whether node ``node_id`` is inside a ``while`` body, at any depth.
+    #[pyo3(name = "in_loop", text_signature = "(self, node_id, /)")]
+    fn py_in_loop(&self, node_id: usize) -> bool {
+        self.in_loop(node_id)
+    }
+
+    /// The expected number of executions of node ``node_id``.
+    #[pyo3(name = "multiplicity", text_signature = "(self, node_id, /)")]
+    fn py_multiplicity(&self, node_id: usize) -> f64 {
+        self.multiplicity(node_id)
+    }
+
+    /// The number of scopes.
+    fn __len__(&self) -> usize {
+        self.scopes.len()
+    }
+
+    fn __getstate__(&self) -> PickleState<&'static str> {
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|scope| match scope.kind {
+                BlockKind::IfElse { probability } => ("if_else", probability, scope.parent),
+                BlockKind::While {
+                    expected_iterations,
+                } => ("while", expected_iterations, scope.parent),
+            })
+            .collect();
+        (scopes, self.node_scope.clone())
+    }
+
+    fn __setstate__(&mut self, state: PickleState<String>) -> PyResult<()> {
+        let (scopes, node_scope) = state;
+        let mut model = ControlFlowModel::new();
+        for (kind, value, parent) in scopes {
+            match kind.as_str() {
+                "if_else" => model.add_if_else(value, parent)?,
+                "while" => model.add_while(value, parent)?,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown control-flow block '{kind}'"
+                    )))
+                }
+            };
+        }
+        for (node_id, scope) in node_scope {
+            model.assign(node_id, Some(scope))?;
+        }
+        *self = model;
+        Ok(())
+    }
+}
+
+impl ControlFlowModel {
+    pub fn new() -> Self {
+        ControlFlowModel::default()
+    }
+
+    pub fn add_if_else(
+        &mut self,
+        probability: f64,
+        parent: Option<usize>,
+    ) -> Result<usize, MappingError> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(MappingError::Value(format!(
+                "branch probability must be in [0, 1], got {probability}"
+            )));
+        }
+        self.add_scope(BlockKind::IfElse { probability }, parent)
+    }
+
+    pub fn add_while(
+        &mut self,
+        expected_iterations: f64,
+        parent: Option<usize>,
+    ) -> Result<usize, MappingError> {
+        if !(expected_iterations.is_finite() && expected_iterations >= 0.) {
+            return Err(MappingError::Value(format!(
+                "expected iterations must be a non-negative finite number, got {expected_iterations}"
+            )));
+        }
+        self.add_scope(
+            BlockKind::While {
+                expected_iterations,
+            },
+            parent,
+        )
+    }
+
+    pub fn assign(&mut self, node_id: usize, scope: Option<usize>) -> Result<(), MappingError> {
+        match scope {
+            Some(scope) => {
+                self.check_scope(scope)?;
+                self.node_scope.insert(node_id, scope);
+            }
+            None => {
+                self.node_scope.remove(&node_id);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn scope_of(&self, node_id: usize) -> Option<usize> {
+        self.node_scope.get(&node_id).copied()
+    }
+
+    pub fn parent(&self, scope: usize) -> Result<Option<usize>, MappingError> {
+        self.check_scope(scope)?;
+        Ok(self.scopes[scope].parent)
+    }
+
+    pub fn in_loop(&self, node_id: usize) -> bool {
+        let mut scope = self.scope_of(node_id);
+        while let Some(current) = scope {
+            if matches!(self.scopes[current].kind, BlockKind::While { .. }) {
+                return true;
+            }
+            scope = self.scopes[current].parent;
+        }
+        false
+    }
+
+    pub fn multiplicity(&self, node_id: usize) -> f64 {
+        self.scope_of(node_id)
+            .map_or(1., |scope| self.scopes[scope].multiplicity)
+    }
+
+    /// The kind of scope `scope`.
+    pub fn kind(&self, scope: usize) -> Option<BlockKind> {
+        self.scopes.get(scope).map(|scope| scope.kind)
+    }
+
+    fn add_scope(&mut self, kind: BlockKind, parent: Option<usize>) -> Result<usize, MappingError> {
+        let parent_multiplicity = match parent {
+            Some(parent) => {
+                self.check_scope(parent)?;
+                self.scopes[parent].multiplicity
+            }
+            None => 1.,
+        };
+        self.scopes.push(Scope {
+            kind,
+            parent,
+            multiplicity: parent_multiplicity * kind.multiplicity(),
+        });
+        Ok(self.scopes.len() - 1)
+    }
+
+    fn check_scope(&self, scope: usize) -> Result<(), MappingError> {
+        if scope < self.scopes.len() {
+            Ok(())
+        } else {
+            Err(MappingError::Index(format!(
+                "scope {} is out of range for {} scopes",
+                scope,
+                self.scopes.len()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_flow_multiplicity() {
+        let mut model = ControlFlowModel::new();
+        let branch = model.add_if_else(0.5, None).unwrap();
+        let body = model.add_while(4., Some(branch)).unwrap();
+        model.assign(1, Some(branch)).unwrap();
+        model.assign(2, Some(body)).unwrap();
+
+        assert_eq!(model.multiplicity(0), 1.);
+        assert_eq!(model.multiplicity(1), 0.5);
+        assert_eq!(model.multiplicity(2), 2.);
+        assert!(!model.in_loop(1));
+        assert!(model.in_loop(2));
+        assert_eq!(model.parent(body).unwrap(), Some(branch));
+        assert_eq!(
+            model.kind(body),
+            Some(BlockKind::While {
+                expected_iterations: 4.
+            })
+        );
+
+        model.assign(2, None).unwrap();
+        assert_eq!(model.multiplicity(2), 1.);
+        assert!(model.add_if_else(1.5, None).is_err());
+        assert!(model.add_while(2., Some(5)).is_err());
+        assert!(model.assign(3, Some(5)).is_err());
+    }
+}
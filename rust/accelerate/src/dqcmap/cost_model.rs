@@ -0,0 +1,152 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::{error::MappingError, state::DqcMapState, swap::Swap};
+
+/// A heuristic scoring how much a swap improves the controller locality of the cif pairs of a
+/// [DqcMapState].  Higher is better, 0 means the swap is neutral.
+///
+/// New heuristics only need to implement this trait and be set on the state with
+/// [DqcMapState::set_cost_model], the router picks them up without further changes.  The model
+/// is shared between forks of a state and across threads, so it must not hold mutable state.
+pub trait SwapCostModel: Debug + Send + Sync {
+    /// The name the model is selected by from Python.
+    fn name(&self) -> &'static str;
+
+    /// Score `swap` against the cif pairs of `active_nodes` in `state`.
+    fn score(
+        &self,
+        state: &DqcMapState,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError>;
+
+    /// Whether the score of a swap only depends on the cif pairs holding one of its qubits, so
+    /// that applying a swap keeps the cached scores of the swaps on other qubits, see
+    /// [super::score_cache::ScoreCache].
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+/// The difference in the weighted number of cross-controller feedbacks, see
+/// [DqcMapState::score].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FeedbackCount;
+
+impl SwapCostModel for FeedbackCount {
+    fn name(&self) -> &'static str {
+        "feedback_count"
+    }
+
+    fn score(
+        &self,
+        state: &DqcMapState,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError> {
+        Ok(state.score(swap, active_nodes)?.unwrap_or(0.))
+    }
+}
+
+/// The difference in the latency of the cross-controller feedbacks given by the controller
+/// topology of the state, see [DqcMapState::score_weighted].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatencyWeighted;
+
+impl SwapCostModel for LatencyWeighted {
+    fn name(&self) -> &'static str {
+        "latency_weighted"
+    }
+
+    fn score(
+        &self,
+        state: &DqcMapState,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError> {
+        Ok(state.score_weighted(swap, active_nodes)?.unwrap_or(0.))
+    }
+}
+
+/// The difference in the latency of the cross-controller transfers when all feedbacks of the
+/// active nodes between the same two controllers are batched into one, see
+/// [DqcMapState::score_batched].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FeedbackBatched;
+
+impl SwapCostModel for FeedbackBatched {
+    fn name(&self) -> &'static str {
+        "feedback_batched"
+    }
+
+    fn score(
+        &self,
+        state: &DqcMapState,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError> {
+        Ok(state.score_batched(swap, active_nodes)?.unwrap_or(0.))
+    }
+
+    fn is_local(&self) -> bool {
+        // a swap can merge or split the transfers of pairs on any other qubits
+        false
+    }
+}
+
+/// The latency-weighted score minus the noise, crosstalk and bandwidth costs of the swap given by
+/// the noise model, the crosstalk map and the controller topology of the state, see
+/// [DqcMapState::score_noise_aware].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FidelityWeighted;
+
+impl SwapCostModel for FidelityWeighted {
+    fn name(&self) -> &'static str {
+        "fidelity_weighted"
+    }
+
+    fn score(
+        &self,
+        state: &DqcMapState,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError> {
+        state.score_noise_aware(swap, active_nodes)
+    }
+}
+
+/// The names of the built-in cost models, in the order they are listed to users.
+pub const COST_MODEL_NAMES: [&str; 4] = [
+    "feedback_count",
+    "latency_weighted",
+    "feedback_batched",
+    "fidelity_weighted",
+];
+
+/// The built-in cost model called `name`.
+pub fn cost_model_by_name(name: &str) -> Result<Arc<dyn SwapCostModel>, MappingError> {
+    match name {
+        "feedback_count" => Ok(Arc::new(FeedbackCount)),
+        "latency_weighted" => Ok(Arc::new(LatencyWeighted)),
+        "feedback_batched" => Ok(Arc::new(FeedbackBatched)),
+        "fidelity_weighted" => Ok(Arc::new(FidelityWeighted)),
+        _ => Err(MappingError::UnknownCostModel(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_model_by_name() {
+        for name in COST_MODEL_NAMES {
+            assert_eq!(cost_model_by_name(name).unwrap().name(), name);
+        }
+        assert_eq!(
+            cost_model_by_name("distance").unwrap_err(),
+            MappingError::UnknownCostModel("distance".to_string())
+        );
+    }
+}
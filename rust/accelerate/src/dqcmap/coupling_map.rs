@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+#[cfg(feature = "python")]
+use {numpy::PyArray2, pyo3::exceptions::PyValueError, pyo3::prelude::*};
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{distance::DistanceMatrix, error::MappingError};
+
+/// An undirected coupling map of physical qubits with precomputed hop distances.
+///
+/// Swaps are symmetric, so the direction of the input edges is ignored; duplicated edges and
+/// self-loops are dropped.  Hop distances between all pairs of qubits are computed once on
+/// construction into a ``DistanceMatrix``, so that candidate generation and distance heuristics
+/// never have to go back to Python.  The coupling map is immutable and its data is shared
+/// between clones, so it can be used by concurrent routing calls from several threads.
+///
+/// Args:
+///     edges (list[tuple[int, int]]): The edges of the coupling map.
+///     num_qubits (int): The number of physical qubits.  Defaults to one more than the largest
+///         qubit index in ``edges``.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug)]
+pub struct CouplingMap {
+    edges: Arc<[[PhysicalQubit; 2]]>,
+    neighbors: Arc<[Vec<PhysicalQubit>]>,
+    distance: DistanceMatrix,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CouplingMap {
+    #[new]
+    #[pyo3(signature = (edges, num_qubits=None))]
+    fn py_new(edges: Vec<[PhysicalQubit; 2]>, num_qubits: Option<usize>) -> PyResult<Self> {
+        Ok(CouplingMap::new(edges, num_qubits)?)
+    }
+
+    /// Build a coupling map from a rustworkx ``PyGraph`` or ``PyDiGraph`` whose node indices are
+    /// the physical qubits.  Edge directions and payloads are ignored, and the number of qubits is
+    /// one more than the largest node index so that removed nodes leave isolated qubits.
+    #[staticmethod]
+    #[pyo3(text_signature = "(graph, /)")]
+    pub fn from_graph(graph: &Bound<PyAny>) -> PyResult<Self> {
+        let edges: Vec<[PhysicalQubit; 2]> = graph.call_method0("edge_list")?.extract()?;
+        let nodes: Vec<usize> = graph.call_method0("node_indices")?.extract()?;
+        let num_qubits = nodes.into_iter().max().map_or(0, |node| node + 1);
+        Ok(CouplingMap::new(edges, Some(num_qubits))?)
+    }
+
+    /// The number of physical qubits.
+    #[pyo3(name = "num_qubits", text_signature = "(self, /)")]
+    fn py_num_qubits(&self) -> usize {
+        self.num_qubits()
+    }
+
+    /// The deduplicated list of undirected edges.
+    #[pyo3(name = "edges", text_signature = "(self, /)")]
+    fn py_edges(&self) -> Vec<(PhysicalQubit, PhysicalQubit)> {
+        self.edges.iter().map(|&[a, b]| (a, b)).collect()
+    }
+
+    /// The physical qubits adjacent to ``qubit``.
+    #[pyo3(name = "neighbors", text_signature = "(self, qubit, /)")]
+    fn py_neighbors(&self, qubit: PhysicalQubit) -> PyResult<Vec<PhysicalQubit>> {
+        self.check_qubit(qubit)?;
+        Ok(self.neighbors(qubit).to_vec())
+    }
+
+    /// The number of physical qubits adjacent to ``qubit``.
+    #[pyo3(text_signature = "(self, qubit, /)")]
+    fn degree(&self, qubit: PhysicalQubit) -> PyResult<usize> {
+        self.check_qubit(qubit)?;
+        Ok(self.neighbors(qubit).len())
+    }
+
+    /// Whether there is an edge between ``qubit_a`` and ``qubit_b``.
+    #[pyo3(name = "is_adjacent", text_signature = "(self, qubit_a, qubit_b, /)")]
+    fn py_is_adjacent(&self, qubit_a: PhysicalQubit, qubit_b: PhysicalQubit) -> PyResult<bool> {
+        self.check_qubit(qubit_a)?;
+        self.check_qubit(qubit_b)?;
+        Ok(self.is_adjacent(qubit_a, qubit_b))
+    }
+
+    /// The number of edges on a shortest path between ``qubit_a`` and ``qubit_b``.
+    ///
+    /// Raises:
+    ///     ValueError: if the two qubits are not connected.
+    #[pyo3(name = "distance", text_signature = "(self, qubit_a, qubit_b, /)")]
+    fn py_distance(&self, qubit_a: PhysicalQubit, qubit_b: PhysicalQubit) -> PyResult<usize> {
+        self.check_qubit(qubit_a)?;
+        self.check_qubit(qubit_b)?;
+        match self.distance(qubit_a, qubit_b) {
+            usize::MAX => Err(PyValueError::new_err(format!(
+                "physical qubits {} and {} are not connected",
+                qubit_a.index(),
+                qubit_b.index()
+            ))),
+            dist => Ok(dist),
+        }
+    }
+
+    /// The hop distances between all pairs of physical qubits as a ``(num_qubits, num_qubits)``
+    /// float array, with ``inf`` for pairs of qubits that are not connected.
+    #[pyo3(name = "distance_matrix", text_signature = "(self, /)")]
+    fn py_distance_matrix<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        self.distance.to_numpy(py)
+    }
+
+    /// Whether every physical qubit can reach every other one.
+    #[pyo3(name = "is_connected", text_signature = "(self, /)")]
+    fn py_is_connected(&self) -> bool {
+        self.is_connected()
+    }
+
+    fn __len__(&self) -> usize {
+        self.num_qubits()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (self.edges.to_vec(), self.num_qubits()),
+        )
+            .into_py(py)
+    }
+}
+
+impl CouplingMap {
+    pub fn new(
+        edges: Vec<[PhysicalQubit; 2]>,
+        num_qubits: Option<usize>,
+    ) -> Result<Self, MappingError> {
+        let num_qubits = num_qubits.unwrap_or_else(|| {
+            edges
+                .iter()
+                .flat_map(|edge| edge.iter().map(|q| q.index() + 1))
+                .max()
+                .unwrap_or(0)
+        });
+        let mut neighbors = vec![Vec::new(); num_qubits];
+        let mut unique_edges = Vec::with_capacity(edges.len());
+        for [a, b] in edges {
+            if a.index() >= num_qubits || b.index() >= num_qubits {
+                return Err(MappingError::Index(format!(
+                    "edge ({}, {}) is out of range for {} physical qubits",
+                    a.index(),
+                    b.index(),
+                    num_qubits
+                )));
+            }
+            if a != b && !neighbors[a.index()].contains(&b) {
+                neighbors[a.index()].push(b);
+                neighbors[b.index()].push(a);
+                unique_edges.push([a, b]);
+            }
+        }
+        let distance = DistanceMatrix::from_neighbors(&neighbors);
+        Ok(CouplingMap {
+            edges: unique_edges.into(),
+            neighbors: neighbors.into(),
+            distance,
+        })
+    }
+
+    #[inline]
+    pub fn num_qubits(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    #[inline]
+    pub fn edges(&self) -> &[[PhysicalQubit; 2]] {
+        &self.edges
+    }
+
+    #[inline]
+    pub fn neighbors(&self, qubit: PhysicalQubit) -> &[PhysicalQubit] {
+        &self.neighbors[qubit.index()]
+    }
+
+    #[inline]
+    pub fn is_adjacent(&self, qubit_a: PhysicalQubit, qubit_b: PhysicalQubit) -> bool {
+        self.distance(qubit_a, qubit_b) == 1
+    }
+
+    /// Hop distance between two physical qubits, or `usize::MAX` if they are not connected.
+    #[inline]
+    pub fn distance(&self, qubit_a: PhysicalQubit, qubit_b: PhysicalQubit) -> usize {
+        self.distance.get(qubit_a, qubit_b)
+    }
+
+    /// The cached distances between all pairs of physical qubits.
+    #[inline]
+    pub fn distance_matrix(&self) -> &DistanceMatrix {
+        &self.distance
+    }
+
+    /// Whether every physical qubit can reach every other one.
+    pub fn is_connected(&self) -> bool {
+        self.num_qubits() == 0
+            || self
+                .distance
+                .row(PhysicalQubit::new(0))
+                .iter()
+                .all(|&dist| dist != usize::MAX)
+    }
+
+    /// Fail with an `Index` error if `qubit` is not a qubit of the coupling map.
+    pub fn check_qubit(&self, qubit: PhysicalQubit) -> Result<(), MappingError> {
+        if qubit.index() < self.num_qubits() {
+            Ok(())
+        } else {
+            Err(MappingError::Index(format!(
+                "qubit index {} is out of range for {} physical qubits",
+                qubit.index(),
+                self.num_qubits()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "python")]
+    use pyo3::types::PyDict;
+
+    fn edges(edges: &[(u32, u32)]) -> Vec<[PhysicalQubit; 2]> {
+        edges
+            .iter()
+            .map(|&(a, b)| [PhysicalQubit::new(a), PhysicalQubit::new(b)])
+            .collect()
+    }
+
+    #[test]
+    fn test_coupling_map_queries() {
+        // 0 - 1 - 2 - 3, with a duplicated reverse edge and a self-loop
+        let coupling =
+            CouplingMap::new(edges(&[(0, 1), (1, 0), (1, 2), (2, 3), (3, 3)]), None).unwrap();
+        let q = PhysicalQubit::new;
+
+        assert_eq!(coupling.num_qubits(), 4);
+        assert_eq!(coupling.edges().len(), 3);
+        assert_eq!(coupling.neighbors(q(1)), &[q(0), q(2)]);
+        assert!(coupling.is_adjacent(q(2), q(1)));
+        assert!(!coupling.is_adjacent(q(0), q(2)));
+        assert_eq!(coupling.distance(q(0), q(3)), 3);
+        assert_eq!(coupling.distance(q(3), q(3)), 0);
+        assert!(coupling.is_connected());
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_coupling_map_from_graph() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            // stand-in for a rustworkx graph whose node 2 was removed
+            let locals = PyDict::new_bound(py);
+            py.run_bound(
+                r#"
+class Graph:
+    def edge_list(self):
+        return [(0, 1), (3, 1)]
+
+    def node_indices(self):
+        return [0, 1, 3]
+
+graph = Graph()
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let graph = locals.get_item("graph").unwrap().unwrap();
+            let coupling = CouplingMap::from_graph(&graph).unwrap();
+            let q = PhysicalQubit::new;
+
+            assert_eq!(coupling.num_qubits(), 4);
+            assert_eq!(coupling.distance(q(0), q(3)), 2);
+            assert_eq!(coupling.neighbors(q(2)), &[]);
+        });
+    }
+
+    #[test]
+    fn test_coupling_map_disconnected() {
+        let coupling = CouplingMap::new(edges(&[(0, 1)]), Some(3)).unwrap();
+        assert_eq!(
+            coupling.distance(PhysicalQubit::new(0), PhysicalQubit::new(2)),
+            usize::MAX
+        );
+        assert!(!coupling.is_connected());
+        assert!(CouplingMap::new(edges(&[(0, 5)]), Some(3)).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{error::MappingError, swap::Swap};
+
+/// A coupling edge keyed by its (smaller, larger) qubit indices.
+type Edge = (i32, i32);
+
+/// Pairs of coupling edges that suffer from crosstalk when driven simultaneously, used to keep
+/// feedback operations away from crosstalk-prone neighborhoods.
+///
+/// The exposure of a physical qubit is the number of crosstalk pairs with an edge touching it.
+/// When a swap moves a qubit of a cif pair, the increase of exposure between the two physical
+/// qubits is penalized, so that measured and conditioned qubits are kept where the fast feedback
+/// operations are not disturbed by neighboring gates.
+///
+/// Args:
+///     edge_pairs (list[tuple[tuple[int, int], tuple[int, int]]]): The pairs of edges that
+///         suffer from crosstalk, the direction of the edges and their order is ignored.
+///     weight (float): Multiplier applied to the crosstalk cost when it is combined with the
+///         cross-controller feedback score.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CrosstalkMap {
+    // keyed by the (smaller, larger) edges
+    pairs: HashSet<(Edge, Edge)>,
+    exposure: HashMap<i32, usize>,
+    pub weight: f64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CrosstalkMap {
+    #[new]
+    #[pyo3(signature = (edge_pairs=Vec::new(), weight=1.0))]
+    fn py_new(edge_pairs: Vec<(Edge, Edge)>, weight: f64) -> PyResult<Self> {
+        Ok(CrosstalkMap::new(edge_pairs, weight)?)
+    }
+
+    #[getter(weight)]
+    fn get_weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Record that ``edge0`` and ``edge1`` suffer from crosstalk, which does nothing if it is
+    /// already recorded.
+    ///
+    /// Raises:
+    ///     ValueError: if an edge connects a qubit to itself or both edges are the same.
+    #[pyo3(name = "add_pair", text_signature = "(self, edge0, edge1, /)")]
+    fn py_add_pair(&mut self, edge0: Edge, edge1: Edge) -> PyResult<()> {
+        Ok(self.add_pair(edge0, edge1)?)
+    }
+
+    /// Whether ``edge0`` and ``edge1`` suffer from crosstalk.
+    #[pyo3(name = "is_crosstalk", text_signature = "(self, edge0, edge1, /)")]
+    fn py_is_crosstalk(&self, edge0: Edge, edge1: Edge) -> bool {
+        self.is_crosstalk(edge0, edge1)
+    }
+
+    /// The number of crosstalk pairs with an edge touching ``qubit``.
+    #[pyo3(name = "exposure", text_signature = "(self, qubit, /)")]
+    fn py_exposure(&self, qubit: i32) -> usize {
+        self.exposure(qubit)
+    }
+
+    /// The number of crosstalk pairs.
+    fn __len__(&self) -> usize {
+        self.num_pairs()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        let mut edge_pairs: Vec<(Edge, Edge)> = self.pairs.iter().copied().collect();
+        edge_pairs.sort_unstable();
+        (py.get_type_bound::<Self>(), (edge_pairs, self.weight)).into_py(py)
+    }
+}
+
+impl CrosstalkMap {
+    pub fn new(edge_pairs: Vec<(Edge, Edge)>, weight: f64) -> Result<Self, MappingError> {
+        if !(weight.is_finite() && weight >= 0.) {
+            return Err(MappingError::Value(format!(
+                "crosstalk weight must be a non-negative finite number, got {weight}"
+            )));
+        }
+        let mut crosstalk = CrosstalkMap {
+            pairs: HashSet::with_capacity(edge_pairs.len()),
+            exposure: HashMap::new(),
+            weight,
+        };
+        for (edge0, edge1) in edge_pairs {
+            crosstalk.add_pair(edge0, edge1)?;
+        }
+        Ok(crosstalk)
+    }
+
+    pub fn add_pair(&mut self, edge0: Edge, edge1: Edge) -> Result<(), MappingError> {
+        let (edge0, edge1) = (edge_key(edge0)?, edge_key(edge1)?);
+        if edge0 == edge1 {
+            return Err(MappingError::Value(format!(
+                "an edge cannot suffer from crosstalk with itself, got {edge0:?}"
+            )));
+        }
+        if !self.pairs.insert(pair_key(edge0, edge1)) {
+            return Ok(());
+        }
+        let mut qubits = vec![edge0.0, edge0.1, edge1.0, edge1.1];
+        qubits.sort_unstable();
+        qubits.dedup();
+        for qubit in qubits {
+            *self.exposure.entry(qubit).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    pub fn is_crosstalk(&self, edge0: Edge, edge1: Edge) -> bool {
+        let key = |(q0, q1): Edge| (q0.min(q1), q0.max(q1));
+        self.pairs.contains(&pair_key(key(edge0), key(edge1)))
+    }
+
+    pub fn exposure(&self, qubit: i32) -> usize {
+        *self.exposure.get(&qubit).unwrap_or(&0)
+    }
+
+    pub fn num_pairs(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// The change of exposure of the qubits of `pair` caused by `swap`, where a qubit shared by
+    /// the conditioned and the measured side counts once.
+    pub fn exposure_delta(&self, pair: &[i32], swap: Swap) -> f64 {
+        let moved_delta = self.exposure(swap[1]) as f64 - self.exposure(swap[0]) as f64;
+        let first = pair.first().copied();
+        let second = pair.get(1).copied().filter(|&qubit| Some(qubit) != first);
+        first
+            .into_iter()
+            .chain(second)
+            .map(|qubit| {
+                if qubit == swap[0] {
+                    moved_delta
+                } else if qubit == swap[1] {
+                    -moved_delta
+                } else {
+                    0.
+                }
+            })
+            .sum()
+    }
+}
+
+#[inline]
+fn pair_key(edge0: Edge, edge1: Edge) -> (Edge, Edge) {
+    (edge0.min(edge1), edge0.max(edge1))
+}
+
+fn edge_key((q0, q1): Edge) -> Result<Edge, MappingError> {
+    if q0 == q1 {
+        return Err(MappingError::Value(format!(
+            "an edge must connect two different qubits, got ({q0}, {q1})"
+        )));
+    }
+    Ok((q0.min(q1), q0.max(q1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crosstalk_map() {
+        let mut crosstalk =
+            CrosstalkMap::new(vec![((1, 0), (2, 3)), ((1, 2), (0, 1))], 1.).unwrap();
+        assert!(crosstalk.is_crosstalk((3, 2), (0, 1)));
+        assert!(!crosstalk.is_crosstalk((0, 1), (3, 4)));
+        assert_eq!(crosstalk.num_pairs(), 2);
+        assert_eq!(
+            (0..5).map(|q| crosstalk.exposure(q)).collect::<Vec<_>>(),
+            vec![2, 2, 2, 1, 0]
+        );
+        // already recorded
+        crosstalk.add_pair((3, 2), (1, 0)).unwrap();
+        assert_eq!(crosstalk.exposure(3), 1);
+
+        // moving the measured qubit from 3 to 4 lowers its exposure by one
+        assert_eq!(
+            crosstalk.exposure_delta(&[0, 3], Swap::new(3, 4).unwrap()),
+            -1.
+        );
+        assert_eq!(
+            crosstalk.exposure_delta(&[4, 4], Swap::new(3, 4).unwrap()),
+            1.
+        );
+        assert_eq!(
+            crosstalk.exposure_delta(&[0, 1], Swap::new(3, 4).unwrap()),
+            0.
+        );
+
+        assert!(crosstalk.add_pair((0, 0), (1, 2)).is_err());
+        assert!(crosstalk.add_pair((0, 1), (1, 0)).is_err());
+        assert!(CrosstalkMap::new(Vec::new(), f64::NAN).is_err());
+    }
+}
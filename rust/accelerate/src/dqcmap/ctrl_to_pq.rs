@@ -1,21 +1,56 @@
+#[cfg(feature = "python")]
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
 use hashbrown::HashMap;
-use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList};
+use ndarray::ArrayView1;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "python")]
+use {
+    hashbrown::HashSet,
+    numpy::{AllowTypeChange, PyArrayLike1},
+    pyo3::exceptions::PyValueError,
+    pyo3::prelude::*,
+    pyo3::types::{PyDict, PyList},
+};
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    error::MappingError,
+    memory::{map_bytes, vec_bytes},
+};
+
+/// A control channel of a backend, by number or by name, e.g. ``"d0"``.
+#[cfg_attr(feature = "python", derive(FromPyObject))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    Index(i64),
+    Name(String),
+}
 
-#[pyclass(module = "dqcmap._accelerate.dqcmap")]
-#[derive(Clone, Debug)]
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ctrl2Pq {
+    // All maps are copy-on-write, so that clones passed to concurrent routing calls share them
+    // until one of them is modified.
     // mapping between controller id and the list of physical qubit indexes
     // this controller connects to
-    pub map: HashMap<i32, Vec<i32>>,
+    pub map: Arc<HashMap<i32, Vec<i32>>>,
     // mapping between (physical) qubit index and controller id
-    pub reverse_map: HashMap<i32, i32>,
+    pub reverse_map: Arc<HashMap<i32, i32>>,
+    // maximum number of qubits (AWG channels) of a controller, unbounded if missing
+    pub capacity: Arc<HashMap<i32, usize>>,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl Ctrl2Pq {
     #[new]
-    fn new(obj: Bound<PyDict>) -> PyResult<Self> {
+    #[pyo3(signature = (obj, capacity=None))]
+    fn new(obj: Bound<PyDict>, capacity: Option<HashMap<i32, usize>>) -> PyResult<Self> {
         let mut map = HashMap::new();
         let mut reverse_map = HashMap::new();
         for (k, v) in obj.iter() {
@@ -32,23 +67,490 @@ impl Ctrl2Pq {
             map.insert(ctrl_id, vec);
         }
 
-        Ok(Ctrl2Pq { map, reverse_map })
+        let mut ctrl2pq = Ctrl2Pq {
+            map: Arc::new(map),
+            reverse_map: Arc::new(reverse_map),
+            capacity: Arc::default(),
+        };
+        for (ctrl_id, capacity) in capacity.unwrap_or_default() {
+            ctrl2pq.set_capacity(ctrl_id, Some(capacity))?;
+        }
+        Ok(ctrl2pq)
+    }
+
+    /// Limit controller ``ctrl_id`` to ``capacity`` qubits, e.g. its number of AWG channels, or
+    /// lift its limit if ``capacity`` is ``None``.
+    ///
+    /// Raises:
+    ///     ValueError: if the controller already has more qubits than ``capacity``.
+    #[pyo3(name = "set_capacity", text_signature = "(self, ctrl_id, capacity, /)")]
+    fn py_set_capacity(&mut self, ctrl_id: i32, capacity: Option<usize>) -> PyResult<()> {
+        Ok(self.set_capacity(ctrl_id, capacity)?)
+    }
+
+    /// The maximum number of qubits of controller ``ctrl_id``, ``None`` if it is unbounded.
+    #[pyo3(name = "get_capacity", text_signature = "(self, ctrl_id, /)")]
+    fn py_get_capacity(&self, ctrl_id: i32) -> Option<usize> {
+        self.get_capacity(ctrl_id)
+    }
+
+    /// The number of qubits that can still be connected to controller ``ctrl_id``, ``None`` if it
+    /// is unbounded.
+    #[pyo3(name = "free_capacity", text_signature = "(self, ctrl_id, /)")]
+    fn py_free_capacity(&self, ctrl_id: i32) -> Option<usize> {
+        self.free_capacity(ctrl_id)
+    }
+
+    /// The id of the controller connected to physical qubit ``qubit_idx``, if any.
+    #[pyo3(
+        name = "get_controller_by_qubit",
+        text_signature = "(self, qubit_idx, /)"
+    )]
+    fn py_get_controller_by_qubit(&self, qubit_idx: i32) -> Option<i32> {
+        self.get_controller_by_qubit(qubit_idx).copied()
+    }
+
+    /// The physical qubits connected to controller ``ctrl_id``, if it exists.
+    #[pyo3(
+        name = "get_qubits_by_controller",
+        text_signature = "(self, ctrl_id, /)"
+    )]
+    fn py_get_qubits_by_controller(&self, ctrl_id: i32) -> Option<Vec<i32>> {
+        self.get_qubits_by_controller(ctrl_id)
+            .map(|qubits| qubits.to_vec())
+    }
+
+    /// The number of controllers.
+    #[pyo3(name = "num_controllers", text_signature = "(self, /)")]
+    fn py_num_controllers(&self) -> usize {
+        self.num_controllers()
+    }
+
+    /// The number of physical qubits connected to a controller.
+    #[pyo3(name = "num_qubits", text_signature = "(self, /)")]
+    fn py_num_qubits(&self) -> usize {
+        self.num_qubits()
+    }
+
+    /// The sorted list of controller ids.
+    #[pyo3(name = "controllers", text_signature = "(self, /)")]
+    fn py_controllers(&self) -> Vec<i32> {
+        self.controllers()
+    }
+
+    /// Serialize the mapping to a JSON object from controller ids to their physical qubits, with
+    /// the controller ids sorted so that the output is stable.  Capacities are not included.
+    #[pyo3(name = "to_json", text_signature = "(self, /)")]
+    fn py_to_json(&self) -> PyResult<String> {
+        Ok(self.to_json()?)
+    }
+
+    /// Build a mapping from two equally long integer arrays, connecting physical qubit
+    /// ``qubits[i]`` to controller ``ctrl_ids[i]``.  ``int64`` arrays are read without copying,
+    /// other integer arrays are converted.
+    ///
+    /// Raises:
+    ///     ValueError: if the arrays differ in length, a value does not fit in 32 bits or a qubit
+    ///         appears twice.
+    #[staticmethod]
+    #[pyo3(text_signature = "(qubits, ctrl_ids, /)")]
+    fn from_arrays(
+        qubits: PyArrayLike1<i64, AllowTypeChange>,
+        ctrl_ids: PyArrayLike1<i64, AllowTypeChange>,
+    ) -> PyResult<Self> {
+        Ok(Ctrl2Pq::from_columns(
+            qubits.as_array(),
+            ctrl_ids.as_array(),
+        )?)
+    }
+
+    /// Derive the controllers of a backend from its configuration, e.g.
+    /// ``backend.configuration().to_dict()``.
+    ///
+    /// The qubits of every group of the ``meas_map``, which are read out together, share a
+    /// controller, and the qubits below ``n_qubits`` missing from the ``meas_map`` share one more.
+    /// Without a ``meas_map`` all ``n_qubits`` qubits form a single group.  With
+    /// ``qubits_per_controller``, e.g. the number of AWG channels of the control electronics,
+    /// every group is split into controllers of at most that many qubits in increasing order, and
+    /// it becomes their capacity.  Controllers are numbered from 0 in group order.
+    ///
+    /// Raises:
+    ///     ValueError: if the configuration has neither ``meas_map`` nor ``n_qubits``, a qubit
+    ///         appears in two groups or ``qubits_per_controller`` is 0.
+    #[staticmethod]
+    #[pyo3(
+        signature = (configuration, qubits_per_controller=None),
+        text_signature = "(configuration, /, qubits_per_controller=None)"
+    )]
+    pub fn from_backend_configuration(
+        configuration: Bound<PyDict>,
+        qubits_per_controller: Option<usize>,
+    ) -> PyResult<Self> {
+        let n_qubits: Option<i32> = match configuration.get_item("n_qubits")? {
+            Some(n_qubits) => n_qubits.extract()?,
+            None => None,
+        };
+        let meas_map: Option<Vec<Vec<i32>>> = match configuration.get_item("meas_map")? {
+            Some(meas_map) => meas_map.extract()?,
+            None => None,
+        };
+        let mut groups = match (meas_map, n_qubits) {
+            (Some(meas_map), _) => meas_map,
+            (None, Some(_)) => Vec::new(),
+            (None, None) => {
+                return Err(PyValueError::new_err(
+                    "the backend configuration has neither a meas_map nor n_qubits",
+                ))
+            }
+        };
+        if let Some(n_qubits) = n_qubits {
+            let listed: HashSet<i32> = groups.iter().flatten().copied().collect();
+            groups.push((0..n_qubits).filter(|q| !listed.contains(q)).collect());
+        }
+        Ok(Ctrl2Pq::from_groups(&groups, qubits_per_controller)?)
+    }
+
+    /// Group the qubits driven through the same channel, given as a ``{qubit: channel}`` dict
+    /// with channel numbers or names, onto one controller each.  Controllers are numbered from 0
+    /// in the sorted order of their channels.
+    #[staticmethod]
+    #[pyo3(name = "from_channel_map", text_signature = "(channels, /)")]
+    fn py_from_channel_map(channels: HashMap<i32, Channel>) -> PyResult<Self> {
+        Ok(Ctrl2Pq::from_channel_map(channels)?)
+    }
+
+    /// A mapping without any controller, to be filled with ``assign``.
+    #[staticmethod]
+    #[pyo3(name = "empty", text_signature = "()")]
+    fn py_empty() -> Self {
+        Ctrl2Pq::empty()
+    }
+
+    /// Load a mapping from the output of ``to_json``.
+    #[staticmethod]
+    #[pyo3(name = "from_json", text_signature = "(json, /)")]
+    fn py_from_json(json: &str) -> PyResult<Self> {
+        Ok(Ctrl2Pq::from_json(json)?)
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (HashMap::clone(&self.map), HashMap::clone(&self.capacity)),
+        )
+            .into_py(py)
+    }
+
+    /// Mappings are equal if they connect the same qubits, in the same order, to the same
+    /// controllers with the same capacities.
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    /// A hash of the content of the mapping, which changes as the mapping is modified.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Whether ``ctrl_id`` is a controller of this mapping.
+    fn __contains__(&self, ctrl_id: i32) -> bool {
+        self.map.contains_key(&ctrl_id)
+    }
+
+    /// Connect the unassigned physical qubit ``qubit`` to controller ``ctrl_id``, which is created
+    /// if it does not exist yet.
+    ///
+    /// Raises:
+    ///     ValueError: if the qubit is already connected to a controller or the controller is at
+    ///         its capacity.
+    #[pyo3(name = "assign_qubit", text_signature = "(self, qubit, ctrl_id, /)")]
+    fn py_assign_qubit(&mut self, qubit: i32, ctrl_id: i32) -> PyResult<()> {
+        Ok(self.assign_qubit(qubit, ctrl_id)?)
+    }
+
+    /// Disconnect ``qubit`` from its controller and return the id of that controller.  The
+    /// controller is kept even if it has no qubit left.
+    ///
+    /// Raises:
+    ///     ValueError: if the qubit is not connected to any controller.
+    #[pyo3(name = "remove_qubit", text_signature = "(self, qubit, /)")]
+    fn py_remove_qubit(&mut self, qubit: i32) -> PyResult<i32> {
+        Ok(self.remove_qubit(qubit)?)
+    }
+
+    /// Reconnect ``qubit`` to controller ``new_ctrl_id`` and return the id of its previous
+    /// controller.
+    ///
+    /// Raises:
+    ///     ValueError: if the qubit is not connected to any controller or the new controller is at
+    ///         its capacity.
+    #[pyo3(name = "move_qubit", text_signature = "(self, qubit, new_ctrl_id, /)")]
+    fn py_move_qubit(&mut self, qubit: i32, new_ctrl_id: i32) -> PyResult<i32> {
+        Ok(self.move_qubit(qubit, new_ctrl_id)?)
+    }
+
+    /// Connect ``qubit`` to controller ``ctrl_id`` whether or not it is connected yet, moving it
+    /// from its previous controller if any, so that a mapping can be filled while traversing a
+    /// device without tracking which qubits were seen.
+    ///
+    /// Raises:
+    ///     ValueError: if the controller is at its capacity.
+    #[pyo3(name = "assign", text_signature = "(self, qubit, ctrl_id, /)")]
+    fn py_assign(&mut self, qubit: i32, ctrl_id: i32) -> PyResult<()> {
+        Ok(self.assign(qubit, ctrl_id)?)
+    }
+}
+
+// The reverse map is derived from the map, so only the map and the capacities are compared.
+impl PartialEq for Ctrl2Pq {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map && self.capacity == other.capacity
+    }
+}
+
+impl Eq for Ctrl2Pq {}
+
+impl Hash for Ctrl2Pq {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // in controller order, so that the hash does not depend on the hasher of the maps
+        self.map.iter().collect::<BTreeMap<_, _>>().hash(state);
+        self.capacity.iter().collect::<BTreeMap<_, _>>().hash(state);
     }
 }
 
 // Non-python methods
 impl Ctrl2Pq {
+    pub fn set_capacity(
+        &mut self,
+        ctrl_id: i32,
+        capacity: Option<usize>,
+    ) -> Result<(), MappingError> {
+        let Some(capacity) = capacity else {
+            Arc::make_mut(&mut self.capacity).remove(&ctrl_id);
+            return Ok(());
+        };
+        let num_qubits = self.map.get(&ctrl_id).map_or(0, Vec::len);
+        if num_qubits > capacity {
+            return Err(MappingError::Value(format!(
+                "controller {ctrl_id} has {num_qubits} qubits, more than its capacity of {capacity}"
+            )));
+        }
+        Arc::make_mut(&mut self.capacity).insert(ctrl_id, capacity);
+        Ok(())
+    }
+
+    pub fn get_capacity(&self, ctrl_id: i32) -> Option<usize> {
+        self.capacity.get(&ctrl_id).copied()
+    }
+
+    pub fn free_capacity(&self, ctrl_id: i32) -> Option<usize> {
+        let num_qubits = self.map.get(&ctrl_id).map_or(0, Vec::len);
+        self.get_capacity(ctrl_id)
+            .map(|capacity| capacity.saturating_sub(num_qubits))
+    }
+
+    pub fn num_controllers(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.reverse_map.len()
+    }
+
+    pub fn controllers(&self) -> Vec<i32> {
+        let mut controllers: Vec<i32> = self.map.keys().copied().collect();
+        controllers.sort_unstable();
+        controllers
+    }
+
+    pub fn to_json(&self) -> Result<String, MappingError> {
+        let sorted: BTreeMap<&i32, &Vec<i32>> = self.map.iter().collect();
+        serde_json::to_string(&sorted).map_err(|err| MappingError::Value(err.to_string()))
+    }
+
+    pub fn from_channel_map(channels: HashMap<i32, Channel>) -> Result<Self, MappingError> {
+        let mut groups: BTreeMap<Channel, Vec<i32>> = BTreeMap::new();
+        for (qubit, channel) in channels {
+            groups.entry(channel).or_default().push(qubit);
+        }
+        let groups: Vec<Vec<i32>> = groups
+            .into_values()
+            .map(|mut qubits| {
+                qubits.sort_unstable();
+                qubits
+            })
+            .collect();
+        Ctrl2Pq::from_groups(&groups, None)
+    }
+
+    pub fn empty() -> Self {
+        Ctrl2Pq::from_map(HashMap::new())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MappingError> {
+        let map: BTreeMap<i32, Vec<i32>> = serde_json::from_str(json)
+            .map_err(|err| MappingError::Value(format!("invalid Ctrl2Pq JSON: {err}")))?;
+        Ok(Ctrl2Pq::from_map(map.into_iter().collect()))
+    }
+
+    pub fn assign_qubit(&mut self, qubit: i32, ctrl_id: i32) -> Result<(), MappingError> {
+        if let Some(ctrl) = self.reverse_map.get(&qubit) {
+            return Err(MappingError::Value(format!(
+                "qubit {qubit} is already connected to controller {ctrl}"
+            )));
+        }
+        self.check_free_capacity(ctrl_id)?;
+        Arc::make_mut(&mut self.map)
+            .entry(ctrl_id)
+            .or_default()
+            .push(qubit);
+        Arc::make_mut(&mut self.reverse_map).insert(qubit, ctrl_id);
+        Ok(())
+    }
+
+    pub fn remove_qubit(&mut self, qubit: i32) -> Result<i32, MappingError> {
+        let ctrl_id = Arc::make_mut(&mut self.reverse_map)
+            .remove(&qubit)
+            .ok_or_else(|| {
+                MappingError::Value(format!("qubit {qubit} is not connected to any controller"))
+            })?;
+        if let Some(qubits) = Arc::make_mut(&mut self.map).get_mut(&ctrl_id) {
+            qubits.retain(|&q| q != qubit);
+        }
+        Ok(ctrl_id)
+    }
+
+    pub fn move_qubit(&mut self, qubit: i32, new_ctrl_id: i32) -> Result<i32, MappingError> {
+        if self.reverse_map.get(&qubit) != Some(&new_ctrl_id) {
+            // check before disconnecting, so that a failed move leaves the mapping untouched
+            self.check_free_capacity(new_ctrl_id)?;
+        }
+        let old_ctrl_id = self.remove_qubit(qubit)?;
+        self.assign_qubit(qubit, new_ctrl_id)?;
+        Ok(old_ctrl_id)
+    }
+
+    pub fn assign(&mut self, qubit: i32, ctrl_id: i32) -> Result<(), MappingError> {
+        if self.reverse_map.contains_key(&qubit) {
+            self.move_qubit(qubit, ctrl_id)?;
+            Ok(())
+        } else {
+            self.assign_qubit(qubit, ctrl_id)
+        }
+    }
+
+    /// An estimate of the heap memory held by the maps.
+    pub fn allocated_bytes(&self) -> usize {
+        map_bytes(&self.map)
+            + self.map.values().map(vec_bytes).sum::<usize>()
+            + map_bytes(&self.reverse_map)
+            + map_bytes(&self.capacity)
+    }
+
+    /// Build the mapping from the physical qubits of every controller.
+    pub fn from_map(map: HashMap<i32, Vec<i32>>) -> Self {
+        let reverse_map = map
+            .iter()
+            .flat_map(|(&ctrl_id, qubits)| qubits.iter().map(move |&qubit| (qubit, ctrl_id)))
+            .collect();
+        Ctrl2Pq {
+            map: Arc::new(map),
+            reverse_map: Arc::new(reverse_map),
+            capacity: Arc::default(),
+        }
+    }
+
+    /// Connect every group of qubits to a controller of its own, numbered from 0, or to as many
+    /// controllers as needed to hold at most `qubits_per_controller` qubits each, which then
+    /// becomes their capacity.  Empty groups are skipped.
+    pub fn from_groups(
+        groups: &[Vec<i32>],
+        qubits_per_controller: Option<usize>,
+    ) -> Result<Self, MappingError> {
+        if qubits_per_controller == Some(0) {
+            return Err(MappingError::Value(
+                "qubits_per_controller must be positive".to_string(),
+            ));
+        }
+        let mut ctrl2pq = Ctrl2Pq::from_map(HashMap::new());
+        let mut ctrl_id = 0;
+        for group in groups {
+            let chunk_size = qubits_per_controller.unwrap_or(group.len().max(1));
+            for chunk in group.chunks(chunk_size) {
+                for &qubit in chunk {
+                    ctrl2pq.assign_qubit(qubit, ctrl_id)?;
+                }
+                ctrl2pq.set_capacity(ctrl_id, qubits_per_controller)?;
+                ctrl_id += 1;
+            }
+        }
+        Ok(ctrl2pq)
+    }
+
+    fn check_free_capacity(&self, ctrl_id: i32) -> Result<(), MappingError> {
+        if self.free_capacity(ctrl_id) == Some(0) {
+            return Err(MappingError::Value(format!(
+                "controller {ctrl_id} is at its capacity of {} qubits",
+                self.capacity[&ctrl_id]
+            )));
+        }
+        Ok(())
+    }
+
+    /// See [Ctrl2Pq::from_arrays].
+    pub fn from_columns(
+        qubits: ArrayView1<i64>,
+        ctrl_ids: ArrayView1<i64>,
+    ) -> Result<Self, MappingError> {
+        if qubits.len() != ctrl_ids.len() {
+            return Err(MappingError::Value(format!(
+                "qubits and ctrl_ids must have the same length, got {} and {}",
+                qubits.len(),
+                ctrl_ids.len()
+            )));
+        }
+        let to_i32 = |value: i64| {
+            i32::try_from(value)
+                .map_err(|_| MappingError::Value(format!("{value} does not fit in 32 bits")))
+        };
+        let mut ctrl2pq = Ctrl2Pq {
+            map: Arc::default(),
+            reverse_map: Arc::new(HashMap::with_capacity(qubits.len())),
+            capacity: Arc::default(),
+        };
+        for (&qubit, &ctrl_id) in qubits.iter().zip(&ctrl_ids) {
+            ctrl2pq.assign_qubit(to_i32(qubit)?, to_i32(ctrl_id)?)?;
+        }
+        Ok(ctrl2pq)
+    }
+
     pub fn get_controller_by_qubit(&self, qubit_idx: i32) -> Option<&i32> {
         self.reverse_map.get(&qubit_idx)
     }
+
+    /// Like [Ctrl2Pq::get_controller_by_qubit], typed so that routing code cannot look up the
+    /// controller of a virtual qubit by mistake.
+    #[inline]
+    pub fn controller_of(&self, qubit: PhysicalQubit) -> Option<i32> {
+        self.reverse_map.get(&(qubit.index() as i32)).copied()
+    }
+
+    pub fn get_qubits_by_controller(&self, ctrl_id: i32) -> Option<&[i32]> {
+        self.map.get(&ctrl_id).map(Vec::as_slice)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "python")]
     use pyo3::types::IntoPyDict;
+    #[cfg(feature = "python")]
     use pyo3::Python;
 
+    #[cfg(feature = "python")]
     #[test]
     fn test_ctrl2pq_creation() {
         pyo3::prepare_freethreaded_python();
@@ -58,11 +560,177 @@ mod tests {
             let py_dict = data.into_py_dict_bound(py);
 
             // Create an instance of Ctrl2Pq
-            let ctrl2pq = Ctrl2Pq::new(py_dict).unwrap();
+            let ctrl2pq = Ctrl2Pq::new(py_dict, None).unwrap();
 
             // Check if the mapping is correct
             assert_eq!(ctrl2pq.map.get(&1), Some(&vec![1, 2, 3]));
             assert_eq!(ctrl2pq.map.get(&2), Some(&vec![4, 5, 6]));
+
+            assert_eq!(ctrl2pq.get_qubits_by_controller(2), Some(&[4, 5, 6][..]));
+            assert_eq!(ctrl2pq.get_qubits_by_controller(3), None);
+            assert_eq!(ctrl2pq.controller_of(PhysicalQubit::new(5)), Some(2));
+            assert_eq!(ctrl2pq.controller_of(PhysicalQubit::new(7)), None);
+            assert_eq!(ctrl2pq.num_controllers(), 2);
+            assert_eq!(ctrl2pq.num_qubits(), 6);
+            assert_eq!(ctrl2pq.controllers(), vec![1, 2]);
+            assert!(ctrl2pq.__contains__(1));
+            assert!(!ctrl2pq.__contains__(4));
         });
     }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_ctrl2pq_eq_and_hash() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let same = Ctrl2Pq::from_map(HashMap::from([(1, vec![2, 3]), (0, vec![0, 1])]));
+        assert!(ctrl2pq.__eq__(&same));
+        assert_eq!(ctrl2pq.__hash__(), same.__hash__());
+
+        let mut limited = same.clone();
+        limited.set_capacity(0, Some(2)).unwrap();
+        assert_ne!(ctrl2pq, limited);
+        let reordered = Ctrl2Pq::from_map(HashMap::from([(0, vec![1, 0]), (1, vec![2, 3])]));
+        assert_ne!(ctrl2pq, reordered);
+        assert_ne!(ctrl2pq.__hash__(), reordered.__hash__());
+    }
+
+    #[test]
+    fn test_ctrl2pq_from_columns() {
+        let qubits = ndarray::arr1(&[0, 1, 2]);
+        let ctrl2pq =
+            Ctrl2Pq::from_columns(qubits.view(), ndarray::arr1(&[5, 6, 5]).view()).unwrap();
+        assert_eq!(ctrl2pq.get_qubits_by_controller(5), Some(&[0, 2][..]));
+        assert_eq!(ctrl2pq.get_controller_by_qubit(1), Some(&6));
+
+        // a qubit connected to two controllers
+        let duplicated = ndarray::arr1(&[0, 0]);
+        assert!(Ctrl2Pq::from_columns(duplicated.view(), duplicated.view()).is_err());
+        assert!(Ctrl2Pq::from_columns(qubits.view(), duplicated.view()).is_err());
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_ctrl2pq_from_backend_configuration() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let configuration = PyDict::new_bound(py);
+            configuration.set_item("n_qubits", 7).unwrap();
+            configuration
+                .set_item("meas_map", vec![vec![0, 1, 2, 3], vec![4]])
+                .unwrap();
+            let ctrl2pq = Ctrl2Pq::from_backend_configuration(configuration.clone(), None).unwrap();
+            assert_eq!(ctrl2pq.get_qubits_by_controller(0), Some(&[0, 1, 2, 3][..]));
+            assert_eq!(ctrl2pq.get_qubits_by_controller(2), Some(&[5, 6][..]));
+            assert_eq!(ctrl2pq.get_capacity(0), None);
+
+            let ctrl2pq =
+                Ctrl2Pq::from_backend_configuration(configuration.clone(), Some(3)).unwrap();
+            assert_eq!(ctrl2pq.controllers(), vec![0, 1, 2, 3]);
+            assert_eq!(ctrl2pq.get_qubits_by_controller(1), Some(&[3][..]));
+            assert_eq!(ctrl2pq.get_controller_by_qubit(4), Some(&2));
+            assert_eq!(ctrl2pq.get_capacity(3), Some(3));
+            assert!(Ctrl2Pq::from_backend_configuration(configuration, Some(0)).is_err());
+
+            assert!(Ctrl2Pq::from_backend_configuration(PyDict::new_bound(py), None).is_err());
+            let overlapping = PyDict::new_bound(py);
+            overlapping
+                .set_item("meas_map", vec![vec![0, 1], vec![1]])
+                .unwrap();
+            assert!(Ctrl2Pq::from_backend_configuration(overlapping, None).is_err());
+        });
+    }
+
+    #[test]
+    fn test_ctrl2pq_from_channel_map() {
+        let ctrl2pq = Ctrl2Pq::from_channel_map(HashMap::from([
+            (0, Channel::Name("u1".to_string())),
+            (1, Channel::Name("u0".to_string())),
+            (2, Channel::Name("u1".to_string())),
+            (3, Channel::Index(4)),
+        ]))
+        .unwrap();
+        // numbered channels sort before named ones
+        assert_eq!(ctrl2pq.get_qubits_by_controller(0), Some(&[3][..]));
+        assert_eq!(ctrl2pq.get_qubits_by_controller(1), Some(&[1][..]));
+        assert_eq!(ctrl2pq.get_qubits_by_controller(2), Some(&[0, 2][..]));
+    }
+
+    #[test]
+    fn test_ctrl2pq_capacity() {
+        let mut ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(1, vec![1, 2]), (2, vec![3])]));
+        assert!(ctrl2pq.set_capacity(1, Some(1)).is_err());
+        ctrl2pq.set_capacity(1, Some(2)).unwrap();
+        ctrl2pq.set_capacity(2, Some(2)).unwrap();
+        assert_eq!(ctrl2pq.get_capacity(1), Some(2));
+        assert_eq!(ctrl2pq.free_capacity(2), Some(1));
+        assert_eq!(ctrl2pq.free_capacity(3), None);
+
+        assert!(ctrl2pq.assign_qubit(4, 1).is_err());
+        // a failed move keeps the qubit on its controller
+        assert!(ctrl2pq.move_qubit(3, 1).is_err());
+        assert_eq!(ctrl2pq.get_controller_by_qubit(3), Some(&2));
+        assert_eq!(ctrl2pq.move_qubit(1, 1).unwrap(), 1);
+        ctrl2pq.assign_qubit(4, 2).unwrap();
+        assert!(ctrl2pq.assign_qubit(5, 2).is_err());
+
+        ctrl2pq.set_capacity(2, None).unwrap();
+        ctrl2pq.assign_qubit(5, 2).unwrap();
+    }
+
+    #[test]
+    fn test_ctrl2pq_assign() {
+        let mut ctrl2pq = Ctrl2Pq::empty();
+        assert_eq!(ctrl2pq.num_controllers(), 0);
+        ctrl2pq.assign(0, 1).unwrap();
+        ctrl2pq.assign(1, 1).unwrap();
+        ctrl2pq.assign(2, 0).unwrap();
+        // assigning again moves the qubit
+        ctrl2pq.assign(1, 0).unwrap();
+        ctrl2pq.assign(1, 0).unwrap();
+        assert_eq!(
+            ctrl2pq,
+            Ctrl2Pq::from_map(HashMap::from([(0, vec![2, 1]), (1, vec![0])]))
+        );
+
+        ctrl2pq.set_capacity(1, Some(1)).unwrap();
+        assert!(ctrl2pq.assign(2, 1).is_err());
+        assert_eq!(ctrl2pq.get_controller_by_qubit(2), Some(&0));
+    }
+
+    #[test]
+    fn test_ctrl2pq_json_round_trip() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(2, vec![3]), (1, vec![1, 2])]));
+        let json = ctrl2pq.to_json().unwrap();
+        assert_eq!(json, r#"{"1":[1,2],"2":[3]}"#);
+
+        let loaded = Ctrl2Pq::from_json(&json).unwrap();
+        assert_eq!(loaded.map, ctrl2pq.map);
+        assert_eq!(loaded.get_controller_by_qubit(2), Some(&1));
+        assert!(Ctrl2Pq::from_json("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_ctrl2pq_reassign_qubits() {
+        let mut ctrl2pq = Ctrl2Pq {
+            map: HashMap::from([(1, vec![1, 2]), (2, vec![3])]).into(),
+            reverse_map: HashMap::from([(1, 1), (2, 1), (3, 2)]).into(),
+            capacity: HashMap::new().into(),
+        };
+
+        assert_eq!(ctrl2pq.move_qubit(2, 2).unwrap(), 1);
+        assert_eq!(ctrl2pq.get_controller_by_qubit(2), Some(&2));
+        assert_eq!(ctrl2pq.get_qubits_by_controller(1), Some(&[1][..]));
+        assert_eq!(ctrl2pq.get_qubits_by_controller(2), Some(&[3, 2][..]));
+
+        ctrl2pq.assign_qubit(4, 3).unwrap();
+        assert_eq!(ctrl2pq.controllers(), vec![1, 2, 3]);
+        assert!(ctrl2pq.assign_qubit(4, 1).is_err());
+
+        assert_eq!(ctrl2pq.remove_qubit(1).unwrap(), 1);
+        assert_eq!(ctrl2pq.get_controller_by_qubit(1), None);
+        assert_eq!(ctrl2pq.get_qubits_by_controller(1), Some(&[][..]));
+        assert!(ctrl2pq.remove_qubit(1).is_err());
+        assert!(ctrl2pq.move_qubit(1, 2).is_err());
+        assert_eq!(ctrl2pq.num_qubits(), 3);
+    }
 }
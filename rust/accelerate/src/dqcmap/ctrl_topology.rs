@@ -0,0 +1,501 @@
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{cif_pairs::CifPairs, ctrl_to_pq::Ctrl2Pq, error::MappingError};
+
+/// Latency model of the classical links between controllers.
+///
+/// A feedback between two qubits on the same controller is free, while a feedback between two
+/// different controllers costs the latency of the link between them.  Controller pairs without an
+/// explicit latency cost ``default_latency``.  Latencies are symmetric.
+///
+/// The bandwidth of a controller is the maximum number of cross-controller feedback transfers it
+/// can send or receive concurrently, the links saturating beyond it.  Controllers without an
+/// explicit bandwidth have ``default_bandwidth``, and are unlimited if it is ``None``.
+///
+/// Args:
+///     latency (dict[tuple[int, int], float]): The cost of a feedback between two controllers.
+///     default_latency (float): The cost of controller pairs that are not in ``latency``.
+///     bandwidth (dict[int, int]): The maximum number of concurrent feedback transfers of a
+///         controller.
+///     default_bandwidth (int | None): The bandwidth of controllers that are not in
+///         ``bandwidth``.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CtrlTopology {
+    // latency between two controllers, keyed by the (smaller, larger) controller ids
+    pub latency: HashMap<(i32, i32), f64>,
+    pub default_latency: f64,
+    pub bandwidth: HashMap<i32, usize>,
+    pub default_bandwidth: Option<usize>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CtrlTopology {
+    #[new]
+    #[pyo3(signature = (latency=HashMap::new(), default_latency=1.0, bandwidth=HashMap::new(), default_bandwidth=None))]
+    fn py_new(
+        latency: HashMap<(i32, i32), f64>,
+        default_latency: f64,
+        bandwidth: HashMap<i32, usize>,
+        default_bandwidth: Option<usize>,
+    ) -> PyResult<Self> {
+        Ok(CtrlTopology::new(
+            latency,
+            default_latency,
+            bandwidth,
+            default_bandwidth,
+        )?)
+    }
+
+    #[getter(default_latency)]
+    fn get_default_latency(&self) -> f64 {
+        self.default_latency
+    }
+
+    #[getter(default_bandwidth)]
+    fn get_default_bandwidth(&self) -> Option<usize> {
+        self.default_bandwidth
+    }
+
+    #[setter(default_bandwidth)]
+    fn set_default_bandwidth(&mut self, default_bandwidth: Option<usize>) {
+        self.default_bandwidth = default_bandwidth;
+    }
+
+    /// Build a topology from a rustworkx ``PyGraph`` or ``PyDiGraph`` whose node indices are the
+    /// controller ids and whose edges are the links between them.
+    ///
+    /// Args:
+    ///     graph (PyGraph | PyDiGraph): The interconnect of the controllers.
+    ///     default_latency (float): The cost of controller pairs without an edge.
+    ///     weight_fn (Callable[[Any], float]): Maps an edge payload to the latency of its link.
+    ///         The payloads must be floats if not given.
+    #[staticmethod]
+    #[pyo3(
+        signature = (graph, default_latency=1.0, weight_fn=None),
+        text_signature = "(graph, /, default_latency=1.0, weight_fn=None)"
+    )]
+    pub fn from_graph(
+        graph: &Bound<PyAny>,
+        default_latency: f64,
+        weight_fn: Option<&Bound<PyAny>>,
+    ) -> PyResult<Self> {
+        let mut topology =
+            CtrlTopology::new(HashMap::new(), default_latency, HashMap::new(), None)?;
+        let edges: Vec<(i32, i32, Bound<PyAny>)> =
+            graph.call_method0("weighted_edge_list")?.extract()?;
+        for (ctrl0, ctrl1, weight) in edges {
+            let latency: f64 = match weight_fn {
+                Some(weight_fn) => weight_fn.call1((weight,))?.extract()?,
+                None => weight.extract()?,
+            };
+            topology.set_latency(ctrl0, ctrl1, latency)?;
+        }
+        Ok(topology)
+    }
+
+    /// Build a topology from the classical network between the controllers, where not all
+    /// controllers are directly linked and a feedback between two controllers costs the number
+    /// of links on a shortest path between them times ``hop_latency``.
+    ///
+    /// Args:
+    ///     links (list[tuple[int, int]]): The direct links between the controllers.
+    ///     hop_latency (float): The cost of a feedback over a single link.
+    ///     default_latency (float | None): The cost of a feedback between controllers that are
+    ///         not connected by the links, by default one hop more than the longest possible
+    ///         path.
+    #[staticmethod]
+    #[pyo3(
+        name = "from_links",
+        signature = (links, hop_latency=1.0, default_latency=None),
+        text_signature = "(links, /, hop_latency=1.0, default_latency=None)"
+    )]
+    fn py_from_links(
+        links: Vec<(i32, i32)>,
+        hop_latency: f64,
+        default_latency: Option<f64>,
+    ) -> PyResult<Self> {
+        Ok(CtrlTopology::from_links(
+            links,
+            hop_latency,
+            default_latency,
+        )?)
+    }
+
+    /// Set the latency of the link between ``ctrl0`` and ``ctrl1``.
+    #[pyo3(
+        name = "set_latency",
+        text_signature = "(self, ctrl0, ctrl1, latency, /)"
+    )]
+    fn py_set_latency(&mut self, ctrl0: i32, ctrl1: i32, latency: f64) -> PyResult<()> {
+        Ok(self.set_latency(ctrl0, ctrl1, latency)?)
+    }
+
+    /// Set the maximum number of concurrent feedback transfers of ``ctrl``.
+    #[pyo3(name = "set_bandwidth", text_signature = "(self, ctrl, bandwidth, /)")]
+    fn py_set_bandwidth(&mut self, ctrl: i32, bandwidth: usize) {
+        self.set_bandwidth(ctrl, bandwidth);
+    }
+
+    /// The maximum number of concurrent feedback transfers of ``ctrl``, ``None`` if unlimited.
+    #[pyo3(name = "bandwidth", text_signature = "(self, ctrl, /)")]
+    fn py_bandwidth(&self, ctrl: i32) -> Option<usize> {
+        self.get_bandwidth(ctrl)
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.latency.clone(),
+                self.default_latency,
+                self.bandwidth.clone(),
+                self.default_bandwidth,
+            ),
+        )
+            .into_py(py)
+    }
+
+    /// The cost of a feedback between ``ctrl0`` and ``ctrl1``, 0 if they are the same controller.
+    #[pyo3(name = "cost", text_signature = "(self, ctrl0, ctrl1, /)")]
+    fn py_cost(&self, ctrl0: i32, ctrl1: i32) -> f64 {
+        self.cost(ctrl0, ctrl1)
+    }
+}
+
+impl CtrlTopology {
+    pub fn new(
+        latency: HashMap<(i32, i32), f64>,
+        default_latency: f64,
+        bandwidth: HashMap<i32, usize>,
+        default_bandwidth: Option<usize>,
+    ) -> Result<Self, MappingError> {
+        check_latency(default_latency)?;
+        let mut topology = CtrlTopology {
+            latency: HashMap::with_capacity(latency.len()),
+            default_latency,
+            bandwidth,
+            default_bandwidth,
+        };
+        for ((ctrl0, ctrl1), cost) in latency {
+            topology.set_latency(ctrl0, ctrl1, cost)?;
+        }
+        Ok(topology)
+    }
+
+    /// See [CtrlTopology::py_from_links].
+    pub fn from_links(
+        links: Vec<(i32, i32)>,
+        hop_latency: f64,
+        default_latency: Option<f64>,
+    ) -> Result<Self, MappingError> {
+        check_latency(hop_latency)?;
+        let mut neighbors: HashMap<i32, Vec<i32>> = HashMap::new();
+        for &(ctrl0, ctrl1) in &links {
+            if ctrl0 != ctrl1 {
+                neighbors.entry(ctrl0).or_default().push(ctrl1);
+                neighbors.entry(ctrl1).or_default().push(ctrl0);
+            }
+        }
+        let default_latency =
+            default_latency.unwrap_or(neighbors.len().max(1) as f64 * hop_latency);
+        let mut topology =
+            CtrlTopology::new(HashMap::new(), default_latency, HashMap::new(), None)?;
+        for &source in neighbors.keys() {
+            for (target, hops) in hop_counts(&neighbors, source) {
+                if source < target {
+                    topology.set_latency(source, target, hops as f64 * hop_latency)?;
+                }
+            }
+        }
+        Ok(topology)
+    }
+
+    pub fn set_latency(
+        &mut self,
+        ctrl0: i32,
+        ctrl1: i32,
+        latency: f64,
+    ) -> Result<(), MappingError> {
+        check_latency(latency)?;
+        if ctrl0 != ctrl1 {
+            self.latency.insert(key(ctrl0, ctrl1), latency);
+        }
+        Ok(())
+    }
+
+    pub fn set_bandwidth(&mut self, ctrl: i32, bandwidth: usize) {
+        self.bandwidth.insert(ctrl, bandwidth);
+    }
+
+    pub fn get_bandwidth(&self, ctrl: i32) -> Option<usize> {
+        self.bandwidth
+            .get(&ctrl)
+            .copied()
+            .or(self.default_bandwidth)
+    }
+
+    pub fn cost(&self, ctrl0: i32, ctrl1: i32) -> f64 {
+        if ctrl0 == ctrl1 {
+            0.
+        } else {
+            *self
+                .latency
+                .get(&key(ctrl0, ctrl1))
+                .unwrap_or(&self.default_latency)
+        }
+    }
+
+    /// Whether the bandwidth of some controller is limited.
+    pub fn has_bandwidth(&self) -> bool {
+        self.default_bandwidth.is_some() || !self.bandwidth.is_empty()
+    }
+
+    /// The number of concurrent transfers of every controller, where every cross-controller
+    /// feedback in `transfers` is a transfer for both of its controllers.
+    pub fn transfer_counts<I>(transfers: I) -> HashMap<i32, usize>
+    where
+        I: IntoIterator<Item = (i32, i32)>,
+    {
+        let mut counts = HashMap::new();
+        for (ctrl0, ctrl1) in transfers {
+            if ctrl0 != ctrl1 {
+                *counts.entry(ctrl0).or_insert(0) += 1;
+                *counts.entry(ctrl1).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// The number of concurrent `transfers` beyond the bandwidth of their controllers, summed
+    /// over the controllers.
+    pub fn excess_transfers<I>(&self, transfers: I) -> usize
+    where
+        I: IntoIterator<Item = (i32, i32)>,
+    {
+        CtrlTopology::transfer_counts(transfers)
+            .into_iter()
+            .map(|(ctrl, count)| {
+                self.get_bandwidth(ctrl)
+                    .map_or(0, |bandwidth| count.saturating_sub(bandwidth))
+            })
+            .sum()
+    }
+}
+
+/// The number of cross-controller feedbacks between every two controllers over all `circuits`,
+/// keyed by the (smaller, larger) controller ids.
+pub fn feedback_frequency(circuits: &[&CifPairs], ctrl2pq: &Ctrl2Pq) -> HashMap<(i32, i32), usize> {
+    let mut frequency = HashMap::new();
+    for cif_pairs in circuits {
+        for (ctrl_pair, count) in cif_pairs.stats(ctrl2pq).per_controller_pair {
+            if ctrl_pair.0 != ctrl_pair.1 {
+                *frequency.entry(ctrl_pair).or_insert(0) += count;
+            }
+        }
+    }
+    frequency
+}
+
+/// The controller links of [recommend_interconnect], most used first.
+pub fn recommend_links(
+    circuits: &[&CifPairs],
+    ctrl2pq: &Ctrl2Pq,
+    max_links: Option<usize>,
+    min_feedbacks: usize,
+) -> Vec<(i32, i32, usize)> {
+    let mut links: Vec<(i32, i32, usize)> = feedback_frequency(circuits, ctrl2pq)
+        .into_iter()
+        .filter(|&(_, count)| count >= min_feedbacks.max(1))
+        .map(|((ctrl0, ctrl1), count)| (ctrl0, ctrl1, count))
+        .collect();
+    links.sort_unstable_by(|a, b| b.2.cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    links.truncate(max_links.unwrap_or(usize::MAX));
+    links
+}
+
+/// Infer which controller pairs need a low-latency link to run a set of circuits, from how often
+/// their cross-controller feedbacks go between them.  The GIL is released while counting.
+///
+/// The result is a weighted edge list, e.g. for ``rustworkx.PyGraph.extend_from_weighted_edge_list``,
+/// whose weights are the number of feedbacks carried by every link.
+///
+/// Args:
+///     circuits (list[CifPairs]): The cif pairs of the circuits on physical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     max_links (int | None): The maximum number of links to recommend, unlimited if ``None``.
+///     min_feedbacks (int): The minimum number of feedbacks for a controller pair to get a link.
+///
+/// Returns:
+///     list[tuple[int, int, int]]: The (smaller controller, larger controller, number of
+///     feedbacks) of every recommended link, most used first and ties in controller order.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(
+    name = "recommend_interconnect",
+    signature = (circuits, ctrl2pq, max_links=None, min_feedbacks=1),
+    text_signature = "(circuits, ctrl2pq, /, max_links=None, min_feedbacks=1)"
+)]
+pub fn py_recommend_interconnect(
+    py: Python,
+    circuits: Vec<PyRef<CifPairs>>,
+    ctrl2pq: &Ctrl2Pq,
+    max_links: Option<usize>,
+    min_feedbacks: usize,
+) -> Vec<(i32, i32, usize)> {
+    let circuits: Vec<&CifPairs> = circuits.iter().map(|cif_pairs| &**cif_pairs).collect();
+    py.allow_threads(|| recommend_links(&circuits, ctrl2pq, max_links, min_feedbacks))
+}
+
+/// The number of links on a shortest path from `source` to every controller reachable from it.
+fn hop_counts(neighbors: &HashMap<i32, Vec<i32>>, source: i32) -> HashMap<i32, usize> {
+    let mut hops = HashMap::from([(source, 0)]);
+    let mut queue = VecDeque::from([source]);
+    while let Some(ctrl) = queue.pop_front() {
+        let next = hops[&ctrl] + 1;
+        for &neighbor in neighbors.get(&ctrl).into_iter().flatten() {
+            hops.entry(neighbor).or_insert_with(|| {
+                queue.push_back(neighbor);
+                next
+            });
+        }
+    }
+    hops
+}
+
+#[inline]
+fn key(ctrl0: i32, ctrl1: i32) -> (i32, i32) {
+    (ctrl0.min(ctrl1), ctrl0.max(ctrl1))
+}
+
+fn check_latency(latency: f64) -> Result<(), MappingError> {
+    if latency.is_finite() && latency >= 0. {
+        Ok(())
+    } else {
+        Err(MappingError::Value(format!(
+            "latency must be a non-negative finite number, got {latency}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "python")]
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_ctrl_topology_cost() {
+        let topology =
+            CtrlTopology::new(HashMap::from([((2, 1), 5.)]), 1.5, HashMap::new(), None).unwrap();
+        assert_eq!(topology.cost(1, 1), 0.);
+        assert_eq!(topology.cost(1, 2), 5.);
+        assert_eq!(topology.cost(2, 1), 5.);
+        assert_eq!(topology.cost(1, 3), 1.5);
+        assert!(
+            CtrlTopology::new(HashMap::from([((0, 1), -1.)]), 1., HashMap::new(), None).is_err()
+        );
+    }
+
+    #[test]
+    fn test_ctrl_topology_bandwidth() {
+        let mut topology =
+            CtrlTopology::new(HashMap::new(), 1., HashMap::from([(0, 1)]), None).unwrap();
+        assert!(topology.has_bandwidth());
+        assert_eq!(topology.get_bandwidth(0), Some(1));
+        assert_eq!(topology.get_bandwidth(1), None);
+        // controller 0 sends two transfers at once, intra-controller feedbacks are free
+        let transfers = [(0, 1), (0, 2), (1, 1)];
+        assert_eq!(topology.excess_transfers(transfers), 1);
+        topology.default_bandwidth = Some(0);
+        topology.set_bandwidth(0, 2);
+        assert_eq!(topology.excess_transfers(transfers), 2);
+    }
+
+    #[test]
+    fn test_ctrl_topology_from_links() {
+        // a line 0 - 1 - 2 and a separate link 3 - 4
+        let links = vec![(0, 1), (2, 1), (3, 4), (0, 0)];
+        let topology = CtrlTopology::from_links(links.clone(), 2., None).unwrap();
+        assert_eq!(topology.cost(0, 0), 0.);
+        assert_eq!(topology.cost(0, 1), 2.);
+        assert_eq!(topology.cost(2, 0), 4.);
+        assert_eq!(topology.cost(3, 4), 2.);
+        // unconnected controllers cost more than any path
+        assert_eq!(topology.cost(0, 3), 10.);
+        assert_eq!(topology.cost(0, 7), 10.);
+
+        let topology = CtrlTopology::from_links(links.clone(), 1., Some(100.)).unwrap();
+        assert_eq!(topology.cost(2, 0), 2.);
+        assert_eq!(topology.cost(2, 4), 100.);
+        assert!(CtrlTopology::from_links(links, -1., None).is_err());
+    }
+
+    #[test]
+    fn test_recommend_links() {
+        let ctrl2pq =
+            Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2]), (2, vec![3])]));
+        let circuit0 = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![0, 2], vec![1, 2]]),
+            (1, vec![vec![0, 1], vec![2, 3]]),
+        ]));
+        let circuit1 = CifPairs::from_pairs(HashMap::from([(0, vec![vec![3, 2], vec![0, 3]])]));
+        let circuits = [&circuit0, &circuit1];
+
+        // intra-controller feedbacks need no link
+        assert_eq!(
+            feedback_frequency(&circuits, &ctrl2pq),
+            HashMap::from([((0, 1), 2), ((1, 2), 2), ((0, 2), 1)])
+        );
+        assert_eq!(
+            recommend_links(&circuits, &ctrl2pq, None, 1),
+            vec![(0, 1, 2), (1, 2, 2), (0, 2, 1)]
+        );
+        assert_eq!(
+            recommend_links(&circuits, &ctrl2pq, None, 2),
+            vec![(0, 1, 2), (1, 2, 2)]
+        );
+        assert_eq!(
+            recommend_links(&circuits, &ctrl2pq, Some(1), 0),
+            vec![(0, 1, 2)]
+        );
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_ctrl_topology_from_graph() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            // stand-in for a rustworkx graph with dict payloads on its edges
+            let locals = PyDict::new_bound(py);
+            py.run_bound(
+                r#"
+class Graph:
+    def weighted_edge_list(self):
+        return [(0, 1, {"latency": 2.0}), (2, 1, {"latency": 3.0})]
+
+graph = Graph()
+weight_fn = lambda payload: payload["latency"]
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let graph = locals.get_item("graph").unwrap().unwrap();
+            let weight_fn = locals.get_item("weight_fn").unwrap().unwrap();
+
+            let topology = CtrlTopology::from_graph(&graph, 5., Some(&weight_fn)).unwrap();
+            assert_eq!(topology.cost(1, 0), 2.);
+            assert_eq!(topology.cost(1, 2), 3.);
+            assert_eq!(topology.cost(0, 2), 5.);
+            // dict payloads are not latencies
+            assert!(CtrlTopology::from_graph(&graph, 5., None).is_err());
+        });
+    }
+}
@@ -0,0 +1,531 @@
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyIndexError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use rustworkx_core::petgraph::prelude::*;
+
+use crate::nlayout::VirtualQubit;
+
+use super::{
+    cif_pairs::CifPairs, classical_flow::ClassicalFlow, error::MappingError, router::RouterGate,
+};
+
+/// A flat instruction as received from Python: operation name, qubits, clbits and the clbits
+/// of its classical condition, if any.
+pub type Instruction = (String, Vec<usize>, Vec<usize>, Option<Vec<usize>>);
+
+/// A node of the [CircuitDag], its index in the graph is the position of the instruction in the
+/// input list.
+#[derive(Clone, Debug)]
+pub struct DagNode {
+    pub name: String,
+    pub qubits: Vec<usize>,
+    pub clbits: Vec<usize>,
+    pub condition: Option<Vec<usize>>,
+}
+
+impl DagNode {
+    #[inline]
+    pub fn is_measure(&self) -> bool {
+        self.name == "measure"
+    }
+
+    #[inline]
+    pub fn is_directive(&self) -> bool {
+        self.name == "barrier"
+    }
+
+    /// The basis in which the node acts on its qubit at `position`, `None` if it does not
+    /// commute with other nodes on that qubit.  Measurements, conditioned nodes and nodes with
+    /// clbits never commute.
+    pub fn commutation_basis(&self, position: usize) -> Option<Basis> {
+        if !self.clbits.is_empty() || self.condition.is_some() {
+            return None;
+        }
+        match self.name.as_str() {
+            "z" | "s" | "sdg" | "t" | "tdg" | "rz" | "p" | "u1" | "cz" | "cp" | "cu1" | "crz"
+            | "ccz" | "rzz" => Some(Basis::Z),
+            "x" | "rx" | "sx" | "sxdg" | "rxx" => Some(Basis::X),
+            // controls act diagonally, the target like an x
+            "cx" | "cnot" | "ccx" | "mcx" if position + 1 < self.qubits.len() => Some(Basis::Z),
+            "cx" | "cnot" | "ccx" | "mcx" => Some(Basis::X),
+            _ => None,
+        }
+    }
+}
+
+/// The basis in which a node acts on one of its qubits, nodes acting on a qubit in the same
+/// basis commute on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Basis {
+    /// Diagonal, such as a ``z``, ``rz``, ``cz`` or the control of a ``cx``.
+    Z,
+    /// Diagonal in the ``x`` basis, such as an ``x``, ``rx`` or the target of a ``cx``.
+    X,
+}
+
+/// The nodes acting on a qubit so far, grouped into runs of nodes commuting on it.
+#[derive(Default)]
+struct QubitRuns {
+    basis: Option<Basis>,
+    last: Vec<NodeIndex>,
+    previous: Vec<NodeIndex>,
+}
+
+impl QubitRuns {
+    /// Add `node` acting in `basis` and return the nodes it depends on.  A node commuting with
+    /// the last run joins it and depends on the run before, otherwise it starts a new run.
+    fn push(&mut self, node: NodeIndex, basis: Option<Basis>) -> &[NodeIndex] {
+        if basis.is_none() || basis != self.basis || self.last.is_empty() {
+            self.previous = std::mem::take(&mut self.last);
+            self.basis = basis;
+        }
+        self.last.push(node);
+        &self.previous
+    }
+}
+
+/// A dependency DAG of a circuit built from a flat instruction list.
+///
+/// Every instruction depends on the previous instructions acting on any of its qubits, clbits or
+/// condition clbits.  Node ids are the positions of the instructions in the input list, so they
+/// can be used as keys of ``CifPairs`` directly.  The cif pairs of conditioned instructions are
+/// extracted on construction by tracking which qubit was last measured into each clbit, as
+/// ``[conditioned qubit, measured qubit, clbit]``, see ``extract_cif_pairs``.
+///
+/// With ``commutation``, nodes commuting on a qubit do not depend on each other through it:
+/// diagonal gates and controls of ``cx`` gates commute with each other, and so do ``x``-like
+/// gates and targets of ``cx`` gates.  Such nodes can then be executed in any order, which
+/// gives the router a larger front layer.
+///
+/// Args:
+///     num_qubits (int): The number of qubits of the circuit.
+///     num_clbits (int): The number of clbits of the circuit.
+///     instructions (list[tuple[str, list[int], list[int], list[int] | None]]): The operation
+///         name, qubits, clbits and condition clbits of every instruction, in a valid
+///         topological order.
+///     commutation (bool): Whether to drop the dependencies between commuting nodes.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug)]
+pub struct CircuitDag {
+    pub num_qubits: usize,
+    pub num_clbits: usize,
+    pub commutation: bool,
+    pub dag: DiGraph<DagNode, ()>,
+    pub first_layer: Vec<NodeIndex>,
+    // cif pairs of the conditioned nodes, as [conditioned qubit, measured qubit, clbit]
+    pub cif_pairs: HashMap<usize, Vec<Vec<i32>>>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl CircuitDag {
+    #[new]
+    #[pyo3(signature = (num_qubits, num_clbits, instructions, commutation=false))]
+    fn py_new(
+        num_qubits: usize,
+        num_clbits: usize,
+        instructions: Vec<Instruction>,
+        commutation: bool,
+    ) -> PyResult<Self> {
+        Ok(CircuitDag::new(
+            num_qubits,
+            num_clbits,
+            instructions,
+            commutation,
+        )?)
+    }
+
+    #[getter(num_qubits)]
+    fn get_num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    #[getter(num_clbits)]
+    fn get_num_clbits(&self) -> usize {
+        self.num_clbits
+    }
+
+    #[getter(commutation)]
+    fn get_commutation(&self) -> bool {
+        self.commutation
+    }
+
+    /// The number of instructions.
+    fn __len__(&self) -> usize {
+        self.dag.node_count()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.num_qubits,
+                self.num_clbits,
+                self.instructions(),
+                self.commutation,
+            ),
+        )
+            .into_py(py)
+    }
+
+    /// The ids of the nodes that do not depend on any other node.
+    #[pyo3(name = "first_layer", text_signature = "(self, /)")]
+    fn py_first_layer(&self) -> Vec<usize> {
+        self.first_layer.iter().map(|node| node.index()).collect()
+    }
+
+    /// The ids of the nodes that are not in ``executed`` but whose predecessors all are.
+    #[pyo3(name = "front_layer", text_signature = "(self, executed, /)")]
+    fn py_front_layer(&self, executed: Vec<usize>) -> PyResult<Vec<usize>> {
+        if let Some(&node) = executed.iter().find(|&&n| n >= self.dag.node_count()) {
+            return Err(PyIndexError::new_err(format!(
+                "node {} is out of range for {} nodes",
+                node,
+                self.dag.node_count()
+            )));
+        }
+        let mut is_executed = vec![false; self.dag.node_count()];
+        for node in executed {
+            is_executed[node] = true;
+        }
+        Ok(self.front_layer(&is_executed))
+    }
+
+    /// The ids of the direct successors of ``node``.
+    #[pyo3(name = "successors", text_signature = "(self, node, /)")]
+    fn py_successors(&self, node: usize) -> PyResult<Vec<usize>> {
+        self.check_node(node)?;
+        let mut successors: Vec<usize> = self
+            .dag
+            .neighbors_directed(NodeIndex::new(node), Direction::Outgoing)
+            .map(|n| n.index())
+            .collect();
+        successors.sort_unstable();
+        Ok(successors)
+    }
+
+    /// The ids of the direct predecessors of ``node``.
+    #[pyo3(name = "predecessors", text_signature = "(self, node, /)")]
+    fn py_predecessors(&self, node: usize) -> PyResult<Vec<usize>> {
+        self.check_node(node)?;
+        let mut predecessors: Vec<usize> = self
+            .dag
+            .neighbors_directed(NodeIndex::new(node), Direction::Incoming)
+            .map(|n| n.index())
+            .collect();
+        predecessors.sort_unstable();
+        Ok(predecessors)
+    }
+
+    /// The cif pairs extracted from the conditioned instructions, keyed by node id.
+    #[pyo3(name = "cif_pairs", text_signature = "(self, /)")]
+    fn py_cif_pairs(&self) -> CifPairs {
+        self.cif_pairs()
+    }
+}
+
+impl CircuitDag {
+    /// Build the DAG of `instructions`, see [CircuitDag::py_new].
+    pub fn new(
+        num_qubits: usize,
+        num_clbits: usize,
+        instructions: Vec<Instruction>,
+        commutation: bool,
+    ) -> Result<Self, MappingError> {
+        let mut qubit_runs: Vec<QubitRuns> =
+            (0..num_qubits).map(|_| QubitRuns::default()).collect();
+        let mut clbit_pos: Vec<Option<NodeIndex>> = vec![None; num_clbits];
+        let mut flow = ClassicalFlow::new(num_clbits);
+        let mut dag = DiGraph::with_capacity(instructions.len(), 2 * instructions.len());
+        let mut first_layer = Vec::new();
+        let mut cif_pairs = HashMap::new();
+
+        for (name, qubits, clbits, condition) in instructions {
+            if let Some(&qubit) = qubits.iter().find(|&&q| q >= num_qubits) {
+                return Err(MappingError::Index(format!(
+                    "qubit index {} is out of range for {} qubits",
+                    qubit, num_qubits
+                )));
+            }
+            let condition_clbits = condition.iter().flatten();
+            if let Some(&clbit) = clbits
+                .iter()
+                .chain(condition_clbits.clone())
+                .find(|&&c| c >= num_clbits)
+            {
+                return Err(MappingError::Index(format!(
+                    "clbit index {} is out of range for {} clbits",
+                    clbit, num_clbits
+                )));
+            }
+
+            let node = dag.add_node(DagNode {
+                name,
+                qubits: qubits.clone(),
+                clbits: clbits.clone(),
+                condition: condition.clone(),
+            });
+            let mut predecessors: Vec<NodeIndex> = Vec::new();
+            for (position, &q) in qubits.iter().enumerate() {
+                let basis = if commutation {
+                    dag[node].commutation_basis(position)
+                } else {
+                    None
+                };
+                predecessors.extend_from_slice(qubit_runs[q].push(node, basis));
+            }
+            for &c in clbits.iter().chain(condition_clbits.clone()) {
+                if let Some(predecessor) = clbit_pos[c].replace(node) {
+                    predecessors.push(predecessor);
+                }
+            }
+            predecessors.sort_unstable();
+            predecessors.dedup();
+            predecessors.retain(|&predecessor| predecessor != node);
+            if predecessors.is_empty() {
+                first_layer.push(node);
+            }
+            for predecessor in predecessors {
+                dag.add_edge(predecessor, node, ());
+            }
+
+            match flow.process(
+                dag[node].is_measure(),
+                &qubits,
+                &clbits,
+                condition.as_deref(),
+            ) {
+                (_, Some(clbit)) => {
+                    return Err(MappingError::Value(format!(
+                        "node {} is conditioned on clbit {} before any measurement into it",
+                        node.index(),
+                        clbit
+                    )));
+                }
+                (Some(pairs), None) => {
+                    cif_pairs.insert(node.index(), pairs);
+                }
+                (None, None) => {}
+            }
+        }
+
+        Ok(CircuitDag {
+            num_qubits,
+            num_clbits,
+            commutation,
+            dag,
+            first_layer,
+            cif_pairs,
+        })
+    }
+
+    /// The nodes that are not executed but whose predecessors all are, in increasing order of id.
+    pub fn front_layer(&self, is_executed: &[bool]) -> Vec<usize> {
+        self.dag
+            .node_indices()
+            .filter(|node| {
+                !is_executed[node.index()]
+                    && self
+                        .dag
+                        .neighbors_directed(*node, Direction::Incoming)
+                        .all(|predecessor| is_executed[predecessor.index()])
+            })
+            .map(|node| node.index())
+            .collect()
+    }
+
+    /// The instructions the DAG was built from, in their original order.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        self.dag
+            .node_weights()
+            .map(|node| {
+                (
+                    node.name.clone(),
+                    node.qubits.clone(),
+                    node.clbits.clone(),
+                    node.condition.clone(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn cif_pairs(&self) -> CifPairs {
+        CifPairs::from_pairs(self.cif_pairs.clone())
+    }
+
+    /// Whether `node` is routed, which excludes directives and nodes without qubits.
+    #[inline]
+    pub fn is_router_gate(&self, node: NodeIndex) -> bool {
+        !self.dag[node].is_directive() && !self.dag[node].qubits.is_empty()
+    }
+
+    /// The non-directive instructions acting on qubits, as gates for [super::router::route].
+    pub fn router_gates(&self) -> Vec<RouterGate> {
+        self.dag
+            .node_indices()
+            .filter(|&node| self.is_router_gate(node))
+            .map(|node| RouterGate {
+                node_id: node.index(),
+                qubits: self.dag[node]
+                    .qubits
+                    .iter()
+                    .map(|&q| VirtualQubit::new(q as u32))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The successors of every gate of [CircuitDag::router_gates] as indices into them, where the
+    /// dependencies through the other nodes are kept.
+    pub fn router_successors(&self) -> Vec<Vec<usize>> {
+        let mut gate_index = vec![usize::MAX; self.dag.node_count()];
+        let mut num_gates = 0;
+        for node in self.dag.node_indices() {
+            if self.is_router_gate(node) {
+                gate_index[node.index()] = num_gates;
+                num_gates += 1;
+            }
+        }
+        let mut successors = Vec::with_capacity(num_gates);
+        for node in self.dag.node_indices() {
+            if !self.is_router_gate(node) {
+                continue;
+            }
+            let mut gate_successors = Vec::new();
+            let mut visited = vec![false; self.dag.node_count()];
+            let mut to_visit: Vec<NodeIndex> = self
+                .dag
+                .neighbors_directed(node, Direction::Outgoing)
+                .collect();
+            while let Some(next) = to_visit.pop() {
+                if std::mem::replace(&mut visited[next.index()], true) {
+                    continue;
+                }
+                if self.is_router_gate(next) {
+                    gate_successors.push(gate_index[next.index()]);
+                } else {
+                    to_visit.extend(self.dag.neighbors_directed(next, Direction::Outgoing));
+                }
+            }
+            gate_successors.sort_unstable();
+            successors.push(gate_successors);
+        }
+        successors
+    }
+
+    #[cfg(feature = "python")]
+    fn check_node(&self, node: usize) -> PyResult<()> {
+        if node < self.dag.node_count() {
+            Ok(())
+        } else {
+            Err(PyIndexError::new_err(format!(
+                "node {} is out of range for {} nodes",
+                node,
+                self.dag.node_count()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(
+        name: &str,
+        qubits: &[usize],
+        clbits: &[usize],
+        condition: Option<&[usize]>,
+    ) -> Instruction {
+        (
+            name.to_string(),
+            qubits.to_vec(),
+            clbits.to_vec(),
+            condition.map(<[usize]>::to_vec),
+        )
+    }
+
+    #[test]
+    fn test_circuit_dag_layers_and_cif_pairs() {
+        let dag = CircuitDag::new(
+            3,
+            1,
+            vec![
+                instruction("h", &[0], &[], None),
+                instruction("cx", &[1, 2], &[], None),
+                instruction("measure", &[0], &[0], None),
+                instruction("x", &[2], &[], Some(&[0])),
+                instruction("cx", &[0, 1], &[], None),
+            ],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(dag.front_layer(&[false; 5]), vec![0, 1]);
+        assert_eq!(
+            dag.front_layer(&[true, false, false, false, false]),
+            vec![1, 2]
+        );
+        assert_eq!(
+            dag.front_layer(&[true, true, true, false, false]),
+            vec![3, 4]
+        );
+        #[cfg(feature = "python")]
+        assert_eq!(dag.py_predecessors(3).unwrap(), vec![1, 2]);
+        assert_eq!(dag.cif_pairs.get(&3), Some(&vec![vec![2, 0, 0]]));
+        assert_eq!(dag.router_gates().len(), 5);
+
+        let rebuilt = CircuitDag::new(3, 1, dag.instructions(), false).unwrap();
+        assert_eq!(rebuilt.instructions(), dag.instructions());
+        assert_eq!(rebuilt.cif_pairs, dag.cif_pairs);
+    }
+
+    #[test]
+    fn test_circuit_dag_commutation() {
+        let instructions = vec![
+            instruction("cx", &[0, 1], &[], None),
+            instruction("cz", &[0, 2], &[], None),
+            instruction("x", &[1], &[], None),
+            instruction("h", &[0], &[], None),
+            instruction("cx", &[2, 1], &[], None),
+            instruction("rz", &[1], &[], None),
+        ];
+        let dag = CircuitDag::new(3, 0, instructions.clone(), false).unwrap();
+        assert_eq!(dag.front_layer(&[false; 6]), vec![0]);
+
+        let dag = CircuitDag::new(3, 0, instructions, true).unwrap();
+        // the controls of the cx commute with the cz, their targets with the x
+        assert_eq!(dag.front_layer(&[false; 6]), vec![0, 1, 2, 4]);
+        #[cfg(feature = "python")]
+        assert_eq!(dag.py_predecessors(3).unwrap(), vec![0, 1]);
+        #[cfg(feature = "python")]
+        assert_eq!(dag.py_predecessors(5).unwrap(), vec![0, 2, 4]);
+        // a barrier between gates is kept as a dependency of the router
+        let dag = CircuitDag::new(
+            2,
+            0,
+            vec![
+                instruction("x", &[0], &[], None),
+                instruction("barrier", &[0, 1], &[], None),
+                instruction("x", &[1], &[], None),
+            ],
+            true,
+        )
+        .unwrap();
+        assert_eq!(dag.router_successors(), vec![vec![1], vec![]]);
+    }
+
+    #[test]
+    fn test_circuit_dag_errors() {
+        assert!(CircuitDag::new(1, 0, vec![instruction("x", &[1], &[], None)], false).is_err());
+        assert!(
+            CircuitDag::new(1, 1, vec![instruction("x", &[0], &[], Some(&[1]))], false).is_err()
+        );
+        // conditioned on a clbit that was never measured
+        assert!(
+            CircuitDag::new(1, 1, vec![instruction("x", &[0], &[], Some(&[0]))], false).is_err()
+        );
+    }
+}
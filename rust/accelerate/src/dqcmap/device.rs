@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::MappingError,
+    noise::{check_error_rate, key, NoiseModel},
+    sim::check_durations,
+};
+
+/// Calibration data of a device: two-qubit gate and readout error rates, coherence times and
+/// gate durations.
+///
+/// The same properties feed noise-aware swap scoring through ``noise_model()`` and the
+/// scheduler and execution time estimators through ``gate_durations``, which accept a
+/// ``DeviceProperties`` in place of a durations dict.  Qubits, edges and gates without an
+/// explicit entry are ideal, have unknown coherence times or take no time.
+///
+/// Args:
+///     cx_error (dict[tuple[int, int], float]): The CX error rate of each edge, the direction of
+///         the edge is ignored.
+///     t1 (dict[int, float]): The T1 relaxation time of each physical qubit.
+///     t2 (dict[int, float]): The T2 dephasing time of each physical qubit.
+///     readout_error (dict[int, float]): The readout error rate of each physical qubit.
+///     gate_durations (dict[str, float]): The duration of every operation by name.
+///
+/// Raises:
+///     ValueError: if an error rate is not in [0, 1], a coherence time is not a positive finite
+///         number or a duration is negative or not finite.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceProperties {
+    // error rate of an edge, keyed by the (smaller, larger) qubit indices
+    pub cx_error: HashMap<(i32, i32), f64>,
+    pub t1: HashMap<i32, f64>,
+    pub t2: HashMap<i32, f64>,
+    pub readout_error: HashMap<i32, f64>,
+    pub gate_durations: HashMap<String, f64>,
+}
+
+/// The JSON layout of [DeviceProperties], with the CX error rates as ``[q0, q1, error]`` lists
+/// since JSON objects only have string keys.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DevicePropertiesJson {
+    cx_error: Vec<(i32, i32, f64)>,
+    t1: BTreeMap<i32, f64>,
+    t2: BTreeMap<i32, f64>,
+    readout_error: BTreeMap<i32, f64>,
+    gate_durations: BTreeMap<String, f64>,
+}
+
+/// The keys accepted by ``DeviceProperties.from_dict``.
+#[cfg(feature = "python")]
+const DICT_KEYS: [&str; 5] = ["cx_error", "t1", "t2", "readout_error", "gate_durations"];
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DeviceProperties {
+    #[new]
+    #[pyo3(signature = (cx_error=HashMap::new(), t1=HashMap::new(), t2=HashMap::new(), readout_error=HashMap::new(), gate_durations=HashMap::new()))]
+    fn py_new(
+        cx_error: HashMap<(i32, i32), f64>,
+        t1: HashMap<i32, f64>,
+        t2: HashMap<i32, f64>,
+        readout_error: HashMap<i32, f64>,
+        gate_durations: HashMap<String, f64>,
+    ) -> PyResult<Self> {
+        Ok(DeviceProperties::new(
+            cx_error,
+            t1,
+            t2,
+            readout_error,
+            gate_durations,
+        )?)
+    }
+
+    #[getter(gate_durations)]
+    fn get_gate_durations(&self) -> HashMap<String, f64> {
+        self.gate_durations.clone()
+    }
+
+    /// Load the properties from a dict with the keyword arguments of the constructor as keys,
+    /// all of them optional.
+    ///
+    /// Raises:
+    ///     ValueError: if a key is unknown or a value is invalid.
+    #[staticmethod]
+    #[pyo3(text_signature = "(properties, /)")]
+    pub fn from_dict(properties: &Bound<PyDict>) -> PyResult<Self> {
+        for key in properties.keys() {
+            let key: String = key.extract()?;
+            if !DICT_KEYS.contains(&key.as_str()) {
+                return Err(PyValueError::new_err(format!(
+                    "unknown device property '{key}', expected one of {DICT_KEYS:?}"
+                )));
+            }
+        }
+        fn get<'py, T: FromPyObject<'py> + Default>(
+            properties: &Bound<'py, PyDict>,
+            key: &str,
+        ) -> PyResult<T> {
+            match properties.get_item(key)? {
+                Some(value) => value.extract(),
+                None => Ok(T::default()),
+            }
+        }
+        Ok(DeviceProperties::new(
+            get(properties, "cx_error")?,
+            get(properties, "t1")?,
+            get(properties, "t2")?,
+            get(properties, "readout_error")?,
+            get(properties, "gate_durations")?,
+        )?)
+    }
+
+    /// Load the properties from a JSON object with the keys of ``from_dict``, where
+    /// ``cx_error`` is a list of ``[q0, q1, error]`` lists.
+    ///
+    /// Raises:
+    ///     ValueError: if the JSON is malformed or a value is invalid.
+    #[staticmethod]
+    #[pyo3(name = "from_json", text_signature = "(json, /)")]
+    fn py_from_json(json: &str) -> PyResult<Self> {
+        Ok(DeviceProperties::from_json(json)?)
+    }
+
+    /// Serialize the properties to the JSON layout of ``from_json``, sorted so that the output
+    /// is stable.
+    #[pyo3(name = "to_json", text_signature = "(self, /)")]
+    fn py_to_json(&self) -> PyResult<String> {
+        Ok(self.to_json()?)
+    }
+
+    /// The CX error rate of the edge between ``q0`` and ``q1``, 0 if unknown.
+    #[pyo3(name = "cx", text_signature = "(self, q0, q1, /)")]
+    fn py_cx(&self, q0: i32, q1: i32) -> f64 {
+        self.cx(q0, q1)
+    }
+
+    /// The readout error rate of ``qubit``, 0 if unknown.
+    #[pyo3(name = "readout", text_signature = "(self, qubit, /)")]
+    fn py_readout(&self, qubit: i32) -> f64 {
+        self.readout(qubit)
+    }
+
+    /// The T1 time of ``qubit``, ``None`` if unknown.
+    #[pyo3(name = "t1", text_signature = "(self, qubit, /)")]
+    fn py_t1(&self, qubit: i32) -> Option<f64> {
+        self.get_t1(qubit)
+    }
+
+    /// The T2 time of ``qubit``, ``None`` if unknown.
+    #[pyo3(name = "t2", text_signature = "(self, qubit, /)")]
+    fn py_t2(&self, qubit: i32) -> Option<f64> {
+        self.get_t2(qubit)
+    }
+
+    /// The duration of the operation ``name``, 0 if unknown.
+    #[pyo3(name = "duration", text_signature = "(self, name, /)")]
+    fn py_duration(&self, name: &str) -> f64 {
+        self.duration(name)
+    }
+
+    /// The noise model of noise-aware swap scoring with the CX and readout error rates of the
+    /// device.
+    ///
+    /// Args:
+    ///     weight (float): Multiplier applied to the noise cost, see ``NoiseModel``.
+    #[pyo3(name = "noise_model", signature = (weight=1.0))]
+    fn py_noise_model(&self, weight: f64) -> PyResult<NoiseModel> {
+        Ok(self.noise_model(weight)?)
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.cx_error.clone(),
+                self.t1.clone(),
+                self.t2.clone(),
+                self.readout_error.clone(),
+                self.gate_durations.clone(),
+            ),
+        )
+            .into_py(py)
+    }
+}
+
+impl DeviceProperties {
+    pub fn new(
+        cx_error: HashMap<(i32, i32), f64>,
+        t1: HashMap<i32, f64>,
+        t2: HashMap<i32, f64>,
+        readout_error: HashMap<i32, f64>,
+        gate_durations: HashMap<String, f64>,
+    ) -> Result<Self, MappingError> {
+        for &error in cx_error.values().chain(readout_error.values()) {
+            check_error_rate(error)?;
+        }
+        for &time in t1.values().chain(t2.values()) {
+            check_coherence_time(time)?;
+        }
+        check_durations(&gate_durations, 0.)?;
+        Ok(DeviceProperties {
+            cx_error: cx_error
+                .into_iter()
+                .map(|((q0, q1), error)| (key(q0, q1), error))
+                .collect(),
+            t1,
+            t2,
+            readout_error,
+            gate_durations,
+        })
+    }
+
+    /// See [DeviceProperties::py_from_json].
+    pub fn from_json(json: &str) -> Result<Self, MappingError> {
+        let properties: DevicePropertiesJson = serde_json::from_str(json)
+            .map_err(|err| MappingError::Value(format!("invalid DeviceProperties JSON: {err}")))?;
+        DeviceProperties::new(
+            properties
+                .cx_error
+                .into_iter()
+                .map(|(q0, q1, error)| ((q0, q1), error))
+                .collect(),
+            properties.t1.into_iter().collect(),
+            properties.t2.into_iter().collect(),
+            properties.readout_error.into_iter().collect(),
+            properties.gate_durations.into_iter().collect(),
+        )
+    }
+
+    /// See [DeviceProperties::py_to_json].
+    pub fn to_json(&self) -> Result<String, MappingError> {
+        let mut cx_error: Vec<(i32, i32, f64)> = self
+            .cx_error
+            .iter()
+            .map(|(&(q0, q1), &error)| (q0, q1, error))
+            .collect();
+        cx_error.sort_unstable_by_key(|&(q0, q1, _)| (q0, q1));
+        let properties = DevicePropertiesJson {
+            cx_error,
+            t1: self
+                .t1
+                .iter()
+                .map(|(&qubit, &time)| (qubit, time))
+                .collect(),
+            t2: self
+                .t2
+                .iter()
+                .map(|(&qubit, &time)| (qubit, time))
+                .collect(),
+            readout_error: self
+                .readout_error
+                .iter()
+                .map(|(&qubit, &error)| (qubit, error))
+                .collect(),
+            gate_durations: self
+                .gate_durations
+                .iter()
+                .map(|(name, &duration)| (name.clone(), duration))
+                .collect(),
+        };
+        serde_json::to_string(&properties).map_err(|err| MappingError::Value(err.to_string()))
+    }
+
+    pub fn cx(&self, q0: i32, q1: i32) -> f64 {
+        *self.cx_error.get(&key(q0, q1)).unwrap_or(&0.)
+    }
+
+    pub fn readout(&self, qubit: i32) -> f64 {
+        *self.readout_error.get(&qubit).unwrap_or(&0.)
+    }
+
+    pub fn get_t1(&self, qubit: i32) -> Option<f64> {
+        self.t1.get(&qubit).copied()
+    }
+
+    pub fn get_t2(&self, qubit: i32) -> Option<f64> {
+        self.t2.get(&qubit).copied()
+    }
+
+    pub fn duration(&self, name: &str) -> f64 {
+        *self.gate_durations.get(name).unwrap_or(&0.)
+    }
+
+    /// See [DeviceProperties::py_noise_model].
+    pub fn noise_model(&self, weight: f64) -> Result<NoiseModel, MappingError> {
+        let mut noise = NoiseModel::new(HashMap::new(), HashMap::new(), weight)?;
+        noise.edge_error.clone_from(&self.cx_error);
+        noise.readout_error.clone_from(&self.readout_error);
+        Ok(noise)
+    }
+}
+
+/// Gate durations given either as a dict from operation names to durations or through the
+/// ``gate_durations`` of a [DeviceProperties].
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum GateDurations {
+    Map(HashMap<String, f64>),
+    Device(DeviceProperties),
+}
+
+#[cfg(feature = "python")]
+impl GateDurations {
+    pub fn into_map(self) -> HashMap<String, f64> {
+        match self {
+            GateDurations::Map(durations) => durations,
+            GateDurations::Device(device) => device.gate_durations,
+        }
+    }
+}
+
+/// A noise model given either directly or through the error rates of a [DeviceProperties].
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum NoiseSource {
+    Model(NoiseModel),
+    Device(DeviceProperties),
+}
+
+#[cfg(feature = "python")]
+impl NoiseSource {
+    pub fn into_noise_model(self) -> Result<NoiseModel, MappingError> {
+        match self {
+            NoiseSource::Model(noise) => Ok(noise),
+            NoiseSource::Device(device) => device.noise_model(1.),
+        }
+    }
+}
+
+fn check_coherence_time(time: f64) -> Result<(), MappingError> {
+    if time.is_finite() && time > 0. {
+        Ok(())
+    } else {
+        Err(MappingError::Value(format!(
+            "coherence time must be a positive finite number, got {time}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_properties() {
+        let device = DeviceProperties::new(
+            HashMap::from([((1, 0), 0.5)]),
+            HashMap::from([(0, 100.)]),
+            HashMap::from([(0, 80.)]),
+            HashMap::from([(2, 0.1)]),
+            HashMap::from([("cx".to_string(), 300.)]),
+        )
+        .unwrap();
+        assert_eq!(device.cx(0, 1), 0.5);
+        assert_eq!(device.cx(1, 2), 0.);
+        assert_eq!(device.get_t1(0), Some(100.));
+        assert_eq!(device.get_t2(1), None);
+        assert_eq!(device.duration("cx"), 300.);
+
+        let noise = device.noise_model(2.).unwrap();
+        assert_eq!(noise.swap_error(1, 0), 0.875);
+        assert_eq!(noise.readout(2), 0.1);
+        assert_eq!(noise.weight, 2.);
+
+        let json = device.to_json().unwrap();
+        assert_eq!(DeviceProperties::from_json(&json).unwrap(), device);
+        assert!(DeviceProperties::from_json(r#"{"cx_error": [[0, 1, 2.0]]}"#).is_err());
+        assert!(DeviceProperties::from_json(r#"{"t3": {}}"#).is_err());
+
+        assert!(DeviceProperties::new(
+            HashMap::new(),
+            HashMap::from([(0, 0.)]),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new()
+        )
+        .is_err());
+        assert!(DeviceProperties::new(
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::from([("x".to_string(), -1.)])
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+#[cfg(feature = "python")]
+use {
+    super::coupling_map::CouplingMap,
+    numpy::{IntoPyArray, PyArray2},
+    pyo3::exceptions::{PyIndexError, PyValueError},
+    pyo3::prelude::*,
+};
+
+use crate::getenv_use_multiple_threads;
+use crate::nlayout::PhysicalQubit;
+
+/// Minimum number of qubits before the breadth-first searches are spread over a thread pool.
+const PARALLEL_BFS_THRESHOLD: usize = 128;
+
+/// Hop distances between all pairs of physical qubits of a coupling map.
+///
+/// The matrix is computed once with a breadth-first search per source qubit, in parallel for
+/// large devices, and is shared between copies, so that routers and lookahead heuristics can
+/// query distances in constant time without recomputing them.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map to take the distances of.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug)]
+pub struct DistanceMatrix {
+    num_qubits: usize,
+    // row-major distances, usize::MAX for unreachable pairs
+    distance: Arc<Vec<usize>>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DistanceMatrix {
+    #[new]
+    #[pyo3(text_signature = "(coupling_map, /)")]
+    fn py_new(coupling_map: &CouplingMap) -> Self {
+        coupling_map.distance_matrix().clone()
+    }
+
+    /// The number of physical qubits.
+    #[pyo3(name = "num_qubits", text_signature = "(self, /)")]
+    fn py_num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// The number of edges on a shortest path between ``qubit_a`` and ``qubit_b``, ``None`` if
+    /// they are not connected.
+    #[pyo3(name = "distance", text_signature = "(self, qubit_a, qubit_b, /)")]
+    fn py_distance(
+        &self,
+        qubit_a: PhysicalQubit,
+        qubit_b: PhysicalQubit,
+    ) -> PyResult<Option<usize>> {
+        for qubit in [qubit_a, qubit_b] {
+            if qubit.index() >= self.num_qubits {
+                return Err(PyIndexError::new_err(format!(
+                    "qubit index {} is out of range for {} physical qubits",
+                    qubit.index(),
+                    self.num_qubits
+                )));
+            }
+        }
+        match self.get(qubit_a, qubit_b) {
+            usize::MAX => Ok(None),
+            dist => Ok(Some(dist)),
+        }
+    }
+
+    /// The distances as a ``(num_qubits, num_qubits)`` float array, with ``inf`` for pairs of
+    /// qubits that are not connected.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn to_numpy<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        let distance = self
+            .distance
+            .iter()
+            .map(|&dist| match dist {
+                usize::MAX => f64::INFINITY,
+                dist => dist as f64,
+            })
+            .collect();
+        ndarray::Array2::from_shape_vec((self.num_qubits, self.num_qubits), distance)
+            .unwrap()
+            .into_pyarray_bound(py)
+    }
+
+    fn __len__(&self) -> usize {
+        self.num_qubits
+    }
+
+    fn __reduce__(&self, py: Python) -> PyResult<Py<PyAny>> {
+        Ok((
+            py.get_type_bound::<Self>().getattr("_from_flat")?,
+            (self.num_qubits, self.distance.to_vec()),
+        )
+            .into_py(py))
+    }
+
+    /// Rebuild a matrix from its row-major distances, used by pickle.
+    #[staticmethod]
+    fn _from_flat(num_qubits: usize, distance: Vec<usize>) -> PyResult<Self> {
+        if distance.len() != num_qubits * num_qubits {
+            return Err(PyValueError::new_err(format!(
+                "expected {} distances for {} qubits, got {}",
+                num_qubits * num_qubits,
+                num_qubits,
+                distance.len()
+            )));
+        }
+        Ok(DistanceMatrix {
+            num_qubits,
+            distance: Arc::new(distance),
+        })
+    }
+}
+
+impl DistanceMatrix {
+    /// Compute the distances of the graph with the given adjacency lists.  Graphs of at least
+    /// `PARALLEL_BFS_THRESHOLD` qubits are searched in parallel unless we are already in a
+    /// parallel context.
+    pub fn from_neighbors(neighbors: &[Vec<PhysicalQubit>]) -> Self {
+        let num_qubits = neighbors.len();
+        let rows: Vec<Vec<usize>> =
+            if num_qubits >= PARALLEL_BFS_THRESHOLD && getenv_use_multiple_threads() {
+                (0..num_qubits)
+                    .into_par_iter()
+                    .map(|source| bfs_distances(neighbors, source))
+                    .collect()
+            } else {
+                (0..num_qubits)
+                    .map(|source| bfs_distances(neighbors, source))
+                    .collect()
+            };
+        DistanceMatrix {
+            num_qubits,
+            distance: Arc::new(rows.concat()),
+        }
+    }
+
+    #[inline]
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// Hop distance between two physical qubits, or `usize::MAX` if they are not connected.
+    #[inline]
+    pub fn get(&self, qubit_a: PhysicalQubit, qubit_b: PhysicalQubit) -> usize {
+        self.distance[qubit_a.index() * self.num_qubits + qubit_b.index()]
+    }
+
+    /// The distances from `qubit` to every physical qubit.
+    #[inline]
+    pub fn row(&self, qubit: PhysicalQubit) -> &[usize] {
+        let start = qubit.index() * self.num_qubits;
+        &self.distance[start..start + self.num_qubits]
+    }
+}
+
+/// Hop distances from `source` to every qubit, `usize::MAX` for unreachable ones.
+fn bfs_distances(neighbors: &[Vec<PhysicalQubit>], source: usize) -> Vec<usize> {
+    let mut dist = vec![usize::MAX; neighbors.len()];
+    let mut queue = VecDeque::from([source]);
+    dist[source] = 0;
+    while let Some(node) = queue.pop_front() {
+        for next in &neighbors[node] {
+            if dist[next.index()] == usize::MAX {
+                dist[next.index()] = dist[node] + 1;
+                queue.push_back(next.index());
+            }
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::in_scoped_thread_pool;
+
+    /// Adjacency lists of a ring of `num_qubits` qubits.
+    fn ring(num_qubits: u32) -> Vec<Vec<PhysicalQubit>> {
+        (0..num_qubits)
+            .map(|q| {
+                vec![
+                    PhysicalQubit::new((q + num_qubits - 1) % num_qubits),
+                    PhysicalQubit::new((q + 1) % num_qubits),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_distance_matrix_ring() {
+        let q = PhysicalQubit::new;
+        let distance = DistanceMatrix::from_neighbors(&ring(6));
+        assert_eq!(distance.num_qubits(), 6);
+        assert_eq!(distance.get(q(0), q(3)), 3);
+        assert_eq!(distance.get(q(1), q(5)), 2);
+        assert_eq!(distance.row(q(2)), &[2, 1, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_distance_matrix_parallel() {
+        let neighbors = ring(2 * PARALLEL_BFS_THRESHOLD as u32);
+        let distance =
+            in_scoped_thread_pool(|| DistanceMatrix::from_neighbors(&neighbors)).unwrap();
+        let q = PhysicalQubit::new;
+        assert_eq!(
+            distance.get(q(0), q(PARALLEL_BFS_THRESHOLD as u32)),
+            PARALLEL_BFS_THRESHOLD
+        );
+        assert_eq!(distance.row(q(3))[..], bfs_distances(&neighbors, 3)[..]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{
+    cif_pairs::{is_well_formed, CifPairs},
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+};
+
+const CROSS_COLOR: &str = "red";
+const INTRA_COLOR: &str = "gray";
+
+/// Render a controller assignment as a Graphviz DOT digraph.
+///
+/// Every controller is a cluster holding its qubits as nodes.  Every distinct cif pair is an
+/// edge from its measured to its conditioned qubit, red if it crosses controllers, gray if it
+/// does not and dotted if a qubit is not connected to any controller, labeled with its
+/// multiplicity if it occurs more than once.  The links of `ctrl_topology` are drawn as dashed
+/// edges between the clusters, labeled with their latency.  The output only depends on the
+/// contents of the inputs, so that it can be diffed.
+pub fn export_dot(
+    ctrl2pq: &Ctrl2Pq,
+    cif_pairs: Option<&CifPairs>,
+    ctrl_topology: Option<&CtrlTopology>,
+) -> String {
+    // writing to a String cannot fail
+    let mut dot = String::from("digraph dqcmap {\n    compound=true;\n    node [shape=circle];\n");
+    for ctrl in ctrl2pq.controllers() {
+        let mut qubits = ctrl2pq.get_qubits_by_controller(ctrl).unwrap().to_vec();
+        qubits.sort_unstable();
+        writeln!(dot, "    subgraph \"cluster_ctrl_{ctrl}\" {{").unwrap();
+        writeln!(dot, "        label=\"controller {ctrl}\";").unwrap();
+        // an invisible anchor for the edges between controllers
+        writeln!(dot, "        \"ctrl_{ctrl}\" [shape=point, style=invis];").unwrap();
+        for qubit in qubits {
+            writeln!(dot, "        \"q{qubit}\" [label=\"{qubit}\"];").unwrap();
+        }
+        dot.push_str("    }\n");
+    }
+
+    if let Some(ctrl_topology) = ctrl_topology {
+        let links: BTreeMap<(i32, i32), f64> = ctrl_topology
+            .latency
+            .iter()
+            .map(|(&key, &latency)| (key, latency))
+            .collect();
+        for ((ctrl0, ctrl1), latency) in links {
+            writeln!(
+                dot,
+                "    \"ctrl_{ctrl0}\" -> \"ctrl_{ctrl1}\" [ltail=\"cluster_ctrl_{ctrl0}\", \
+                 lhead=\"cluster_ctrl_{ctrl1}\", dir=none, style=dashed, label=\"{latency}\"];"
+            )
+            .unwrap();
+        }
+    }
+
+    if let Some(cif_pairs) = cif_pairs {
+        let mut edges: BTreeMap<(i32, i32), usize> = BTreeMap::new();
+        for pair in cif_pairs.pairs().values().flat_map(|pairs| pairs.iter()) {
+            if is_well_formed(pair) {
+                *edges.entry((pair[1], pair[0])).or_insert(0) += 1;
+            }
+        }
+        for ((measured, conditioned), count) in edges {
+            let style = match (
+                ctrl2pq.get_controller_by_qubit(measured),
+                ctrl2pq.get_controller_by_qubit(conditioned),
+            ) {
+                (Some(ctrl0), Some(ctrl1)) if ctrl0 != ctrl1 => format!("color={CROSS_COLOR}"),
+                (Some(_), Some(_)) => format!("color={INTRA_COLOR}"),
+                _ => "style=dotted".to_string(),
+            };
+            let label = if count > 1 {
+                format!(", label=\"{count}\"")
+            } else {
+                String::new()
+            };
+            writeln!(
+                dot,
+                "    \"q{measured}\" -> \"q{conditioned}\" [{style}{label}];"
+            )
+            .unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashbrown::HashMap;
+
+    #[test]
+    fn test_export_dot() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![1, 0]), (1, vec![2])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![2, 0], vec![1, 0, 3]]),
+            (1, vec![vec![2, 0], vec![5, 2]]),
+        ]));
+        let ctrl_topology =
+            CtrlTopology::new(HashMap::from([((1, 0), 2.5)]), 1., HashMap::new(), None).unwrap();
+        let dot = export_dot(&ctrl2pq, Some(&cif_pairs), Some(&ctrl_topology));
+        assert_eq!(
+            dot,
+            r#"digraph dqcmap {
+    compound=true;
+    node [shape=circle];
+    subgraph "cluster_ctrl_0" {
+        label="controller 0";
+        "ctrl_0" [shape=point, style=invis];
+        "q0" [label="0"];
+        "q1" [label="1"];
+    }
+    subgraph "cluster_ctrl_1" {
+        label="controller 1";
+        "ctrl_1" [shape=point, style=invis];
+        "q2" [label="2"];
+    }
+    "ctrl_0" -> "ctrl_1" [ltail="cluster_ctrl_0", lhead="cluster_ctrl_1", dir=none, style=dashed, label="2.5"];
+    "q0" -> "q1" [color=gray];
+    "q0" -> "q2" [color=red, label="2"];
+    "q2" -> "q5" [style=dotted];
+}
+"#
+        );
+        assert_eq!(export_dot(&ctrl2pq, None, None).matches("->").count(), 0);
+    }
+}
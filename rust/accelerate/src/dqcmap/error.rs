@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "python")]
+use pyo3::create_exception;
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyRuntimeError, PyValueError};
+#[cfg(feature = "python")]
+use pyo3::PyErr;
+
+use super::cost_model::COST_MODEL_NAMES;
+
+#[cfg(feature = "python")]
+create_exception!(
+    dqcmap,
+    DqcMapError,
+    PyValueError,
+    "Raised on invalid input to or invalid operations of the dqcmap accelerators."
+);
+
+/// Errors of the dqcmap accelerators.  In Python, the mapping errors are raised as
+/// :class:`DqcMapError` (a ``ValueError``) and the input errors as the builtin exception they
+/// are named after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MappingError {
+    /// A swap that does not consist of two distinct, non-negative qubits.
+    InvalidSwap(Vec<i32>),
+    /// More swaps were requested to be undone than were applied.
+    UndoOutOfRange { requested: usize, available: usize },
+    /// A cost model name that does not match any of the built-in models.
+    UnknownCostModel(String),
+    /// An input required to build a state that was not given.
+    MissingInput(&'static str),
+    /// A qubit of a cif pair or of a controller outside of the qubits of the device.
+    QubitOutOfRange {
+        qubit: i32,
+        num_qubits: Option<usize>,
+    },
+    /// A qubit of a cif pair of `node` that is not connected to any controller.
+    UnmappedQubit { node: usize, qubit: i32 },
+    /// An invalid argument, raised as a ``ValueError``.
+    Value(String),
+    /// An index out of range, raised as an ``IndexError``.
+    Index(String),
+    /// A missing key, raised as a ``KeyError``.
+    Key(String),
+    /// An internal failure, raised as a ``RuntimeError``.
+    Runtime(String),
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MappingError::InvalidSwap(swap) => write!(
+                f,
+                "swap must consist of two distinct non-negative qubits, got {swap:?}"
+            ),
+            MappingError::UndoOutOfRange {
+                requested,
+                available,
+            } => write!(
+                f,
+                "cannot undo {requested} swaps, only {available} were applied"
+            ),
+            MappingError::UnknownCostModel(name) => write!(
+                f,
+                "unknown cost model '{name}', expected one of {}",
+                COST_MODEL_NAMES.join(", ")
+            ),
+            MappingError::MissingInput(input) => write!(f, "{input} must be set to build a state"),
+            MappingError::QubitOutOfRange {
+                qubit,
+                num_qubits: Some(num_qubits),
+            } => write!(
+                f,
+                "qubit {qubit} is out of range for a device of {num_qubits} qubits"
+            ),
+            MappingError::QubitOutOfRange {
+                qubit,
+                num_qubits: None,
+            } => write!(f, "qubit {qubit} must be non-negative"),
+            MappingError::UnmappedQubit { node, qubit } => write!(
+                f,
+                "qubit {qubit} of a cif pair of node {node} is not connected to any controller"
+            ),
+            MappingError::Value(message)
+            | MappingError::Index(message)
+            | MappingError::Key(message)
+            | MappingError::Runtime(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for MappingError {}
+
+#[cfg(feature = "python")]
+impl From<MappingError> for PyErr {
+    fn from(err: MappingError) -> PyErr {
+        match err {
+            MappingError::Value(message) => PyValueError::new_err(message),
+            MappingError::Index(message) => PyIndexError::new_err(message),
+            MappingError::Key(message) => PyKeyError::new_err(message),
+            MappingError::Runtime(message) => PyRuntimeError::new_err(message),
+            err => DqcMapError::new_err(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::swap::Swap;
+    #[cfg(feature = "python")]
+    use pyo3::prelude::*;
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_mapping_error_to_pyerr() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let err = PyErr::from(MappingError::UndoOutOfRange {
+                requested: 3,
+                available: 1,
+            });
+            assert!(err.is_instance_of::<DqcMapError>(py));
+            // DqcMapError subclasses ValueError, so existing ``except ValueError`` keep working
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(
+                err.value_bound(py).to_string(),
+                "cannot undo 3 swaps, only 1 were applied"
+            );
+
+            let err = PyErr::from(MappingError::UnknownCostModel("depth".to_string()));
+            assert!(err.is_instance_of::<DqcMapError>(py));
+            assert!(err.value_bound(py).to_string().contains("feedback_count"));
+
+            // input errors keep the builtin exception they are named after
+            let err = PyErr::from(MappingError::Index("qubit 5 is out of range".to_string()));
+            assert!(err.is_instance_of::<PyIndexError>(py));
+            assert!(!err.is_instance_of::<DqcMapError>(py));
+            assert_eq!(err.value_bound(py).to_string(), "qubit 5 is out of range");
+            let err = PyErr::from(MappingError::Value("negative weight".to_string()));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(!err.is_instance_of::<DqcMapError>(py));
+        });
+    }
+
+    #[test]
+    fn test_malformed_swaps() {
+        for qubits in [vec![], vec![0], vec![2, 2], vec![-1, 0], vec![0, 1, 2]] {
+            let err = Swap::try_from(qubits.as_slice()).unwrap_err();
+            assert_eq!(err, MappingError::InvalidSwap(qubits.clone()));
+            assert_eq!(
+                err.to_string(),
+                format!("swap must consist of two distinct non-negative qubits, got {qubits:?}")
+            );
+        }
+        assert_eq!(
+            Swap::try_from((3, -2)),
+            Err(MappingError::InvalidSwap(vec![3, -2]))
+        );
+    }
+
+    #[test]
+    fn test_mapping_error_messages() {
+        assert_eq!(
+            MappingError::QubitOutOfRange {
+                qubit: 5,
+                num_qubits: Some(4)
+            }
+            .to_string(),
+            "qubit 5 is out of range for a device of 4 qubits"
+        );
+        assert_eq!(
+            MappingError::QubitOutOfRange {
+                qubit: -1,
+                num_qubits: None
+            }
+            .to_string(),
+            "qubit -1 must be non-negative"
+        );
+        assert_eq!(
+            MappingError::MissingInput("cif_pairs").to_string(),
+            "cif_pairs must be set to build a state"
+        );
+        assert_eq!(
+            MappingError::UnmappedQubit { node: 2, qubit: 7 }.to_string(),
+            "qubit 7 of a cif pair of node 2 is not connected to any controller"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use hashbrown::HashSet;
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyIndexError, PyValueError};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use rustworkx_core::petgraph::prelude::*;
+
+use super::dag::CircuitDag;
+
+/// The nodes of a circuit that are ready to execute, maintained as nodes are retired.
+///
+/// A node joins the front layer once all its predecessors are retired, so that the active nodes
+/// of a routing step can be taken from the front layer instead of being tracked by the caller.
+/// Node ids are the ones of the ``CircuitDag`` the layer is built from.
+///
+/// Args:
+///     dag (CircuitDag): The circuit whose nodes are to be executed.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug)]
+pub struct FrontLayer {
+    /// Shared between forks of the layer, as it never changes.
+    successors: Arc<Vec<Vec<usize>>>,
+    /// Number of predecessors of every node that are not retired yet.
+    required_predecessors: Vec<usize>,
+    /// Nodes whose predecessors are all retired, in the order they became ready.
+    nodes: Vec<usize>,
+    /// Number of retired nodes.
+    num_retired: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl FrontLayer {
+    #[new]
+    #[pyo3(text_signature = "(dag, /)")]
+    fn py_new(dag: &CircuitDag) -> Self {
+        FrontLayer::from_dag(dag)
+    }
+
+    /// The number of retired nodes.
+    #[getter(num_retired)]
+    fn get_num_retired(&self) -> usize {
+        self.num_retired
+    }
+
+    /// The number of nodes in the front layer.
+    fn __len__(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn __contains__(&self, node: usize) -> bool {
+        self.contains(node)
+    }
+
+    /// The ids of the nodes that are ready to execute, in the order they became ready.
+    #[pyo3(name = "nodes", text_signature = "(self, /)")]
+    fn py_nodes(&self) -> Vec<usize> {
+        self.nodes.clone()
+    }
+
+    /// Retire ``node`` from the front layer, activating the successors whose predecessors are
+    /// now all retired.
+    ///
+    /// Returns:
+    ///     list[int]: The ids of the newly activated nodes.
+    ///
+    /// Raises:
+    ///     IndexError: If ``node`` is out of range.
+    ///     ValueError: If ``node`` is not in the front layer.
+    #[pyo3(name = "retire", text_signature = "(self, node, /)")]
+    fn py_retire(&mut self, node: usize) -> PyResult<Vec<usize>> {
+        if node >= self.num_nodes() {
+            return Err(PyIndexError::new_err(format!(
+                "node {} is out of range for {} nodes",
+                node,
+                self.num_nodes()
+            )));
+        }
+        if !self.contains(node) {
+            return Err(PyValueError::new_err(format!(
+                "node {} is not in the front layer",
+                node
+            )));
+        }
+        Ok(self.retire(node))
+    }
+
+    /// The first ``size`` nodes reached from the front layer in breadth-first order, excluding
+    /// the front layer itself, as used for a lookahead.
+    #[pyo3(name = "extended_set", text_signature = "(self, size, /)")]
+    fn py_extended_set(&self, size: usize) -> Vec<usize> {
+        self.extended_set(size)
+    }
+
+    /// Whether every node is retired.
+    #[pyo3(name = "is_done", text_signature = "(self, /)")]
+    fn py_is_done(&self) -> bool {
+        self.is_done()
+    }
+}
+
+impl FrontLayer {
+    /// A front layer over nodes `0..successors.len()`, where `successors[i]` are the distinct
+    /// nodes depending on node `i`.
+    pub fn from_successors(successors: Vec<Vec<usize>>) -> Self {
+        let mut required_predecessors = vec![0; successors.len()];
+        for &successor in successors.iter().flatten() {
+            required_predecessors[successor] += 1;
+        }
+        let nodes = (0..successors.len())
+            .filter(|&node| required_predecessors[node] == 0)
+            .collect();
+        FrontLayer {
+            successors: Arc::new(successors),
+            required_predecessors,
+            nodes,
+            num_retired: 0,
+        }
+    }
+
+    pub fn from_dag(dag: &CircuitDag) -> Self {
+        FrontLayer::from_successors(
+            dag.dag
+                .node_indices()
+                .map(|node| {
+                    let mut successors: Vec<usize> = dag
+                        .dag
+                        .neighbors_directed(node, Direction::Outgoing)
+                        .map(|n| n.index())
+                        .collect();
+                    successors.sort_unstable();
+                    successors
+                })
+                .collect(),
+        )
+    }
+
+    #[inline]
+    pub fn num_nodes(&self) -> usize {
+        self.successors.len()
+    }
+
+    #[inline]
+    pub fn num_retired(&self) -> usize {
+        self.num_retired
+    }
+
+    #[inline]
+    pub fn nodes(&self) -> &[usize] {
+        &self.nodes
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.num_retired == self.num_nodes()
+    }
+
+    #[inline]
+    pub fn contains(&self, node: usize) -> bool {
+        self.nodes.contains(&node)
+    }
+
+    /// Remove `node` from the front layer and append the successors it was the last pending
+    /// predecessor of, which are also returned.  `node` must be in the front layer.
+    pub fn retire(&mut self, node: usize) -> Vec<usize> {
+        let position = self
+            .nodes
+            .iter()
+            .position(|&n| n == node)
+            .expect("only nodes of the front layer can be retired");
+        // keep the order in which the nodes became ready
+        self.nodes.remove(position);
+        self.num_retired += 1;
+        let mut activated = Vec::new();
+        for &successor in &self.successors[node] {
+            self.required_predecessors[successor] -= 1;
+            if self.required_predecessors[successor] == 0 {
+                activated.push(successor);
+            }
+        }
+        self.nodes.extend_from_slice(&activated);
+        activated
+    }
+
+    /// See [FrontLayer::py_extended_set].
+    pub fn extended_set(&self, size: usize) -> Vec<usize> {
+        let mut visited: HashSet<usize> = self.nodes.iter().copied().collect();
+        let mut to_visit: VecDeque<usize> = self.nodes.iter().copied().collect();
+        let mut extended_set = Vec::with_capacity(size);
+        while let Some(node) = to_visit.pop_front() {
+            for &successor in &self.successors[node] {
+                if extended_set.len() == size {
+                    return extended_set;
+                }
+                if visited.insert(successor) {
+                    extended_set.push(successor);
+                    to_visit.push_back(successor);
+                }
+            }
+        }
+        extended_set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_layer_retire_and_extended_set() {
+        // 0 -> 2, 1 -> 2, 2 -> 3, 2 -> 4
+        let mut front_layer =
+            FrontLayer::from_successors(vec![vec![2], vec![2], vec![3, 4], vec![], vec![]]);
+        assert_eq!(front_layer.nodes(), &[0, 1]);
+        assert_eq!(front_layer.extended_set(2), vec![2, 3]);
+        assert_eq!(front_layer.extended_set(10), vec![2, 3, 4]);
+
+        assert!(front_layer.retire(1).is_empty());
+        assert_eq!(front_layer.retire(0), vec![2]);
+        assert_eq!(front_layer.retire(2), vec![3, 4]);
+        assert_eq!(front_layer.nodes(), &[3, 4]);
+        #[cfg(feature = "python")]
+        assert!(front_layer.py_retire(0).is_err());
+        #[cfg(feature = "python")]
+        assert!(front_layer.py_retire(5).is_err());
+        front_layer.retire(4);
+        front_layer.retire(3);
+        assert!(front_layer.is_empty() && front_layer.is_done());
+    }
+
+    #[test]
+    fn test_front_layer_from_dag() {
+        let dag = CircuitDag::new(
+            2,
+            0,
+            vec![
+                ("h".to_string(), vec![0], vec![], None),
+                ("cx".to_string(), vec![0, 1], vec![], None),
+                ("x".to_string(), vec![1], vec![], None),
+            ],
+            false,
+        )
+        .unwrap();
+        let mut front_layer = FrontLayer::from_dag(&dag);
+        assert_eq!(front_layer.nodes(), &[0]);
+        assert_eq!(front_layer.retire(0), vec![1]);
+        assert_eq!(front_layer.retire(1), vec![2]);
+    }
+}
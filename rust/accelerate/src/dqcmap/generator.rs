@@ -0,0 +1,311 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    cif_pairs::CifPairs, coupling_map::CouplingMap, dag::Instruction, pair_list::PairList,
+};
+
+/// The shape of a synthetic workload, see ``random_dynamic_circuit``.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkloadShape {
+    pub num_qubits: usize,
+    /// Probability that an operation is a feedback rather than a two-qubit gate.
+    pub feedback_density: f64,
+    /// Probability that the second qubit of an operation is a neighbor of the first one by index
+    /// rather than any other qubit.
+    pub locality: f64,
+}
+
+impl WorkloadShape {
+    pub fn new(num_qubits: usize, feedback_density: f64, locality: f64) -> PyResult<Self> {
+        if num_qubits < 2 {
+            return Err(PyValueError::new_err(format!(
+                "a workload needs at least 2 qubits, got {num_qubits}"
+            )));
+        }
+        for (name, value) in [
+            ("feedback_density", feedback_density),
+            ("locality", locality),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(PyValueError::new_err(format!(
+                    "{name} must be in [0, 1], got {value}"
+                )));
+            }
+        }
+        Ok(WorkloadShape {
+            num_qubits,
+            feedback_density,
+            locality,
+        })
+    }
+
+    /// A qubit other than `qubit`, one of its index neighbors with probability `locality`.
+    fn partner(&self, rng: &mut Pcg64Mcg, qubit: usize) -> usize {
+        if rng.gen_bool(self.locality) {
+            if qubit == 0 || (qubit + 1 < self.num_qubits && rng.gen_bool(0.5)) {
+                qubit + 1
+            } else {
+                qubit - 1
+            }
+        } else {
+            let other = rng.gen_range(0..self.num_qubits - 1);
+            if other >= qubit {
+                other + 1
+            } else {
+                other
+            }
+        }
+    }
+}
+
+fn rng_from_seed(seed: Option<u64>) -> Pcg64Mcg {
+    match seed {
+        Some(seed) => Pcg64Mcg::seed_from_u64(seed),
+        None => Pcg64Mcg::from_entropy(),
+    }
+}
+
+/// A random dynamic circuit of `num_operations` operations, each of them either a `cx` or, with
+/// probability `feedback_density`, a feedback: a measurement into a fresh clbit followed by an
+/// `x` conditioned on it.  Returns the instructions and the number of clbits.
+pub fn random_dynamic_circuit(
+    shape: WorkloadShape,
+    num_operations: usize,
+    seed: Option<u64>,
+) -> (Vec<Instruction>, usize) {
+    let mut rng = rng_from_seed(seed);
+    let mut instructions = Vec::with_capacity(num_operations);
+    let mut num_clbits = 0;
+    for _ in 0..num_operations {
+        let qubit = rng.gen_range(0..shape.num_qubits);
+        let partner = shape.partner(&mut rng, qubit);
+        if rng.gen_bool(shape.feedback_density) {
+            instructions.push(("measure".to_string(), vec![qubit], vec![num_clbits], None));
+            instructions.push((
+                "x".to_string(),
+                vec![partner],
+                Vec::new(),
+                Some(vec![num_clbits]),
+            ));
+            num_clbits += 1;
+        } else {
+            instructions.push(("cx".to_string(), vec![qubit, partner], Vec::new(), None));
+        }
+    }
+    (instructions, num_clbits)
+}
+
+/// Random cif pairs for nodes `0..num_nodes`, `pairs_per_node` each, as `[conditioned,
+/// measured]` pairs whose qubits are index neighbors with probability `locality`.
+pub fn random_cif_pairs(
+    shape: WorkloadShape,
+    num_nodes: usize,
+    pairs_per_node: usize,
+    seed: Option<u64>,
+) -> CifPairs {
+    let mut rng = rng_from_seed(seed);
+    let pairs: HashMap<usize, PairList> = (0..num_nodes)
+        .map(|node_id| {
+            let mut node_pairs = PairList::with_capacity(pairs_per_node, 2 * pairs_per_node);
+            for _ in 0..pairs_per_node {
+                let measured = rng.gen_range(0..shape.num_qubits);
+                let conditioned = shape.partner(&mut rng, measured);
+                node_pairs.push(&[conditioned as i32, measured as i32]);
+            }
+            (node_id, node_pairs)
+        })
+        .collect();
+    CifPairs::from_lists(pairs)
+}
+
+/// The edges of a standard device topology: `"line"`, `"ring"`, `"grid"` with `num_columns`
+/// columns, or `"full"` for all-to-all connectivity.
+pub fn topology_edges(
+    topology: &str,
+    num_qubits: usize,
+    num_columns: Option<usize>,
+) -> PyResult<Vec<[usize; 2]>> {
+    let edges = match topology {
+        "line" => (1..num_qubits).map(|q| [q - 1, q]).collect(),
+        "ring" => {
+            let mut edges: Vec<[usize; 2]> = (1..num_qubits).map(|q| [q - 1, q]).collect();
+            if num_qubits > 2 {
+                edges.push([num_qubits - 1, 0]);
+            }
+            edges
+        }
+        "grid" => {
+            let columns = num_columns
+                .filter(|&columns| columns > 0 && num_qubits % columns == 0)
+                .ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "a grid needs a number of columns dividing {num_qubits}, got {num_columns:?}"
+                    ))
+                })?;
+            (0..num_qubits)
+                .flat_map(|q| {
+                    let right = (q % columns + 1 < columns).then_some([q, q + 1]);
+                    let down = (q + columns < num_qubits).then_some([q, q + columns]);
+                    right.into_iter().chain(down)
+                })
+                .collect()
+        }
+        "full" => (0..num_qubits)
+            .flat_map(|q0| (q0 + 1..num_qubits).map(move |q1| [q0, q1]))
+            .collect(),
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown topology '{topology}', expected 'line', 'ring', 'grid' or 'full'"
+            )))
+        }
+    };
+    Ok(edges)
+}
+
+/// Generate a random dynamic circuit for synthetic benchmarks.  The same seed always gives the
+/// same circuit.
+///
+/// Args:
+///     num_qubits (int): The number of qubits, at least 2.
+///     num_operations (int): The number of operations, each of them a ``cx`` or a feedback.
+///     feedback_density (float): The probability that an operation is a feedback, i.e. a
+///         measurement into a fresh clbit followed by an ``x`` conditioned on it.
+///     locality (float): The probability that the second qubit of an operation is an index
+///         neighbor of the first one rather than any other qubit.
+///     seed (int): The seed of the generator, a random one if not given.
+///
+/// Returns:
+///     (instructions, num_clbits): The instructions in the format of ``CircuitDag`` and the
+///     number of clbits.
+///
+/// Raises:
+///     ValueError: if there are less than 2 qubits or a probability is not in [0, 1].
+#[pyfunction]
+#[pyo3(
+    name = "random_dynamic_circuit",
+    signature = (num_qubits, num_operations, feedback_density=0.1, locality=0.5, seed=None)
+)]
+pub fn py_random_dynamic_circuit(
+    num_qubits: usize,
+    num_operations: usize,
+    feedback_density: f64,
+    locality: f64,
+    seed: Option<u64>,
+) -> PyResult<(Vec<Instruction>, usize)> {
+    let shape = WorkloadShape::new(num_qubits, feedback_density, locality)?;
+    Ok(random_dynamic_circuit(shape, num_operations, seed))
+}
+
+/// Generate random cif pairs for synthetic benchmarks, as ``[conditioned, measured]`` pairs.
+///
+/// Args:
+///     num_qubits (int): The number of qubits, at least 2.
+///     num_nodes (int): The number of nodes, with ids ``0..num_nodes``.
+///     pairs_per_node (int): The number of pairs of every node.
+///     locality (float): The probability that the qubits of a pair are index neighbors rather
+///         than any two qubits.
+///     seed (int): The seed of the generator, a random one if not given.
+///
+/// Returns:
+///     CifPairs: The generated cif pairs.
+///
+/// Raises:
+///     ValueError: if there are less than 2 qubits or ``locality`` is not in [0, 1].
+#[pyfunction]
+#[pyo3(
+    name = "random_cif_pairs",
+    signature = (num_qubits, num_nodes, pairs_per_node=1, locality=0.5, seed=None)
+)]
+pub fn py_random_cif_pairs(
+    num_qubits: usize,
+    num_nodes: usize,
+    pairs_per_node: usize,
+    locality: f64,
+    seed: Option<u64>,
+) -> PyResult<CifPairs> {
+    let shape = WorkloadShape::new(num_qubits, 0., locality)?;
+    Ok(random_cif_pairs(shape, num_nodes, pairs_per_node, seed))
+}
+
+/// Build the coupling map of a standard device topology.
+///
+/// Args:
+///     topology (str): ``"line"``, ``"ring"``, ``"grid"`` or ``"full"``.
+///     num_qubits (int): The number of physical qubits.
+///     num_columns (int): The number of columns of a grid, which must divide ``num_qubits``.
+///
+/// Returns:
+///     CouplingMap: The coupling map.
+///
+/// Raises:
+///     ValueError: if the topology is unknown or the grid has no valid number of columns.
+#[pyfunction]
+#[pyo3(signature = (topology, num_qubits, num_columns=None))]
+pub fn standard_coupling_map(
+    topology: &str,
+    num_qubits: usize,
+    num_columns: Option<usize>,
+) -> PyResult<CouplingMap> {
+    let edges = topology_edges(topology, num_qubits, num_columns)?
+        .into_iter()
+        .map(|[q0, q1]| [PhysicalQubit::new(q0 as u32), PhysicalQubit::new(q1 as u32)])
+        .collect();
+    Ok(CouplingMap::new(edges, Some(num_qubits))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::dag::CircuitDag;
+
+    #[test]
+    fn test_random_workloads() {
+        let shape = WorkloadShape::new(6, 0.5, 1.).unwrap();
+        let (instructions, num_clbits) = random_dynamic_circuit(shape, 20, Some(7));
+        assert_eq!(random_dynamic_circuit(shape, 20, Some(7)).0, instructions);
+        let num_feedbacks = instructions.iter().filter(|inst| inst.3.is_some()).count();
+        assert_eq!(num_feedbacks, num_clbits);
+        assert_eq!(instructions.len(), 20 + num_feedbacks);
+        // full locality only pairs index neighbors
+        for (name, qubits, _, _) in &instructions {
+            if name == "cx" {
+                assert_eq!(qubits[0].abs_diff(qubits[1]), 1);
+            }
+        }
+        assert!(CircuitDag::new(6, num_clbits, instructions, false).is_ok());
+
+        let shape = WorkloadShape::new(4, 0., 0.).unwrap();
+        let cif_pairs = random_cif_pairs(shape, 3, 2, Some(1));
+        assert_eq!(cif_pairs.node_ids(), vec![0, 1, 2]);
+        for pairs in cif_pairs.pairs().values() {
+            assert_eq!(pairs.len(), 2);
+            assert!(pairs.iter().all(|pair| pair[0] != pair[1]));
+        }
+
+        assert!(WorkloadShape::new(1, 0., 0.).is_err());
+        assert!(WorkloadShape::new(4, 1.5, 0.).is_err());
+    }
+
+    #[test]
+    fn test_topology_edges() {
+        assert_eq!(
+            topology_edges("ring", 4, None).unwrap(),
+            vec![[0, 1], [1, 2], [2, 3], [3, 0]]
+        );
+        assert_eq!(
+            topology_edges("grid", 4, Some(2)).unwrap(),
+            vec![[0, 1], [0, 2], [1, 3], [2, 3]]
+        );
+        assert_eq!(topology_edges("full", 4, None).unwrap().len(), 6);
+        assert!(topology_edges("grid", 5, Some(2)).is_err());
+        assert!(topology_edges("torus", 4, None).is_err());
+        let coupling = standard_coupling_map("line", 3, None).unwrap();
+        assert_eq!(coupling.edges().len(), 2);
+    }
+}
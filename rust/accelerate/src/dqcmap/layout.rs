@@ -0,0 +1,443 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    cif_pairs::{is_well_formed, CifPairs},
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    metrics::{self, Phase},
+};
+
+/// The number of feedbacks between every two logical qubits, as a map from partner to weight for
+/// every logical qubit.
+pub fn feedback_weights(
+    cif_pairs: &CifPairs,
+    num_logical: usize,
+) -> PyResult<Vec<HashMap<usize, usize>>> {
+    let mut weights: Vec<HashMap<usize, usize>> = vec![HashMap::new(); num_logical];
+    for pair in cif_pairs.pairs().values().flat_map(|pairs| pairs.iter()) {
+        if !is_well_formed(pair) {
+            continue;
+        }
+        let [q0, q1] = [pair[0], pair[1]].map(|q| q as usize);
+        if pair[0] < 0 || pair[1] < 0 || q0 >= num_logical || q1 >= num_logical {
+            return Err(PyIndexError::new_err(format!(
+                "cif pair {:?} is out of range for {} logical qubits",
+                pair, num_logical
+            )));
+        }
+        if q0 != q1 {
+            *weights[q0].entry(q1).or_insert(0) += 1;
+            *weights[q1].entry(q0).or_insert(0) += 1;
+        }
+    }
+    Ok(weights)
+}
+
+/// Place `num_logical` logical qubits onto the physical qubits of `ctrl2pq` so that the qubits of
+/// the cif pairs land on the same controller where possible.
+///
+/// Logical qubits are placed greedily, always picking the unplaced qubit with the most feedbacks
+/// towards the already placed ones (the busiest qubit when there is none).  It goes to the
+/// controller holding most of its feedback partners, ties broken by the number of free physical
+/// qubits and then by the controller id.  Within a controller, the physical qubit closest to the
+/// placed partners on `coupling` is used, or the first free one without a coupling map.
+///
+/// Returns the mapping from logical to physical qubit index.
+pub fn controller_aware_layout(
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    num_logical: usize,
+    coupling: Option<&CouplingMap>,
+) -> PyResult<HashMap<usize, i32>> {
+    let _timer = metrics::time_phase(Phase::Layout);
+    let weights = feedback_weights(cif_pairs, num_logical)?;
+
+    let controllers = ctrl2pq.controllers();
+    let mut free: HashMap<i32, Vec<i32>> = HashMap::clone(&ctrl2pq.map);
+    // never use more qubits of a controller than it has channels for
+    for (ctrl, qubits) in free.iter_mut() {
+        if let Some(capacity) = ctrl2pq.get_capacity(*ctrl) {
+            qubits.truncate(capacity);
+        }
+    }
+    let capacity: usize = free.values().map(Vec::len).sum();
+    if num_logical > capacity {
+        return Err(PyValueError::new_err(format!(
+            "{} logical qubits do not fit on the {} usable physical qubits of the controllers",
+            num_logical, capacity
+        )));
+    }
+
+    if let Some(coupling) = coupling {
+        if let Some(phys) = free
+            .values()
+            .flatten()
+            .find(|&&phys| phys < 0 || phys as usize >= coupling.num_qubits())
+        {
+            return Err(PyIndexError::new_err(format!(
+                "qubit index {} is out of range for {} physical qubits",
+                phys,
+                coupling.num_qubits()
+            )));
+        }
+    }
+
+    let mut layout: HashMap<usize, i32> = HashMap::with_capacity(num_logical);
+    let mut affinity = vec![0; num_logical];
+    for _ in 0..num_logical {
+        let logical = (0..num_logical)
+            .filter(|q| !layout.contains_key(q))
+            .max_by_key(|&q| {
+                (
+                    affinity[q],
+                    weights[q].values().sum::<usize>(),
+                    usize::MAX - q,
+                )
+            })
+            .unwrap();
+
+        let ctrl_affinity = |ctrl: i32| -> usize {
+            weights[logical]
+                .iter()
+                .filter(|(partner, _)| {
+                    layout
+                        .get(*partner)
+                        .and_then(|&phys| ctrl2pq.get_controller_by_qubit(phys))
+                        == Some(&ctrl)
+                })
+                .map(|(_, weight)| weight)
+                .sum()
+        };
+        let ctrl = controllers
+            .iter()
+            .copied()
+            .filter(|ctrl| !free[ctrl].is_empty())
+            .max_by_key(|&ctrl| (ctrl_affinity(ctrl), free[&ctrl].len(), -ctrl))
+            .unwrap();
+
+        let candidates = &free[&ctrl];
+        let position = match coupling {
+            Some(coupling) => (0..candidates.len())
+                .min_by_key(|&i| {
+                    weights[logical]
+                        .iter()
+                        .filter_map(|(partner, weight)| {
+                            let phys = *layout.get(partner)?;
+                            let dist = coupling.distance(
+                                PhysicalQubit::new(candidates[i] as u32),
+                                PhysicalQubit::new(phys as u32),
+                            );
+                            Some(dist.saturating_mul(*weight))
+                        })
+                        .fold(0usize, usize::saturating_add)
+                })
+                .unwrap(),
+            None => 0,
+        };
+        let phys = free.get_mut(&ctrl).unwrap().remove(position);
+        layout.insert(logical, phys);
+        for (&partner, weight) in &weights[logical] {
+            affinity[partner] += weight;
+        }
+    }
+    Ok(layout)
+}
+
+/// Compute a controller-aware initial layout.  The GIL is released while placing the qubits.
+///
+/// Args:
+///     cif_pairs (CifPairs): The cif pairs of the circuit on logical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     num_qubits (int): The number of logical qubits to place.
+///     coupling_map (CouplingMap): If given, qubits are placed close to their feedback partners
+///         within a controller.
+///
+/// Returns:
+///     dict[int, int]: The physical qubit of every logical qubit.
+#[pyfunction]
+#[pyo3(signature = (cif_pairs, ctrl2pq, num_qubits, coupling_map=None))]
+pub fn dqcmap_layout(
+    py: Python,
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    num_qubits: usize,
+    coupling_map: Option<&CouplingMap>,
+) -> PyResult<HashMap<usize, i32>> {
+    py.allow_threads(|| controller_aware_layout(cif_pairs, ctrl2pq, num_qubits, coupling_map))
+}
+
+/// Quality metrics of a layout of the cif pairs of a circuit, see ``compare_mappings``.
+///
+/// Attributes:
+///     cross_ctrl_feedbacks (int): The number of cif pairs whose qubits land on different
+///         controllers.
+///     total_distance (int | None): The sum of the hop distances between the qubits of the cif
+///         pairs on the coupling map, ``None`` without a coupling map.  Pairs on disconnected
+///         qubits are not counted.
+///     load_imbalance (float): The largest number of cif pairs involving a single controller
+///         divided by the mean over all controllers, 1 for perfectly balanced controllers.
+#[pyclass(frozen, module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutMetrics {
+    #[pyo3(get)]
+    pub cross_ctrl_feedbacks: usize,
+    #[pyo3(get)]
+    pub total_distance: Option<usize>,
+    #[pyo3(get)]
+    pub load_imbalance: f64,
+}
+
+#[pymethods]
+impl LayoutMetrics {
+    fn __repr__(&self) -> String {
+        format!(
+            "LayoutMetrics(cross_ctrl_feedbacks={}, total_distance={}, load_imbalance={})",
+            self.cross_ctrl_feedbacks,
+            self.total_distance
+                .map_or_else(|| "None".to_string(), |distance| distance.to_string()),
+            self.load_imbalance
+        )
+    }
+}
+
+/// The metrics of two layouts of the same cif pairs, as returned by ``compare_mappings``, with
+/// the change of every metric from ``a`` to ``b``.  Negative deltas mean ``b`` is better.
+#[pyclass(frozen, module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MappingComparison {
+    #[pyo3(get)]
+    pub a: LayoutMetrics,
+    #[pyo3(get)]
+    pub b: LayoutMetrics,
+}
+
+#[pymethods]
+impl MappingComparison {
+    /// The change in cross-controller feedbacks.
+    #[getter]
+    pub fn cross_ctrl_feedbacks_delta(&self) -> i64 {
+        self.b.cross_ctrl_feedbacks as i64 - self.a.cross_ctrl_feedbacks as i64
+    }
+
+    /// The change in total distance, ``None`` without a coupling map.
+    #[getter]
+    pub fn total_distance_delta(&self) -> Option<i64> {
+        Some(self.b.total_distance? as i64 - self.a.total_distance? as i64)
+    }
+
+    /// The change in load imbalance.
+    #[getter]
+    pub fn load_imbalance_delta(&self) -> f64 {
+        self.b.load_imbalance - self.a.load_imbalance
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "MappingComparison(cross_ctrl_feedbacks_delta={}, total_distance_delta={}, \
+             load_imbalance_delta={})",
+            self.cross_ctrl_feedbacks_delta(),
+            self.total_distance_delta()
+                .map_or_else(|| "None".to_string(), |delta| delta.to_string()),
+            self.load_imbalance_delta()
+        )
+    }
+}
+
+/// The metrics of the well-formed cif pairs of logical qubits once placed by `layout`, from
+/// logical to physical qubit.
+pub fn layout_metrics(
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    layout: &HashMap<usize, i32>,
+    coupling: Option<&CouplingMap>,
+) -> PyResult<LayoutMetrics> {
+    let physical = |logical: i32, pair: &[i32]| {
+        usize::try_from(logical)
+            .ok()
+            .and_then(|logical| layout.get(&logical))
+            .copied()
+            .ok_or_else(|| {
+                PyIndexError::new_err(format!(
+                    "logical qubit {logical} of cif pair {pair:?} is not in the layout"
+                ))
+            })
+    };
+    let mut loads: HashMap<i32, usize> = ctrl2pq
+        .controllers()
+        .into_iter()
+        .map(|ctrl| (ctrl, 0))
+        .collect();
+    let mut cross_ctrl_feedbacks = 0;
+    let mut total_distance = coupling.map(|_| 0);
+    for pair in cif_pairs.pairs().values().flat_map(|pairs| pairs.iter()) {
+        if !is_well_formed(pair) {
+            continue;
+        }
+        let (p0, p1) = (physical(pair[0], pair)?, physical(pair[1], pair)?);
+        if let (Some(coupling), Some(total)) = (coupling, total_distance.as_mut()) {
+            let [p0, p1] = [p0, p1].map(|p| PhysicalQubit::new(p as u32));
+            coupling.check_qubit(p0)?;
+            coupling.check_qubit(p1)?;
+            let distance = coupling.distance(p0, p1);
+            if distance != usize::MAX {
+                *total += distance;
+            }
+        }
+        let (Some(&ctrl0), Some(&ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(p0),
+            ctrl2pq.get_controller_by_qubit(p1),
+        ) else {
+            continue;
+        };
+        if ctrl0 != ctrl1 {
+            cross_ctrl_feedbacks += 1;
+            *loads.entry(ctrl1).or_insert(0) += 1;
+        }
+        *loads.entry(ctrl0).or_insert(0) += 1;
+    }
+    let total_load: usize = loads.values().sum();
+    let load_imbalance = match loads.values().max() {
+        Some(&max_load) if total_load > 0 => {
+            max_load as f64 * loads.len() as f64 / total_load as f64
+        }
+        _ => 1.,
+    };
+    Ok(LayoutMetrics {
+        cross_ctrl_feedbacks,
+        total_distance,
+        load_imbalance,
+    })
+}
+
+/// Compare two layouts of the same circuit by the number of cross-controller feedbacks, the
+/// total distance between the qubits of the cif pairs and the balance of the feedbacks across
+/// controllers.  The GIL is released while evaluating the layouts.
+///
+/// Args:
+///     cif_pairs (CifPairs): The cif pairs of the circuit on logical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     layout_a (dict[int, int]): The physical qubit of every logical qubit in the first layout.
+///     layout_b (dict[int, int]): The physical qubit of every logical qubit in the second
+///         layout.
+///     coupling_map (CouplingMap): The coupling map measuring the distance between the qubits
+///         of the cif pairs.  The total distance is not computed if not given.
+///
+/// Returns:
+///     MappingComparison: The metrics of both layouts and their deltas from ``layout_a`` to
+///     ``layout_b``.
+///
+/// Raises:
+///     IndexError: if a qubit of a cif pair is missing from a layout or a physical qubit is not
+///         in the coupling map.
+#[pyfunction]
+#[pyo3(signature = (cif_pairs, ctrl2pq, layout_a, layout_b, coupling_map=None))]
+pub fn compare_mappings(
+    py: Python,
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    layout_a: HashMap<usize, i32>,
+    layout_b: HashMap<usize, i32>,
+    coupling_map: Option<&CouplingMap>,
+) -> PyResult<MappingComparison> {
+    py.allow_threads(|| {
+        Ok(MappingComparison {
+            a: layout_metrics(cif_pairs, ctrl2pq, &layout_a, coupling_map)?,
+            b: layout_metrics(cif_pairs, ctrl2pq, &layout_b, coupling_map)?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl2pq() -> Ctrl2Pq {
+        Ctrl2Pq {
+            map: HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]).into(),
+            reverse_map: HashMap::from([(0, 0), (1, 0), (2, 1), (3, 1)]).into(),
+            capacity: HashMap::new().into(),
+        }
+    }
+
+    #[test]
+    fn test_layout_groups_cif_pairs() {
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 2], vec![1, 3]])]));
+        let ctrl2pq = ctrl2pq();
+        let layout = controller_aware_layout(&cif_pairs, &ctrl2pq, 4, None).unwrap();
+
+        assert_eq!(layout.len(), 4);
+        let ctrl = |q: usize| ctrl2pq.get_controller_by_qubit(layout[&q]);
+        assert_eq!(ctrl(0), ctrl(2));
+        assert_eq!(ctrl(1), ctrl(3));
+        assert_ne!(ctrl(0), ctrl(1));
+    }
+
+    #[test]
+    fn test_compare_mappings() {
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 2], vec![1, 3]])]));
+        let coupling = CouplingMap::new(
+            (0..3)
+                .map(|q| [PhysicalQubit::new(q), PhysicalQubit::new(q + 1)])
+                .collect(),
+            None,
+        )
+        .unwrap();
+        let ctrl2pq = ctrl2pq();
+        let trivial = HashMap::from([(0, 0), (1, 1), (2, 2), (3, 3)]);
+        let grouped = HashMap::from([(0, 0), (1, 2), (2, 1), (3, 3)]);
+
+        let a = layout_metrics(&cif_pairs, &ctrl2pq, &trivial, Some(&coupling)).unwrap();
+        assert_eq!(a.cross_ctrl_feedbacks, 2);
+        assert_eq!(a.total_distance, Some(4));
+        assert_eq!(a.load_imbalance, 1.);
+        let b = layout_metrics(&cif_pairs, &ctrl2pq, &grouped, None).unwrap();
+        assert_eq!((b.cross_ctrl_feedbacks, b.total_distance), (0, None));
+        assert_eq!(b.load_imbalance, 1.);
+
+        let comparison = MappingComparison {
+            a: a.clone(),
+            b: layout_metrics(&cif_pairs, &ctrl2pq, &grouped, Some(&coupling)).unwrap(),
+        };
+        assert_eq!(comparison.cross_ctrl_feedbacks_delta(), -2);
+        assert_eq!(comparison.total_distance_delta(), Some(-2));
+        assert_eq!(comparison.load_imbalance_delta(), 0.);
+        assert_eq!(
+            MappingComparison { a: a.clone(), b }.total_distance_delta(),
+            None
+        );
+
+        // one of two controllers holding all pairs is twice as loaded as the mean
+        let unbalanced = Ctrl2Pq::from_map(HashMap::from([(0, vec![0]), (1, vec![1, 2, 3, 4])]));
+        let one_side = HashMap::from([(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert_eq!(
+            layout_metrics(&cif_pairs, &unbalanced, &one_side, None)
+                .unwrap()
+                .load_imbalance,
+            2.
+        );
+        assert!(layout_metrics(&cif_pairs, &ctrl2pq, &HashMap::from([(0, 0)]), None).is_err());
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 5]])]));
+        assert!(controller_aware_layout(&cif_pairs, &ctrl2pq(), 4, None).is_err());
+        let cif_pairs = CifPairs::from_pairs(HashMap::new());
+        assert!(controller_aware_layout(&cif_pairs, &ctrl2pq(), 5, None).is_err());
+        let mut limited = ctrl2pq();
+        std::sync::Arc::make_mut(&mut limited.capacity).insert(1, 1);
+        assert!(controller_aware_layout(&cif_pairs, &limited, 4, None).is_err());
+        assert_eq!(
+            controller_aware_layout(&cif_pairs, &limited, 3, None)
+                .unwrap()
+                .values()
+                .filter(|&&phys| phys >= 2)
+                .count(),
+            1
+        );
+    }
+}
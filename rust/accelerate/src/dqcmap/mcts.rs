@@ -0,0 +1,541 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
+
+use crate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+use super::{
+    astar::{virtual_gate_pairs, GatePairs},
+    cif_pairs::CifPairs,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    front_layer::FrontLayer,
+    metrics::{self, Phase},
+    router::{gate_dependencies, RouterGate, RouterOutput, RoutingResult, SwapList},
+    state::{DqcMapState, DEFAULT_FEEDBACK_WEIGHT},
+};
+
+/// Number of simulations run before committing to every swap by default.
+pub const DEFAULT_ITERATIONS: usize = 200;
+/// Weight of the exploration term of the UCT selection.
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+/// Epsilon used in minimum-score calculations.
+const BEST_EPSILON: f64 = 1e-10;
+
+/// A partially routed circuit, where every reachable gate executable on `layout` is routed.
+#[derive(Clone)]
+struct SimState {
+    layout: NLayout,
+    front_layer: FrontLayer,
+    /// Number of swaps plus the weighted cross-controller feedbacks of the routed gates.
+    cost: f64,
+    cross_ctrl_feedbacks: f64,
+    swaps_without_progress: usize,
+    /// Indices of the gates routed right after the last swap, in routing order.
+    routed_gates: Vec<usize>,
+}
+
+/// A node of the search tree, reached from its parent by `swap`.
+struct TreeNode {
+    state: SimState,
+    parent: Option<usize>,
+    swap: Option<[PhysicalQubit; 2]>,
+    children: Vec<usize>,
+    /// Candidate swaps not expanded yet.
+    untried: Vec<[PhysicalQubit; 2]>,
+    visits: usize,
+    /// Sum of the costs of the complete routings simulated through this node.
+    total_cost: f64,
+}
+
+struct Mcts<'a> {
+    coupling: &'a CouplingMap,
+    gates: &'a [RouterGate],
+    /// The cif pairs of every gate as conditioned and measured virtual qubits, with their weight.
+    gate_pairs: GatePairs,
+    ctrl2pq: Option<&'a Ctrl2Pq>,
+    feedback_weight: f64,
+    /// Number of swaps without routing a gate after which a rollout forces the closest gate of
+    /// the front layer to be routable.
+    max_swaps_without_progress: usize,
+}
+
+impl<'a> Mcts<'a> {
+    #[inline]
+    fn distance(&self, layout: &NLayout, gate: usize) -> usize {
+        match self.gates[gate].qubits[..] {
+            [a, b] => self.coupling.distance(a.to_phys(layout), b.to_phys(layout)),
+            _ => 1,
+        }
+    }
+
+    #[inline]
+    fn is_cross_controller(&self, layout: &NLayout, pair: [VirtualQubit; 2]) -> bool {
+        let Some(ctrl2pq) = self.ctrl2pq else {
+            return false;
+        };
+        match (
+            ctrl2pq.controller_of(pair[0].to_phys(layout)),
+            ctrl2pq.controller_of(pair[1].to_phys(layout)),
+        ) {
+            (Some(ctrl0), Some(ctrl1)) => ctrl0 != ctrl1,
+            _ => false,
+        }
+    }
+
+    /// The weighted number of cif pairs of the front layer crossing controllers on `layout`.
+    fn front_cross_controller(&self, state: &SimState, layout: &NLayout) -> f64 {
+        state
+            .front_layer
+            .nodes()
+            .iter()
+            .flat_map(|&gate| &self.gate_pairs[gate])
+            .filter(|&&(pair, _)| self.is_cross_controller(layout, pair))
+            .map(|&(_, weight)| weight)
+            .sum()
+    }
+
+    fn candidate_swaps(&self, state: &SimState) -> Vec<[PhysicalQubit; 2]> {
+        DqcMapState::candidate_swaps(
+            self.coupling,
+            state.front_layer.nodes().iter().flat_map(|&gate| {
+                self.gates[gate]
+                    .qubits
+                    .iter()
+                    .map(|qubit| qubit.to_phys(&state.layout))
+            }),
+        )
+    }
+
+    /// Route every gate reachable from `gates` that is executable on the layout of `state`,
+    /// fixing the cost of its cif pairs.
+    fn route_reachable_gates(&self, state: &mut SimState, gates: Vec<usize>) {
+        let mut to_visit = VecDeque::from(gates);
+        while let Some(gate) = to_visit.pop_front() {
+            if self.distance(&state.layout, gate) != 1 {
+                continue;
+            }
+            for &(pair, weight) in &self.gate_pairs[gate] {
+                if self.is_cross_controller(&state.layout, pair) {
+                    state.cross_ctrl_feedbacks += weight;
+                    state.cost += self.feedback_weight * weight;
+                }
+            }
+            state.routed_gates.push(gate);
+            to_visit.extend(state.front_layer.retire(gate));
+        }
+    }
+
+    fn apply_swap(&self, state: &mut SimState, swap: [PhysicalQubit; 2]) {
+        state.layout.swap_physical(swap[0], swap[1]);
+        state.cost += 1.;
+        state.routed_gates.clear();
+        let front = state.front_layer.nodes().to_vec();
+        self.route_reachable_gates(state, front);
+        if state.routed_gates.is_empty() {
+            state.swaps_without_progress += 1;
+        } else {
+            state.swaps_without_progress = 0;
+        }
+    }
+
+    /// The swap of the rollout policy, which is the greedy heuristic of the router: the change
+    /// in distance of the front layer plus `feedback_weight` times the change in its
+    /// cross-controller cif pairs, ties broken at random.  Once the rollout is stuck, the first
+    /// swap on a shortest path of the closest gate is taken instead.
+    fn rollout_swap(
+        &self,
+        state: &SimState,
+        rng: &mut Pcg64Mcg,
+    ) -> PyResult<Option<[PhysicalQubit; 2]>> {
+        if state.swaps_without_progress >= self.max_swaps_without_progress {
+            return self.closest_gate_swap(state).map(Some);
+        }
+        let distance = |layout: &NLayout| -> f64 {
+            state
+                .front_layer
+                .nodes()
+                .iter()
+                .map(|&gate| self.distance(layout, gate) as f64)
+                .sum()
+        };
+        let distance_before = distance(&state.layout);
+        let cross_before = self.front_cross_controller(state, &state.layout);
+        let mut best_score = f64::INFINITY;
+        let mut best_swaps = Vec::new();
+        for swap in self.candidate_swaps(state) {
+            let mut layout = state.layout.clone();
+            layout.swap_physical(swap[0], swap[1]);
+            let score = distance(&layout) - distance_before
+                + self.feedback_weight
+                    * (self.front_cross_controller(state, &layout) - cross_before);
+            if score < best_score - BEST_EPSILON {
+                best_score = score;
+                best_swaps.clear();
+            }
+            if score < best_score + BEST_EPSILON {
+                best_swaps.push(swap);
+            }
+        }
+        Ok(best_swaps.choose(rng).copied())
+    }
+
+    /// The first swap on a shortest path between the qubits of the closest gate of the front
+    /// layer.
+    fn closest_gate_swap(&self, state: &SimState) -> PyResult<[PhysicalQubit; 2]> {
+        let gate = *state
+            .front_layer
+            .nodes()
+            .iter()
+            .min_by_key(|&&gate| self.distance(&state.layout, gate))
+            .unwrap();
+        let qubits = &self.gates[gate].qubits;
+        let [a, b] = [
+            qubits[0].to_phys(&state.layout),
+            qubits[1].to_phys(&state.layout),
+        ];
+        let distance = self.coupling.distance(a, b);
+        if distance == usize::MAX {
+            return Err(PyValueError::new_err(format!(
+                "qubits of node {} are in disconnected components of the coupling map",
+                self.gates[gate].node_id
+            )));
+        }
+        let next = *self
+            .coupling
+            .neighbors(a)
+            .iter()
+            .find(|&&n| self.coupling.distance(n, b) + 1 == distance)
+            .unwrap();
+        Ok([a, next])
+    }
+
+    /// The cost of routing the rest of the circuit from `state` with the rollout policy, plus
+    /// the cost of `state`.
+    fn rollout(&self, mut state: SimState, rng: &mut Pcg64Mcg) -> PyResult<f64> {
+        while !state.front_layer.is_empty() {
+            let Some(swap) = self.rollout_swap(&state, rng)? else {
+                break;
+            };
+            self.apply_swap(&mut state, swap);
+        }
+        Ok(state.cost)
+    }
+
+    fn new_node(
+        &self,
+        state: SimState,
+        parent: Option<usize>,
+        swap: Option<[PhysicalQubit; 2]>,
+        rng: &mut Pcg64Mcg,
+    ) -> TreeNode {
+        let mut untried = if state.front_layer.is_empty() {
+            Vec::new()
+        } else {
+            self.candidate_swaps(&state)
+        };
+        untried.shuffle(rng);
+        TreeNode {
+            state,
+            parent,
+            swap,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            total_cost: 0.,
+        }
+    }
+
+    /// The next swap from `state` and the state it leads to, the most visited child of the root after `iterations`
+    /// simulations.  Every simulation selects a leaf by UCT on the costs normalized by the
+    /// range of the simulated costs, expands one of its swaps and completes the routing with a
+    /// rollout.
+    fn search(
+        &self,
+        state: SimState,
+        iterations: usize,
+        rng: &mut Pcg64Mcg,
+    ) -> PyResult<Option<([PhysicalQubit; 2], SimState)>> {
+        let mut tree = vec![self.new_node(state, None, None, rng)];
+        let (mut min_cost, mut max_cost) = (f64::INFINITY, f64::NEG_INFINITY);
+        for _ in 0..iterations {
+            let mut index = 0;
+            while tree[index].untried.is_empty() && !tree[index].children.is_empty() {
+                let parent = &tree[index];
+                let log_visits = (parent.visits as f64).ln();
+                let range = (max_cost - min_cost).max(BEST_EPSILON);
+                let uct = |child: &TreeNode| {
+                    let mean = child.total_cost / child.visits as f64;
+                    (max_cost - mean) / range
+                        + EXPLORATION * (log_visits / child.visits as f64).sqrt()
+                };
+                index = *parent
+                    .children
+                    .iter()
+                    .max_by(|&&a, &&b| uct(&tree[a]).total_cmp(&uct(&tree[b])))
+                    .unwrap();
+            }
+            if let Some(swap) = tree[index].untried.pop() {
+                let mut child = tree[index].state.clone();
+                self.apply_swap(&mut child, swap);
+                let child = self.new_node(child, Some(index), Some(swap), rng);
+                tree.push(child);
+                let child = tree.len() - 1;
+                tree[index].children.push(child);
+                index = child;
+            }
+            let cost = self.rollout(tree[index].state.clone(), rng)?;
+            min_cost = min_cost.min(cost);
+            max_cost = max_cost.max(cost);
+            let mut current = Some(index);
+            while let Some(node) = current {
+                tree[node].visits += 1;
+                tree[node].total_cost += cost;
+                current = tree[node].parent;
+            }
+        }
+        let best = tree[0].children.iter().copied().max_by(|&a, &b| {
+            let mean = |node: &TreeNode| node.total_cost / node.visits as f64;
+            tree[a]
+                .visits
+                .cmp(&tree[b].visits)
+                .then(mean(&tree[b]).total_cmp(&mean(&tree[a])))
+        });
+        Ok(best.map(|best| {
+            let best = tree.swap_remove(best);
+            (best.swap.unwrap(), best.state)
+        }))
+    }
+}
+
+/// Route `gates` (given in a valid topological order) onto `coupling` like [super::router::route],
+/// but with a Monte Carlo tree search for the swap sequence minimizing the number of swaps plus
+/// `feedback_weight` times the number of cross-controller feedbacks of the routed circuit.
+///
+/// Every swap is committed after `iterations` simulations, each completing the routing with
+/// rollouts of the greedy feedback heuristic, so routing takes `iterations` times longer than
+/// a greedy routing at least.  The rollouts are randomized by an RNG seeded with `seed`, the
+/// result is reproducible for a given seed.
+#[allow(clippy::too_many_arguments)]
+pub fn route_mcts(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    ctrl2pq: Option<&Ctrl2Pq>,
+    cif_pairs: Option<&CifPairs>,
+    feedback_weight: f64,
+    iterations: usize,
+    seed: u64,
+) -> PyResult<RouterOutput> {
+    let _timer = metrics::time_phase(Phase::Routing);
+    let front_layer = gate_dependencies(coupling, initial_layout, gates)?;
+    let search = Mcts {
+        coupling,
+        gates,
+        gate_pairs: virtual_gate_pairs(initial_layout, gates, cif_pairs),
+        ctrl2pq,
+        feedback_weight,
+        max_swaps_without_progress: 10 * coupling.num_qubits().max(1),
+    };
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+
+    let first_layer = front_layer.nodes().to_vec();
+    let mut state = SimState {
+        layout: initial_layout.clone(),
+        front_layer,
+        cost: 0.,
+        cross_ctrl_feedbacks: 0.,
+        swaps_without_progress: 0,
+        routed_gates: Vec::new(),
+    };
+    search.route_reachable_gates(&mut state, first_layer);
+
+    let mut swaps: SwapList = Vec::new();
+    let mut gate_order: Vec<usize> = state
+        .routed_gates
+        .iter()
+        .map(|&gate| gates[gate].node_id)
+        .collect();
+    let mut pending_swaps = Vec::new();
+    while !state.front_layer.is_empty() {
+        let next = if state.swaps_without_progress >= search.max_swaps_without_progress {
+            let swap = search.closest_gate_swap(&state)?;
+            let mut next = state.clone();
+            search.apply_swap(&mut next, swap);
+            Some((swap, next))
+        } else {
+            search.search(state.clone(), iterations.max(1), &mut rng)?
+        };
+        let Some((swap, next)) = next else {
+            return Err(PyValueError::new_err(
+                "no swap sequence routes every gate, the coupling map is disconnected",
+            ));
+        };
+        pending_swaps.push(swap);
+        if let Some(&first) = next.routed_gates.first() {
+            let node_id = gates[first].node_id;
+            swaps.extend(pending_swaps.drain(..).map(|swap| (node_id, swap)));
+            gate_order.extend(next.routed_gates.iter().map(|&gate| gates[gate].node_id));
+        }
+        state = next;
+    }
+    Ok(RouterOutput {
+        swaps,
+        gate_order,
+        final_layout: state.layout,
+        cross_ctrl_feedbacks: (ctrl2pq.is_some() && cif_pairs.is_some())
+            .then_some(state.cross_ctrl_feedbacks),
+        trace: None,
+    })
+}
+
+/// Route a circuit with a Monte Carlo tree search for the swap sequence minimizing the number of
+/// swaps plus ``beta`` times the number of cross-controller feedbacks, instead of the greedy
+/// choices of ``dqcmap_routing``.  Every swap is committed after ``iterations`` simulations
+/// whose rollouts route the rest of the circuit with the greedy feedback heuristic.  The GIL is
+/// released while routing.
+///
+/// The search is much slower than the greedy router and meant to remove the last
+/// cross-controller feedbacks of circuits that are compiled once and run often.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     initial_layout (NLayout): The initial virtual to physical qubit layout.
+///     gates (list[tuple[int, list[int]]]): The node id and virtual qubits of every gate, in a
+///         valid topological order.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     cif_pairs (CifPairs): The cif pairs of the circuit keyed by node id, on the physical
+///         qubits of ``initial_layout``.
+///     beta (float): The cost of a cross-controller feedback relative to a swap.
+///     iterations (int): The number of simulations run before committing to every swap.
+///     seed (int): The seed of the RNG of the rollouts, 0 if not given.
+///
+/// Returns:
+///     RoutingResult: As for ``dqcmap_routing``.
+///
+/// Raises:
+///     ValueError: If ``beta`` is negative, ``iterations`` is 0 or a gate cannot be routed.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (coupling_map, initial_layout, gates, ctrl2pq=None, cif_pairs=None, beta=DEFAULT_FEEDBACK_WEIGHT, iterations=DEFAULT_ITERATIONS, seed=None))]
+pub fn dqcmap_mcts_routing(
+    py: Python,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: Vec<(usize, Vec<VirtualQubit>)>,
+    ctrl2pq: Option<Ctrl2Pq>,
+    cif_pairs: Option<CifPairs>,
+    beta: f64,
+    iterations: usize,
+    seed: Option<u64>,
+) -> PyResult<RoutingResult> {
+    if !(beta.is_finite() && beta >= 0.) {
+        return Err(PyValueError::new_err(format!(
+            "beta must be a non-negative finite number, got {beta}"
+        )));
+    }
+    if iterations == 0 {
+        return Err(PyValueError::new_err(
+            "the number of iterations must be positive",
+        ));
+    }
+    let gates: Vec<RouterGate> = gates
+        .into_iter()
+        .map(|(node_id, qubits)| RouterGate { node_id, qubits })
+        .collect();
+    let start = Instant::now();
+    let output = py.allow_threads(|| {
+        route_mcts(
+            coupling_map,
+            initial_layout,
+            &gates,
+            ctrl2pq.as_ref(),
+            cif_pairs.as_ref(),
+            beta,
+            iterations,
+            seed.unwrap_or(0),
+        )
+    })?;
+    let elapsed = start.elapsed();
+    Ok(RoutingResult::new(output, initial_layout, &gates, elapsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashbrown::HashMap;
+
+    fn line(num_qubits: u32) -> CouplingMap {
+        let edges = (0..num_qubits - 1)
+            .map(|q| [PhysicalQubit::new(q), PhysicalQubit::new(q + 1)])
+            .collect();
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    fn gate(node_id: usize, qubits: &[u32]) -> RouterGate {
+        RouterGate {
+            node_id,
+            qubits: qubits.iter().map(|&q| VirtualQubit::new(q)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_mcts_routes_every_gate() {
+        let layout = NLayout::generate_trivial_layout(5);
+        let gates = vec![
+            gate(0, &[0, 4]),
+            gate(1, &[1, 3]),
+            gate(2, &[0, 2]),
+            gate(3, &[4, 1]),
+        ];
+        let route = |seed| route_mcts(&line(5), &layout, &gates, None, None, 0., 50, seed).unwrap();
+        let output = route(3);
+        assert_eq!(output.gate_order.len(), 4);
+        assert_eq!(output.swaps, route(3).swaps);
+
+        let mut layout = layout.clone();
+        let mut swaps = output.swaps.iter().peekable();
+        for node_id in &output.gate_order {
+            while let Some((_, [a, b])) = swaps.next_if(|(swap_node, _)| swap_node == node_id) {
+                assert_eq!(line(5).distance(*a, *b), 1);
+                layout.swap_physical(*a, *b);
+            }
+            let gate = gates.iter().find(|gate| gate.node_id == *node_id).unwrap();
+            let [p0, p1] = [
+                gate.qubits[0].to_phys(&layout),
+                gate.qubits[1].to_phys(&layout),
+            ];
+            assert_eq!(line(5).distance(p0, p1), 1);
+        }
+        assert!(swaps.next().is_none());
+    }
+
+    #[test]
+    fn test_mcts_avoids_cross_controller_feedback() {
+        // 0 - 1 - 2 - 3 with controllers {0, 1} and {2, 3}; node 1 measures qubit 0 and
+        // conditions qubit 3 after they interact, which is within a controller only if one of
+        // them takes both swaps
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(0, &[0, 3]), gate(1, &[3])];
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![3, 0]])]));
+        let output = route_mcts(
+            &line(4),
+            &layout,
+            &gates,
+            Some(&ctrl2pq),
+            Some(&cif_pairs),
+            10.,
+            DEFAULT_ITERATIONS,
+            0,
+        )
+        .unwrap();
+        assert_eq!(output.cross_ctrl_feedbacks, Some(0.));
+        assert_eq!(output.swaps.len(), 2);
+        assert_eq!(output.gate_order, vec![0, 1]);
+    }
+}
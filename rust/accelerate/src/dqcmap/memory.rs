@@ -0,0 +1,107 @@
+use std::mem::size_of;
+
+use hashbrown::HashMap;
+use im::OrdMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// The heap memory held by a vector, from its capacity.
+#[inline]
+pub fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+/// An estimate of the heap memory held by the table of a hash map, from its capacity: hashbrown
+/// keeps a power-of-two number of buckets at most 7/8 full, each with one control byte.
+pub fn map_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    if map.capacity() == 0 {
+        return 0;
+    }
+    let buckets = (map.capacity() * 8 / 7).next_power_of_two();
+    buckets * (size_of::<(K, V)>() + 1)
+}
+
+/// An estimate of the heap memory held by the nodes of a persistent B-tree map, assuming its
+/// nodes of 64 entries are two thirds full on average.  Nodes shared with clones of the map are
+/// counted as well.
+pub fn ord_map_bytes<K: Clone + Ord, V: Clone>(map: &OrdMap<K, V>) -> usize {
+    const NODE_CAPACITY: usize = 64;
+    let num_nodes = (map.len() * 3 + 2 * NODE_CAPACITY - 1) / (2 * NODE_CAPACITY);
+    num_nodes * (NODE_CAPACITY * (size_of::<K>() + size_of::<V>()) + 2 * size_of::<usize>())
+}
+
+/// The memory used by the cif pairs and the routing state, see ``CifPairs.memory_stats`` and
+/// ``DqcMapState.memory_stats``.
+///
+/// Byte counts are estimates of the heap allocations from the capacity of every container.
+/// Structures shared between clones are counted in full by each of them.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub num_nodes: usize,
+    pub num_pairs: usize,
+    /// Bytes of the pairs of every node.
+    pub pair_bytes: usize,
+    /// Number of (node, position) entries of the qubit index, one per occurrence of a qubit.
+    pub index_entries: usize,
+    /// Bytes of the qubit index and of the per-node qubit bitsets.
+    pub index_bytes: usize,
+    pub ctrl2pq_bytes: usize,
+    pub undo_log_entries: usize,
+    pub undo_log_bytes: usize,
+    pub score_cache_entries: usize,
+    pub score_cache_bytes: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl MemoryStats {
+    /// The sum of all byte counts.
+    #[pyo3(name = "total_bytes", text_signature = "(self, /)")]
+    fn py_total_bytes(&self) -> usize {
+        self.total_bytes()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "MemoryStats(num_nodes={}, num_pairs={}, pair_bytes={}, index_bytes={}, \
+             ctrl2pq_bytes={}, undo_log_bytes={}, score_cache_bytes={})",
+            self.num_nodes,
+            self.num_pairs,
+            self.pair_bytes,
+            self.index_bytes,
+            self.ctrl2pq_bytes,
+            self.undo_log_bytes,
+            self.score_cache_bytes
+        )
+    }
+}
+
+impl MemoryStats {
+    /// The sum of all byte counts.
+    pub fn total_bytes(&self) -> usize {
+        self.pair_bytes
+            + self.index_bytes
+            + self.ctrl2pq_bytes
+            + self.undo_log_bytes
+            + self.score_cache_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_bytes() {
+        assert_eq!(vec_bytes(&Vec::<u64>::with_capacity(4)), 32);
+        assert_eq!(map_bytes(&HashMap::<i32, i32>::new()), 0);
+        let map: HashMap<i32, i32> = HashMap::with_capacity(7);
+        assert!(map_bytes(&map) >= 7 * 9);
+        assert_eq!(ord_map_bytes(&OrdMap::<i32, i32>::new()), 0);
+        assert!(ord_map_bytes(&OrdMap::unit(0, 0)) >= 8);
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+
+/// Whether the counters are updated, off by default so that uninstrumented runs only pay for a
+/// relaxed load per event.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SCORE_CALLS: AtomicU64 = AtomicU64::new(0);
+static PAIRS_SCANNED: AtomicU64 = AtomicU64::new(0);
+static SWAPS_APPLIED: AtomicU64 = AtomicU64::new(0);
+static PHASE_NANOS: [AtomicU64; Phase::ALL.len()] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// A timed phase of the mapping pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Layout,
+    Routing,
+    ScoreBatch,
+    Simulation,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [
+        Phase::Layout,
+        Phase::Routing,
+        Phase::ScoreBatch,
+        Phase::Simulation,
+    ];
+
+    #[cfg(feature = "python")]
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Layout => "layout",
+            Phase::Routing => "routing",
+            Phase::ScoreBatch => "score_batch",
+            Phase::Simulation => "simulation",
+        }
+    }
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Count a swap scored by a [DqcMapState](super::state::DqcMapState).
+#[inline]
+pub fn record_score_call() {
+    if is_enabled() {
+        SCORE_CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Count `num_pairs` cif pairs looked up while scoring.
+#[inline]
+pub fn record_pairs_scanned(num_pairs: usize) {
+    if is_enabled() {
+        PAIRS_SCANNED.fetch_add(num_pairs as u64, Ordering::Relaxed);
+    }
+}
+
+/// Count a swap applied to a [DqcMapState](super::state::DqcMapState).
+#[inline]
+pub fn record_swap_applied() {
+    if is_enabled() {
+        SWAPS_APPLIED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Adds the time until it is dropped to its phase, if the counters were enabled when it was
+/// created.
+pub struct PhaseTimer(Option<(Phase, Instant)>);
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if let Some((phase, start)) = self.0 {
+            PHASE_NANOS[phase as usize]
+                .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Time `phase` until the returned guard goes out of scope.
+#[inline]
+pub fn time_phase(phase: Phase) -> PhaseTimer {
+    PhaseTimer(is_enabled().then(|| (phase, Instant::now())))
+}
+
+/// Turn the instrumentation counters on or off.  They are off by default.
+#[cfg_attr(feature = "python", pyfunction(signature = (enabled=true)))]
+pub fn enable_metrics(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Reset all instrumentation counters to zero.
+#[cfg_attr(feature = "python", pyfunction)]
+pub fn reset_metrics() {
+    for counter in [&SCORE_CALLS, &PAIRS_SCANNED, &SWAPS_APPLIED]
+        .into_iter()
+        .chain(&PHASE_NANOS)
+    {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The instrumentation counters collected since they were last reset, while enabled with
+/// ``enable_metrics``.
+///
+/// Returns:
+///     dict: ``score_calls`` (number of swaps scored), ``pairs_scanned`` (number of cif pairs
+///     looked up while scoring), ``swaps_applied`` and ``phase_seconds``, the time spent in each
+///     of the ``layout``, ``routing``, ``score_batch`` and ``simulation`` phases.
+#[cfg(feature = "python")]
+#[pyfunction]
+pub fn get_metrics(py: Python) -> PyResult<Bound<PyDict>> {
+    let metrics = PyDict::new_bound(py);
+    metrics.set_item("score_calls", SCORE_CALLS.load(Ordering::Relaxed))?;
+    metrics.set_item("pairs_scanned", PAIRS_SCANNED.load(Ordering::Relaxed))?;
+    metrics.set_item("swaps_applied", SWAPS_APPLIED.load(Ordering::Relaxed))?;
+    let phase_seconds = PyDict::new_bound(py);
+    for phase in Phase::ALL {
+        let nanos = PHASE_NANOS[phase as usize].load(Ordering::Relaxed);
+        phase_seconds.set_item(phase.name(), nanos as f64 * 1e-9)?;
+    }
+    metrics.set_item("phase_seconds", phase_seconds)?;
+    Ok(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_counters() {
+        // other tests may run concurrently with the counters enabled, so only lower bounds hold
+        enable_metrics(true);
+        let before = SCORE_CALLS.load(Ordering::Relaxed);
+        record_score_call();
+        record_pairs_scanned(3);
+        {
+            let _timer = time_phase(Phase::Routing);
+        }
+        assert!(SCORE_CALLS.load(Ordering::Relaxed) > before);
+        assert!(PAIRS_SCANNED.load(Ordering::Relaxed) >= 3);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_get_metrics() {
+        enable_metrics(true);
+        {
+            let _timer = time_phase(Phase::Routing);
+        }
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let metrics = get_metrics(py).unwrap();
+            let phase_seconds = metrics.get_item("phase_seconds").unwrap().unwrap();
+            assert!(phase_seconds.get_item("routing").is_ok());
+            assert!(metrics.get_item("swaps_applied").unwrap().is_some());
+        });
+    }
+}
@@ -1,15 +1,152 @@
-use cif_pairs::CifPairs;
-use ctrl_to_pq::Ctrl2Pq;
-use pyo3::prelude::*;
-use pyo3::{types::PyModule, Bound, PyResult};
+#[cfg(feature = "python")]
+use {
+    builder::DqcMapStateBuilder,
+    cif_pairs::{CifPairs, CifPairsBuilder, FeedbackStats},
+    clbit_to_ctrl::Clbit2Ctrl,
+    config::DqcMapConfig,
+    control_flow::ControlFlowModel,
+    coupling_map::CouplingMap,
+    crosstalk::CrosstalkMap,
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+    dag::CircuitDag,
+    device::DeviceProperties,
+    distance::DistanceMatrix,
+    error::DqcMapError,
+    front_layer::FrontLayer,
+    layout::{LayoutMetrics, MappingComparison},
+    memory::MemoryStats,
+    noise::NoiseModel,
+    partition::Refinement,
+    pyo3::prelude::*,
+    pyo3::{types::PyModule, wrap_pyfunction, Bound, PyResult},
+    qubit_layout::Layout,
+    router::RoutingResult,
+    scheduler::Schedule,
+    sim::SimResult,
+    state::DqcMapState,
+    trace::RoutingTrace,
+};
 
+#[cfg(feature = "python")]
+pub mod anneal;
+#[cfg(feature = "python")]
+pub mod astar;
+pub mod bitset;
+pub mod builder;
 pub mod cif_pairs;
+pub mod classical_flow;
+pub mod clbit_to_ctrl;
+pub mod config;
+pub mod control_flow;
+pub mod cost_model;
+pub mod coupling_map;
+pub mod crosstalk;
 pub mod ctrl_to_pq;
+pub mod ctrl_topology;
+pub mod dag;
+pub mod device;
+pub mod distance;
+pub mod dot;
+pub mod error;
+pub mod front_layer;
+#[cfg(feature = "python")]
+pub mod generator;
+#[cfg(feature = "python")]
+pub mod layout;
+#[cfg(feature = "python")]
+pub mod mcts;
+pub mod memory;
+pub mod metrics;
+pub mod noise;
+pub mod pair_list;
+pub mod pareto;
+#[cfg(feature = "python")]
+pub mod partition;
+#[cfg(feature = "python")]
+pub mod qasm3;
+#[cfg(feature = "python")]
+pub mod qubit_layout;
+pub mod router;
+#[cfg(feature = "python")]
+pub mod scheduler;
+pub mod score_cache;
+pub mod sim;
 pub mod state;
+pub mod swap;
+#[cfg(feature = "python")]
+pub mod swap_cancel;
+#[cfg(feature = "python")]
+pub mod token_swap;
+pub mod trace;
+#[cfg(feature = "python")]
+pub mod vf2;
 
+// Free-threaded CPython is not supported yet: the module does not declare `gil_used = false`, so
+// importing it re-enables the GIL.  Declaring it needs pyo3 >= 0.23, whose borrow flags are
+// atomic, a numpy release matching it (the workspace pins numpy 0.21) and a non-abi3 wheel.
+// State of the audit for that step: the read-only models (cif pairs, coupling map, distance
+// matrix, device properties, configuration and results) are frozen pyclasses without borrow
+// flags, and the only global state, the metrics counters, is atomic.  The mutable ones
+// (Ctrl2Pq, DqcMapState, the builders, CircuitDag, FrontLayer, RoutingTrace, ...) have no
+// synchronization of their own and rely on pyo3's borrow checking alone.
+#[cfg(feature = "python")]
 #[pymodule]
 pub fn dqcmap(m: &Bound<PyModule>) -> PyResult<()> {
     m.add_class::<CifPairs>()?;
+    m.add_class::<CifPairsBuilder>()?;
+    m.add_class::<CircuitDag>()?;
+    m.add_class::<Clbit2Ctrl>()?;
+    m.add_class::<ControlFlowModel>()?;
+    m.add_class::<CouplingMap>()?;
+    m.add_class::<CrosstalkMap>()?;
     m.add_class::<Ctrl2Pq>()?;
+    m.add_class::<CtrlTopology>()?;
+    m.add_class::<DeviceProperties>()?;
+    m.add_class::<DistanceMatrix>()?;
+    m.add_class::<DqcMapConfig>()?;
+    m.add_class::<DqcMapState>()?;
+    m.add_class::<DqcMapStateBuilder>()?;
+    m.add_class::<FeedbackStats>()?;
+    m.add_class::<FrontLayer>()?;
+    m.add_class::<Layout>()?;
+    m.add_class::<LayoutMetrics>()?;
+    m.add_class::<MappingComparison>()?;
+    m.add_class::<MemoryStats>()?;
+    m.add_class::<NoiseModel>()?;
+    m.add_class::<Refinement>()?;
+    m.add_class::<RoutingResult>()?;
+    m.add_class::<RoutingTrace>()?;
+    m.add_class::<Schedule>()?;
+    m.add_class::<SimResult>()?;
+    m.add("DqcMapError", m.py().get_type_bound::<DqcMapError>())?;
+    m.add_wrapped(wrap_pyfunction!(anneal::dqcmap_anneal_layout))?;
+    m.add_wrapped(wrap_pyfunction!(astar::dqcmap_astar_routing))?;
+    m.add_wrapped(wrap_pyfunction!(classical_flow::extract_cif_pairs))?;
+    m.add_wrapped(wrap_pyfunction!(ctrl_topology::py_recommend_interconnect))?;
+    m.add_wrapped(wrap_pyfunction!(generator::py_random_cif_pairs))?;
+    m.add_wrapped(wrap_pyfunction!(generator::py_random_dynamic_circuit))?;
+    m.add_wrapped(wrap_pyfunction!(generator::standard_coupling_map))?;
+    m.add_wrapped(wrap_pyfunction!(layout::compare_mappings))?;
+    m.add_wrapped(wrap_pyfunction!(layout::dqcmap_layout))?;
+    m.add_wrapped(wrap_pyfunction!(mcts::dqcmap_mcts_routing))?;
+    m.add_wrapped(wrap_pyfunction!(metrics::enable_metrics))?;
+    m.add_wrapped(wrap_pyfunction!(metrics::get_metrics))?;
+    m.add_wrapped(wrap_pyfunction!(metrics::reset_metrics))?;
+    m.add_wrapped(wrap_pyfunction!(partition::partition_controllers))?;
+    m.add_wrapped(wrap_pyfunction!(partition::refine_controllers))?;
+    m.add_wrapped(wrap_pyfunction!(qasm3::cif_pairs_from_qasm3))?;
+    m.add_wrapped(wrap_pyfunction!(router::dqcmap_routing))?;
+    m.add_wrapped(wrap_pyfunction!(router::py_route))?;
+    m.add_wrapped(wrap_pyfunction!(scheduler::py_schedule))?;
+    m.add_wrapped(wrap_pyfunction!(sim::check_feedback_bandwidth))?;
+    m.add_wrapped(wrap_pyfunction!(sim::estimate_execution_time))?;
+    m.add_wrapped(wrap_pyfunction!(sim::py_estimate_latency))?;
+    m.add_wrapped(wrap_pyfunction!(state::score_swaps))?;
+    m.add_wrapped(wrap_pyfunction!(swap_cancel::py_cancel_swaps))?;
+    m.add_wrapped(wrap_pyfunction!(swap_cancel::route_instructions))?;
+    m.add_wrapped(wrap_pyfunction!(token_swap::py_token_swap))?;
+    m.add_wrapped(wrap_pyfunction!(token_swap::restore_layout))?;
+    m.add_wrapped(wrap_pyfunction!(vf2::dqcmap_vf2_layout))?;
     Ok(())
 }
@@ -0,0 +1,170 @@
+use hashbrown::HashMap;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::error::MappingError;
+
+/// Number of two-qubit gates a swap is decomposed into.
+const GATES_PER_SWAP: i32 = 3;
+
+/// Error rates of the device used to make swap scoring noise-aware.
+///
+/// A swap is decomposed into three two-qubit gates on its edge, so its cost is the probability
+/// that at least one of them fails.  When a swap moves a measured qubit of a cif pair, the change
+/// of readout error between the two physical qubits is added to the cost as well.  Edges and
+/// qubits without an explicit error rate are ideal.
+///
+/// Args:
+///     edge_error (dict[tuple[int, int], float]): The two-qubit gate error rate of each edge, the
+///         direction of the edge is ignored.
+///     readout_error (dict[int, float]): The readout error rate of each physical qubit.
+///     weight (float): Multiplier applied to the noise cost when it is combined with the
+///         cross-controller feedback score.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseModel {
+    // error rate of an edge, keyed by the (smaller, larger) qubit indices
+    pub edge_error: HashMap<(i32, i32), f64>,
+    pub readout_error: HashMap<i32, f64>,
+    pub weight: f64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl NoiseModel {
+    #[new]
+    #[pyo3(signature = (edge_error=HashMap::new(), readout_error=HashMap::new(), weight=1.0))]
+    fn py_new(
+        edge_error: HashMap<(i32, i32), f64>,
+        readout_error: HashMap<i32, f64>,
+        weight: f64,
+    ) -> PyResult<Self> {
+        Ok(NoiseModel::new(edge_error, readout_error, weight)?)
+    }
+
+    #[getter(weight)]
+    fn get_weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Set the two-qubit gate error rate of the edge between ``q0`` and ``q1``.
+    #[pyo3(name = "set_edge_error", text_signature = "(self, q0, q1, error, /)")]
+    fn py_set_edge_error(&mut self, q0: i32, q1: i32, error: f64) -> PyResult<()> {
+        Ok(self.set_edge_error(q0, q1, error)?)
+    }
+
+    /// Set the readout error rate of ``qubit``.
+    #[pyo3(name = "set_readout_error", text_signature = "(self, qubit, error, /)")]
+    fn py_set_readout_error(&mut self, qubit: i32, error: f64) -> PyResult<()> {
+        Ok(self.set_readout_error(qubit, error)?)
+    }
+
+    /// The probability that a swap between ``q0`` and ``q1`` fails.
+    #[pyo3(name = "swap_error", text_signature = "(self, q0, q1, /)")]
+    fn py_swap_error(&self, q0: i32, q1: i32) -> f64 {
+        self.swap_error(q0, q1)
+    }
+
+    /// The readout error rate of ``qubit``.
+    #[pyo3(name = "readout", text_signature = "(self, qubit, /)")]
+    fn py_readout(&self, qubit: i32) -> f64 {
+        self.readout(qubit)
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (
+            py.get_type_bound::<Self>(),
+            (
+                self.edge_error.clone(),
+                self.readout_error.clone(),
+                self.weight,
+            ),
+        )
+            .into_py(py)
+    }
+}
+
+impl NoiseModel {
+    pub fn new(
+        edge_error: HashMap<(i32, i32), f64>,
+        readout_error: HashMap<i32, f64>,
+        weight: f64,
+    ) -> Result<Self, MappingError> {
+        if !(weight.is_finite() && weight >= 0.) {
+            return Err(MappingError::Value(format!(
+                "noise weight must be a non-negative finite number, got {weight}"
+            )));
+        }
+        let mut noise = NoiseModel {
+            edge_error: HashMap::with_capacity(edge_error.len()),
+            readout_error: HashMap::with_capacity(readout_error.len()),
+            weight,
+        };
+        for ((q0, q1), error) in edge_error {
+            noise.set_edge_error(q0, q1, error)?;
+        }
+        for (qubit, error) in readout_error {
+            noise.set_readout_error(qubit, error)?;
+        }
+        Ok(noise)
+    }
+
+    pub fn set_edge_error(&mut self, q0: i32, q1: i32, error: f64) -> Result<(), MappingError> {
+        check_error_rate(error)?;
+        self.edge_error.insert(key(q0, q1), error);
+        Ok(())
+    }
+
+    pub fn set_readout_error(&mut self, qubit: i32, error: f64) -> Result<(), MappingError> {
+        check_error_rate(error)?;
+        self.readout_error.insert(qubit, error);
+        Ok(())
+    }
+
+    pub fn swap_error(&self, q0: i32, q1: i32) -> f64 {
+        let error = self.edge_error.get(&key(q0, q1)).unwrap_or(&0.);
+        1. - (1. - error).powi(GATES_PER_SWAP)
+    }
+
+    pub fn readout(&self, qubit: i32) -> f64 {
+        *self.readout_error.get(&qubit).unwrap_or(&0.)
+    }
+}
+
+#[inline]
+pub(super) fn key(q0: i32, q1: i32) -> (i32, i32) {
+    (q0.min(q1), q0.max(q1))
+}
+
+pub(super) fn check_error_rate(error: f64) -> Result<(), MappingError> {
+    if (0.0..=1.0).contains(&error) {
+        Ok(())
+    } else {
+        Err(MappingError::Value(format!(
+            "error rate must be in [0, 1], got {error}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_model_errors() {
+        let noise = NoiseModel::new(
+            HashMap::from([((1, 0), 0.5)]),
+            HashMap::from([(2, 0.1)]),
+            1.,
+        )
+        .unwrap();
+        assert_eq!(noise.swap_error(0, 1), 0.875);
+        assert_eq!(noise.swap_error(1, 0), 0.875);
+        assert_eq!(noise.swap_error(1, 2), 0.);
+        assert_eq!(noise.readout(2), 0.1);
+        assert_eq!(noise.readout(3), 0.);
+        assert!(NoiseModel::new(HashMap::from([((0, 1), 1.5)]), HashMap::new(), 1.).is_err());
+        assert!(NoiseModel::new(HashMap::new(), HashMap::new(), -1.).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use super::memory::vec_bytes;
+
+/// The cif pairs of a node stored back to back in a single array, with the offset of every pair
+/// in a second one, so that a node costs two allocations however many pairs it has.
+///
+/// Pairs usually hold two qubits, optionally followed by a clbit, but any length is kept as is.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PairList {
+    // pair `i` is `values[offsets[i]..offsets[i + 1]]`
+    offsets: Vec<u32>,
+    values: Vec<i32>,
+}
+
+impl Default for PairList {
+    fn default() -> Self {
+        PairList::with_capacity(0, 0)
+    }
+}
+
+impl fmt::Debug for PairList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PairList {
+    pub fn new() -> Self {
+        PairList::default()
+    }
+
+    /// An empty list with room for `num_pairs` pairs of `num_values` qubits and clbits in total.
+    pub fn with_capacity(num_pairs: usize, num_values: usize) -> Self {
+        let mut offsets = Vec::with_capacity(num_pairs + 1);
+        offsets.push(0);
+        PairList {
+            offsets,
+            values: Vec::with_capacity(num_values),
+        }
+    }
+
+    pub fn push(&mut self, pair: &[i32]) {
+        self.values.extend_from_slice(pair);
+        let end = u32::try_from(self.values.len()).expect("too many cif pairs in a single node");
+        self.offsets.push(end);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&[i32]> {
+        (index < self.len()).then(|| &self[index])
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &[i32]> + '_ {
+        self.offsets
+            .windows(2)
+            .map(|bounds| &self.values[bounds[0] as usize..bounds[1] as usize])
+    }
+
+    /// The heap memory held by the list, from the capacity of its arrays.
+    pub fn allocated_bytes(&self) -> usize {
+        vec_bytes(&self.offsets) + vec_bytes(&self.values)
+    }
+
+    /// A copy of the pairs as separate vectors.
+    pub fn to_vec(&self) -> Vec<Vec<i32>> {
+        self.iter().map(<[i32]>::to_vec).collect()
+    }
+}
+
+impl Index<usize> for PairList {
+    type Output = [i32];
+
+    #[inline]
+    fn index(&self, index: usize) -> &[i32] {
+        &self.values[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+}
+
+impl IndexMut<usize> for PairList {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut [i32] {
+        &mut self.values[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+}
+
+impl<'a> FromIterator<&'a [i32]> for PairList {
+    fn from_iter<I: IntoIterator<Item = &'a [i32]>>(iter: I) -> Self {
+        let mut list = PairList::new();
+        for pair in iter {
+            list.push(pair);
+        }
+        list
+    }
+}
+
+impl From<Vec<Vec<i32>>> for PairList {
+    fn from(pairs: Vec<Vec<i32>>) -> Self {
+        let mut list = PairList::with_capacity(pairs.len(), pairs.iter().map(Vec::len).sum());
+        for pair in &pairs {
+            list.push(pair);
+        }
+        list
+    }
+}
+
+impl From<&[(i32, i32)]> for PairList {
+    /// Pairs of two qubits without clbits, e.g. written as literals.
+    fn from(pairs: &[(i32, i32)]) -> Self {
+        let mut list = PairList::with_capacity(pairs.len(), 2 * pairs.len());
+        for &(q0, q1) in pairs {
+            list.push(&[q0, q1]);
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_list() {
+        let mut list = PairList::from(vec![vec![0, 1], vec![2, 3, 7], vec![]]);
+        assert_eq!(list.len(), 3);
+        assert_eq!(&list[1], &[2, 3, 7]);
+        assert_eq!(list.get(2), Some(&[][..]));
+        assert_eq!(list.get(3), None);
+
+        list[0].swap(0, 1);
+        list.push(&[4, 5]);
+        assert_eq!(
+            list.to_vec(),
+            vec![vec![1, 0], vec![2, 3, 7], vec![], vec![4, 5]]
+        );
+        assert_eq!(list.iter().collect::<PairList>(), list);
+        assert_eq!(format!("{:?}", list), "[[1, 0], [2, 3, 7], [], [4, 5]]");
+        assert!(PairList::new().is_empty());
+        assert_eq!(
+            PairList::from(&[(0, 1), (2, 3)][..]),
+            PairList::from(vec![vec![0, 1], vec![2, 3]])
+        );
+    }
+}
@@ -0,0 +1,36 @@
+/// The objectives of a swap candidate, all to be minimized: the change in distance of the front
+/// layer, the change in latency-weighted cross-controller feedbacks and the error cost.
+pub type Objectives = [f64; 3];
+
+/// Whether `a` is at least as good as `b` in every objective and better in at least one.
+#[inline]
+pub fn dominates(a: &Objectives, b: &Objectives) -> bool {
+    a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y)
+}
+
+/// The indices of the points not dominated by any other point, in increasing order.  Points with
+/// equal objectives do not dominate each other, so they are all kept.
+pub fn pareto_front(points: &[Objectives]) -> Vec<usize> {
+    (0..points.len())
+        .filter(|&i| !points.iter().any(|other| dominates(other, &points[i])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pareto_front() {
+        let points = [
+            [0., 0., 1.],
+            [1., -1., 0.],
+            [1., 0., 1.], // dominated by the first point
+            [0., 0., 1.],
+            [-1., 2., 2.],
+        ];
+        assert_eq!(pareto_front(&points), vec![0, 1, 3, 4]);
+        assert!(pareto_front(&[]).is_empty());
+        assert!(!dominates(&points[0], &points[3]));
+    }
+}
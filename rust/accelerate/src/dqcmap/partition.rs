@@ -0,0 +1,541 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    cif_pairs::{is_well_formed, CifPairs},
+    control_flow::ControlFlowModel,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+};
+
+/// Upper bound on the Kernighan-Lin passes, each of which has to strictly reduce the cut.
+const MAX_PASSES: usize = 16;
+
+/// A change of the partition made by a Kernighan-Lin step, kept to roll it back.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    Move { qubit: usize, from: usize },
+    Swap(usize, usize),
+}
+
+struct Partition<'a> {
+    neighbors: Vec<&'a [PhysicalQubit]>,
+    group: Vec<usize>,
+    sizes: Vec<usize>,
+    capacity: usize,
+}
+
+impl Partition<'_> {
+    /// The number of coupling edges between `qubit` and the qubits of group `target`.
+    fn links(&self, qubit: usize, target: usize) -> usize {
+        self.neighbors[qubit]
+            .iter()
+            .filter(|neighbor| self.group[neighbor.index()] == target)
+            .count()
+    }
+
+    fn move_gain(&self, qubit: usize, target: usize) -> isize {
+        self.links(qubit, target) as isize - self.links(qubit, self.group[qubit]) as isize
+    }
+
+    fn swap_gain(&self, a: usize, b: usize) -> isize {
+        let adjacent = self.neighbors[a].iter().any(|q| q.index() == b);
+        self.move_gain(a, self.group[b]) + self.move_gain(b, self.group[a])
+            - 2 * isize::from(adjacent)
+    }
+
+    fn set_group(&mut self, qubit: usize, target: usize) {
+        self.sizes[self.group[qubit]] -= 1;
+        self.sizes[target] += 1;
+        self.group[qubit] = target;
+    }
+
+    fn apply(&mut self, step: Step, target: usize) {
+        match step {
+            Step::Move { qubit, .. } => self.set_group(qubit, target),
+            Step::Swap(a, b) => self.group.swap(a, b),
+        }
+    }
+
+    fn undo(&mut self, step: Step) {
+        match step {
+            Step::Move { qubit, from } => self.set_group(qubit, from),
+            Step::Swap(a, b) => self.group.swap(a, b),
+        }
+    }
+
+    /// Grow the groups round-robin from seeds spread over the coupling map, every group taking
+    /// the free qubit with the most edges into it, ties broken by distance to its seed.
+    fn grow(&mut self, coupling: &CouplingMap, num_groups: usize) {
+        let num_qubits = self.group.len();
+        let unassigned = num_groups;
+        let qubit = |q: usize| PhysicalQubit::new(q as u32);
+        // the first seed is the qubit farthest from qubit 0, every next one the qubit farthest
+        // from the seeds so far, so that disconnected components get seeds first
+        let mut seeds: Vec<usize> = Vec::with_capacity(num_groups);
+        let mut nearest_seed = vec![usize::MAX; num_qubits];
+        let mut start = (0..num_qubits)
+            .max_by_key(|&q| (coupling.distance(qubit(0), qubit(q)), usize::MAX - q))
+            .unwrap_or(0);
+        while seeds.len() < num_groups {
+            seeds.push(start);
+            for (q, nearest) in nearest_seed.iter_mut().enumerate() {
+                *nearest = (*nearest).min(coupling.distance(qubit(start), qubit(q)));
+            }
+            start = (0..num_qubits)
+                .filter(|q| !seeds.contains(q))
+                .max_by_key(|&q| (nearest_seed[q], usize::MAX - q))
+                .unwrap_or(0);
+        }
+        for (group, &seed) in seeds.iter().enumerate() {
+            self.group[seed] = group;
+            self.sizes[group] += 1;
+        }
+
+        let mut remaining = num_qubits - num_groups;
+        while remaining > 0 {
+            for (group, &seed) in seeds.iter().enumerate() {
+                if remaining == 0 || self.sizes[group] >= self.capacity {
+                    continue;
+                }
+                let next = (0..num_qubits)
+                    .filter(|&q| self.group[q] == unassigned)
+                    .max_by_key(|&q| {
+                        (
+                            self.links(q, group),
+                            usize::MAX - coupling.distance(qubit(seed), qubit(q)),
+                            usize::MAX - q,
+                        )
+                    })
+                    .unwrap();
+                self.group[next] = group;
+                self.sizes[group] += 1;
+                remaining -= 1;
+            }
+        }
+    }
+
+    /// Run one Kernighan-Lin pass: repeatedly apply the best move or swap of unlocked boundary
+    /// qubits, even if it increases the cut, lock the qubits it changed, and finally roll back
+    /// to the best prefix.  Returns the reduction of the cut.
+    fn refine_pass(&mut self) -> usize {
+        let num_qubits = self.group.len();
+        let num_groups = self.sizes.len();
+        let mut locked = vec![false; num_qubits];
+        let mut history: Vec<Step> = Vec::new();
+        let (mut total, mut best, mut best_len) = (0isize, 0isize, 0usize);
+        loop {
+            // unlocked qubits of every group with an edge into every other group
+            let mut boundary: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+            for q in (0..num_qubits).filter(|&q| !locked[q]) {
+                let mut targets: Vec<usize> = self.neighbors[q]
+                    .iter()
+                    .map(|n| self.group[n.index()])
+                    .filter(|&g| g != self.group[q])
+                    .collect();
+                targets.sort_unstable();
+                targets.dedup();
+                for target in targets {
+                    boundary.entry((self.group[q], target)).or_default().push(q);
+                }
+            }
+
+            let mut choice: Option<(isize, Step, usize)> = None;
+            for from in 0..num_groups {
+                for target in (0..num_groups).filter(|&g| g != from) {
+                    let Some(qubits) = boundary.get(&(from, target)) else {
+                        continue;
+                    };
+                    let partners = boundary.get(&(target, from)).map_or(&[][..], Vec::as_slice);
+                    for &a in qubits {
+                        if self.sizes[target] < self.capacity {
+                            let gain = self.move_gain(a, target);
+                            if choice.map_or(true, |(best, _, _)| gain > best) {
+                                choice = Some((gain, Step::Move { qubit: a, from }, target));
+                            }
+                        }
+                        // every exchange is seen from both groups, consider it once
+                        for &b in partners.iter().filter(|_| from < target) {
+                            let gain = self.swap_gain(a, b);
+                            if choice.map_or(true, |(best, _, _)| gain > best) {
+                                choice = Some((gain, Step::Swap(a, b), target));
+                            }
+                        }
+                    }
+                }
+            }
+            let Some((gain, step, target)) = choice else {
+                break;
+            };
+            self.apply(step, target);
+            match step {
+                Step::Move { qubit, .. } => locked[qubit] = true,
+                Step::Swap(a, b) => {
+                    locked[a] = true;
+                    locked[b] = true;
+                }
+            }
+            history.push(step);
+            total += gain;
+            if total > best {
+                best = total;
+                best_len = history.len();
+            }
+        }
+        while history.len() > best_len {
+            let step = history.pop().unwrap();
+            self.undo(step);
+        }
+        best as usize
+    }
+}
+
+/// Partition the qubits of `coupling` into `num_controllers` groups of at most `capacity` qubits,
+/// few coupling edges crossing groups, as the group of every qubit.
+pub fn partition(
+    coupling: &CouplingMap,
+    num_controllers: usize,
+    capacity: Option<usize>,
+) -> PyResult<Vec<usize>> {
+    let num_qubits = coupling.num_qubits();
+    if num_controllers == 0 || num_controllers > num_qubits {
+        return Err(PyValueError::new_err(format!(
+            "cannot partition {num_qubits} qubits into {num_controllers} controllers"
+        )));
+    }
+    let capacity = capacity.unwrap_or((num_qubits + num_controllers - 1) / num_controllers);
+    if capacity * num_controllers < num_qubits {
+        return Err(PyValueError::new_err(format!(
+            "{num_controllers} controllers of {capacity} qubits cannot hold {num_qubits} qubits"
+        )));
+    }
+    let mut partition = Partition {
+        neighbors: (0..num_qubits)
+            .map(|q| coupling.neighbors(PhysicalQubit::new(q as u32)))
+            .collect(),
+        group: vec![num_controllers; num_qubits],
+        sizes: vec![0; num_controllers],
+        capacity,
+    };
+    partition.grow(coupling, num_controllers);
+    for _ in 0..MAX_PASSES {
+        if partition.refine_pass() == 0 {
+            break;
+        }
+    }
+    debug_assert!(partition.sizes.iter().all(|&size| size <= capacity));
+    Ok(partition.group)
+}
+
+/// The number of coupling edges between qubits of different groups of `group`.
+pub fn cut_edges(coupling: &CouplingMap, group: &[usize]) -> usize {
+    coupling
+        .edges()
+        .iter()
+        .filter(|[a, b]| group[a.index()] != group[b.index()])
+        .count()
+}
+
+/// Partition the physical qubits of a coupling map into controllers for hypothetical hardware,
+/// keeping coupled qubits on the same controller as much as possible.
+///
+/// The controllers are grown round-robin from seeds spread over the coupling map, each taking the
+/// free qubit with the most couplings into it, and then refined by Kernighan-Lin passes of qubit
+/// moves and exchanges between controllers while they reduce the number of coupling edges
+/// between controllers.  The GIL is released while partitioning.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     num_controllers (int): The number of controllers.
+///     capacity (int): The maximum number of qubits of a controller, which becomes its capacity.
+///         Defaults to the number of qubits divided by ``num_controllers``, rounded up, without
+///         limiting the controllers of the result.
+///
+/// Returns:
+///     Ctrl2Pq: The qubits of controllers ``0`` to ``num_controllers - 1``.
+///
+/// Raises:
+///     ValueError: if there are no controllers, more controllers than qubits or the controllers
+///         cannot hold all qubits.
+#[pyfunction]
+#[pyo3(signature = (coupling_map, num_controllers, capacity=None))]
+pub fn partition_controllers(
+    py: Python,
+    coupling_map: &CouplingMap,
+    num_controllers: usize,
+    capacity: Option<usize>,
+) -> PyResult<Ctrl2Pq> {
+    let group = py.allow_threads(|| partition(coupling_map, num_controllers, capacity))?;
+    let mut map: HashMap<i32, Vec<i32>> = (0..num_controllers as i32)
+        .map(|ctrl| (ctrl, Vec::new()))
+        .collect();
+    for (qubit, &ctrl) in group.iter().enumerate() {
+        map.get_mut(&(ctrl as i32)).unwrap().push(qubit as i32);
+    }
+    let mut ctrl2pq = Ctrl2Pq::from_map(map);
+    for ctrl in 0..num_controllers as i32 {
+        ctrl2pq.set_capacity(ctrl, capacity)?;
+    }
+    Ok(ctrl2pq)
+}
+
+/// Qubit reassignments proposed by ``refine_controllers`` and their predicted effect.
+#[pyclass(frozen, module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug)]
+pub struct Refinement {
+    /// The (qubit, old controller, new controller) of every reassignment, in the order they are
+    /// applied.
+    #[pyo3(get)]
+    pub moves: Vec<(i32, i32, i32)>,
+    /// Expected number of cross-controller feedbacks under the original assignment.
+    #[pyo3(get)]
+    pub cost_before: f64,
+    /// Expected number of cross-controller feedbacks after the reassignments.
+    #[pyo3(get)]
+    pub cost_after: f64,
+    /// The assignment with all reassignments applied.
+    #[pyo3(get)]
+    pub ctrl2pq: Ctrl2Pq,
+}
+
+#[pymethods]
+impl Refinement {
+    /// The predicted reduction of the expected number of cross-controller feedbacks.
+    #[getter]
+    pub fn improvement(&self) -> f64 {
+        self.cost_before - self.cost_after
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Refinement(moves={:?}, cost_before={}, cost_after={})",
+            self.moves, self.cost_before, self.cost_after
+        )
+    }
+}
+
+/// Greedily reassign qubits of `ctrl2pq` to the controllers of their feedback partners, see
+/// [refine_controllers].
+pub fn refine(
+    ctrl2pq: &Ctrl2Pq,
+    cif_pairs: &CifPairs,
+    max_moves: Option<usize>,
+    control_flow: Option<&ControlFlowModel>,
+) -> PyResult<Refinement> {
+    // expected feedbacks between every two distinct qubits connected to a controller
+    let mut feedbacks: HashMap<i32, HashMap<i32, f64>> = HashMap::new();
+    for (&node_id, pairs) in cif_pairs.pairs() {
+        let weight = control_flow.map_or(1., |control_flow| control_flow.multiplicity(node_id));
+        for pair in pairs.iter().filter(|pair| is_well_formed(pair)) {
+            let (q0, q1) = (pair[0], pair[1]);
+            if q0 == q1
+                || ctrl2pq.get_controller_by_qubit(q0).is_none()
+                || ctrl2pq.get_controller_by_qubit(q1).is_none()
+            {
+                continue;
+            }
+            *feedbacks.entry(q0).or_default().entry(q1).or_insert(0.) += weight;
+            *feedbacks.entry(q1).or_default().entry(q0).or_insert(0.) += weight;
+        }
+    }
+    let mut qubits: Vec<i32> = feedbacks.keys().copied().collect();
+    qubits.sort_unstable();
+
+    let mut refined = ctrl2pq.clone();
+    let ctrl_of = |ctrl2pq: &Ctrl2Pq, qubit: i32| *ctrl2pq.get_controller_by_qubit(qubit).unwrap();
+    // expected feedbacks between `qubit` and the qubits of controller `ctrl`
+    let links = |ctrl2pq: &Ctrl2Pq, qubit: i32, ctrl: i32| -> f64 {
+        feedbacks.get(&qubit).map_or(0., |partners| {
+            partners
+                .iter()
+                .filter(|(&partner, _)| ctrl_of(ctrl2pq, partner) == ctrl)
+                .map(|(_, weight)| weight)
+                .sum()
+        })
+    };
+    let cost = |ctrl2pq: &Ctrl2Pq| -> f64 {
+        feedbacks
+            .iter()
+            .flat_map(|(&q0, partners)| partners.iter().map(move |(&q1, &w)| (q0, q1, w)))
+            .filter(|&(q0, q1, _)| q0 < q1 && ctrl_of(ctrl2pq, q0) != ctrl_of(ctrl2pq, q1))
+            .map(|(_, _, weight)| weight)
+            .sum()
+    };
+
+    let cost_before = cost(ctrl2pq);
+    let mut moves = Vec::new();
+    let max_moves = max_moves.unwrap_or(usize::MAX);
+    loop {
+        // the best (gain, qubit, target controller, exchanged qubit) with a positive gain
+        let mut best: Option<(f64, i32, i32, Option<i32>)> = None;
+        for &qubit in &qubits {
+            let ctrl = ctrl_of(&refined, qubit);
+            let mut targets: Vec<i32> = feedbacks[&qubit]
+                .keys()
+                .map(|&partner| ctrl_of(&refined, partner))
+                .filter(|&target| target != ctrl)
+                .collect();
+            targets.sort_unstable();
+            targets.dedup();
+            let local = links(&refined, qubit, ctrl);
+            for target in targets {
+                let gain = links(&refined, qubit, target) - local;
+                if refined.free_capacity(target) != Some(0) {
+                    if moves.len() < max_moves && best.map_or(gain > 0., |(g, ..)| gain > g) {
+                        best = Some((gain, qubit, target, None));
+                    }
+                    continue;
+                }
+                if moves.len() + 2 > max_moves {
+                    continue;
+                }
+                // the target is full, exchange the qubit with one of the target
+                for &other in refined.get_qubits_by_controller(target).unwrap_or(&[]) {
+                    let shared = feedbacks[&qubit].get(&other).copied().unwrap_or(0.);
+                    let other_gain = links(&refined, other, ctrl) - links(&refined, other, target);
+                    let gain = gain + other_gain - 2. * shared;
+                    if best.map_or(gain > 0., |(g, ..)| gain > g) {
+                        best = Some((gain, qubit, target, Some(other)));
+                    }
+                }
+            }
+        }
+        let Some((_, qubit, target, other)) = best else {
+            break;
+        };
+        let ctrl = refined.remove_qubit(qubit)?;
+        if let Some(other) = other {
+            refined.remove_qubit(other)?;
+            refined.assign_qubit(other, ctrl)?;
+            moves.push((qubit, ctrl, target));
+            moves.push((other, target, ctrl));
+        } else {
+            moves.push((qubit, ctrl, target));
+        }
+        refined.assign_qubit(qubit, target)?;
+    }
+
+    let cost_after = cost(&refined);
+    Ok(Refinement {
+        moves,
+        cost_before,
+        cost_after,
+        ctrl2pq: refined,
+    })
+}
+
+/// Propose qubit reassignments of an existing controller assignment that reduce the expected
+/// number of cross-controller feedbacks of a workload.
+///
+/// Qubits are greedily moved to the controller of their feedback partners, or exchanged with a
+/// qubit of that controller if it is at its capacity, always taking the reassignment that
+/// removes the most expected cross-controller feedbacks, until none removes any or the budget
+/// is spent.  An exchange counts as two reassignments.  Pairs on qubits without a controller
+/// are ignored.  The GIL is released while refining.
+///
+/// Args:
+///     ctrl2pq (Ctrl2Pq): The current assignment, which is not modified.
+///     cif_pairs (CifPairs): The cif pairs of the workload on physical qubits.
+///     max_moves (int): The maximum number of reassigned qubits, unbounded by default.
+///     control_flow (ControlFlowModel): If given, every feedback is weighted by the expected
+///         number of executions of its node.
+///
+/// Returns:
+///     Refinement: The reassignments, the cost before and after and the refined assignment.
+#[pyfunction]
+#[pyo3(signature = (ctrl2pq, cif_pairs, max_moves=None, control_flow=None))]
+pub fn refine_controllers(
+    py: Python,
+    ctrl2pq: &Ctrl2Pq,
+    cif_pairs: &CifPairs,
+    max_moves: Option<usize>,
+    control_flow: Option<ControlFlowModel>,
+) -> PyResult<Refinement> {
+    py.allow_threads(|| refine(ctrl2pq, cif_pairs, max_moves, control_flow.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: u32, cols: u32) -> CouplingMap {
+        let q = |r: u32, c: u32| PhysicalQubit::new(r * cols + c);
+        let mut edges = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                if c + 1 < cols {
+                    edges.push([q(r, c), q(r, c + 1)]);
+                }
+                if r + 1 < rows {
+                    edges.push([q(r, c), q(r + 1, c)]);
+                }
+            }
+        }
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    #[test]
+    fn test_partition_grid() {
+        // a 4x4 grid splits into four 2x2 blocks with 8 edges between them
+        let coupling = grid(4, 4);
+        let group = partition(&coupling, 4, None).unwrap();
+        let mut sizes = vec![0; 4];
+        for &g in &group {
+            sizes[g] += 1;
+        }
+        assert_eq!(sizes, vec![4; 4]);
+        assert_eq!(cut_edges(&coupling, &group), 8);
+
+        // two rows of a 2x8 ladder cut in the middle
+        let coupling = grid(2, 8);
+        let group = partition(&coupling, 2, None).unwrap();
+        assert_eq!(cut_edges(&coupling, &group), 2);
+
+        assert!(partition(&coupling, 0, None).is_err());
+        assert!(partition(&coupling, 17, None).is_err());
+        assert!(partition(&coupling, 2, Some(7)).is_err());
+    }
+
+    #[test]
+    fn test_refine_controllers() {
+        let mut ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        // 0-2 twice and 1-3 once cross the controllers, 0-1 does not
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![0, 2], vec![1, 3]]),
+            (1, vec![vec![2, 0], vec![0, 1]]),
+        ]));
+        ctrl2pq.set_capacity(0, Some(3)).unwrap();
+        let refinement = refine(&ctrl2pq, &cif_pairs, None, None).unwrap();
+        assert_eq!(refinement.cost_before, 3.);
+        assert_eq!(refinement.cost_after, 1.);
+        assert_eq!(refinement.moves, vec![(2, 1, 0)]);
+
+        // with full controllers, qubits 0 and 3 are exchanged instead
+        ctrl2pq.set_capacity(0, Some(2)).unwrap();
+        ctrl2pq.set_capacity(1, Some(2)).unwrap();
+        let refinement = refine(&ctrl2pq, &cif_pairs, None, None).unwrap();
+        assert_eq!(refinement.moves, vec![(0, 0, 1), (3, 1, 0)]);
+        assert_eq!(refinement.improvement(), 2.);
+        assert_eq!(refinement.ctrl2pq.get_controller_by_qubit(0), Some(&1));
+        assert_eq!(ctrl2pq.get_controller_by_qubit(0), Some(&0));
+        assert!(refine(&ctrl2pq, &cif_pairs, Some(1), None)
+            .unwrap()
+            .moves
+            .is_empty());
+    }
+
+    #[test]
+    fn test_partition_disconnected() {
+        let q = PhysicalQubit::new;
+        let coupling = CouplingMap::new(
+            vec![[q(0), q(1)], [q(1), q(2)], [q(3), q(4)], [q(4), q(5)]],
+            None,
+        )
+        .unwrap();
+        let group = partition(&coupling, 2, Some(3)).unwrap();
+        assert_eq!(cut_edges(&coupling, &group), 0);
+    }
+}
@@ -0,0 +1,625 @@
+use std::error::Error;
+use std::fmt;
+
+use hashbrown::HashMap;
+use pyo3::prelude::*;
+use pyo3::PyErr;
+
+use super::{
+    cif_pairs::CifPairs, classical_flow::extract_cif_pairs, dag::Instruction, error::DqcMapError,
+};
+
+/// An error in an OpenQASM 3 program, with the line it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Qasm3Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Qasm3Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "OpenQASM 3 error on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+impl Error for Qasm3Error {}
+
+impl From<Qasm3Error> for PyErr {
+    fn from(err: Qasm3Error) -> PyErr {
+        DqcMapError::new_err(err.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(usize),
+    /// A non-integer numeric literal, only ever skipped as a gate parameter.
+    Real,
+    Str,
+    Symbol(&'static str),
+}
+
+// two-character symbols first, so that they are matched before their prefixes
+const SYMBOLS: [&str; 32] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "->", "**", "(", ")", "[", "]", "{", "}", ";",
+    ",", "=", "@", ":", "!", "~", "&", "|", "^", "<", ">", "+", "-", "*", "/",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, Qasm3Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                line += usize::from(chars[i] == '\n');
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::Str, line));
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_')
+            {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            tokens.push((literal.parse().map_or(Token::Real, Token::Int), line));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+            {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else {
+            let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| Qasm3Error {
+                    line,
+                    message: format!("unexpected character '{c}'"),
+                })?;
+            i += symbol.len();
+            tokens.push((Token::Symbol(symbol), line));
+        }
+    }
+    Ok(tokens)
+}
+
+/// The instructions of an OpenQASM 3 program, with qubits and clbits numbered across registers
+/// in declaration order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Qasm3Program {
+    pub num_qubits: usize,
+    pub num_clbits: usize,
+    /// Name, qubits, clbits and condition clbits of every instruction in program order, see
+    /// [super::dag::CircuitDag].  Classical assignments are named ``"assign"``, have no qubits,
+    /// write their target clbit and are conditioned on the clbits they read.
+    pub instructions: Vec<Instruction>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    program: Qasm3Program,
+    // (first index, size) of every register
+    qregs: HashMap<String, (usize, usize)>,
+    cregs: HashMap<String, (usize, usize)>,
+    // clbits the enclosing `if` statements are conditioned on
+    condition: Vec<usize>,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line)| line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Qasm3Error> {
+        Err(Qasm3Error {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn advance(&mut self) -> Result<Token, Qasm3Error> {
+        match self.tokens.get(self.pos) {
+            Some((token, _)) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => self.error("unexpected end of program"),
+        }
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.is_symbol(symbol);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), Qasm3Error> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            self.error(format!("expected '{symbol}'"))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Qasm3Error> {
+        match self.advance()? {
+            Token::Ident(name) => Ok(name),
+            _ => {
+                self.pos -= 1;
+                self.error("expected an identifier")
+            }
+        }
+    }
+
+    fn int(&mut self) -> Result<usize, Qasm3Error> {
+        match self.advance()? {
+            Token::Int(value) => Ok(value),
+            _ => {
+                self.pos -= 1;
+                self.error("expected an integer")
+            }
+        }
+    }
+
+    /// Skip tokens up to and including the `close` matching an already consumed `open`.
+    fn skip_group(&mut self, open: &str, close: &str) -> Result<(), Qasm3Error> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance()? {
+                Token::Symbol(s) if s == open => depth += 1,
+                Token::Symbol(s) if s == close => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_statement(&mut self) -> Result<(), Qasm3Error> {
+        while !self.eat(";") {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, quantum: bool, name: String, size: usize) -> Result<(), Qasm3Error> {
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return self.error(format!("'{name}' is already declared"));
+        }
+        let (count, registers) = if quantum {
+            (&mut self.program.num_qubits, &mut self.qregs)
+        } else {
+            (&mut self.program.num_clbits, &mut self.cregs)
+        };
+        registers.insert(name, (*count, size));
+        *count += size;
+        Ok(())
+    }
+
+    /// A register or one of its bits, as a list of indices.
+    fn operand(&mut self, quantum: bool) -> Result<Vec<usize>, Qasm3Error> {
+        let name = self.ident()?;
+        let registers = if quantum { &self.qregs } else { &self.cregs };
+        let Some(&(start, size)) = registers.get(&name) else {
+            let kind = if quantum { "qubit" } else { "bit" };
+            return self.error(format!("'{name}' is not a declared {kind} register"));
+        };
+        if !self.eat("[") {
+            return Ok((start..start + size).collect());
+        }
+        let index = self.int()?;
+        self.expect("]")?;
+        if index >= size {
+            return self.error(format!(
+                "index {index} is out of range for '{name}' of size {size}"
+            ));
+        }
+        Ok(vec![start + index])
+    }
+
+    /// The clbits read by a classical expression ending at `end`, which is consumed.  Other
+    /// identifiers, e.g. constants or casts, are ignored.
+    fn expression_clbits(&mut self, end: &str) -> Result<Vec<usize>, Qasm3Error> {
+        let mut clbits = Vec::new();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(Token::Symbol(s)) if *s == end && depth == 0 => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(Token::Symbol("(")) => depth += 1,
+                Some(Token::Symbol(")")) => depth -= 1,
+                Some(Token::Ident(name)) if self.cregs.contains_key(name) => {
+                    clbits.extend(self.operand(false)?);
+                    continue;
+                }
+                None => return self.error("unexpected end of program"),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        clbits.sort_unstable();
+        clbits.dedup();
+        Ok(clbits)
+    }
+
+    fn push(
+        &mut self,
+        name: &str,
+        qubits: Vec<usize>,
+        clbits: Vec<usize>,
+        condition: Option<Vec<usize>>,
+    ) {
+        let condition = match (condition, self.condition.is_empty()) {
+            (None, true) => None,
+            (condition, _) => {
+                let mut condition = condition.unwrap_or_default();
+                condition.extend(&self.condition);
+                condition.sort_unstable();
+                condition.dedup();
+                Some(condition)
+            }
+        };
+        self.program
+            .instructions
+            .push((name.to_string(), qubits, clbits, condition));
+    }
+
+    fn measure(&mut self, qubits: Vec<usize>, clbits: Vec<usize>) -> Result<(), Qasm3Error> {
+        if qubits.len() != clbits.len() {
+            return self.error(format!(
+                "cannot measure {} qubits into {} bits",
+                qubits.len(),
+                clbits.len()
+            ));
+        }
+        for (q, c) in qubits.into_iter().zip(clbits) {
+            self.push("measure", vec![q], vec![c], None);
+        }
+        Ok(())
+    }
+
+    /// The right-hand side of an assignment to `targets`, after the `=`.
+    fn assignment(&mut self, targets: Vec<usize>) -> Result<(), Qasm3Error> {
+        if matches!(self.peek(), Some(Token::Ident(name)) if name == "measure") {
+            self.pos += 1;
+            let qubits = self.operand(true)?;
+            self.expect(";")?;
+            return self.measure(qubits, targets);
+        }
+        let sources = self.expression_clbits(";")?;
+        for target in targets {
+            self.push("assign", vec![], vec![target], Some(sources.clone()));
+        }
+        Ok(())
+    }
+
+    fn block_or_statement(&mut self) -> Result<(), Qasm3Error> {
+        if self.eat("{") {
+            while !self.eat("}") {
+                self.statement()?;
+            }
+            Ok(())
+        } else {
+            self.statement()
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), Qasm3Error> {
+        let keyword = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            Some(Token::Symbol(";")) => {
+                self.pos += 1;
+                return Ok(());
+            }
+            _ => return self.error("expected a statement"),
+        };
+        match keyword.as_str() {
+            "OPENQASM" | "include" | "input" | "output" => {
+                self.pos += 1;
+                self.skip_statement()
+            }
+            "qubit" | "bit" => {
+                self.pos += 1;
+                let size = if self.eat("[") {
+                    let size = self.int()?;
+                    self.expect("]")?;
+                    size
+                } else {
+                    1
+                };
+                let name = self.ident()?;
+                self.declare(keyword == "qubit", name.clone(), size)?;
+                if keyword == "bit" && self.eat("=") {
+                    let (start, size) = self.cregs[&name];
+                    return self.assignment((start..start + size).collect());
+                }
+                self.expect(";")
+            }
+            "qreg" | "creg" => {
+                self.pos += 1;
+                let name = self.ident()?;
+                let size = if self.eat("[") {
+                    let size = self.int()?;
+                    self.expect("]")?;
+                    size
+                } else {
+                    1
+                };
+                self.declare(keyword == "qreg", name, size)?;
+                self.expect(";")
+            }
+            // classical variables other than bits do not carry measurements
+            "int" | "uint" | "float" | "angle" | "bool" | "complex" | "duration" | "stretch"
+            | "const" => {
+                self.pos += 1;
+                self.skip_statement()
+            }
+            "gate" | "opaque" => {
+                self.pos += 1;
+                while !self.eat("{") {
+                    if self.eat(";") {
+                        return Ok(());
+                    }
+                    self.advance()?;
+                }
+                self.skip_group("{", "}")
+            }
+            "if" => {
+                self.pos += 1;
+                self.expect("(")?;
+                let clbits = self.expression_clbits(")")?;
+                let depth = self.condition.len();
+                self.condition.extend(clbits);
+                self.block_or_statement()?;
+                if matches!(self.peek(), Some(Token::Ident(name)) if name == "else") {
+                    self.pos += 1;
+                    self.block_or_statement()?;
+                }
+                self.condition.truncate(depth);
+                Ok(())
+            }
+            "measure" => {
+                self.pos += 1;
+                let qubits = self.operand(true)?;
+                self.expect("->")?;
+                let clbits = self.operand(false)?;
+                self.expect(";")?;
+                self.measure(qubits, clbits)
+            }
+            "barrier" => {
+                self.pos += 1;
+                let mut qubits = Vec::new();
+                while !self.eat(";") {
+                    qubits.extend(self.operand(true)?);
+                    self.eat(",");
+                }
+                if qubits.is_empty() {
+                    qubits = (0..self.program.num_qubits).collect();
+                }
+                self.push("barrier", qubits, vec![], None);
+                Ok(())
+            }
+            "for" | "while" | "def" | "switch" | "box" | "let" | "extern" | "cal" | "defcal" => {
+                self.error(format!("'{keyword}' statements are not supported"))
+            }
+            name if self.cregs.contains_key(name) => {
+                let targets = self.operand(false)?;
+                self.expect("=")?;
+                self.assignment(targets)
+            }
+            _ => self.gate_call(),
+        }
+    }
+
+    /// A gate call with optional modifiers and parameters, broadcast over register operands.
+    fn gate_call(&mut self) -> Result<(), Qasm3Error> {
+        let mut name = self.ident()?;
+        while self.is_symbol("@") || self.is_symbol("(") {
+            if self.eat("(") {
+                self.skip_group("(", ")")?;
+            } else {
+                self.pos += 1;
+                name = self.ident()?;
+            }
+        }
+        let mut operands = Vec::new();
+        while !self.eat(";") {
+            operands.push(self.operand(true)?);
+            if !self.is_symbol(";") {
+                self.expect(",")?;
+            }
+        }
+        let width = operands.iter().map(Vec::len).max().unwrap_or(0);
+        if operands
+            .iter()
+            .any(|qubits| qubits.len() != 1 && qubits.len() != width)
+        {
+            return self.error(format!(
+                "cannot broadcast '{name}' over registers of different sizes"
+            ));
+        }
+        for i in 0..width {
+            let qubits = operands
+                .iter()
+                .map(|qubits| {
+                    if qubits.len() == 1 {
+                        qubits[0]
+                    } else {
+                        qubits[i]
+                    }
+                })
+                .collect();
+            self.push(&name, qubits, vec![], None);
+        }
+        Ok(())
+    }
+}
+
+/// Parse an OpenQASM 3 program into its flat instructions.
+///
+/// Supported are qubit and bit declarations (including ``qreg``/``creg``), gate calls with
+/// modifiers and parameters, broadcast over whole registers, measurements in both syntaxes,
+/// ``reset``, ``barrier``, classical assignments from expressions over bits and ``if``/``else``
+/// statements, possibly nested.  Gate definitions are skipped, their calls are kept as opaque
+/// gates.  Loops, subroutines and ``switch`` statements are rejected.
+pub fn parse_qasm3(source: &str) -> Result<Qasm3Program, Qasm3Error> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        program: Qasm3Program::default(),
+        qregs: HashMap::new(),
+        cregs: HashMap::new(),
+        condition: Vec::new(),
+    };
+    while parser.pos < parser.tokens.len() {
+        parser.statement()?;
+    }
+    Ok(parser.program)
+}
+
+/// Extract the cif pairs and the gate order of an OpenQASM 3 program, without building a
+/// circuit in Python.
+///
+/// Node ids are the positions of the instructions in program order, where a gate broadcast over
+/// registers yields an instruction per qubit, classical assignments are instructions of their
+/// own and ``else`` branches are conditioned like their ``if`` branches.  The cif pairs are
+/// derived as by ``extract_cif_pairs``.
+///
+/// Args:
+///     source (str): The OpenQASM 3 program.
+///
+/// Returns:
+///     tuple[CifPairs, list[int]]: The cif pairs ``[conditioned qubit, measured qubit, clbit]``
+///     keyed by node id, and the ids of the nodes acting on qubits, barriers excluded, in
+///     program order.
+///
+/// Raises:
+///     DqcMapError: if the program cannot be parsed or uses unsupported statements.
+#[pyfunction]
+#[pyo3(text_signature = "(source, /)")]
+pub fn cif_pairs_from_qasm3(py: Python, source: &str) -> PyResult<(CifPairs, Vec<usize>)> {
+    let program = py.allow_threads(|| parse_qasm3(source))?;
+    let gate_order = program
+        .instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, qubits, _, _))| name != "barrier" && !qubits.is_empty())
+        .map(|(node_id, _)| node_id)
+        .collect();
+    let cif_pairs = extract_cif_pairs(program.instructions, Some(program.num_clbits))?;
+    Ok((cif_pairs, gate_order))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_qasm3() {
+        let program = parse_qasm3(
+            r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            gate my_cx a, b { cx a, b; }
+            qubit[3] q;
+            qubit r;
+            bit[2] c;
+            creg d[1];
+            /* a register-wide gate
+               and a parametrized one */
+            h q;
+            ctrl @ rz(pi / 2) q[0], r;
+            c[0] = measure q[0];
+            measure q[1] -> c[1];
+            d[0] = c[0] ^ c[1];
+            if (d[0] == 1) {
+                x r;
+            } else if (c == 2) {
+                my_cx q[2], r;
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!((program.num_qubits, program.num_clbits), (4, 3));
+        let names: Vec<&str> = program
+            .instructions
+            .iter()
+            .map(|(name, _, _, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["h", "h", "h", "rz", "measure", "measure", "assign", "x", "my_cx"]
+        );
+        assert_eq!(program.instructions[3].1, vec![0, 3]);
+        assert_eq!(
+            program.instructions[6],
+            ("assign".to_string(), vec![], vec![2], Some(vec![0, 1]))
+        );
+        assert_eq!(program.instructions[7].3, Some(vec![2]));
+        // the else branch is conditioned on the outer and the inner condition
+        assert_eq!(program.instructions[8].3, Some(vec![0, 1, 2]));
+
+        assert!(parse_qasm3("qubit q; x p;").is_err());
+        assert!(parse_qasm3("qubit[2] q; bit c;\nc = measure q;").is_err());
+        let err = parse_qasm3("qubit q;\n\nfor int i in [0:2] { x q; }").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_cif_pairs_from_qasm3() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let (cif_pairs, gate_order) = cif_pairs_from_qasm3(
+                py,
+                "qubit[2] q; bit[2] c; c[0] = measure q[0]; barrier q; if (c[0]) x q[1];",
+            )
+            .unwrap();
+            assert_eq!(gate_order, vec![0, 2]);
+            assert_eq!(cif_pairs.pairs()[&2].to_vec(), vec![vec![1, 0, 0]]);
+        });
+    }
+}
@@ -0,0 +1,246 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+/// A bijective mapping between the virtual qubits of a circuit and the physical qubits of a
+/// device, kept in both directions so that lookups and swaps are constant time.
+///
+/// Routing tracks the evolving permutation with ``apply``, and layouts found by separate passes
+/// are chained with ``compose``.
+///
+/// Args:
+///     virtual_to_physical (list[int]): The physical qubit of every virtual qubit, a permutation
+///         of ``range(len(virtual_to_physical))``.
+#[pyclass(module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    virt_to_phys: Vec<PhysicalQubit>,
+    phys_to_virt: Vec<VirtualQubit>,
+}
+
+#[pymethods]
+impl Layout {
+    #[new]
+    #[pyo3(text_signature = "(virtual_to_physical, /)")]
+    pub fn new(virtual_to_physical: Vec<PhysicalQubit>) -> PyResult<Self> {
+        let num_qubits = virtual_to_physical.len();
+        let mut phys_to_virt: Vec<Option<VirtualQubit>> = vec![None; num_qubits];
+        for (virt, phys) in virtual_to_physical.iter().enumerate() {
+            match phys_to_virt.get_mut(phys.index()) {
+                Some(slot @ None) => *slot = Some(VirtualQubit::new(virt as u32)),
+                Some(Some(_)) => {
+                    return Err(PyValueError::new_err(format!(
+                        "physical qubit {} is assigned more than once",
+                        phys.index()
+                    )))
+                }
+                None => {
+                    return Err(PyValueError::new_err(format!(
+                        "physical qubit {} is out of range for a layout of {} qubits",
+                        phys.index(),
+                        num_qubits
+                    )))
+                }
+            }
+        }
+        Ok(Layout {
+            virt_to_phys: virtual_to_physical,
+            phys_to_virt: phys_to_virt.into_iter().flatten().collect(),
+        })
+    }
+
+    /// The layout mapping every virtual qubit to the physical qubit of the same index.
+    #[staticmethod]
+    #[pyo3(text_signature = "(num_qubits, /)")]
+    pub fn trivial(num_qubits: u32) -> Self {
+        Layout {
+            virt_to_phys: (0..num_qubits).map(PhysicalQubit::new).collect(),
+            phys_to_virt: (0..num_qubits).map(VirtualQubit::new).collect(),
+        }
+    }
+
+    /// The physical qubit of virtual qubit ``virtual``.
+    #[pyo3(name = "virtual_to_physical", text_signature = "(self, virtual, /)")]
+    fn py_virtual_to_physical(&self, r#virtual: VirtualQubit) -> PyResult<PhysicalQubit> {
+        self.check_qubit(r#virtual.index())?;
+        Ok(self.virtual_to_physical(r#virtual))
+    }
+
+    /// The virtual qubit on physical qubit ``physical``.
+    #[pyo3(name = "physical_to_virtual", text_signature = "(self, physical, /)")]
+    fn py_physical_to_virtual(&self, physical: PhysicalQubit) -> PyResult<VirtualQubit> {
+        self.check_qubit(physical.index())?;
+        Ok(self.physical_to_virtual(physical))
+    }
+
+    /// Exchange the virtual qubits on the two physical qubits of ``swap``.
+    ///
+    /// Raises:
+    ///     IndexError: if a qubit of the swap is out of range.
+    #[pyo3(name = "apply", text_signature = "(self, swap, /)")]
+    fn py_apply(&mut self, swap: (PhysicalQubit, PhysicalQubit)) -> PyResult<()> {
+        self.check_qubit(swap.0.index())?;
+        self.check_qubit(swap.1.index())?;
+        self.apply([swap.0, swap.1]);
+        Ok(())
+    }
+
+    /// The layout applying this layout first and ``other`` second, i.e. taking virtual qubit
+    /// ``v`` to ``other.virtual_to_physical(self.virtual_to_physical(v))``.
+    ///
+    /// Raises:
+    ///     ValueError: if the two layouts have a different number of qubits.
+    #[pyo3(text_signature = "(self, other, /)")]
+    pub fn compose(&self, other: &Layout) -> PyResult<Layout> {
+        if self.num_qubits() != other.num_qubits() {
+            return Err(PyValueError::new_err(format!(
+                "cannot compose layouts of {} and {} qubits",
+                self.num_qubits(),
+                other.num_qubits()
+            )));
+        }
+        let virt_to_phys: Vec<PhysicalQubit> = self
+            .virt_to_phys
+            .iter()
+            .map(|phys| other.virt_to_phys[phys.index()])
+            .collect();
+        let mut phys_to_virt = vec![VirtualQubit::new(0); virt_to_phys.len()];
+        for (virt, phys) in virt_to_phys.iter().enumerate() {
+            phys_to_virt[phys.index()] = VirtualQubit::new(virt as u32);
+        }
+        Ok(Layout {
+            virt_to_phys,
+            phys_to_virt,
+        })
+    }
+
+    /// The layout taking every physical qubit of this layout back to its virtual qubit.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn inverse(&self) -> Layout {
+        Layout {
+            virt_to_phys: self
+                .phys_to_virt
+                .iter()
+                .map(|virt| PhysicalQubit::new(virt.index() as u32))
+                .collect(),
+            phys_to_virt: self
+                .virt_to_phys
+                .iter()
+                .map(|phys| VirtualQubit::new(phys.index() as u32))
+                .collect(),
+        }
+    }
+
+    /// The mapping as a ``{virtual: physical}`` dict.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn to_dict(&self) -> HashMap<VirtualQubit, PhysicalQubit> {
+        self.iter_virtual().collect()
+    }
+
+    /// The physical qubit of every virtual qubit.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn to_list(&self) -> Vec<PhysicalQubit> {
+        self.virt_to_phys.clone()
+    }
+
+    /// The same mapping as an ``NLayout``, as taken by ``dqcmap_routing``.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn to_nlayout(&self) -> PyResult<NLayout> {
+        Ok(NLayout::from_virtual_to_physical(
+            self.virt_to_phys.clone(),
+        )?)
+    }
+
+    fn __len__(&self) -> usize {
+        self.num_qubits()
+    }
+
+    fn __reduce__(&self, py: Python) -> Py<PyAny> {
+        (py.get_type_bound::<Self>(), (self.virt_to_phys.clone(),)).into_py(py)
+    }
+
+    fn __repr__(&self) -> String {
+        let physical: Vec<usize> = self.virt_to_phys.iter().map(|p| p.index()).collect();
+        format!("Layout({physical:?})")
+    }
+}
+
+impl Layout {
+    #[inline]
+    pub fn num_qubits(&self) -> usize {
+        self.virt_to_phys.len()
+    }
+
+    #[inline]
+    pub fn virtual_to_physical(&self, r#virtual: VirtualQubit) -> PhysicalQubit {
+        self.virt_to_phys[r#virtual.index()]
+    }
+
+    #[inline]
+    pub fn physical_to_virtual(&self, physical: PhysicalQubit) -> VirtualQubit {
+        self.phys_to_virt[physical.index()]
+    }
+
+    /// Exchange the virtual qubits on the two physical qubits of `swap`.
+    pub fn apply(&mut self, swap: [PhysicalQubit; 2]) {
+        let [a, b] = swap;
+        self.phys_to_virt.swap(a.index(), b.index());
+        self.virt_to_phys[self.phys_to_virt[a.index()].index()] = a;
+        self.virt_to_phys[self.phys_to_virt[b.index()].index()] = b;
+    }
+
+    /// Iterator of `(VirtualQubit, PhysicalQubit)` pairs, in order of the virtual qubits.
+    pub fn iter_virtual(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (VirtualQubit, PhysicalQubit)> + '_ {
+        self.virt_to_phys
+            .iter()
+            .enumerate()
+            .map(|(v, &p)| (VirtualQubit::new(v as u32), p))
+    }
+
+    fn check_qubit(&self, index: usize) -> PyResult<()> {
+        if index < self.num_qubits() {
+            Ok(())
+        } else {
+            Err(PyIndexError::new_err(format!(
+                "qubit index {} is out of range for {} qubits",
+                index,
+                self.num_qubits()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(physical: &[u32]) -> Layout {
+        Layout::new(physical.iter().map(|&p| PhysicalQubit::new(p)).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_layout_apply_compose_inverse() {
+        let q = PhysicalQubit::new;
+        let mut current = Layout::trivial(4);
+        current.apply([q(1), q(2)]);
+        current.apply([q(2), q(3)]);
+        assert_eq!(current, layout(&[0, 3, 1, 2]));
+        assert_eq!(current.physical_to_virtual(q(3)), VirtualQubit::new(1));
+
+        let inverse = current.inverse();
+        assert_eq!(inverse, layout(&[0, 2, 3, 1]));
+        assert_eq!(current.compose(&inverse).unwrap(), Layout::trivial(4));
+        assert_eq!(
+            layout(&[1, 0, 2, 3]).compose(&current).unwrap(),
+            layout(&[3, 0, 1, 2])
+        );
+        assert!(current.compose(&Layout::trivial(3)).is_err());
+
+        assert!(Layout::new(vec![q(0), q(0)]).is_err());
+        assert!(Layout::new(vec![q(0), q(2)]).is_err());
+    }
+}
@@ -0,0 +1,1492 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use hashbrown::HashMap;
+use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
+
+use crate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+use super::{
+    coupling_map::CouplingMap,
+    dag::CircuitDag,
+    error::MappingError,
+    front_layer::FrontLayer,
+    metrics::{self, Phase},
+    state::DqcMapState,
+    swap::Swap,
+    trace::{CandidateTrace, RoutingTrace, TraceStep},
+};
+#[cfg(feature = "python")]
+use {
+    super::{
+        cif_pairs::CifPairs,
+        config::DqcMapConfig,
+        cost_model::cost_model_by_name,
+        crosstalk::CrosstalkMap,
+        ctrl_to_pq::Ctrl2Pq,
+        ctrl_topology::CtrlTopology,
+        dag::Instruction,
+        device::NoiseSource,
+        state::{DEFAULT_DISTANCE_WEIGHT, DEFAULT_FEEDBACK_WEIGHT},
+    },
+    pyo3::{prelude::*, types::PyDict},
+    std::time::Instant,
+};
+
+/// Decay coefficient for penalizing serial swaps on the same qubits.
+const DECAY_RATE: f64 = 0.001;
+/// How often to reset all decay rates to 1.
+const DECAY_RESET_INTERVAL: u8 = 5;
+/// Epsilon used in minimum-score calculations.
+const BEST_EPSILON: f64 = 1e-10;
+
+/// A gate of the circuit to be routed, identified by the node id used as key in `CifPairs`.
+#[derive(Clone, Debug)]
+pub struct RouterGate {
+    pub node_id: usize,
+    pub qubits: Vec<VirtualQubit>,
+}
+
+/// The parameters of the beam search replacing the greedy swap selection of [route].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeamSearch {
+    /// Number of swap sequences kept after every step.
+    pub width: usize,
+    /// Maximum length of a swap sequence.
+    pub depth: usize,
+}
+
+/// The options of a routing run with [route], all off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RouteOptions {
+    /// Seed of the RNG breaking ties between equally scored swaps, the smallest one is taken
+    /// if unset.
+    pub seed: Option<u64>,
+    /// Whether to record every swap selection in [RouterOutput::trace].
+    pub record_trace: bool,
+    /// Beam search replacing the greedy swap selection.
+    pub beam: Option<BeamSearch>,
+    /// Temperature of the softmax selection replacing the greedy swap selection, a positive
+    /// finite number.
+    pub temperature: Option<f64>,
+}
+
+impl RouteOptions {
+    fn validate(&self) -> Result<(), MappingError> {
+        if let Some(temperature) = self.temperature {
+            if !(temperature.is_finite() && temperature > 0.) {
+                return Err(MappingError::Value(format!(
+                    "temperature must be a positive finite number, got {temperature}"
+                )));
+            }
+            if self.beam.is_some() {
+                return Err(MappingError::Value(
+                    "the beam search does not support the softmax selection".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A swap sequence explored by the beam search, with the layout and the state it leads to.
+#[derive(Clone)]
+struct BeamBranch {
+    layout: NLayout,
+    dqcmap_state: DqcMapState,
+    swaps: Vec<[PhysicalQubit; 2]>,
+    /// Sum of the composite scores of the swaps, lower is better.
+    score: f64,
+    /// Whether the last swap makes a front-layer gate routable, which ends the sequence.
+    progressed: bool,
+}
+
+/// Inserted swaps on physical qubits, each paired with the node id of the gate it precedes.
+pub type SwapList = Vec<(usize, [PhysicalQubit; 2])>;
+
+/// The swaps per node, the final permutation and the stats returned by `route` to Python.
+#[cfg(feature = "python")]
+type RouteResult = (
+    HashMap<usize, Vec<(PhysicalQubit, PhysicalQubit)>>,
+    Vec<PhysicalQubit>,
+    Py<PyDict>,
+);
+
+/// Output of a routing run.
+#[derive(Clone, Debug)]
+pub struct RouterOutput {
+    pub swaps: SwapList,
+    /// Order in which the node ids were routed.
+    pub gate_order: Vec<usize>,
+    pub final_layout: NLayout,
+    /// Number of cross-controller feedbacks of the routed circuit, weighted as in `cif_pairs`,
+    /// `None` if the state misses `ctrl2pq`/`cif_pairs`.
+    pub cross_ctrl_feedbacks: Option<f64>,
+    /// The swap selections, if requested.
+    pub trace: Option<RoutingTrace>,
+}
+
+/// The number of layers of an inserted swap, decomposed into three CX gates.
+const SWAP_DEPTH: usize = 3;
+
+/// The outcome of a routing run, as returned by ``dqcmap_routing``, ``dqcmap_astar_routing`` and
+/// ``dqcmap_mcts_routing``.
+///
+/// Attributes:
+///     swaps (list[tuple[int, list[int]]]): The ``(node_id, [p0, p1])`` inserted swaps, each with
+///         the node it precedes, in insertion order.
+///     gate_order (list[int]): The order in which the nodes were routed.
+///     final_layout (NLayout): The virtual to physical qubit layout at the end of the circuit.
+///     cross_ctrl_feedbacks (float | None): The number of cross-controller feedbacks of the
+///         routed circuit, every cif pair counting as many times as its weight, ``None`` without
+///         ``ctrl2pq`` or ``cif_pairs``.
+///     depth_increase (int): The estimated increase of the depth of the circuit, every gate
+///         taking one layer and every swap three.
+///     elapsed (float): The wall-clock time of the routing in seconds.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug)]
+pub struct RoutingResult {
+    pub swaps: SwapList,
+    pub gate_order: Vec<usize>,
+    pub final_layout: NLayout,
+    pub cross_ctrl_feedbacks: Option<f64>,
+    pub depth_increase: usize,
+    pub elapsed: f64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RoutingResult {
+    /// The number of inserted swaps.
+    #[getter]
+    fn num_swaps(&self) -> usize {
+        self.swaps.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "RoutingResult(num_swaps={}, cross_ctrl_feedbacks={}, depth_increase={}, elapsed={})",
+            self.swaps.len(),
+            self.cross_ctrl_feedbacks
+                .map_or_else(|| "None".to_string(), |count| count.to_string()),
+            self.depth_increase,
+            self.elapsed
+        )
+    }
+}
+
+impl RoutingResult {
+    /// The report of `output`, the routing of `gates` from `initial_layout` that took `elapsed`.
+    pub fn new(
+        output: RouterOutput,
+        initial_layout: &NLayout,
+        gates: &[RouterGate],
+        elapsed: Duration,
+    ) -> Self {
+        RoutingResult {
+            depth_increase: depth_increase(initial_layout, gates, &output),
+            swaps: output.swaps,
+            gate_order: output.gate_order,
+            final_layout: output.final_layout,
+            cross_ctrl_feedbacks: output.cross_ctrl_feedbacks,
+            elapsed: elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// The increase of the depth of `gates` caused by the swaps of `output`, with every gate taking
+/// one layer and every swap [SWAP_DEPTH] layers as soon as its qubits are free.
+pub fn depth_increase(
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    output: &RouterOutput,
+) -> usize {
+    // schedule the qubits of a gate in the layer after the latest of them, returning it
+    fn schedule(levels: &mut [usize], qubits: &[usize], duration: usize) -> usize {
+        let level = qubits.iter().map(|&q| levels[q]).max().unwrap_or(0) + duration;
+        for &q in qubits {
+            levels[q] = level;
+        }
+        level
+    }
+
+    let mut levels = vec![0; initial_layout.iter_virtual().len()];
+    let depth_before = gates
+        .iter()
+        .map(|gate| {
+            let qubits: Vec<usize> = gate.qubits.iter().map(|q| q.index()).collect();
+            schedule(&mut levels, &qubits, 1)
+        })
+        .max()
+        .unwrap_or(0);
+
+    let gate_qubits: HashMap<usize, &[VirtualQubit]> = gates
+        .iter()
+        .map(|gate| (gate.node_id, gate.qubits.as_slice()))
+        .collect();
+    let mut node_swaps: HashMap<usize, Vec<[PhysicalQubit; 2]>> = HashMap::new();
+    for &(node_id, swap) in &output.swaps {
+        node_swaps.entry(node_id).or_default().push(swap);
+    }
+    let mut layout = initial_layout.clone();
+    let mut levels = vec![0; initial_layout.iter_physical().len()];
+    let mut depth_after = 0;
+    for node_id in &output.gate_order {
+        for &[p0, p1] in node_swaps.get(node_id).into_iter().flatten() {
+            depth_after =
+                depth_after.max(schedule(&mut levels, &[p0.index(), p1.index()], SWAP_DEPTH));
+            layout.swap_physical(p0, p1);
+        }
+        if let Some(qubits) = gate_qubits.get(node_id) {
+            let qubits: Vec<usize> = qubits.iter().map(|q| q.to_phys(&layout).index()).collect();
+            depth_after = depth_after.max(schedule(&mut levels, &qubits, 1));
+        }
+    }
+    depth_after.saturating_sub(depth_before)
+}
+
+/// Internal state of a single routing run.
+struct RouterState<'a> {
+    coupling: &'a CouplingMap,
+    gates: &'a [RouterGate],
+    /// Over indices into `gates`, once every reachable gate is routed it holds the 2q gates that
+    /// are waiting for their qubits to be adjacent.
+    front_layer: FrontLayer,
+    layout: NLayout,
+    qubits_decay: Vec<f64>,
+    dqcmap_state: DqcMapState,
+    /// Breaks ties between equally scored swaps, the first of them is taken if unset.
+    rng: Option<Pcg64Mcg>,
+    /// Records every greedy swap selection if set.
+    trace: Option<RoutingTrace>,
+    /// Samples the greedy selections from the softmax of the negated scores at this
+    /// temperature if set, in which case `rng` is set too.
+    temperature: Option<f64>,
+    /// Explores swap sequences instead of picking swaps greedily if set.
+    beam: Option<BeamSearch>,
+    swaps: SwapList,
+    gate_order: Vec<usize>,
+}
+
+impl<'a> RouterState<'a> {
+    #[inline]
+    fn physical_qubits(&self, gate: usize) -> [PhysicalQubit; 2] {
+        let qubits = &self.gates[gate].qubits;
+        [
+            qubits[0].to_phys(&self.layout),
+            qubits[1].to_phys(&self.layout),
+        ]
+    }
+
+    #[inline]
+    fn is_routable(&self, gate: usize) -> bool {
+        match self.gates[gate].qubits[..] {
+            [a, b] => {
+                self.coupling
+                    .distance(a.to_phys(&self.layout), b.to_phys(&self.layout))
+                    == 1
+            }
+            _ => true,
+        }
+    }
+
+    /// Route every gate reachable from `gates` that is executable on the current layout, the
+    /// remaining ones stay in the front layer.
+    fn route_reachable_gates(&mut self, gates: Vec<usize>) {
+        let mut to_visit = VecDeque::from(gates);
+        while let Some(gate) = to_visit.pop_front() {
+            if self.is_routable(gate) {
+                self.gate_order.push(self.gates[gate].node_id);
+                to_visit.extend(self.front_layer.retire(gate));
+            }
+        }
+    }
+
+    #[inline]
+    fn has_routable_gate(&self) -> bool {
+        self.front_layer
+            .nodes()
+            .iter()
+            .any(|&gate| self.is_routable(gate))
+    }
+
+    /// Candidate swaps are all coupling edges touching a qubit of the front layer, sorted so that
+    /// ties are broken independently of the order of the front layer.
+    fn candidate_swaps(&self) -> Vec<[PhysicalQubit; 2]> {
+        self.candidate_swaps_on(&self.layout)
+    }
+
+    /// The candidate swaps if the qubits were laid out as in `layout`.
+    fn candidate_swaps_on(&self, layout: &NLayout) -> Vec<[PhysicalQubit; 2]> {
+        DqcMapState::candidate_swaps(
+            self.coupling,
+            self.front_layer.nodes().iter().flat_map(|&gate| {
+                self.gates[gate]
+                    .qubits
+                    .iter()
+                    .map(|qubit| qubit.to_phys(layout))
+            }),
+        )
+    }
+
+    /// The decayed change in distance of the front layer caused by `swap`, and the node ids of
+    /// the gates it makes routable.
+    fn distance_delta(&self, swap: [PhysicalQubit; 2]) -> (f64, Vec<usize>) {
+        self.distance_delta_on(&self.layout, swap)
+    }
+
+    /// [RouterState::distance_delta] if the qubits were laid out as in `layout_before`.
+    fn distance_delta_on(
+        &self,
+        layout_before: &NLayout,
+        swap: [PhysicalQubit; 2],
+    ) -> (f64, Vec<usize>) {
+        let mut layout = layout_before.clone();
+        layout.swap_physical(swap[0], swap[1]);
+        let mut distance_before = 0.;
+        let mut distance = 0.;
+        let mut active_nodes = Vec::new();
+        for &gate in self.front_layer.nodes() {
+            let qubits = &self.gates[gate].qubits;
+            distance_before += self.coupling.distance(
+                qubits[0].to_phys(layout_before),
+                qubits[1].to_phys(layout_before),
+            ) as f64;
+            let dist = self
+                .coupling
+                .distance(qubits[0].to_phys(&layout), qubits[1].to_phys(&layout));
+            if dist == 1 {
+                active_nodes.push(self.gates[gate].node_id);
+            }
+            distance += dist as f64;
+        }
+        // the decay penalizes the distance reached by the swap, as in SABRE
+        let decay = self.qubits_decay[swap[0].index()].max(self.qubits_decay[swap[1].index()]);
+        (decay * distance - distance_before, active_nodes)
+    }
+
+    fn score_swap(&self, swap: [PhysicalQubit; 2]) -> Result<f64, MappingError> {
+        let (distance_delta, active_nodes) = self.distance_delta(swap);
+        self.dqcmap_state
+            .composite_score(Swap::from_physical(swap), &active_nodes, distance_delta)
+    }
+
+    /// The score of `swap` with its breakdown, for the trace.
+    fn trace_candidate(&self, swap: [PhysicalQubit; 2]) -> Result<CandidateTrace, MappingError> {
+        let (distance_delta, active_nodes) = self.distance_delta(swap);
+        let dqcmap_swap = Swap::from_physical(swap);
+        Ok(CandidateTrace {
+            swap,
+            distance_delta,
+            feedback_score: self.dqcmap_state.score_swap(dqcmap_swap, &active_nodes)?,
+            score: self
+                .dqcmap_state
+                .composite_score(dqcmap_swap, &active_nodes, distance_delta)?,
+        })
+    }
+
+    fn choose_best_swap(&mut self) -> Result<Option<[PhysicalQubit; 2]>, MappingError> {
+        let mut best_swaps = Vec::new();
+        let mut min_score = f64::MAX;
+        let mut candidates = Vec::new();
+        let mut scored = Vec::new();
+        for swap in self.candidate_swaps() {
+            let score = if self.trace.is_some() {
+                let candidate = self.trace_candidate(swap)?;
+                let score = candidate.score;
+                candidates.push(candidate);
+                score
+            } else {
+                self.score_swap(swap)?
+            };
+            scored.push((swap, score));
+            if score < min_score - BEST_EPSILON {
+                min_score = score;
+                best_swaps.clear();
+                best_swaps.push(swap);
+            } else if (score - min_score).abs() < BEST_EPSILON {
+                best_swaps.push(swap);
+            }
+        }
+        let chosen = match (self.rng.as_mut(), self.temperature) {
+            (Some(rng), Some(temperature)) => {
+                // shifted by the best score so that the weights cannot overflow
+                scored
+                    .choose_weighted(rng, |&(_, score)| {
+                        (-(score - min_score) / temperature).exp()
+                    })
+                    .ok()
+                    .map(|&(swap, _)| swap)
+            }
+            (Some(rng), None) => best_swaps.choose(rng).copied(),
+            (None, _) => best_swaps.first().copied(),
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            trace.steps.push(TraceStep {
+                front_layer: self
+                    .front_layer
+                    .nodes()
+                    .iter()
+                    .map(|&gate| self.gates[gate].node_id)
+                    .collect(),
+                candidates,
+                chosen,
+            });
+        }
+        Ok(chosen)
+    }
+
+    /// The best sequence of up to `beam.depth` swaps, ending at the first swap that makes a gate
+    /// routable.  Every step extends the kept sequences by each candidate swap and keeps the
+    /// `beam.width` ones with the lowest total composite score, sequences that made progress
+    /// competing with their score so far.  Equally scored sequences keep the order of their
+    /// candidates, so the result is deterministic.
+    fn choose_swaps_beam(&self, beam: BeamSearch) -> Result<Vec<[PhysicalQubit; 2]>, MappingError> {
+        let mut dqcmap_state = self.dqcmap_state.fork();
+        // every branch modifies its own cif pairs
+        dqcmap_state.enable_score_cache(false);
+        let mut branches = vec![BeamBranch {
+            layout: self.layout.clone(),
+            dqcmap_state,
+            swaps: Vec::new(),
+            score: 0.,
+            progressed: false,
+        }];
+        for _ in 0..beam.depth {
+            if branches[0].progressed {
+                break;
+            }
+            // (parent branch, swap extending it, total score, progressed)
+            let mut children = Vec::new();
+            for (index, branch) in branches.iter().enumerate() {
+                if branch.progressed {
+                    children.push((index, None, branch.score, true));
+                    continue;
+                }
+                for swap in self.candidate_swaps_on(&branch.layout) {
+                    let (distance_delta, active_nodes) =
+                        self.distance_delta_on(&branch.layout, swap);
+                    let score = branch.dqcmap_state.composite_score(
+                        Swap::from_physical(swap),
+                        &active_nodes,
+                        distance_delta,
+                    )?;
+                    children.push((
+                        index,
+                        Some(swap),
+                        branch.score + score,
+                        !active_nodes.is_empty(),
+                    ));
+                }
+            }
+            if children.is_empty() {
+                break;
+            }
+            children.sort_by(|a, b| a.2.total_cmp(&b.2));
+            children.truncate(beam.width);
+            branches = children
+                .into_iter()
+                .map(|(index, swap, score, progressed)| {
+                    let mut branch = branches[index].clone();
+                    if let Some(swap) = swap {
+                        branch.layout.swap_physical(swap[0], swap[1]);
+                        branch
+                            .dqcmap_state
+                            .apply_swap(Swap::from_physical(swap), &self.gate_order)?;
+                        branch.swaps.push(swap);
+                    }
+                    branch.score = score;
+                    branch.progressed = progressed;
+                    Ok(branch)
+                })
+                .collect::<Result<_, MappingError>>()?;
+        }
+        Ok(branches.swap_remove(0).swaps)
+    }
+
+    fn apply_swap(&mut self, swap: [PhysicalQubit; 2]) -> Result<(), MappingError> {
+        self.layout.swap_physical(swap[0], swap[1]);
+        self.dqcmap_state
+            .apply_swap(Swap::from_physical(swap), &self.gate_order)
+    }
+
+    /// Greedily bring the closest front-layer gate together along a shortest path.  This is the
+    /// "release valve" that guarantees progress when the heuristic gets stuck.
+    fn force_enable_closest_gate(&mut self) -> Result<Vec<[PhysicalQubit; 2]>, MappingError> {
+        let gate = *self
+            .front_layer
+            .nodes()
+            .iter()
+            .min_by_key(|&&gate| {
+                let [a, b] = self.physical_qubits(gate);
+                self.coupling.distance(a, b)
+            })
+            .unwrap();
+        let [mut a, b] = self.physical_qubits(gate);
+        if self.coupling.distance(a, b) == usize::MAX {
+            return Err(MappingError::Value(format!(
+                "qubits of node {} are in disconnected components of the coupling map",
+                self.gates[gate].node_id
+            )));
+        }
+        let mut swaps = Vec::new();
+        while self.coupling.distance(a, b) > 1 {
+            let next = *self
+                .coupling
+                .neighbors(a)
+                .iter()
+                .find(|&&n| self.coupling.distance(n, b) + 1 == self.coupling.distance(a, b))
+                .unwrap();
+            self.apply_swap([a, next])?;
+            swaps.push([a, next]);
+            a = next;
+        }
+        Ok(swaps)
+    }
+
+    /// Attach `swaps` to the first routable gate of the front layer and route everything that
+    /// becomes reachable.  Fails if no gate of the front layer is routable.
+    fn update_route(&mut self, swaps: Vec<[PhysicalQubit; 2]>) -> Result<(), MappingError> {
+        let routable: Vec<usize> = self
+            .front_layer
+            .nodes()
+            .iter()
+            .copied()
+            .filter(|&gate| self.is_routable(gate))
+            .collect();
+        let Some(&first) = routable.first() else {
+            return Err(MappingError::Runtime(
+                "no gate of the front layer is routable after forcing the closest one".to_string(),
+            ));
+        };
+        let node_id = self.gates[first].node_id;
+        self.swaps
+            .extend(swaps.into_iter().map(|swap| (node_id, swap)));
+        self.route_reachable_gates(routable);
+        Ok(())
+    }
+}
+
+/// The dependencies between `gates` (given in a valid topological order), derived from the
+/// virtual qubits they share, as a front layer over their indices.  Fails if `coupling` does not
+/// match `initial_layout` or if a gate is not a 1q or 2q gate on qubits of the layout.
+pub fn gate_dependencies(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+) -> Result<FrontLayer, MappingError> {
+    check_gates(coupling, initial_layout, gates)?;
+    let mut successors = vec![Vec::new(); gates.len()];
+    let mut last_gate_on_qubit: HashMap<VirtualQubit, usize> = HashMap::new();
+    for (index, gate) in gates.iter().enumerate() {
+        for qubit in &gate.qubits {
+            if let Some(predecessor) = last_gate_on_qubit.insert(*qubit, index) {
+                if !successors[predecessor].contains(&index) {
+                    successors[predecessor].push(index);
+                }
+            }
+        }
+    }
+    Ok(FrontLayer::from_successors(successors))
+}
+
+/// Check that `gates` can be routed onto `coupling` from `initial_layout`.
+fn check_gates(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+) -> Result<(), MappingError> {
+    let num_physical = initial_layout.iter_physical().len();
+    let num_virtual = initial_layout.iter_virtual().len();
+    if coupling.num_qubits() != num_physical {
+        return Err(MappingError::Value(format!(
+            "the coupling map has {} qubits but the layout has {} physical qubits",
+            coupling.num_qubits(),
+            num_physical
+        )));
+    }
+
+    for gate in gates {
+        if gate.qubits.len() > 2 {
+            return Err(MappingError::Value(format!(
+                "node {} acts on {} qubits, only 1q and 2q gates can be routed",
+                gate.node_id,
+                gate.qubits.len()
+            )));
+        }
+        for qubit in &gate.qubits {
+            if qubit.index() >= num_virtual {
+                return Err(MappingError::Index(format!(
+                    "qubit index {} is out of range for {} virtual qubits",
+                    qubit.index(),
+                    num_virtual
+                )));
+            }
+        }
+        if let [a, b] = gate.qubits[..] {
+            if a == b {
+                return Err(MappingError::Value(format!(
+                    "node {} acts twice on qubit {}",
+                    gate.node_id,
+                    a.index()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Route `gates` (given in a valid topological order) onto `coupling`, starting from
+/// `initial_layout`.  The dependencies between gates are derived from the virtual qubits they
+/// share.  Swaps are chosen by a SABRE-like greedy search over the front layer, where the
+/// change in distance of the front layer is combined with the score of the cost model of
+/// `dqcmap_state` as given by [DqcMapState::composite_score], by default the latency-weighted
+/// difference in cross-controller feedbacks minus the swap noise cost.  Equally scored swaps are
+/// picked at random by an RNG seeded with the `seed` of `options`, or the smallest one is taken
+/// without a seed, so that the result is reproducible either way.  With `record_trace`, every
+/// swap selection is recorded in [RouterOutput::trace].
+///
+/// With `temperature`, every greedy selection instead samples a candidate swap with probability
+/// proportional to `exp(-score / temperature)` from the RNG seeded with `seed`, or from the OS
+/// without a seed, which lets the search escape local minima.
+///
+/// With `beam`, sequences of swaps are explored with a beam search on forks of `dqcmap_state`
+/// instead, and the best one up to the first routable gate is taken.  Its swap selections are
+/// not traced.  It cannot be combined with `temperature`.
+pub fn route(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    dqcmap_state: DqcMapState,
+    options: RouteOptions,
+) -> Result<RouterOutput, MappingError> {
+    let front_layer = gate_dependencies(coupling, initial_layout, gates)?;
+    route_with_dependencies(
+        coupling,
+        initial_layout,
+        gates,
+        front_layer,
+        dqcmap_state,
+        options,
+    )
+}
+
+/// Route the gates of `dag` like [route], but with the dependencies of the DAG, which include
+/// the classical dependencies and leave out the ones between commuting gates if the DAG was
+/// built with commutation.  Directives are not routed.
+pub fn route_dag(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    dag: &CircuitDag,
+    dqcmap_state: DqcMapState,
+    options: RouteOptions,
+) -> Result<RouterOutput, MappingError> {
+    let gates = dag.router_gates();
+    check_gates(coupling, initial_layout, &gates)?;
+    route_with_dependencies(
+        coupling,
+        initial_layout,
+        &gates,
+        FrontLayer::from_successors(dag.router_successors()),
+        dqcmap_state,
+        options,
+    )
+}
+
+fn route_with_dependencies(
+    coupling: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: &[RouterGate],
+    front_layer: FrontLayer,
+    mut dqcmap_state: DqcMapState,
+    options: RouteOptions,
+) -> Result<RouterOutput, MappingError> {
+    options.validate()?;
+    let RouteOptions {
+        seed,
+        record_trace,
+        beam,
+        temperature,
+    } = options;
+    let _timer = metrics::time_phase(Phase::Routing);
+    // the front layer is often unchanged between swap selections
+    dqcmap_state.enable_score_cache(true);
+    let num_physical = initial_layout.iter_physical().len();
+    let first_layer = front_layer.nodes().to_vec();
+
+    let mut state = RouterState {
+        coupling,
+        gates,
+        front_layer,
+        layout: initial_layout.clone(),
+        qubits_decay: vec![1.; num_physical],
+        dqcmap_state,
+        rng: match (seed, temperature) {
+            (Some(seed), _) => Some(Pcg64Mcg::seed_from_u64(seed)),
+            (None, Some(_)) => Some(Pcg64Mcg::from_entropy()),
+            (None, None) => None,
+        },
+        temperature,
+        trace: record_trace.then(RoutingTrace::default),
+        beam,
+        swaps: Vec::new(),
+        gate_order: Vec::with_capacity(gates.len()),
+    };
+    state.route_reachable_gates(first_layer);
+
+    let max_iterations_without_progress = 10 * num_physical;
+    let mut num_search_steps: u8 = 0;
+    while !state.front_layer.is_empty() {
+        let mut current_swaps: Vec<[PhysicalQubit; 2]> = Vec::new();
+        'search: while current_swaps.len() <= max_iterations_without_progress {
+            let best_swaps = match state.beam {
+                Some(beam) => state.choose_swaps_beam(beam)?,
+                None => state.choose_best_swap()?.into_iter().collect(),
+            };
+            if best_swaps.is_empty() {
+                break;
+            }
+            for best_swap in best_swaps {
+                state.apply_swap(best_swap)?;
+                current_swaps.push(best_swap);
+                if state.has_routable_gate() {
+                    break 'search;
+                }
+                num_search_steps += 1;
+                if num_search_steps >= DECAY_RESET_INTERVAL {
+                    state.qubits_decay.fill(1.);
+                    num_search_steps = 0;
+                } else {
+                    state.qubits_decay[best_swap[0].index()] += DECAY_RATE;
+                    state.qubits_decay[best_swap[1].index()] += DECAY_RATE;
+                }
+            }
+        }
+        if !state.has_routable_gate() {
+            // Unwind to the last progress point and force the closest gate to be routable.
+            for swap in current_swaps.drain(..).rev() {
+                state.apply_swap(swap)?;
+            }
+            current_swaps = state.force_enable_closest_gate()?;
+        }
+        state.update_route(current_swaps)?;
+        state.qubits_decay.fill(1.);
+    }
+
+    Ok(RouterOutput {
+        swaps: state.swaps,
+        gate_order: state.gate_order,
+        final_layout: state.layout,
+        cross_ctrl_feedbacks: state.dqcmap_state.current_cost(),
+        trace: state.trace,
+    })
+}
+
+/// Run the controller-aware router on a circuit.  The GIL is released while routing.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     initial_layout (NLayout): The initial virtual to physical qubit layout.
+///     gates (list[tuple[int, list[int]]]): The node id and virtual qubits of every gate, in a
+///         valid topological order.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     cif_pairs (CifPairs): The cif pairs of the circuit keyed by node id.
+///     ctrl_topology (CtrlTopology): The latency model between controllers.  Every
+///         cross-controller feedback costs 1 if not given.
+///     noise_model (NoiseModel | DeviceProperties): The error rates of the device, used to avoid
+///         swaps over noisy links.  Swaps are noiseless if not given.
+///     cost_model (str): The name of the heuristic scoring the controller locality of a swap,
+///         see ``DqcMapState.available_cost_models()``.  ``"fidelity_weighted"`` if not given.
+///     alpha (float): The weight of the change in distance of the front layer gates.
+///     beta (float): The weight of the change in cross-controller feedbacks.
+///     seed (int): The seed of the RNG breaking ties between equally scored swaps.  The smallest
+///         of them is taken if not given.
+///     config (DqcMapConfig): The weights, cost model, lookahead, selection strategy, seed and
+///         beam search, replacing ``cost_model``, ``alpha``, ``beta`` and ``seed`` if given.
+///     trace (RoutingTrace): A trace receiving every swap selection with its candidates and
+///         their scores.  Nothing is recorded if not given.
+///     crosstalk (CrosstalkMap): The crosstalk-prone pairs of edges, used to keep the qubits of
+///         cif pairs out of their neighborhoods.  Crosstalk is ignored if not given.
+///
+/// Returns:
+///     RoutingResult: The inserted swaps, the order in which the nodes were routed, the final
+///     layout and the statistics of the routing.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (coupling_map, initial_layout, gates, ctrl2pq=None, cif_pairs=None, ctrl_topology=None, noise_model=None, cost_model=None, alpha=DEFAULT_DISTANCE_WEIGHT, beta=DEFAULT_FEEDBACK_WEIGHT, seed=None, config=None, trace=None, crosstalk=None))]
+pub fn dqcmap_routing(
+    py: Python,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    gates: Vec<(usize, Vec<VirtualQubit>)>,
+    ctrl2pq: Option<Ctrl2Pq>,
+    cif_pairs: Option<CifPairs>,
+    ctrl_topology: Option<CtrlTopology>,
+    noise_model: Option<NoiseSource>,
+    cost_model: Option<&str>,
+    alpha: f64,
+    beta: f64,
+    seed: Option<u64>,
+    config: Option<DqcMapConfig>,
+    trace: Option<Bound<RoutingTrace>>,
+    crosstalk: Option<CrosstalkMap>,
+) -> PyResult<RoutingResult> {
+    let gates: Vec<RouterGate> = gates
+        .into_iter()
+        .map(|(node_id, qubits)| RouterGate { node_id, qubits })
+        .collect();
+    let mut dqcmap_state = DqcMapState::new(ctrl2pq, cif_pairs);
+    dqcmap_state.ctrl_topology = ctrl_topology;
+    dqcmap_state.noise_model = noise_model.map(NoiseSource::into_noise_model).transpose()?;
+    dqcmap_state.crosstalk = crosstalk;
+    if let Some(name) = cost_model {
+        dqcmap_state.set_cost_model(cost_model_by_name(name)?);
+    }
+    dqcmap_state.set_objective_weights(alpha, beta)?;
+    let mut options = match config {
+        Some(config) => {
+            dqcmap_state.apply_config(&config)?;
+            config.route_options()
+        }
+        None => RouteOptions {
+            seed,
+            ..RouteOptions::default()
+        },
+    };
+    options.record_trace = trace.is_some();
+    let start = Instant::now();
+    let mut output =
+        py.allow_threads(|| route(coupling_map, initial_layout, &gates, dqcmap_state, options))?;
+    let elapsed = start.elapsed();
+    if let (Some(trace), Some(steps)) = (trace, output.trace.take()) {
+        trace.borrow_mut().steps.extend(steps.steps);
+    }
+    Ok(RoutingResult::new(output, initial_layout, &gates, elapsed))
+}
+
+/// Route the instructions of a circuit in a single call, the fast path of the routing pass.
+///
+/// The cif pairs are extracted from the conditioned instructions as in ``CircuitDag`` and mapped
+/// onto physical qubits through ``initial_layout``.  Directives are not routed.  The GIL is
+/// released while routing.
+///
+/// Args:
+///     dag_instructions (list[tuple[str, list[int], list[int], list[int] | None]]): The name,
+///         virtual qubits, clbits and condition clbits of every instruction in topological
+///         order.  The node id of an instruction is its index.
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     initial_layout (NLayout): The initial virtual to physical qubit layout.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     seed (int): The seed of the RNG breaking ties between equally scored swaps.
+///     config (DqcMapConfig): The weights, cost model, lookahead, selection strategy, seed and
+///         beam search, replacing ``seed`` if given.
+///     trace (RoutingTrace): A trace receiving every swap selection with its candidates and
+///         their scores.  Nothing is recorded if not given.
+///     commutation (bool): Whether commuting gates can be routed in any order, see
+///         ``CircuitDag``.
+///
+/// Returns:
+///     (swaps, permutation, stats): A dict from node id to the ``(p0, p1)`` swaps inserted before
+///     that node, the physical qubit of every virtual qubit at the end of the circuit, and a dict
+///     of ``num_swaps``, ``initial_cross_controller_feedbacks`` and
+///     ``cross_controller_feedbacks``.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(name = "route", signature = (dag_instructions, coupling_map, initial_layout, ctrl2pq, seed=None, config=None, trace=None, commutation=false))]
+pub fn py_route(
+    py: Python,
+    dag_instructions: Vec<Instruction>,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    ctrl2pq: &Ctrl2Pq,
+    seed: Option<u64>,
+    config: Option<DqcMapConfig>,
+    trace: Option<Bound<RoutingTrace>>,
+    commutation: bool,
+) -> PyResult<RouteResult> {
+    let (_, output, initial_cost) = route_instruction_list(
+        py,
+        dag_instructions,
+        coupling_map,
+        initial_layout,
+        ctrl2pq,
+        seed,
+        config,
+        trace,
+        commutation,
+    )?;
+    let mut swaps: HashMap<usize, Vec<(PhysicalQubit, PhysicalQubit)>> = HashMap::new();
+    for (node_id, [p0, p1]) in &output.swaps {
+        swaps.entry(*node_id).or_default().push((*p0, *p1));
+    }
+    let permutation = output
+        .final_layout
+        .iter_virtual()
+        .map(|(_, physical)| physical)
+        .collect();
+    let stats = PyDict::new_bound(py);
+    stats.set_item("num_swaps", output.swaps.len())?;
+    stats.set_item("initial_cross_controller_feedbacks", initial_cost)?;
+    stats.set_item("cross_controller_feedbacks", output.cross_ctrl_feedbacks)?;
+    Ok((swaps, permutation, stats.unbind()))
+}
+
+/// Build the DAG of `dag_instructions` and route it as done by [py_route], returning the DAG,
+/// the output of the router and the initial number of cross-controller feedbacks.
+#[cfg(feature = "python")]
+#[allow(clippy::too_many_arguments)]
+pub(super) fn route_instruction_list(
+    py: Python,
+    dag_instructions: Vec<Instruction>,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    ctrl2pq: &Ctrl2Pq,
+    seed: Option<u64>,
+    config: Option<DqcMapConfig>,
+    trace: Option<Bound<RoutingTrace>>,
+    commutation: bool,
+) -> PyResult<(CircuitDag, RouterOutput, Option<f64>)> {
+    let num_clbits = dag_instructions
+        .iter()
+        .flat_map(|(_, _, clbits, condition)| clbits.iter().chain(condition.iter().flatten()))
+        .max()
+        .map_or(0, |clbit| clbit + 1);
+    let dag = CircuitDag::new(
+        initial_layout.iter_virtual().len(),
+        num_clbits,
+        dag_instructions,
+        commutation,
+    )?;
+    let physical_pairs = dag
+        .cif_pairs
+        .iter()
+        .map(|(&node_id, pairs)| {
+            let pairs = pairs
+                .iter()
+                .map(|pair| {
+                    let mut pair = pair.clone();
+                    // the clbit stays as is
+                    for q in pair.iter_mut().take(2) {
+                        *q = VirtualQubit::new(*q as u32).to_phys(initial_layout).index() as i32;
+                    }
+                    pair
+                })
+                .collect();
+            (node_id, pairs)
+        })
+        .collect();
+    let mut dqcmap_state = DqcMapState::new(
+        Some(ctrl2pq.clone()),
+        Some(CifPairs::from_pairs(physical_pairs)),
+    );
+    let mut options = match config {
+        Some(config) => {
+            dqcmap_state.apply_config(&config)?;
+            config.route_options()
+        }
+        None => RouteOptions {
+            seed,
+            ..RouteOptions::default()
+        },
+    };
+    let initial_cost = dqcmap_state.current_cost();
+    options.record_trace = trace.is_some();
+    let mut output =
+        py.allow_threads(|| route_dag(coupling_map, initial_layout, &dag, dqcmap_state, options))?;
+    if let (Some(trace), Some(steps)) = (trace, output.trace.take()) {
+        trace.borrow_mut().steps.extend(steps.steps);
+    }
+    Ok((dag, output, initial_cost))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::{
+        cif_pairs::CifPairs, cost_model::cost_model_by_name, ctrl_to_pq::Ctrl2Pq,
+        ctrl_topology::CtrlTopology, dag::Instruction, noise::NoiseModel,
+    };
+    use hashbrown::{HashMap, HashSet};
+
+    fn line(num_qubits: u32) -> CouplingMap {
+        let edges = (0..num_qubits - 1)
+            .map(|q| [PhysicalQubit::new(q), PhysicalQubit::new(q + 1)])
+            .collect();
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    fn gate(node_id: usize, qubits: &[u32]) -> RouterGate {
+        RouterGate {
+            node_id,
+            qubits: qubits.iter().map(|&q| VirtualQubit::new(q)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_route_on_line() {
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(0, &[0, 1]), gate(1, &[0, 3]), gate(2, &[1, 2])];
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(output.gate_order.len(), 3);
+        assert_eq!(output.swaps.len(), 2);
+        // every swap must be on a coupling edge and precede node 1, the only non-local gate
+        for (node_id, [a, b]) in &output.swaps {
+            assert_eq!(*node_id, 1);
+            assert_eq!((a.index() as i32 - b.index() as i32).abs(), 1);
+        }
+        let [p0, p3] = [
+            VirtualQubit::new(0).to_phys(&output.final_layout),
+            VirtualQubit::new(3).to_phys(&output.final_layout),
+        ];
+        assert_eq!((p0.index() as i32 - p3.index() as i32).abs(), 1);
+    }
+
+    #[test]
+    fn test_routing_result() {
+        let layout = NLayout::generate_trivial_layout(3);
+        let gates = vec![gate(0, &[0, 1]), gate(1, &[0, 2])];
+        let output = route(
+            &line(3),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(output.swaps.len(), 1);
+
+        // either swap holds qubit 1, so it waits for the first gate and delays the second
+        let result = RoutingResult::new(output, &layout, &gates, Duration::from_millis(5));
+        assert_eq!(result.depth_increase, SWAP_DEPTH);
+        assert_eq!(result.gate_order, vec![0, 1]);
+        assert_eq!(result.elapsed, 0.005);
+        #[cfg(feature = "python")]
+        assert!(result.__repr__().starts_with("RoutingResult(num_swaps=1,"));
+    }
+
+    #[test]
+    fn test_route_beam_search() {
+        let layout = NLayout::generate_trivial_layout(5);
+        let gates = vec![gate(0, &[0, 4]), gate(1, &[1, 3]), gate(2, &[0, 2])];
+        let beam = BeamSearch { width: 4, depth: 3 };
+        let output = route(
+            &line(5),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions {
+                beam: Some(beam),
+                ..RouteOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output.gate_order.len(), 3);
+        assert!(output.swaps.len() >= 3);
+        let mut layout = layout;
+        let mut gate_order = output.gate_order.iter();
+        let mut swaps = output.swaps.iter().peekable();
+        for node_id in gate_order.by_ref() {
+            while let Some((_, [a, b])) = swaps.next_if(|(swap_node, _)| swap_node == node_id) {
+                assert_eq!((a.index() as i32 - b.index() as i32).abs(), 1);
+                layout.swap_physical(*a, *b);
+            }
+            let gate = gates.iter().find(|gate| gate.node_id == *node_id).unwrap();
+            let [p0, p1] = [
+                gate.qubits[0].to_phys(&layout),
+                gate.qubits[1].to_phys(&layout),
+            ];
+            assert_eq!((p0.index() as i32 - p1.index() as i32).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn test_route_shared_device_concurrently() {
+        use std::sync::Arc;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CouplingMap>();
+        assert_send_sync::<Ctrl2Pq>();
+        assert_send_sync::<CifPairs>();
+
+        let coupling = line(4);
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 2]])]));
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(1, &[0, 2])];
+        // clones share the device model instead of copying it
+        let state = DqcMapState::new(Some(ctrl2pq.clone()), Some(cif_pairs));
+        assert!(Arc::ptr_eq(
+            &state.ctrl2pq.as_ref().unwrap().map,
+            &ctrl2pq.map
+        ));
+
+        let expected = route(
+            &coupling,
+            &layout,
+            &gates,
+            state.clone(),
+            RouteOptions::default(),
+        )
+        .unwrap()
+        .swaps;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let state = state.clone();
+                    let (coupling, layout, gates) = (&coupling, &layout, &gates);
+                    scope.spawn(move || {
+                        route(coupling, layout, gates, state, RouteOptions::default())
+                            .unwrap()
+                            .swaps
+                    })
+                })
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_route_prefers_intra_controller_feedback() {
+        // Line 0 - 1 - 2 - 3 with controller 0 owning {0, 1} and controller 1 owning {2, 3}.
+        let ctrl2pq = Ctrl2Pq {
+            map: HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]).into(),
+            reverse_map: HashMap::from([(0, 0), (1, 0), (2, 1), (3, 1)]).into(),
+            capacity: HashMap::new().into(),
+        };
+        // Node 1 conditions qubit 2 on a measurement of qubit 0.
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 2]])]));
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(1, &[0, 2])];
+        let mut dqcmap_state = DqcMapState::new(Some(ctrl2pq), Some(cif_pairs));
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            dqcmap_state.clone(),
+            RouteOptions::default(),
+        )
+        .unwrap();
+
+        // Both (0, 1) and (1, 2) make the gate routable, but only (1, 2) moves the conditioned
+        // qubit onto the controller of the measured one.
+        assert_eq!(
+            output.swaps,
+            vec![(1, [PhysicalQubit::new(1), PhysicalQubit::new(2)])]
+        );
+
+        // the trace shows why (1, 2) won over (0, 1)
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            dqcmap_state.clone(),
+            RouteOptions {
+                record_trace: true,
+                ..RouteOptions::default()
+            },
+        )
+        .unwrap();
+        let trace = output.trace.unwrap();
+        assert_eq!(trace.steps.len(), 1);
+        let step = &trace.steps[0];
+        assert_eq!(step.front_layer, vec![1]);
+        assert_eq!(
+            step.chosen,
+            Some([PhysicalQubit::new(1), PhysicalQubit::new(2)])
+        );
+        let scores: Vec<_> = step
+            .candidates
+            .iter()
+            .map(|candidate| (candidate.swap[0].index(), candidate.feedback_score))
+            .collect();
+        assert_eq!(scores, vec![(0, 0.), (1, 1.), (2, 0.)]);
+        assert!(step.candidates[1].score < step.candidates[0].score);
+
+        // without the feedback term the first candidate wins
+        dqcmap_state.set_objective_weights(1., 0.).unwrap();
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            dqcmap_state,
+            RouteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.swaps,
+            vec![(1, [PhysicalQubit::new(0), PhysicalQubit::new(1)])]
+        );
+    }
+
+    #[test]
+    fn test_route_avoids_noisy_link() {
+        // Line 0 - 1 - 2 - 3 without controllers: swaps (0, 1) and (1, 2) both make the gate on
+        // virtual qubits 0 and 2 routable, but (0, 1) is a noisy link.
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(0, &[0, 2])];
+        let mut dqcmap_state = DqcMapState::new(None, None);
+        dqcmap_state.noise_model =
+            Some(NoiseModel::new(HashMap::from([((0, 1), 0.2)]), HashMap::new(), 1.).unwrap());
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            dqcmap_state.clone(),
+            RouteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.swaps,
+            vec![(0, [PhysicalQubit::new(1), PhysicalQubit::new(2)])]
+        );
+
+        // the feedback count ignores the noise model, so the first candidate wins
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_count").unwrap());
+        let output = route(
+            &line(4),
+            &layout,
+            &gates,
+            dqcmap_state,
+            RouteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.swaps,
+            vec![(0, [PhysicalQubit::new(0), PhysicalQubit::new(1)])]
+        );
+    }
+
+    #[test]
+    fn test_route_bandwidth_limit() {
+        // Controllers own {0, 1}, {2, 3} and {4, 5} of the line and send one feedback at a time.
+        // The swaps routing node 0 change the bandwidth cost of the swaps routing node 1 without
+        // touching their qubits, so that scores cached before them are stale.
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([
+            (0, vec![0, 1]),
+            (1, vec![2, 3]),
+            (2, vec![4, 5]),
+        ]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([
+            (0, vec![vec![3, 0]]),
+            (1, vec![vec![0, 2], vec![0, 4]]),
+        ]));
+        let mut dqcmap_state = DqcMapState::new(Some(ctrl2pq), Some(cif_pairs));
+        dqcmap_state.ctrl_topology =
+            Some(CtrlTopology::new(HashMap::new(), 1., HashMap::new(), Some(1)).unwrap());
+        let output = route(
+            &line(6),
+            &NLayout::generate_trivial_layout(6),
+            &[gate(0, &[3, 0]), gate(1, &[5, 1])],
+            dqcmap_state,
+            RouteOptions::default(),
+        )
+        .unwrap();
+        let swaps: Vec<_> = output
+            .swaps
+            .iter()
+            .map(|&(node_id, [p0, p1])| (node_id, [p0.index(), p1.index()]))
+            .collect();
+        assert_eq!(
+            swaps,
+            vec![(0, [1, 2]), (0, [2, 3]), (0, [1, 2]), (1, [4, 5])]
+        );
+    }
+
+    #[test]
+    fn test_route_seeded_tie_breaking() {
+        // (0, 1) and (1, 2) both make the gate routable with the same score
+        let layout = NLayout::generate_trivial_layout(4);
+        let gates = vec![gate(0, &[0, 2])];
+        let route_seeded = |seed| {
+            route(
+                &line(4),
+                &layout,
+                &gates,
+                DqcMapState::new(None, None),
+                RouteOptions {
+                    seed: Some(seed),
+                    ..RouteOptions::default()
+                },
+            )
+            .unwrap()
+            .swaps
+        };
+        for seed in 0..8 {
+            assert_eq!(route_seeded(seed), route_seeded(seed));
+        }
+        let chosen: HashSet<_> = (0..32).map(|seed| route_seeded(seed)[0].1).collect();
+        assert_eq!(chosen.len(), 2);
+    }
+
+    #[test]
+    fn test_route_softmax_selection() {
+        let layout = NLayout::generate_trivial_layout(6);
+        let gates = vec![gate(0, &[0, 5]), gate(1, &[1, 4])];
+        let route_softmax = |seed, temperature| {
+            route(
+                &line(6),
+                &layout,
+                &gates,
+                DqcMapState::new(None, None),
+                RouteOptions {
+                    seed: Some(seed),
+                    temperature: Some(temperature),
+                    ..RouteOptions::default()
+                },
+            )
+            .unwrap()
+            .swaps
+        };
+        for seed in 0..8 {
+            assert_eq!(route_softmax(seed, 10.), route_softmax(seed, 10.));
+        }
+        // a hot distribution also samples swaps that are not the best ones
+        let first_swaps: HashSet<_> = (0..32).map(|seed| route_softmax(seed, 10.)[0].1).collect();
+        assert!(first_swaps.len() > 2);
+        // a cold one is as good as greedy
+        let greedy = route(
+            &line(6),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(route_softmax(0, 1e-6).len(), greedy.swaps.len());
+
+        // the beam search has no softmax selection
+        let options = RouteOptions {
+            seed: Some(0),
+            beam: Some(BeamSearch { width: 2, depth: 2 }),
+            temperature: Some(1.),
+            ..RouteOptions::default()
+        };
+        let err = route(
+            &line(6),
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            options,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("softmax"));
+    }
+
+    #[test]
+    fn test_route_disconnected() {
+        let layout = NLayout::generate_trivial_layout(4);
+        let coupling = CouplingMap::new(
+            vec![[PhysicalQubit::new(0), PhysicalQubit::new(1)]],
+            Some(4),
+        )
+        .unwrap();
+        let gates = vec![gate(0, &[0, 3])];
+        assert!(route(
+            &coupling,
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_route_duplicate_qubit() {
+        let layout = NLayout::generate_trivial_layout(3);
+        let coupling = line(3);
+        let gates = vec![gate(0, &[0, 2]), gate(1, &[1, 1])];
+        let err = route(
+            &coupling,
+            &layout,
+            &gates,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "node 1 acts twice on qubit 1");
+    }
+
+    #[test]
+    fn test_route_dag_commutation() {
+        // both cx share their control, so the adjacent one can be routed first
+        let instructions: Vec<Instruction> = vec![
+            ("cx".to_string(), vec![0, 2], vec![], None),
+            ("cx".to_string(), vec![0, 1], vec![], None),
+        ];
+        let layout = NLayout::generate_trivial_layout(3);
+        let route_with = |commutation| {
+            let dag = CircuitDag::new(3, 0, instructions.clone(), commutation).unwrap();
+            route_dag(
+                &line(3),
+                &layout,
+                &dag,
+                DqcMapState::new(None, None),
+                RouteOptions::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(route_with(false).gate_order, vec![0, 1]);
+        let output = route_with(true);
+        assert_eq!(output.gate_order, vec![1, 0]);
+        assert_eq!(output.swaps.len(), 1);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_py_route_instructions() {
+        // measure qubit 0 and condition a gate on qubit 2 with it, then a cx between 0 and 2
+        let instructions: Vec<Instruction> = vec![
+            ("measure".to_string(), vec![0], vec![0], None),
+            ("x".to_string(), vec![2], vec![], Some(vec![0])),
+            ("cx".to_string(), vec![0, 2], vec![], None),
+            ("barrier".to_string(), vec![0, 1, 2, 3], vec![], None),
+        ];
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let layout = NLayout::generate_trivial_layout(4);
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let (swaps, permutation, stats) = py_route(
+                py,
+                instructions,
+                &line(4),
+                &layout,
+                &ctrl2pq,
+                None,
+                None,
+                None,
+                false,
+            )
+            .unwrap();
+            let q = PhysicalQubit::new;
+            // the feedback of node 1 is already behind, so the tie goes to the smallest swap
+            assert_eq!(swaps, HashMap::from([(2, vec![(q(0), q(1))])]));
+            assert_eq!(permutation, vec![q(1), q(0), q(2), q(3)]);
+            let stats = stats.bind(py);
+            let get = |key: &str| -> Option<f64> {
+                stats.get_item(key).unwrap().unwrap().extract().unwrap()
+            };
+            assert_eq!(get("num_swaps"), Some(1.));
+            assert_eq!(get("initial_cross_controller_feedbacks"), Some(1.));
+            assert_eq!(get("cross_controller_feedbacks"), Some(1.));
+        });
+    }
+}
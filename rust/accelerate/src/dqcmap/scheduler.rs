@@ -0,0 +1,351 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rustworkx_core::petgraph::prelude::*;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    config::FeedbackModel,
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+    dag::CircuitDag,
+    device::GateDurations,
+    metrics::{self, Phase},
+    sim::check_durations,
+};
+
+/// When the operations of a routed circuit are scheduled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleMethod {
+    /// Every operation starts as soon as possible.
+    Asap,
+    /// Every operation starts as late as possible without delaying the end of the circuit.
+    Alap,
+}
+
+impl ScheduleMethod {
+    pub fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "asap" => Ok(ScheduleMethod::Asap),
+            "alap" => Ok(ScheduleMethod::Alap),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown schedule method '{name}', expected 'asap' or 'alap'"
+            ))),
+        }
+    }
+}
+
+/// The start times of the operations of a routed circuit, see ``schedule``.
+#[pyclass(frozen, module = "dqcmap._accelerate.dqcmap")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    /// Start time of every node, by node id.
+    #[pyo3(get)]
+    pub start_times: Vec<f64>,
+    /// Time every node waits for the results of remote measurements after its other
+    /// dependencies have finished, by node id.
+    #[pyo3(get)]
+    pub stalls: Vec<f64>,
+    /// Time at which the last operation finishes.
+    #[pyo3(get)]
+    pub total_time: f64,
+}
+
+#[pymethods]
+impl Schedule {
+    /// The number of scheduled nodes.
+    fn __len__(&self) -> usize {
+        self.start_times.len()
+    }
+
+    /// The total time spent waiting for feedbacks.
+    #[pyo3(text_signature = "(self, /)")]
+    pub fn total_stall(&self) -> f64 {
+        self.stalls.iter().sum()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Schedule(num_nodes={}, total_time={}, total_stall={})",
+            self.start_times.len(),
+            self.total_time,
+            self.total_stall()
+        )
+    }
+}
+
+/// The measurements every node waits for with the latency of their feedback, following the
+/// latency model of [super::sim::simulate].
+fn feedback_dependencies(
+    dag: &CircuitDag,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    feedback_model: FeedbackModel,
+) -> Vec<Vec<(NodeIndex, f64)>> {
+    let latency = |ctrl0: i32, ctrl1: i32| feedback_model.latency(ctrl_topology, ctrl0, ctrl1);
+    let mut feedbacks = vec![Vec::new(); dag.dag.node_count()];
+    // the measurement node and measured qubit of the last measurement into every clbit
+    let mut last_measure: Vec<Option<(NodeIndex, usize)>> = vec![None; dag.num_clbits];
+    for node in dag.dag.node_indices() {
+        let weight = &dag.dag[node];
+        for &clbit in weight.condition.iter().flatten() {
+            let Some((measure, measured)) = last_measure[clbit] else {
+                continue;
+            };
+            for &qubit in &weight.qubits {
+                if let (Some(ctrl_measured), Some(ctrl_conditioned)) = (
+                    ctrl2pq.controller_of(PhysicalQubit::new(measured as u32)),
+                    ctrl2pq.controller_of(PhysicalQubit::new(qubit as u32)),
+                ) {
+                    feedbacks[node.index()]
+                        .push((measure, latency(ctrl_measured, ctrl_conditioned)));
+                }
+            }
+        }
+        if weight.is_measure() && weight.qubits.len() == weight.clbits.len() {
+            for (&qubit, &clbit) in weight.qubits.iter().zip(&weight.clbits) {
+                last_measure[clbit] = Some((node, qubit));
+            }
+        }
+    }
+    feedbacks
+}
+
+/// Assign a start time to every operation of `dag` with `method`.
+///
+/// An operation starts after all its predecessors have finished, and a conditioned operation
+/// additionally after the results of the measurements it depends on have reached its controller,
+/// which takes the feedback latency of `ctrl_topology` under `feedback_model` as in
+/// [super::sim::simulate].  The wait for
+/// the feedback beyond the other dependencies of an operation is recorded as its stall.  With
+/// [ScheduleMethod::Alap], the total time is the one of the as-soon-as-possible schedule and
+/// every operation is moved as late as its successors and the feedbacks it sends allow.
+pub fn schedule(
+    dag: &CircuitDag,
+    durations: &HashMap<String, f64>,
+    default_duration: f64,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    feedback_model: FeedbackModel,
+    method: ScheduleMethod,
+) -> Schedule {
+    let _timer = metrics::time_phase(Phase::Simulation);
+    let num_nodes = dag.dag.node_count();
+    let duration: Vec<f64> = dag
+        .dag
+        .node_weights()
+        .map(|weight| {
+            if weight.is_directive() {
+                0.
+            } else {
+                *durations.get(&weight.name).unwrap_or(&default_duration)
+            }
+        })
+        .collect();
+    let feedbacks = feedback_dependencies(dag, ctrl2pq, ctrl_topology, feedback_model);
+
+    // node indices follow the input order, which is topological
+    let mut start = vec![0.; num_nodes];
+    let mut total_time: f64 = 0.;
+    for node in dag.dag.node_indices() {
+        let ready = dag
+            .dag
+            .neighbors_directed(node, Direction::Incoming)
+            .map(|predecessor| start[predecessor.index()] + duration[predecessor.index()])
+            .fold(0., f64::max);
+        start[node.index()] = feedbacks[node.index()]
+            .iter()
+            .map(|&(measure, latency)| start[measure.index()] + duration[measure.index()] + latency)
+            .fold(ready, f64::max);
+        total_time = total_time.max(start[node.index()] + duration[node.index()]);
+    }
+
+    if method == ScheduleMethod::Alap {
+        // the latest finish time of every node, bounded by its successors and the nodes waiting
+        // for its measurement
+        let mut latest_finish = vec![total_time; num_nodes];
+        let mut feedback_successors = vec![Vec::new(); num_nodes];
+        for (node, node_feedbacks) in feedbacks.iter().enumerate() {
+            for &(measure, latency) in node_feedbacks {
+                feedback_successors[measure.index()].push((node, latency));
+            }
+        }
+        for node in dag.dag.node_indices().rev() {
+            let finish = dag
+                .dag
+                .neighbors_directed(node, Direction::Outgoing)
+                .map(|successor| start[successor.index()])
+                .chain(
+                    feedback_successors[node.index()]
+                        .iter()
+                        .map(|&(successor, latency)| start[successor] - latency),
+                )
+                .fold(latest_finish[node.index()], f64::min);
+            latest_finish[node.index()] = finish;
+            start[node.index()] = finish - duration[node.index()];
+        }
+    }
+
+    let stalls = dag
+        .dag
+        .node_indices()
+        .map(|node| {
+            let ready = dag
+                .dag
+                .neighbors_directed(node, Direction::Incoming)
+                .map(|predecessor| start[predecessor.index()] + duration[predecessor.index()])
+                .fold(0., f64::max);
+            let arrival = feedbacks[node.index()]
+                .iter()
+                .map(|&(measure, latency)| {
+                    start[measure.index()] + duration[measure.index()] + latency
+                })
+                .fold(ready, f64::max);
+            arrival - ready
+        })
+        .collect();
+    Schedule {
+        start_times: start,
+        stalls,
+        total_time,
+    }
+}
+
+/// Schedule the operations of a routed circuit given their durations and the feedback latencies
+/// between controllers, stalling conditioned operations until the results of remote measurements
+/// have arrived.  The GIL is released while scheduling.
+///
+/// Args:
+///     dag (CircuitDag): The routed circuit on physical qubits.
+///     durations (dict[str, float] | DeviceProperties): The duration of every operation by
+///         name, or the device whose ``gate_durations`` to use.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     ctrl_topology (CtrlTopology): The feedback latency between controllers.  Every
+///         cross-controller feedback takes 1 if not given.
+///     default_duration (float): The duration of operations missing from ``durations``.
+///     method (str): ``"asap"`` to start every operation as soon as possible, or ``"alap"`` to
+///         start it as late as possible without delaying the end of the circuit.
+///     feedback_model (str): ``"point_to_point"`` or ``"broadcast"``, see ``DqcMapConfig``.
+///
+/// Returns:
+///     Schedule: The start time and feedback stall of every node and the total time.
+///
+/// Raises:
+///     ValueError: if a duration is negative or not finite, or the method or feedback model is
+///         unknown.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(
+    name = "schedule",
+    signature = (dag, durations, ctrl2pq, ctrl_topology=None, default_duration=0.0, method="asap", feedback_model="point_to_point")
+)]
+pub fn py_schedule(
+    py: Python,
+    dag: &CircuitDag,
+    durations: GateDurations,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    default_duration: f64,
+    method: &str,
+    feedback_model: &str,
+) -> PyResult<Schedule> {
+    let durations = durations.into_map();
+    check_durations(&durations, default_duration)?;
+    let method = ScheduleMethod::from_name(method)?;
+    let feedback_model = FeedbackModel::from_name(feedback_model)?;
+    Ok(py.allow_threads(|| {
+        schedule(
+            dag,
+            &durations,
+            default_duration,
+            ctrl2pq,
+            ctrl_topology,
+            feedback_model,
+            method,
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::dag::Instruction;
+
+    fn instruction(
+        name: &str,
+        qubits: &[usize],
+        clbits: &[usize],
+        condition: Option<&[usize]>,
+    ) -> Instruction {
+        (
+            name.to_string(),
+            qubits.to_vec(),
+            clbits.to_vec(),
+            condition.map(<[usize]>::to_vec),
+        )
+    }
+
+    #[test]
+    fn test_schedule_asap_alap() {
+        // measure qubit 0 and flip qubit 2 on another controller with it, while qubit 1 runs
+        // an independent x
+        let dag = CircuitDag::new(
+            3,
+            1,
+            vec![
+                instruction("measure", &[0], &[0], None),
+                instruction("x", &[2], &[], Some(&[0])),
+                instruction("x", &[1], &[], None),
+                instruction("h", &[0], &[], None),
+            ],
+            false,
+        )
+        .unwrap();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2])]));
+        let durations = HashMap::from([("measure".to_string(), 10.), ("x".to_string(), 2.)]);
+        let topology =
+            CtrlTopology::new(HashMap::from([((0, 1), 5.)]), 1., HashMap::new(), None).unwrap();
+
+        let asap = schedule(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            Some(&topology),
+            FeedbackModel::PointToPoint,
+            ScheduleMethod::Asap,
+        );
+        assert_eq!(asap.start_times, vec![0., 15., 0., 10.]);
+        assert_eq!(asap.stalls, vec![0., 5., 0., 0.]);
+        assert_eq!(asap.total_time, 17.);
+
+        let alap = schedule(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            Some(&topology),
+            FeedbackModel::PointToPoint,
+            ScheduleMethod::Alap,
+        );
+        assert_eq!(alap.start_times, vec![0., 15., 15., 16.]);
+        assert_eq!(alap.stalls, vec![0., 5., 0., 0.]);
+        assert_eq!(alap.total_time, 17.);
+
+        // broadcasts take the default latency of the topology
+        let broadcast = schedule(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            Some(&topology),
+            FeedbackModel::Broadcast,
+            ScheduleMethod::Asap,
+        );
+        assert_eq!(broadcast.stalls, vec![0., 1., 0., 0.]);
+        assert_eq!(broadcast.total_time, 13.);
+
+        assert!(ScheduleMethod::from_name("random").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::{memory::map_bytes, swap::Swap};
+
+/// A swap, normalized to (smaller, larger) qubit, with the hash of the active nodes it was scored
+/// against, sorted unless their order matters.
+pub type ScoreKey = ([i32; 2], u64);
+
+/// Scores of swaps memoized by [super::state::DqcMapState::score_swap].
+///
+/// The score of a swap usually only depends on the cif pairs holding one of its qubits, so applying
+/// or reverting a swap invalidates the entries of the swaps that share a qubit with the pairs it
+/// changed, see [ScoreCache::invalidate].  The state clears the whole cache instead for cost
+/// models that are not [super::cost_model::SwapCostModel::is_local].  Anything else the score depends on, such as the cost
+/// model, the topology or the noise model, requires [ScoreCache::clear] when it changes.
+#[derive(Debug, Default)]
+pub struct ScoreCache {
+    entries: Mutex<HashMap<ScoreKey, f64>>,
+}
+
+impl Clone for ScoreCache {
+    fn clone(&self) -> Self {
+        ScoreCache {
+            entries: Mutex::new(self.entries.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl ScoreCache {
+    pub fn key(swap: Swap, active_nodes: &[usize]) -> ScoreKey {
+        let mut nodes = active_nodes.to_vec();
+        nodes.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        nodes.hash(&mut hasher);
+        (swap.sorted(), hasher.finish())
+    }
+
+    /// Like [ScoreCache::key], but keeps the active nodes in order, for scores weighting the
+    /// nodes by their position.
+    pub fn ordered_key(swap: Swap, active_nodes: &[usize]) -> ScoreKey {
+        let mut hasher = DefaultHasher::new();
+        active_nodes.hash(&mut hasher);
+        (swap.sorted(), hasher.finish())
+    }
+
+    pub fn get(&self, key: &ScoreKey) -> Option<f64> {
+        self.entries.lock().unwrap().get(key).copied()
+    }
+
+    pub fn insert(&self, key: ScoreKey, score: f64) {
+        self.entries.lock().unwrap().insert(key, score);
+    }
+
+    /// Drop the entries of every swap acting on one of `qubits`.
+    pub fn invalidate(&self, qubits: &HashSet<i32>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|([q0, q1], _), _| !qubits.contains(q0) && !qubits.contains(q1));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// An estimate of the heap memory held by the entries.
+    pub fn allocated_bytes(&self) -> usize {
+        map_bytes(&self.entries.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_cache_keys_and_invalidation() {
+        let cache = ScoreCache::default();
+        assert_eq!(
+            ScoreCache::key(Swap::new(2, 1).unwrap(), &[3, 1]),
+            ScoreCache::key(Swap::new(1, 2).unwrap(), &[1, 3])
+        );
+        assert_ne!(
+            ScoreCache::ordered_key(Swap::new(1, 2).unwrap(), &[3, 1]),
+            ScoreCache::ordered_key(Swap::new(1, 2).unwrap(), &[1, 3])
+        );
+        assert_ne!(
+            ScoreCache::key(Swap::new(1, 2).unwrap(), &[1]),
+            ScoreCache::key(Swap::new(1, 2).unwrap(), &[1, 3])
+        );
+
+        cache.insert(ScoreCache::key(Swap::new(0, 1).unwrap(), &[1]), 1.);
+        cache.insert(ScoreCache::key(Swap::new(2, 3).unwrap(), &[1]), 2.);
+        cache.invalidate(&HashSet::from([1, 5]));
+        assert_eq!(
+            cache.get(&ScoreCache::key(Swap::new(0, 1).unwrap(), &[1])),
+            None
+        );
+        assert_eq!(
+            cache.get(&ScoreCache::key(Swap::new(3, 2).unwrap(), &[1])),
+            Some(2.)
+        );
+        assert_eq!(cache.clone().len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,574 @@
+use hashbrown::HashMap;
+use rustworkx_core::petgraph::prelude::*;
+#[cfg(feature = "python")]
+use {super::device::GateDurations, pyo3::prelude::*};
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    config::FeedbackModel,
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+    dag::{CircuitDag, Instruction},
+    error::MappingError,
+    metrics::{self, Phase},
+};
+
+/// Estimated execution time of a routed circuit, see ``estimate_execution_time``.
+#[cfg_attr(
+    feature = "python",
+    pyclass(frozen, get_all, module = "dqcmap._accelerate.dqcmap")
+)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimResult {
+    /// Time at which the last instruction finishes.
+    pub total_time: f64,
+    /// Total time that conditioned instructions waited for their feedback after all their
+    /// dependencies had finished.
+    pub feedback_stall: f64,
+    pub num_feedbacks: usize,
+    pub num_cross_controller_feedbacks: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SimResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "SimResult(total_time={}, feedback_stall={}, num_feedbacks={}, \
+             num_cross_controller_feedbacks={})",
+            self.total_time,
+            self.feedback_stall,
+            self.num_feedbacks,
+            self.num_cross_controller_feedbacks
+        )
+    }
+}
+
+/// Schedule `dag` as soon as possible and return its execution time.
+///
+/// Every instruction starts once all its predecessors have finished.  A conditioned instruction
+/// additionally waits until the results of the measurements it depends on have travelled from the
+/// controller of the measured qubit to the controller of every conditioned qubit, which takes the
+/// latency of `ctrl_topology` under `feedback_model` (1 per cross-controller feedback without a
+/// topology).  Qubits that are not connected to any controller are treated as local to the other
+/// qubit of the feedback.
+pub fn simulate(
+    dag: &CircuitDag,
+    durations: &HashMap<String, f64>,
+    default_duration: f64,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    feedback_model: FeedbackModel,
+) -> SimResult {
+    let _timer = metrics::time_phase(Phase::Simulation);
+    let latency = |ctrl0: i32, ctrl1: i32| feedback_model.latency(ctrl_topology, ctrl0, ctrl1);
+    let mut result = SimResult::default();
+    let mut finish = vec![0.; dag.dag.node_count()];
+    // the measurement node and measured qubit of the last measurement into every clbit
+    let mut last_measure: Vec<Option<(NodeIndex, usize)>> = vec![None; dag.num_clbits];
+
+    // node indices follow the input order, which is topological
+    for node in dag.dag.node_indices() {
+        let weight = &dag.dag[node];
+        let ready = dag
+            .dag
+            .neighbors_directed(node, Direction::Incoming)
+            .map(|predecessor| finish[predecessor.index()])
+            .fold(0., f64::max);
+
+        let mut start = ready;
+        for &clbit in weight.condition.iter().flatten() {
+            let Some((measure, measured)) = last_measure[clbit] else {
+                continue;
+            };
+            for &qubit in &weight.qubits {
+                result.num_feedbacks += 1;
+                let (Some(ctrl_measured), Some(ctrl_conditioned)) = (
+                    ctrl2pq.controller_of(PhysicalQubit::new(measured as u32)),
+                    ctrl2pq.controller_of(PhysicalQubit::new(qubit as u32)),
+                ) else {
+                    continue;
+                };
+                if ctrl_measured != ctrl_conditioned {
+                    result.num_cross_controller_feedbacks += 1;
+                }
+                start =
+                    start.max(finish[measure.index()] + latency(ctrl_measured, ctrl_conditioned));
+            }
+        }
+        result.feedback_stall += start - ready;
+
+        let duration = if weight.is_directive() {
+            0.
+        } else {
+            *durations.get(&weight.name).unwrap_or(&default_duration)
+        };
+        finish[node.index()] = start + duration;
+        result.total_time = result.total_time.max(finish[node.index()]);
+
+        if weight.is_measure() && weight.qubits.len() == weight.clbits.len() {
+            for (&qubit, &clbit) in weight.qubits.iter().zip(&weight.clbits) {
+                last_measure[clbit] = Some((node, qubit));
+            }
+        }
+    }
+    result
+}
+
+/// The critical-path execution time of `instructions`, with the same timing model as [simulate]
+/// but in a single pass over the instructions without building a [CircuitDag].
+///
+/// Every instruction starts once the previous instructions on its qubits, clbits and condition
+/// clbits have finished, and a conditioned instruction additionally waits for the feedback
+/// latency after the last measurement into its condition clbits.
+pub fn estimate_latency(
+    instructions: &[Instruction],
+    durations: &HashMap<String, f64>,
+    default_duration: f64,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    feedback_model: FeedbackModel,
+) -> f64 {
+    let latency = |ctrl0: i32, ctrl1: i32| feedback_model.latency(ctrl_topology, ctrl0, ctrl1);
+    // the time at which the last instruction on every qubit and clbit finishes
+    let mut qubit_free: HashMap<usize, f64> = HashMap::new();
+    let mut clbit_free: HashMap<usize, f64> = HashMap::new();
+    // the finish time and measured qubit of the last measurement into every clbit
+    let mut last_measure: HashMap<usize, (f64, usize)> = HashMap::new();
+    let mut total_time: f64 = 0.;
+
+    for (name, qubits, clbits, condition) in instructions {
+        let condition = condition.as_deref().unwrap_or_default();
+        let ready = qubits
+            .iter()
+            .filter_map(|qubit| qubit_free.get(qubit))
+            .chain(
+                clbits
+                    .iter()
+                    .chain(condition)
+                    .filter_map(|clbit| clbit_free.get(clbit)),
+            )
+            .fold(0., |ready: f64, &free| ready.max(free));
+        let mut start = ready;
+        for (measure_finish, measured) in
+            condition.iter().filter_map(|clbit| last_measure.get(clbit))
+        {
+            for &qubit in qubits {
+                if let (Some(ctrl_measured), Some(ctrl_conditioned)) = (
+                    ctrl2pq.controller_of(PhysicalQubit::new(*measured as u32)),
+                    ctrl2pq.controller_of(PhysicalQubit::new(qubit as u32)),
+                ) {
+                    start = start.max(measure_finish + latency(ctrl_measured, ctrl_conditioned));
+                }
+            }
+        }
+
+        let duration = if name == "barrier" {
+            0.
+        } else {
+            *durations.get(name).unwrap_or(&default_duration)
+        };
+        let finish = start + duration;
+        total_time = total_time.max(finish);
+        qubit_free.extend(qubits.iter().map(|&qubit| (qubit, finish)));
+        clbit_free.extend(clbits.iter().chain(condition).map(|&clbit| (clbit, finish)));
+        if name == "measure" && qubits.len() == clbits.len() {
+            last_measure.extend(
+                clbits
+                    .iter()
+                    .zip(qubits)
+                    .map(|(&clbit, &qubit)| (clbit, (finish, qubit))),
+            );
+        }
+    }
+    total_time
+}
+
+/// A controller exceeding its bandwidth: the layer of the DAG, the controller, its number of
+/// concurrent feedback transfers and its bandwidth.
+pub type BandwidthViolation = (usize, i32, usize, usize);
+
+/// The controllers whose concurrent cross-controller feedback transfers exceed their bandwidth
+/// given by `ctrl_topology`, ordered by layer and controller.
+///
+/// Instructions are concurrent if they are in the same layer of the as-soon-as-possible
+/// layering of `dag`, and every cross-controller feedback of a conditioned instruction is a
+/// transfer for both the controller of the measured qubit and the one of the conditioned qubit.
+pub fn bandwidth_violations(
+    dag: &CircuitDag,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: &CtrlTopology,
+) -> Vec<BandwidthViolation> {
+    let mut layer = vec![0; dag.dag.node_count()];
+    let mut transfers: Vec<Vec<(i32, i32)>> = Vec::new();
+    // the measured qubit of the last measurement into every clbit
+    let mut last_measure: Vec<Option<usize>> = vec![None; dag.num_clbits];
+    for node in dag.dag.node_indices() {
+        let weight = &dag.dag[node];
+        layer[node.index()] = dag
+            .dag
+            .neighbors_directed(node, Direction::Incoming)
+            .map(|predecessor| layer[predecessor.index()] + 1)
+            .max()
+            .unwrap_or(0);
+        for &clbit in weight.condition.iter().flatten() {
+            let Some(measured) = last_measure[clbit] else {
+                continue;
+            };
+            for &qubit in &weight.qubits {
+                if let (Some(ctrl_measured), Some(ctrl_conditioned)) = (
+                    ctrl2pq.controller_of(PhysicalQubit::new(measured as u32)),
+                    ctrl2pq.controller_of(PhysicalQubit::new(qubit as u32)),
+                ) {
+                    let node_layer = layer[node.index()];
+                    if transfers.len() <= node_layer {
+                        transfers.resize(node_layer + 1, Vec::new());
+                    }
+                    transfers[node_layer].push((ctrl_measured, ctrl_conditioned));
+                }
+            }
+        }
+        if weight.is_measure() && weight.qubits.len() == weight.clbits.len() {
+            for (&qubit, &clbit) in weight.qubits.iter().zip(&weight.clbits) {
+                last_measure[clbit] = Some(qubit);
+            }
+        }
+    }
+
+    let mut violations = Vec::new();
+    for (layer, transfers) in transfers.into_iter().enumerate() {
+        let mut counts: Vec<(i32, usize)> = CtrlTopology::transfer_counts(transfers)
+            .into_iter()
+            .collect();
+        counts.sort_unstable();
+        for (ctrl, count) in counts {
+            match ctrl_topology.get_bandwidth(ctrl) {
+                Some(bandwidth) if count > bandwidth => {
+                    violations.push((layer, ctrl, count, bandwidth))
+                }
+                _ => {}
+            }
+        }
+    }
+    violations
+}
+
+/// Check that no controller of a routed circuit sends or receives more concurrent
+/// cross-controller feedbacks than its bandwidth.  Instructions are concurrent if they are in the
+/// same layer of the as-soon-as-possible layering of the circuit.
+///
+/// Args:
+///     dag (CircuitDag): The routed circuit on physical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     ctrl_topology (CtrlTopology): The bandwidth of the controllers.
+///
+/// Returns:
+///     list[tuple[int, int, int, int]]: The layer, controller, number of concurrent transfers
+///     and bandwidth of every controller exceeding its bandwidth, empty if the mapping is valid.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (dag, ctrl2pq, ctrl_topology))]
+pub fn check_feedback_bandwidth(
+    py: Python,
+    dag: &CircuitDag,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: &CtrlTopology,
+) -> Vec<BandwidthViolation> {
+    py.allow_threads(|| bandwidth_violations(dag, ctrl2pq, ctrl_topology))
+}
+
+/// Estimate the wall-clock execution time of a routed circuit including feedback stalls.  The
+/// GIL is released while simulating.
+///
+/// Args:
+///     dag (CircuitDag): The routed circuit on physical qubits.
+///     durations (dict[str, float] | DeviceProperties): The duration of every operation by
+///         name, or the device whose ``gate_durations`` to use.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     ctrl_topology (CtrlTopology): The feedback latency between controllers.  Every
+///         cross-controller feedback takes 1 if not given.
+///     default_duration (float): The duration of operations missing from ``durations``.
+///     feedback_model (str): ``"point_to_point"`` or ``"broadcast"``, see ``DqcMapConfig``.
+///
+/// Returns:
+///     SimResult: The total time, the time spent waiting for feedbacks and the number of
+///     feedbacks.
+///
+/// Raises:
+///     ValueError: if a duration is negative or not finite, or the feedback model is unknown.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (dag, durations, ctrl2pq, ctrl_topology=None, default_duration=0.0, feedback_model="point_to_point"))]
+pub fn estimate_execution_time(
+    py: Python,
+    dag: &CircuitDag,
+    durations: GateDurations,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_topology: Option<&CtrlTopology>,
+    default_duration: f64,
+    feedback_model: &str,
+) -> PyResult<SimResult> {
+    let durations = durations.into_map();
+    check_durations(&durations, default_duration)?;
+    let feedback_model = FeedbackModel::from_name(feedback_model)?;
+    Ok(py.allow_threads(|| {
+        simulate(
+            dag,
+            &durations,
+            default_duration,
+            ctrl2pq,
+            ctrl_topology,
+            feedback_model,
+        )
+    }))
+}
+
+/// Quickly estimate the critical-path execution time of a routed circuit including feedback
+/// latencies, without building its DAG.  Gives the same total time as
+/// ``estimate_execution_time``, so mapping candidates can be compared cheaply.  The GIL is
+/// released while estimating.
+///
+/// Args:
+///     routed_ops (list[tuple[str, list[int], list[int], list[int] | None]]): The routed
+///         instructions on physical qubits as name, qubits, clbits and condition clbits.
+///     durations (dict[str, float] | DeviceProperties): The duration of every operation by
+///         name, or the device whose ``gate_durations`` to use.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     ctrl_latency (CtrlTopology): The feedback latency between controllers.  Every
+///         cross-controller feedback takes 1 if not given.
+///     default_duration (float): The duration of operations missing from ``durations``.
+///     feedback_model (str): ``"point_to_point"`` or ``"broadcast"``, see ``DqcMapConfig``.
+///
+/// Returns:
+///     float: The estimated execution time.
+///
+/// Raises:
+///     ValueError: if a duration is negative or not finite, or the feedback model is unknown.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(
+    name = "estimate_latency",
+    signature = (routed_ops, durations, ctrl2pq, ctrl_latency=None, default_duration=0.0, feedback_model="point_to_point")
+)]
+pub fn py_estimate_latency(
+    py: Python,
+    routed_ops: Vec<Instruction>,
+    durations: GateDurations,
+    ctrl2pq: &Ctrl2Pq,
+    ctrl_latency: Option<&CtrlTopology>,
+    default_duration: f64,
+    feedback_model: &str,
+) -> PyResult<f64> {
+    let durations = durations.into_map();
+    check_durations(&durations, default_duration)?;
+    let feedback_model = FeedbackModel::from_name(feedback_model)?;
+    Ok(py.allow_threads(|| {
+        estimate_latency(
+            &routed_ops,
+            &durations,
+            default_duration,
+            ctrl2pq,
+            ctrl_latency,
+            feedback_model,
+        )
+    }))
+}
+
+/// Check that every duration is a non-negative finite number.
+pub(super) fn check_durations(
+    durations: &HashMap<String, f64>,
+    default_duration: f64,
+) -> Result<(), MappingError> {
+    let invalid = durations
+        .iter()
+        .map(|(name, duration)| (name.as_str(), *duration))
+        .chain([("default", default_duration)])
+        .find(|(_, duration)| !(duration.is_finite() && *duration >= 0.));
+    match invalid {
+        Some((name, duration)) => Err(MappingError::Value(format!(
+            "duration of '{name}' must be a non-negative finite number, got {duration}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::dag::Instruction;
+
+    fn instruction(
+        name: &str,
+        qubits: &[usize],
+        clbits: &[usize],
+        condition: Option<&[usize]>,
+    ) -> Instruction {
+        (
+            name.to_string(),
+            qubits.to_vec(),
+            clbits.to_vec(),
+            condition.map(<[usize]>::to_vec),
+        )
+    }
+
+    #[test]
+    fn test_simulate_feedback_stalls() {
+        // measure qubit 0, then flip qubits 2 (other controller) and 1 (same controller) on it;
+        // both read clbit 0, so the second flip waits for the first one
+        let dag = CircuitDag::new(
+            3,
+            1,
+            vec![
+                instruction("h", &[0], &[], None),
+                instruction("measure", &[0], &[0], None),
+                instruction("x", &[2], &[], Some(&[0])),
+                instruction("x", &[1], &[], Some(&[0])),
+                instruction("barrier", &[1, 2], &[], None),
+            ],
+            false,
+        )
+        .unwrap();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2])]));
+        let durations = HashMap::from([("measure".to_string(), 10.), ("x".to_string(), 2.)]);
+
+        let result = simulate(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            None,
+            FeedbackModel::PointToPoint,
+        );
+        assert_eq!(
+            result,
+            SimResult {
+                total_time: 16.,
+                feedback_stall: 1.,
+                num_feedbacks: 2,
+                num_cross_controller_feedbacks: 1,
+            }
+        );
+
+        let topology =
+            CtrlTopology::new(HashMap::from([((0, 1), 5.)]), 1., HashMap::new(), None).unwrap();
+        let result = simulate(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            Some(&topology),
+            FeedbackModel::PointToPoint,
+        );
+        assert_eq!(result.total_time, 20.);
+        assert_eq!(result.feedback_stall, 5.);
+        // broadcasts take the default latency of the topology
+        let result = simulate(
+            &dag,
+            &durations,
+            1.,
+            &ctrl2pq,
+            Some(&topology),
+            FeedbackModel::Broadcast,
+        );
+        assert_eq!(result.total_time, 16.);
+        assert_eq!(result.feedback_stall, 1.);
+
+        assert!(check_durations(&durations, -1.).is_err());
+        #[cfg(feature = "python")]
+        {
+            pyo3::prepare_freethreaded_python();
+            Python::with_gil(|py| {
+                assert!(estimate_execution_time(
+                    py,
+                    &dag,
+                    GateDurations::Map(durations),
+                    &ctrl2pq,
+                    None,
+                    -1.,
+                    "point_to_point"
+                )
+                .is_err());
+            });
+        }
+    }
+
+    #[test]
+    fn test_estimate_latency() {
+        let instructions = vec![
+            instruction("h", &[0], &[], None),
+            instruction("measure", &[0], &[0], None),
+            instruction("x", &[2], &[], Some(&[0])),
+            instruction("x", &[1], &[], Some(&[0])),
+            instruction("cx", &[1, 2], &[], None),
+            instruction("measure", &[2], &[1], None),
+            instruction("x", &[0], &[], Some(&[1])),
+        ];
+        let dag = CircuitDag::new(3, 2, instructions.clone(), false).unwrap();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2])]));
+        let durations = HashMap::from([("measure".to_string(), 10.), ("x".to_string(), 2.)]);
+        let topology =
+            CtrlTopology::new(HashMap::from([((0, 1), 5.)]), 1., HashMap::new(), None).unwrap();
+
+        for ctrl_topology in [None, Some(&topology)] {
+            for feedback_model in [FeedbackModel::PointToPoint, FeedbackModel::Broadcast] {
+                let estimate = estimate_latency(
+                    &instructions,
+                    &durations,
+                    1.,
+                    &ctrl2pq,
+                    ctrl_topology,
+                    feedback_model,
+                );
+                let result = simulate(
+                    &dag,
+                    &durations,
+                    1.,
+                    &ctrl2pq,
+                    ctrl_topology,
+                    feedback_model,
+                );
+                assert_eq!(estimate, result.total_time);
+            }
+        }
+        // 1 + 10 + 5 + 2 + 2 + 1 + 10 + 5 + 2
+        assert_eq!(
+            estimate_latency(
+                &instructions,
+                &durations,
+                1.,
+                &ctrl2pq,
+                Some(&topology),
+                FeedbackModel::PointToPoint
+            ),
+            38.
+        );
+    }
+
+    #[test]
+    fn test_bandwidth_violations() {
+        // two concurrent feedbacks from controller 0 to controller 1
+        let dag = CircuitDag::new(
+            4,
+            2,
+            vec![
+                instruction("measure", &[0], &[0], None),
+                instruction("measure", &[1], &[1], None),
+                instruction("x", &[2], &[], Some(&[0])),
+                instruction("x", &[3], &[], Some(&[1])),
+                instruction("x", &[2], &[], Some(&[0])),
+            ],
+            false,
+        )
+        .unwrap();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let mut topology =
+            CtrlTopology::new(HashMap::new(), 1., HashMap::from([(1, 2)]), Some(1)).unwrap();
+        assert_eq!(
+            bandwidth_violations(&dag, &ctrl2pq, &topology),
+            vec![(1, 0, 2, 1)]
+        );
+        topology.default_bandwidth = None;
+        assert!(bandwidth_violations(&dag, &ctrl2pq, &topology).is_empty());
+    }
+}
@@ -1,136 +1,2018 @@
-use super::{cif_pairs::CifPairs, ctrl_to_pq::Ctrl2Pq};
+use std::sync::Arc;
 
-fn swap_involved_pairs(involved_pairs: &Vec<Vec<i32>>, swap: &Vec<i32>) -> Vec<Vec<i32>> {
-    let mut swapped_pairs = Vec::new();
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "python")]
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use rand::prelude::*;
+use rand_pcg::Pcg64Mcg;
+use rayon::prelude::*;
 
-    for pair in involved_pairs {
-        let mut new_pair = pair.clone();
+use crate::getenv_use_multiple_threads;
+use crate::nlayout::PhysicalQubit;
 
-        for qubit in &mut new_pair {
-            if *qubit == swap[0] {
-                *qubit = swap[1];
-            } else if *qubit == swap[1] {
-                *qubit = swap[0];
-            }
-        }
+use super::{
+    builder::check_mapping,
+    cif_pairs::{is_cross_controller, is_well_formed, pair_clbit, CifPairs},
+    clbit_to_ctrl::Clbit2Ctrl,
+    config::{DqcMapConfig, FeedbackModel},
+    control_flow::ControlFlowModel,
+    cost_model::{cost_model_by_name, FidelityWeighted, SwapCostModel},
+    coupling_map::CouplingMap,
+    crosstalk::CrosstalkMap,
+    ctrl_to_pq::Ctrl2Pq,
+    ctrl_topology::CtrlTopology,
+    dot,
+    error::MappingError,
+    memory::{vec_bytes, MemoryStats},
+    metrics::{self, Phase},
+    noise::NoiseModel,
+    pareto::{self, Objectives},
+    score_cache::ScoreCache,
+    swap::Swap,
+};
+#[cfg(feature = "python")]
+use super::{cost_model::COST_MODEL_NAMES, device::NoiseSource, front_layer::FrontLayer};
+
+/// Minimum number of candidate swaps in a batch before scoring is spread over a thread pool.
+const PARALLEL_SCORE_THRESHOLD: usize = 64;
+/// Epsilon under which two scores are considered equal by `best_swap`.
+const BEST_EPSILON: f64 = 1e-10;
+/// Default decay factor applied per step of the lookahead window.
+pub const DEFAULT_LOOKAHEAD_DECAY: f64 = 0.5;
+/// Default weight of the distance change in `composite_score`.
+pub const DEFAULT_DISTANCE_WEIGHT: f64 = 1.0;
+/// Default weight of the cross-controller feedback change in `composite_score`.
+pub const DEFAULT_FEEDBACK_WEIGHT: f64 = 0.1;
+/// Version of the format written by `to_bytes`, stored ahead of the state.
+const CHECKPOINT_VERSION: u32 = 8;
+
+/// A swap recorded in the undo log, with the (node id, position) of the cif pairs it changed.
+type UndoEntry = ([i32; 2], Vec<(usize, usize)>);
 
-        swapped_pairs.push(new_pair);
+/// A swap with its distance, feedback and error objectives, as returned by `pareto_front`.
+#[cfg(feature = "python")]
+type ParetoCandidate = ((i32, i32), f64, f64, f64);
+
+/// The fields of a [DqcMapState] as pickled, with the cost model stored by name.
+type PickleState = (
+    Option<Ctrl2Pq>,
+    Option<CifPairs>,
+    (usize, f64, f64),
+    Option<CtrlTopology>,
+    Option<NoiseModel>,
+    Option<CrosstalkMap>,
+    Option<ControlFlowModel>,
+    Option<HashMap<usize, f64>>,
+    Option<Clbit2Ctrl>,
+    (f64, f64),
+    (String, String),
+    Vec<UndoEntry>,
+);
+
+/// Check that every entry of a restored undo log holds a valid swap and the existing pairs of
+/// `cif_pairs` it touched, sorted as [CifPairs::revert_swap] expects.
+fn check_undo_log(
+    undo_log: &[UndoEntry],
+    cif_pairs: Option<&CifPairs>,
+) -> Result<(), MappingError> {
+    for (swap, touched) in undo_log {
+        Swap::try_from(&swap[..])
+            .map_err(|err| MappingError::Value(format!("invalid undo log: {err}")))?;
+        if !touched.windows(2).all(|entries| entries[0] < entries[1]) {
+            return Err(MappingError::Value(format!(
+                "invalid undo log: the pairs touched by swap {swap:?} are not sorted"
+            )));
+        }
+        let missing = touched.iter().find(|&&(node_id, position)| {
+            cif_pairs
+                .and_then(|cif_pairs| cif_pairs.pairs().get(&node_id))
+                .map_or(true, |pairs| position >= pairs.len())
+        });
+        if let Some((node_id, position)) = missing {
+            return Err(MappingError::Value(format!(
+                "invalid undo log: swap {swap:?} touched pair {position} of node {node_id}, \
+                 which does not exist"
+            )));
+        }
     }
+    Ok(())
+}
 
-    swapped_pairs
+/// The (q0, q1) qubits of every pair with at least two values, in a flat array.
+fn pair_qubits<'a>(pairs: impl IntoIterator<Item = &'a [i32]>) -> Vec<(i32, i32)> {
+    pairs
+        .into_iter()
+        .filter(|pair| pair.len() >= 2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
 }
 
-fn count_ctrl_pairs(
-    involved_pairs: &Vec<Vec<i32>>,
-    ctrl2pq: &Ctrl2Pq,
-    ctrl0: &i32,
-    ctrl1: &i32,
-) -> i32 {
-    let mut count = 0;
-
-    for pair in involved_pairs {
-        let c0 = ctrl2pq.get_controller_by_qubit(pair[0]);
-        let c1 = ctrl2pq.get_controller_by_qubit(pair[1]);
-
-        match (c0, c1) {
-            (Some(ctrl_c0), Some(ctrl_c1)) => {
-                // we do not need to count the cross-controller feedbacks other than
-                // the controllers involved by the swap because they are not changed
-                if (ctrl_c0 == ctrl0 && ctrl_c1 == ctrl1) || (ctrl_c0 == ctrl1 && ctrl_c1 == ctrl0)
-                {
-                    count += 1;
-                }
-            }
-            _ => continue,
+/// Whether the qubits of a pair are connected to `ctrl0` and `ctrl1`, in any order.
+///
+/// We do not need to count the cross-controller feedbacks other than the controllers involved by
+/// the swap because they are not changed.
+#[inline]
+fn is_between_ctrls(ctrl2pq: &Ctrl2Pq, (q0, q1): (i32, i32), ctrl0: &i32, ctrl1: &i32) -> bool {
+    match (
+        ctrl2pq.get_controller_by_qubit(q0),
+        ctrl2pq.get_controller_by_qubit(q1),
+    ) {
+        (Some(ctrl_c0), Some(ctrl_c1)) => {
+            (ctrl_c0 == ctrl0 && ctrl_c1 == ctrl1) || (ctrl_c0 == ctrl1 && ctrl_c1 == ctrl0)
         }
+        _ => false,
     }
-
-    count
 }
 
+/// Scorer that tracks the cif pairs of a circuit against a controller mapping and calculates
+/// how a swap changes the number of cross-controller feedbacks.
+///
+/// The state owns copies of its ``Ctrl2Pq`` and ``CifPairs``, kept in sync with the layout by
+/// ``apply_swap`` and restored by ``undo_last``/``undo_n``.  Scores are ``None`` while either is
+/// missing or when a swapped qubit has no controller; ``DqcMapStateBuilder`` checks that they
+/// agree up front.  The router ranks swaps with ``score_swap``, which delegates to the cost model
+/// selected by ``set_cost_model``: ``"feedback_count"`` (``score``), ``"latency_weighted"``
+/// (``score_weighted``), ``"feedback_batched"`` (``score_batched``) or ``"fidelity_weighted"``
+/// (``score_noise_aware``, the default).  All scoring methods release the GIL while they run.
+///
+/// Attributes:
+///     lookahead_window (int): The number of upcoming nodes also scored by ``score_lookahead``,
+///         0 disables the lookahead.
+///     lookahead_decay (float): The weight multiplier per step into the lookahead window.
+///     time_decay (float): The decay λ weighting the pairs of the ``k``-th active node by
+///         ``exp(-λ k)`` in ``score_weighted``, 0 weighs all active nodes equally.
+///     ctrl_topology (CtrlTopology | None): The latency and bandwidth of the links between
+///         controllers used by ``score_weighted``, a flat cost of 1 per feedback if unset.
+///     noise_model (NoiseModel | None): The error rates penalizing swaps over noisy links in
+///         ``score_noise_aware``, swaps are noiseless if unset.
+///     crosstalk (CrosstalkMap | None): The crosstalk-prone pairs of edges penalized in
+///         ``score_noise_aware``, ignored if unset.
+///     control_flow (ControlFlowModel | None): The ``if_else`` and ``while`` scopes weighting
+///         the pairs of their nodes by how often they execute, once if unset.
+///     criticality (dict[int, float] | None): The weight of the pairs of every node, e.g. from
+///         its slack on the critical path, 1 for unlisted nodes.
+///     clbit2ctrl (Clbit2Ctrl | None): The controllers of the clbits, through which
+///         ``score_weighted`` routes the feedbacks carrying them.
+///     distance_weight (float): The weight α of the distance change in ``composite_score``.
+///     feedback_weight (float): The weight β of the cross-controller feedback change in
+///         ``composite_score``.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug)]
 pub struct DqcMapState {
     pub ctrl2pq: Option<Ctrl2Pq>,
     pub cif_pairs: Option<CifPairs>,
+    /// Number of upcoming nodes considered by `score_lookahead`, 0 disables the lookahead.
+    pub lookahead_window: usize,
+    /// Weight multiplier applied per step into the lookahead window.
+    pub lookahead_decay: f64,
+    /// Decay constant λ weighting the pairs of the `k`-th active node (starting at 0) by
+    /// `exp(-λ k)` in `score_weighted`, 0 weighs all active nodes equally.
+    pub time_decay: f64,
+    /// Latency model between controllers, a flat cost of 1 per cross-controller feedback if unset.
+    pub ctrl_topology: Option<CtrlTopology>,
+    /// Whether feedbacks are sent point-to-point over the links of `ctrl_topology` or broadcast
+    /// at its default latency.
+    pub feedback_model: FeedbackModel,
+    /// Error rates of the device, swaps are considered noiseless if unset.
+    pub noise_model: Option<NoiseModel>,
+    /// Crosstalk-prone pairs of edges, the neighborhoods of feedbacks are ignored if unset.
+    pub crosstalk: Option<CrosstalkMap>,
+    /// Control-flow scopes of the nodes, every node executes once if unset.
+    pub control_flow: Option<ControlFlowModel>,
+    /// Criticality of the nodes weighting their cif pairs, e.g. derived from their slack on the
+    /// critical path, 1 for the nodes it does not list.  Every node weighs 1 if unset.
+    pub criticality: Option<HashMap<usize, f64>>,
+    /// Controllers of the clbits, feedbacks go directly between the qubits if unset.
+    pub clbit2ctrl: Option<Clbit2Ctrl>,
+    /// Weight α of the distance change in `composite_score`.
+    pub distance_weight: f64,
+    /// Weight β of the cross-controller feedback change in `composite_score`.
+    pub feedback_weight: f64,
+    /// Heuristic used by `score_swap`.
+    cost_model: Arc<dyn SwapCostModel>,
+    /// Applied swaps, most recent last.
+    undo_log: Vec<UndoEntry>,
+    /// Weighted number of cross-controller cif pairs, kept up to date by `apply_swap` and
+    /// `undo_last`.
+    cross_ctrl_count: Option<f64>,
+    /// Memoized `score_swap` results, disabled if unset.  Must be cleared whenever a field the
+    /// scores depend on other than the cif pairs is changed.
+    score_cache: Option<ScoreCache>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DqcMapState {
+    #[new]
+    #[pyo3(signature = (ctrl2pq=None, cif_pairs=None, cost_model=None, config=None))]
+    fn py_new(
+        ctrl2pq: Option<Ctrl2Pq>,
+        cif_pairs: Option<CifPairs>,
+        cost_model: Option<&str>,
+        config: Option<DqcMapConfig>,
+    ) -> PyResult<Self> {
+        let mut state = DqcMapState::new(ctrl2pq, cif_pairs);
+        if let Some(name) = cost_model {
+            state.set_cost_model(cost_model_by_name(name)?);
+        }
+        if let Some(config) = config {
+            state.apply_config(&config)?;
+        }
+        Ok(state)
+    }
+
+    #[getter(lookahead_window)]
+    fn get_lookahead_window(&self) -> usize {
+        self.lookahead_window
+    }
+
+    #[getter(lookahead_decay)]
+    fn get_lookahead_decay(&self) -> f64 {
+        self.lookahead_decay
+    }
+
+    #[getter(time_decay)]
+    fn get_time_decay(&self) -> f64 {
+        self.time_decay
+    }
+
+    #[getter(ctrl_topology)]
+    fn get_ctrl_topology(&self) -> Option<CtrlTopology> {
+        self.ctrl_topology.clone()
+    }
+
+    #[getter(noise_model)]
+    fn get_noise_model(&self) -> Option<NoiseModel> {
+        self.noise_model.clone()
+    }
+
+    #[getter(crosstalk)]
+    fn get_crosstalk(&self) -> Option<CrosstalkMap> {
+        self.crosstalk.clone()
+    }
+
+    #[getter(control_flow)]
+    fn get_control_flow(&self) -> Option<ControlFlowModel> {
+        self.control_flow.clone()
+    }
+
+    #[getter(criticality)]
+    fn get_criticality(&self) -> Option<HashMap<usize, f64>> {
+        self.criticality.clone()
+    }
+
+    #[getter(clbit2ctrl)]
+    fn get_clbit2ctrl(&self) -> Option<Clbit2Ctrl> {
+        self.clbit2ctrl.clone()
+    }
+
+    #[getter(distance_weight)]
+    fn get_distance_weight(&self) -> f64 {
+        self.distance_weight
+    }
+
+    #[getter(feedback_weight)]
+    fn get_feedback_weight(&self) -> f64 {
+        self.feedback_weight
+    }
+
+    /// Take the objective weights, the lookahead, the cost model and the feedback model from
+    /// ``config``, replacing the ones set before.
+    ///
+    /// Raises:
+    ///     DqcMapError: if the cost model of ``config`` is unknown.
+    #[pyo3(name = "apply_config", text_signature = "(self, config, /)")]
+    fn py_apply_config(&mut self, config: &DqcMapConfig) -> PyResult<()> {
+        Ok(self.apply_config(config)?)
+    }
+
+    /// The name of the feedback model.
+    #[getter(feedback_model)]
+    fn get_feedback_model(&self) -> &'static str {
+        self.feedback_model.name()
+    }
+
+    /// Send the feedbacks ``"point_to_point"`` over the links of ``ctrl_topology``, or
+    /// ``"broadcast"`` them to all controllers at its default latency.
+    ///
+    /// Raises:
+    ///     ValueError: if the feedback model is unknown.
+    #[pyo3(
+        name = "set_feedback_model",
+        text_signature = "(self, feedback_model, /)"
+    )]
+    fn py_set_feedback_model(&mut self, feedback_model: &str) -> PyResult<()> {
+        Ok(self.set_feedback_model(feedback_model)?)
+    }
+
+    /// Swap in an updated controller mapping, e.g. between routing passes of an experiment
+    /// reassigning qubits to controllers.  The cross-controller count is recomputed and the score
+    /// cache cleared, while the cif pairs and the undo log are kept, since neither depends on the
+    /// controllers.
+    ///
+    /// Raises:
+    ///     DqcMapError: if a qubit is negative or a qubit of a cif pair is not connected to any
+    ///         controller, as in ``DqcMapStateBuilder.validate``.  The state is left unchanged.
+    #[pyo3(name = "set_ctrl2pq", text_signature = "(self, ctrl2pq, /)")]
+    fn py_set_ctrl2pq(&mut self, ctrl2pq: Ctrl2Pq) -> PyResult<()> {
+        Ok(self.set_ctrl2pq(ctrl2pq)?)
+    }
+
+    /// Weight the pairs of the ``k``-th active node (starting at 0) by ``exp(-decay * k)`` in
+    /// ``score_weighted``, so that feedbacks coming soon in the gate order count more than
+    /// distant ones.  A decay of 0 weighs all active nodes equally.
+    ///
+    /// Raises:
+    ///     ValueError: if ``decay`` is negative or not finite.
+    #[pyo3(name = "set_time_decay", text_signature = "(self, decay, /)")]
+    fn py_set_time_decay(&mut self, decay: f64) -> PyResult<()> {
+        Ok(self.set_time_decay(decay)?)
+    }
+
+    /// Set the weights of ``composite_score``: ``alpha`` for the change in distance of the front
+    /// layer gates and ``beta`` for the change in cross-controller feedbacks.
+    ///
+    /// Raises:
+    ///     ValueError: if a weight is negative or not finite.
+    #[pyo3(
+        name = "set_objective_weights",
+        text_signature = "(self, alpha, beta, /)"
+    )]
+    fn py_set_objective_weights(&mut self, alpha: f64, beta: f64) -> PyResult<()> {
+        Ok(self.set_objective_weights(alpha, beta)?)
+    }
+
+    /// Combine the change in distance of the front layer gates caused by a swap with its change in
+    /// cross-controller feedbacks given by ``score_swap``.  Lower is better.
+    ///
+    /// Raises:
+    ///     DqcMapError: if ``swap`` does not consist of two distinct non-negative qubits.
+    #[pyo3(
+        name = "composite_score",
+        text_signature = "(self, swap, active_nodes, distance_delta, /)"
+    )]
+    fn py_composite_score(
+        &self,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+        distance_delta: f64,
+    ) -> PyResult<f64> {
+        Ok(self.composite_score(swap, &active_nodes, distance_delta)?)
+    }
+
+    // Only the built-in cost models can be pickled, since they are restored by name.
+    fn __getstate__(&self) -> PickleState {
+        self.get_state()
+    }
+
+    fn __setstate__(&mut self, state: PickleState) -> PyResult<()> {
+        Ok(self.set_state(state)?)
+    }
+
+    /// Serialize the state to a compact binary checkpoint, including its undo log, so that long
+    /// routing jobs can be resumed and failing states can be shared.  Like pickling, only the
+    /// built-in cost models are supported.
+    #[pyo3(name = "to_bytes", text_signature = "(self, /)")]
+    fn py_to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new_bound(py, &self.to_bytes()?))
+    }
+
+    /// Restore a state from the output of ``to_bytes``.
+    ///
+    /// Raises:
+    ///     ValueError: if ``data`` is not a checkpoint of this version, or holds invalid
+    ///         parameters or an undo log that does not match its cif pairs.
+    #[staticmethod]
+    #[pyo3(name = "from_bytes", text_signature = "(data, /)")]
+    fn py_from_bytes(data: &[u8]) -> PyResult<Self> {
+        Ok(DqcMapState::from_bytes(data)?)
+    }
+
+    #[setter(ctrl_topology)]
+    fn py_set_ctrl_topology(&mut self, ctrl_topology: Option<CtrlTopology>) {
+        self.ctrl_topology = ctrl_topology;
+        self.clear_score_cache();
+    }
+
+    #[setter(noise_model)]
+    fn py_set_noise_model(&mut self, noise_model: Option<NoiseSource>) -> PyResult<()> {
+        self.noise_model = noise_model.map(NoiseSource::into_noise_model).transpose()?;
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    #[setter(crosstalk)]
+    fn py_set_crosstalk(&mut self, crosstalk: Option<CrosstalkMap>) {
+        self.crosstalk = crosstalk;
+        self.clear_score_cache();
+    }
+
+    #[setter(control_flow)]
+    fn py_set_control_flow(&mut self, control_flow: Option<ControlFlowModel>) {
+        self.control_flow = control_flow;
+        self.clear_score_cache();
+    }
+
+    /// Raises:
+    ///     ValueError: if a criticality is negative or not finite.
+    #[setter(criticality)]
+    fn py_set_criticality(&mut self, criticality: Option<HashMap<usize, f64>>) -> PyResult<()> {
+        Ok(self.set_criticality(criticality)?)
+    }
+
+    #[setter(clbit2ctrl)]
+    fn py_set_clbit2ctrl(&mut self, clbit2ctrl: Option<Clbit2Ctrl>) {
+        self.clbit2ctrl = clbit2ctrl;
+        self.clear_score_cache();
+    }
+
+    /// Memoize the results of ``score_swap`` by swap and set of active nodes, or stop doing so.
+    /// Applying or undoing a swap only drops the entries of the swaps it may have changed, or all
+    /// entries for ``feedback_batched`` and under a bandwidth limit, where scores depend on every
+    /// active pair, and setting a model or the cost model clears the cache.
+    #[pyo3(name = "enable_score_cache", signature = (enabled=true), text_signature = "(self, /, enabled=True)")]
+    fn py_enable_score_cache(&mut self, enabled: bool) {
+        self.enable_score_cache(enabled)
+    }
+
+    /// Drop all memoized scores.
+    #[pyo3(name = "clear_score_cache", text_signature = "(self, /)")]
+    fn py_clear_score_cache(&self) {
+        self.clear_score_cache()
+    }
+
+    /// The number of memoized scores, ``None`` if the cache is disabled.
+    #[pyo3(name = "score_cache_size", text_signature = "(self, /)")]
+    fn py_score_cache_size(&self) -> Option<usize> {
+        self.score_cache_size()
+    }
+
+    /// The name of the cost model used by ``score_swap``.
+    #[getter(cost_model)]
+    fn get_cost_model(&self) -> &'static str {
+        self.cost_model.name()
+    }
+
+    /// Select the cost model used by ``score_swap`` by name.
+    ///
+    /// Raises:
+    ///     DqcMapError: if ``name`` is not one of ``available_cost_models()``.
+    #[pyo3(name = "set_cost_model", text_signature = "(self, name, /)")]
+    fn py_set_cost_model(&mut self, name: &str) -> PyResult<()> {
+        self.set_cost_model(cost_model_by_name(name)?);
+        Ok(())
+    }
+
+    /// The names of the built-in cost models.
+    #[staticmethod]
+    fn available_cost_models() -> Vec<&'static str> {
+        COST_MODEL_NAMES.to_vec()
+    }
+
+    /// Score a swap against the cif pairs of the given active nodes with the cost model of the
+    /// state.  Higher is better, 0 means the swap is neutral.
+    ///
+    /// Raises:
+    ///     DqcMapError: if ``swap`` does not consist of two distinct non-negative qubits.
+    #[pyo3(name = "score_swap", text_signature = "(self, swap, active_nodes, /)")]
+    fn py_score_swap(&self, py: Python, swap: Swap, active_nodes: Vec<usize>) -> PyResult<f64> {
+        Ok(py.allow_threads(|| self.score_swap(swap, &active_nodes))?)
+    }
+
+    /// The swaps worth scoring for the given front layer: every coupling edge touching a qubit
+    /// of a front-layer gate, as sorted ``(p0, p1)`` tuples with ``p0 < p1``.
+    ///
+    /// Args:
+    ///     coupling_map (CouplingMap): The coupling map of the device.
+    ///     front_layer (list[list[int]]): The physical qubits of every gate of the front layer.
+    ///
+    /// Raises:
+    ///     IndexError: if a qubit is not in the coupling map.
+    #[pyo3(
+        name = "candidate_swaps",
+        text_signature = "(self, coupling_map, front_layer, /)"
+    )]
+    fn py_candidate_swaps(
+        &self,
+        coupling_map: &CouplingMap,
+        front_layer: Vec<Vec<PhysicalQubit>>,
+    ) -> PyResult<Vec<(PhysicalQubit, PhysicalQubit)>> {
+        for &qubit in front_layer.iter().flatten() {
+            coupling_map.check_qubit(qubit)?;
+        }
+        Ok(
+            DqcMapState::candidate_swaps(coupling_map, front_layer.into_iter().flatten())
+                .into_iter()
+                .map(|[p0, p1]| (p0, p1))
+                .collect(),
+        )
+    }
+
+    /// Configure the lookahead used by ``score_lookahead``: the first ``window`` upcoming nodes
+    /// contribute their score scaled by ``decay ** k`` for the ``k``-th node (starting at 1).
+    #[pyo3(name = "set_lookahead", signature = (window, decay=DEFAULT_LOOKAHEAD_DECAY), text_signature = "(self, window, /, decay=0.5)")]
+    fn py_set_lookahead(&mut self, window: usize, decay: f64) -> PyResult<()> {
+        Ok(self.set_lookahead(window, decay)?)
+    }
+
+    /// Score a swap against the cif pairs of the given active nodes, see ``DqcMapState::score``,
+    /// every pair counting as many times as its weight in ``cif_pairs``.  Returns ``None`` if the
+    /// state misses ``ctrl2pq``/``cif_pairs`` or a qubit of the swap is not connected to any
+    /// controller.
+    ///
+    /// Raises:
+    ///     DqcMapError: if ``swap`` does not consist of two distinct non-negative qubits.
+    #[pyo3(name = "score", text_signature = "(self, swap, active_nodes, /)")]
+    fn py_score(&self, py: Python, swap: Swap, active_nodes: Vec<usize>) -> PyResult<Option<f64>> {
+        Ok(py.allow_threads(|| self.score(swap, &active_nodes))?)
+    }
+
+    /// Like ``score``, but every cross-controller feedback is weighted by the latency between its
+    /// controllers in ``ctrl_topology``.  Positive values mean the swap reduces the total latency.
+    #[pyo3(
+        name = "score_weighted",
+        text_signature = "(self, swap, active_nodes, /)"
+    )]
+    fn py_score_weighted(
+        &self,
+        py: Python,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+    ) -> PyResult<Option<f64>> {
+        Ok(py.allow_threads(|| self.score_weighted(swap, &active_nodes))?)
+    }
+
+    /// Like ``score_weighted``, but all feedbacks of the active nodes between the same two
+    /// controllers are batched into a single transfer, so that swaps consolidating the feedbacks
+    /// onto fewer controller pairs score higher.
+    #[pyo3(
+        name = "score_batched",
+        text_signature = "(self, swap, active_nodes, /)"
+    )]
+    fn py_score_batched(
+        &self,
+        py: Python,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+    ) -> PyResult<Option<f64>> {
+        Ok(py.allow_threads(|| self.score_batched(swap, &active_nodes))?)
+    }
+
+    /// Like ``score_weighted``, minus the noise cost of the swap given by ``noise_model``, its
+    /// crosstalk cost given by ``crosstalk`` and its bandwidth cost given by ``ctrl_topology``, so
+    /// that swaps over bad links, into crosstalk-prone neighborhoods or saturating controller
+    /// links are avoided even when they are feedback-neutral.  A missing
+    /// ``ctrl2pq``/``cif_pairs`` or unmapped qubit counts as a feedback-neutral swap.
+    #[pyo3(
+        name = "score_noise_aware",
+        text_signature = "(self, swap, active_nodes, /)"
+    )]
+    fn py_score_noise_aware(
+        &self,
+        py: Python,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+    ) -> PyResult<f64> {
+        Ok(py.allow_threads(|| self.score_noise_aware(swap, &active_nodes))?)
+    }
+
+    /// The noise cost of a swap given by ``noise_model``, 0 if it is unset.
+    #[pyo3(name = "noise_cost", text_signature = "(self, swap, active_nodes, /)")]
+    fn py_noise_cost(&self, swap: Swap, active_nodes: Vec<usize>) -> PyResult<f64> {
+        Ok(self.noise_cost(swap, &active_nodes)?)
+    }
+
+    /// The increase of the cross-controller feedback transfers of the active nodes beyond the
+    /// bandwidth of their controllers given by ``ctrl_topology``, 0 if it is unset or does not
+    /// limit the bandwidth.
+    #[pyo3(
+        name = "bandwidth_cost",
+        text_signature = "(self, swap, active_nodes, /)"
+    )]
+    fn py_bandwidth_cost(&self, swap: Swap, active_nodes: Vec<usize>) -> PyResult<f64> {
+        Ok(self.bandwidth_cost(swap, &active_nodes)?)
+    }
+
+    /// The crosstalk cost of a swap given by ``crosstalk``, 0 if it is unset.
+    #[pyo3(
+        name = "crosstalk_cost",
+        text_signature = "(self, swap, active_nodes, /)"
+    )]
+    fn py_crosstalk_cost(&self, swap: Swap, active_nodes: Vec<usize>) -> PyResult<f64> {
+        Ok(self.crosstalk_cost(swap, &active_nodes)?)
+    }
+
+    /// Total latency-weighted cost of the cross-controller feedbacks of the current cif pairs.
+    #[pyo3(name = "total_cross_ctrl_cost", text_signature = "(self, /)")]
+    fn py_total_cross_ctrl_cost(&self) -> Option<f64> {
+        self.total_cross_ctrl_cost()
+    }
+
+    /// Score a swap against the active nodes plus the cif pairs of ``future_nodes``, which are
+    /// ordered by how soon they will be routed.  See ``set_lookahead``.
+    #[pyo3(
+        name = "score_lookahead",
+        text_signature = "(self, swap, active_nodes, future_nodes, /)"
+    )]
+    fn py_score_lookahead(
+        &self,
+        py: Python,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+        future_nodes: Vec<usize>,
+    ) -> PyResult<Option<f64>> {
+        Ok(py.allow_threads(|| self.score_lookahead(swap, &active_nodes, &future_nodes))?)
+    }
+
+    /// Like ``score``, plus the best feedback delta reachable by following the swap with up to
+    /// ``depth - 1`` more swaps among ``candidates``, found by a depth-first search.  Stopping
+    /// early is always allowed, so the continuation never lowers the score.  The search visits up
+    /// to ``len(candidates) ** (depth - 1)`` sequences, which suits small devices.  The GIL is
+    /// released while searching.
+    ///
+    /// Args:
+    ///     swap (tuple[int, int]): The two qubits of the swap to score.
+    ///     active_nodes (list[int]): The nodes whose cif pairs are scored.
+    ///     candidates (list[tuple[int, int]]): The swaps that may follow.
+    ///     depth (int): The maximum length of the explored swap sequences, 1 for ``score``.
+    ///
+    /// Returns:
+    ///     float | None: The score, ``None`` if ``swap`` involves a qubit that is not connected
+    ///     to any controller.
+    ///
+    /// Raises:
+    ///     ValueError: if ``depth`` is 0.
+    ///     DqcMapError: if a swap does not consist of two distinct non-negative qubits.
+    #[pyo3(
+        name = "score_dfs",
+        text_signature = "(self, swap, active_nodes, candidates, depth, /)"
+    )]
+    fn py_score_dfs(
+        &self,
+        py: Python,
+        swap: Swap,
+        active_nodes: Vec<usize>,
+        candidates: Vec<(i32, i32)>,
+        depth: usize,
+    ) -> PyResult<Option<f64>> {
+        if depth == 0 {
+            return Err(PyValueError::new_err("depth must be at least 1"));
+        }
+        Ok(py.allow_threads(|| self.score_dfs(swap, &active_nodes, &candidates, depth))?)
+    }
+
+    /// Score all candidate ``swaps`` against the same ``active_nodes`` in a single call, returning
+    /// one score per swap in the input order.
+    ///
+    /// The GIL is released while scoring.  Large batches are scored in parallel, on the global
+    /// thread pool by default or on a dedicated pool of ``num_threads`` threads if given.
+    #[pyo3(
+        name = "score_batch",
+        signature = (swaps, active_nodes, num_threads=None),
+        text_signature = "(self, swaps, active_nodes, /, num_threads=None)"
+    )]
+    fn py_score_batch(
+        &self,
+        py: Python,
+        swaps: Vec<(i32, i32)>,
+        active_nodes: Vec<usize>,
+        num_threads: Option<usize>,
+    ) -> PyResult<Vec<Option<f64>>> {
+        py.allow_threads(|| match num_threads {
+            Some(num_threads) => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+                Ok(pool.install(|| self.score_batch_inner(&swaps, &active_nodes, true))?)
+            }
+            None => Ok(self.score_batch(&swaps, &active_nodes)?),
+        })
+    }
+
+    /// Evaluate every candidate swap on three objectives and keep the Pareto-optimal ones, so
+    /// that the final choice between them can be left to the policy of the caller.
+    ///
+    /// All objectives are lower is better: the change in distance of the front layer given with
+    /// the swap, the change in latency-weighted cross-controller feedbacks (the negated
+    /// ``score_weighted``) and the error cost (``noise_cost`` plus ``crosstalk_cost``).  The GIL is
+    /// released while evaluating.
+    ///
+    /// Args:
+    ///     candidates (list[tuple[tuple[int, int], float]]): Every swap with the change in
+    ///         distance of the front layer it causes.
+    ///     active_nodes (list[int]): The nodes whose cif pairs are scored.
+    ///
+    /// Returns:
+    ///     list[tuple[tuple[int, int], float, float, float]]: The non-dominated swaps with their
+    ///     distance, feedback and error objectives, in the order of ``candidates``.
+    #[pyo3(
+        name = "pareto_front",
+        text_signature = "(self, candidates, active_nodes, /)"
+    )]
+    fn py_pareto_front(
+        &self,
+        py: Python,
+        candidates: Vec<((i32, i32), f64)>,
+        active_nodes: Vec<usize>,
+    ) -> PyResult<Vec<ParetoCandidate>> {
+        let front = py.allow_threads(|| self.pareto_front(&candidates, &active_nodes))?;
+        Ok(front
+            .into_iter()
+            .map(|(index, [distance, feedback, error])| {
+                (candidates[index].0, distance, feedback, error)
+            })
+            .collect())
+    }
+
+    /// Score all candidate ``swaps`` with ``score_swap`` and return the best one, so that only
+    /// the winner crosses back to Python.  The GIL is released while scoring.
+    ///
+    /// Args:
+    ///     swaps (list[tuple[int, int]]): The candidate swaps.
+    ///     active_nodes (list[int]): The nodes whose cif pairs are scored.
+    ///     seed (int): The seed of the RNG breaking ties between equally scored swaps.  The first
+    ///         of them in ``swaps`` is taken if not given.
+    ///
+    /// Returns:
+    ///     tuple[tuple[int, int], float] | None: The best swap with its score, ``None`` if there
+    ///     are no candidates.
+    ///
+    /// Raises:
+    ///     DqcMapError: if a swap does not consist of two distinct non-negative qubits.
+    #[pyo3(
+        name = "best_swap",
+        signature = (swaps, active_nodes, seed=None),
+        text_signature = "(self, swaps, active_nodes, /, seed=None)"
+    )]
+    fn py_best_swap(
+        &self,
+        py: Python,
+        swaps: Vec<(i32, i32)>,
+        active_nodes: Vec<usize>,
+        seed: Option<u64>,
+    ) -> PyResult<Option<((i32, i32), f64)>> {
+        let best = py.allow_threads(|| self.best_swap(&swaps, &active_nodes, seed))?;
+        Ok(best.map(|(index, score)| (swaps[index], score)))
+    }
+
+    /// Score all candidate ``swaps`` with ``score_swap`` and return the ``k`` best ones, best
+    /// first, equally scored swaps keeping their order in ``swaps``.  The GIL is released while
+    /// scoring.
+    ///
+    /// Returns:
+    ///     list[tuple[tuple[int, int], float]]: Up to ``k`` swaps with their score.
+    ///
+    /// Raises:
+    ///     DqcMapError: if a swap does not consist of two distinct non-negative qubits.
+    #[pyo3(name = "top_k", text_signature = "(self, swaps, active_nodes, k, /)")]
+    fn py_top_k(
+        &self,
+        py: Python,
+        swaps: Vec<(i32, i32)>,
+        active_nodes: Vec<usize>,
+        k: usize,
+    ) -> PyResult<Vec<((i32, i32), f64)>> {
+        let ranked = py.allow_threads(|| self.top_k(&swaps, &active_nodes, k))?;
+        Ok(ranked
+            .into_iter()
+            .map(|(index, score)| (swaps[index], score))
+            .collect())
+    }
+
+    /// Score all candidate ``swaps`` against the nodes of ``front_layer``, see ``score_batch``.
+    #[pyo3(
+        name = "score_front_layer",
+        text_signature = "(self, swaps, front_layer, /)"
+    )]
+    fn py_score_front_layer(
+        &self,
+        py: Python,
+        swaps: Vec<(i32, i32)>,
+        front_layer: &FrontLayer,
+    ) -> PyResult<Vec<Option<f64>>> {
+        Ok(py.allow_threads(|| self.score_batch(&swaps, front_layer.nodes()))?)
+    }
+
+    /// Apply a swap to all cif pairs whose nodes are not yet in ``gate_order``.
+    #[pyo3(name = "apply_swap", text_signature = "(self, swap, gate_order, /)")]
+    fn py_apply_swap(&mut self, swap: Swap, gate_order: Vec<usize>) -> PyResult<()> {
+        Ok(self.apply_swap(swap, &gate_order)?)
+    }
+
+    /// A fork of the state with ``swap`` applied to the cif pairs whose nodes are not yet in
+    /// ``gate_order``, leaving this state unchanged.  The swap is the only entry of the undo log
+    /// of the new state.
+    ///
+    /// Returns:
+    ///     DqcMapState: The new state.
+    #[pyo3(name = "with_swap", text_signature = "(self, swap, gate_order, /)")]
+    fn py_with_swap(&self, swap: Swap, gate_order: Vec<usize>) -> PyResult<Self> {
+        Ok(self.with_swap(swap, &gate_order)?)
+    }
+
+    /// Net change in the weighted number of cross-controller feedbacks after applying all
+    /// ``swaps`` in order to the cif pairs whose nodes are not in ``gate_order``, without
+    /// modifying the state.  Positive values mean the sequence reduces the number of
+    /// cross-controller feedbacks.
+    #[pyo3(
+        name = "score_sequence",
+        text_signature = "(self, swaps, gate_order, /)"
+    )]
+    fn py_score_sequence(
+        &self,
+        py: Python,
+        swaps: Vec<(i32, i32)>,
+        gate_order: Vec<usize>,
+    ) -> PyResult<Option<f64>> {
+        Ok(py.allow_threads(|| self.score_sequence(&swaps, &gate_order))?)
+    }
+
+    /// Revert the most recently applied swap and return it, or ``None`` if there is nothing to
+    /// undo.
+    #[pyo3(name = "undo_last", text_signature = "(self, /)")]
+    fn py_undo_last(&mut self) -> PyResult<Option<(i32, i32)>> {
+        Ok(self.undo_last()?.map(|[q0, q1]| (q0, q1)))
+    }
+
+    /// Revert the ``k`` most recently applied swaps and return them, most recent first.
+    ///
+    /// Raises:
+    ///     DqcMapError: if fewer than ``k`` swaps were applied.
+    #[pyo3(name = "undo_n", text_signature = "(self, k, /)")]
+    fn py_undo_n(&mut self, k: usize) -> PyResult<Vec<(i32, i32)>> {
+        Ok(self
+            .undo_n(k)?
+            .into_iter()
+            .map(|[q0, q1]| (q0, q1))
+            .collect())
+    }
+
+    /// A copy of the state for exploring a speculative branch.  The cif pairs are shared with
+    /// this state until either of them modifies them, so forking is cheap even for large
+    /// circuits.  The fork starts with an empty undo log.
+    #[pyo3(name = "fork", text_signature = "(self, /)")]
+    fn py_fork(&self) -> Self {
+        self.fork()
+    }
+
+    /// Remove the cif pairs of nodes whose gates have been routed, so that scoring only scans
+    /// the pairs of the remaining nodes.  The retired pairs no longer count in ``current_cost``
+    /// and are not restored by undoing swaps.
+    ///
+    /// Returns:
+    ///     int: The number of removed pairs.
+    #[pyo3(name = "retire_nodes", text_signature = "(self, node_ids, /)")]
+    fn py_retire_nodes(&mut self, node_ids: Vec<usize>) -> usize {
+        self.retire_nodes(&node_ids)
+    }
+
+    /// The number of applied swaps that can be undone.
+    #[pyo3(name = "undo_depth", text_signature = "(self, /)")]
+    fn py_undo_depth(&self) -> usize {
+        self.undo_depth()
+    }
+
+    /// Forget all recorded swaps, e.g. once a search has committed to a branch.
+    #[pyo3(name = "clear_undo_log", text_signature = "(self, /)")]
+    fn py_clear_undo_log(&mut self) {
+        self.clear_undo_log()
+    }
+
+    /// Report the memory held by the cif pairs, the mapping, the undo log and the score cache.
+    ///
+    /// Returns:
+    ///     MemoryStats: The statistics.
+    #[pyo3(name = "memory_stats", text_signature = "(self, /)")]
+    fn py_memory_stats(&self) -> MemoryStats {
+        self.memory_stats()
+    }
+
+    /// The weighted number of cross-controller feedbacks of the current cif pairs, maintained
+    /// incrementally as swaps are applied and undone.  ``None`` if the state misses
+    /// ``ctrl2pq``/``cif_pairs``.
+    #[pyo3(name = "current_cost", text_signature = "(self, /)")]
+    fn py_current_cost(&self) -> Option<f64> {
+        self.current_cost()
+    }
+
+    /// Total weighted number of cross-controller feedbacks of the current cif pairs, recounted
+    /// from scratch.
+    #[pyo3(name = "total_cross_ctrl_fb", text_signature = "(self, /)")]
+    fn py_total_cross_ctrl_fb(&self) -> Option<f64> {
+        self.total_cross_ctrl_fb()
+    }
+
+    /// Render the controllers, the links of ``ctrl_topology`` and the current cif pairs as a
+    /// Graphviz DOT digraph, with controllers as clusters of their qubits and cif pairs as edges
+    /// from the measured to the conditioned qubit, red across controllers and gray within one.
+    /// ``None`` if the state misses ``ctrl2pq``.
+    #[pyo3(name = "export_dot", text_signature = "(self, /)")]
+    fn py_export_dot(&self) -> Option<String> {
+        self.export_dot()
+    }
 }
 
 impl DqcMapState {
+    pub fn apply_config(&mut self, config: &DqcMapConfig) -> Result<(), MappingError> {
+        self.set_cost_model(cost_model_by_name(&config.cost_model)?);
+        self.distance_weight = config.distance_weight;
+        self.feedback_weight = config.feedback_weight;
+        self.lookahead_window = config.lookahead_window;
+        self.lookahead_decay = config.lookahead_decay;
+        self.feedback_model = config.feedback_model;
+        self.set_time_decay(config.time_decay)
+    }
+
+    pub fn set_feedback_model(&mut self, feedback_model: &str) -> Result<(), MappingError> {
+        self.feedback_model = FeedbackModel::from_name(feedback_model)?;
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    pub fn set_ctrl2pq(&mut self, ctrl2pq: Ctrl2Pq) -> Result<(), MappingError> {
+        if let Some(cif_pairs) = self.cif_pairs.as_ref() {
+            check_mapping(&ctrl2pq, cif_pairs, None)?;
+        }
+        self.ctrl2pq = Some(ctrl2pq);
+        self.recount_cross_ctrl();
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    pub fn set_time_decay(&mut self, decay: f64) -> Result<(), MappingError> {
+        if !(decay.is_finite() && decay >= 0.) {
+            return Err(MappingError::Value(format!(
+                "time decay must be finite and non-negative, got {decay}"
+            )));
+        }
+        self.time_decay = decay;
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    pub fn set_objective_weights(&mut self, alpha: f64, beta: f64) -> Result<(), MappingError> {
+        for weight in [alpha, beta] {
+            if !(weight.is_finite() && weight >= 0.) {
+                return Err(MappingError::Value(format!(
+                    "objective weights must be non-negative finite numbers, got {weight}"
+                )));
+            }
+        }
+        self.distance_weight = alpha;
+        self.feedback_weight = beta;
+        Ok(())
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MappingError> {
+        let invalid =
+            |err: bincode::Error| MappingError::Value(format!("invalid checkpoint: {err}"));
+        let version: u32 = bincode::deserialize(data).map_err(invalid)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(MappingError::Value(format!(
+                "unsupported checkpoint version {version}, expected {CHECKPOINT_VERSION}"
+            )));
+        }
+        let (_, state): (u32, PickleState) = bincode::deserialize(data).map_err(invalid)?;
+        let mut dqcmap_state = DqcMapState::new(None, None);
+        dqcmap_state.set_state(state)?;
+        Ok(dqcmap_state)
+    }
+
+    fn get_state(&self) -> PickleState {
+        (
+            self.ctrl2pq.clone(),
+            self.cif_pairs.clone(),
+            (self.lookahead_window, self.lookahead_decay, self.time_decay),
+            self.ctrl_topology.clone(),
+            self.noise_model.clone(),
+            self.crosstalk.clone(),
+            self.control_flow.clone(),
+            self.criticality.clone(),
+            self.clbit2ctrl.clone(),
+            (self.distance_weight, self.feedback_weight),
+            (
+                self.cost_model.name().to_string(),
+                self.feedback_model.name().to_string(),
+            ),
+            self.undo_log.clone(),
+        )
+    }
+
+    fn set_state(&mut self, state: PickleState) -> Result<(), MappingError> {
+        let (
+            ctrl2pq,
+            cif_pairs,
+            (lookahead_window, lookahead_decay, time_decay),
+            ctrl_topology,
+            noise_model,
+            crosstalk,
+            control_flow,
+            criticality,
+            clbit2ctrl,
+            (distance_weight, feedback_weight),
+            (cost_model, feedback_model),
+            undo_log,
+        ) = state;
+        check_undo_log(&undo_log, cif_pairs.as_ref())?;
+        self.cost_model = cost_model_by_name(&cost_model)?;
+        self.feedback_model = FeedbackModel::from_name(&feedback_model)?;
+        self.set_lookahead(lookahead_window, lookahead_decay)?;
+        self.set_time_decay(time_decay)?;
+        self.set_criticality(criticality)?;
+        self.set_objective_weights(distance_weight, feedback_weight)?;
+        self.ctrl2pq = ctrl2pq;
+        self.cif_pairs = cif_pairs;
+        self.ctrl_topology = ctrl_topology;
+        self.noise_model = noise_model;
+        self.crosstalk = crosstalk;
+        self.control_flow = control_flow;
+        self.clbit2ctrl = clbit2ctrl;
+        self.undo_log = undo_log;
+        self.recount_cross_ctrl();
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    pub fn enable_score_cache(&mut self, enabled: bool) {
+        self.score_cache = enabled.then(ScoreCache::default);
+    }
+
+    pub fn clear_score_cache(&self) {
+        if let Some(cache) = self.score_cache.as_ref() {
+            cache.clear();
+        }
+    }
+
+    pub fn score_cache_size(&self) -> Option<usize> {
+        self.score_cache.as_ref().map(ScoreCache::len)
+    }
+
+    pub fn set_lookahead(&mut self, window: usize, decay: f64) -> Result<(), MappingError> {
+        if !(0.0..=1.0).contains(&decay) {
+            return Err(MappingError::Value(format!(
+                "lookahead decay must be in [0, 1], got {decay}"
+            )));
+        }
+        self.lookahead_window = window;
+        self.lookahead_decay = decay;
+        Ok(())
+    }
+
+    pub fn fork(&self) -> Self {
+        DqcMapState {
+            ctrl2pq: self.ctrl2pq.clone(),
+            cif_pairs: self.cif_pairs.clone(),
+            lookahead_window: self.lookahead_window,
+            lookahead_decay: self.lookahead_decay,
+            time_decay: self.time_decay,
+            ctrl_topology: self.ctrl_topology.clone(),
+            feedback_model: self.feedback_model,
+            noise_model: self.noise_model.clone(),
+            crosstalk: self.crosstalk.clone(),
+            control_flow: self.control_flow.clone(),
+            criticality: self.criticality.clone(),
+            clbit2ctrl: self.clbit2ctrl.clone(),
+            distance_weight: self.distance_weight,
+            feedback_weight: self.feedback_weight,
+            cost_model: self.cost_model.clone(),
+            undo_log: Vec::new(),
+            cross_ctrl_count: self.cross_ctrl_count,
+            score_cache: self.score_cache.clone(),
+        }
+    }
+
+    pub fn undo_depth(&self) -> usize {
+        self.undo_log.len()
+    }
+
+    pub fn clear_undo_log(&mut self) {
+        self.undo_log.clear();
+    }
+
+    pub fn export_dot(&self) -> Option<String> {
+        let ctrl2pq = self.ctrl2pq.as_ref()?;
+        Some(dot::export_dot(
+            ctrl2pq,
+            self.cif_pairs.as_ref(),
+            self.ctrl_topology.as_ref(),
+        ))
+    }
+
     pub fn new(ctrl2pq: Option<Ctrl2Pq>, cif_pairs: Option<CifPairs>) -> Self {
-        DqcMapState { ctrl2pq, cif_pairs }
+        let mut state = DqcMapState {
+            ctrl2pq,
+            cif_pairs,
+            lookahead_window: 0,
+            lookahead_decay: DEFAULT_LOOKAHEAD_DECAY,
+            time_decay: 0.,
+            ctrl_topology: None,
+            feedback_model: FeedbackModel::PointToPoint,
+            noise_model: None,
+            crosstalk: None,
+            control_flow: None,
+            criticality: None,
+            clbit2ctrl: None,
+            distance_weight: DEFAULT_DISTANCE_WEIGHT,
+            feedback_weight: DEFAULT_FEEDBACK_WEIGHT,
+            cost_model: Arc::new(FidelityWeighted),
+            undo_log: Vec::new(),
+            cross_ctrl_count: None,
+            score_cache: None,
+        };
+        state.recount_cross_ctrl();
+        state
+    }
+
+    /// See [DqcMapState::py_retire_nodes].
+    pub fn retire_nodes(&mut self, node_ids: &[usize]) -> usize {
+        let Some(cif_pairs) = self.cif_pairs.as_mut() else {
+            return 0;
+        };
+        if let (Some(count), Some(ctrl2pq)) = (self.cross_ctrl_count.as_mut(), &self.ctrl2pq) {
+            // the weights of the pairs are retired with them
+            *count -= node_ids
+                .iter()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .map(|&node_id| cif_pairs.count_node_cross_controller(node_id, ctrl2pq))
+                .sum::<f64>();
+        }
+        let retired = cif_pairs.retire_nodes(node_ids);
+        // the retired pairs are not restored by undoing swaps
+        for (_, touched) in self.undo_log.iter_mut() {
+            touched.retain(|(node_id, _)| !retired.contains_key(node_id));
+        }
+        let retired_pairs = || retired.values().flat_map(|node_pairs| node_pairs.iter());
+        self.invalidate_cached_scores(
+            &retired_pairs()
+                .flat_map(|pair| pair.iter().take(2).copied())
+                .collect(),
+        );
+        retired_pairs().count()
+    }
+
+    /// See [DqcMapState::py_memory_stats].
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = self
+            .cif_pairs
+            .as_ref()
+            .map(CifPairs::memory_stats)
+            .unwrap_or_default();
+        stats.ctrl2pq_bytes = self.ctrl2pq.as_ref().map_or(0, Ctrl2Pq::allocated_bytes);
+        stats.undo_log_entries = self.undo_log.len();
+        stats.undo_log_bytes = vec_bytes(&self.undo_log)
+            + self
+                .undo_log
+                .iter()
+                .map(|(_, touched)| vec_bytes(touched))
+                .sum::<usize>();
+        if let Some(cache) = &self.score_cache {
+            stats.score_cache_entries = cache.len();
+            stats.score_cache_bytes = cache.allocated_bytes();
+        }
+        stats
+    }
+
+    /// Every coupling edge touching one of `qubits`, sorted and without duplicates so that the
+    /// order of the candidates does not depend on the order of the qubits.
+    pub fn candidate_swaps<I>(coupling: &CouplingMap, qubits: I) -> Vec<[PhysicalQubit; 2]>
+    where
+        I: IntoIterator<Item = PhysicalQubit>,
+    {
+        let mut swaps: Vec<[PhysicalQubit; 2]> = qubits
+            .into_iter()
+            .flat_map(|p| {
+                coupling
+                    .neighbors(p)
+                    .iter()
+                    .map(move |&n| if p < n { [p, n] } else { [n, p] })
+            })
+            .collect();
+        swaps.sort_unstable();
+        swaps.dedup();
+        swaps
+    }
+
+    /// Recount the cross-controller cif pairs from scratch.  Only needed after modifying
+    /// `ctrl2pq` or `cif_pairs` directly, [DqcMapState::apply_swap] and the undo methods keep the
+    /// count up to date.
+    pub fn recount_cross_ctrl(&mut self) {
+        self.cross_ctrl_count = match (self.ctrl2pq.as_ref(), self.cif_pairs.as_ref()) {
+            (Some(ctrl2pq), Some(cif_pairs)) => Some(cif_pairs.count_cross_controller(ctrl2pq)),
+            _ => None,
+        };
+    }
+
+    /// The current weighted number of cross-controller cif pairs, without recounting them.
+    pub fn current_cost(&self) -> Option<f64> {
+        self.cross_ctrl_count
+    }
+
+    /// Update the count of cross-controller pairs after the qubits of `swap` were exchanged in
+    /// the `touched` pairs, either by applying or by reverting the swap.
+    fn update_cross_ctrl_count(&mut self, swap: [i32; 2], touched: &[(usize, usize)]) {
+        let (Some(count), Some(ctrl2pq), Some(cif_pairs)) = (
+            self.cross_ctrl_count,
+            self.ctrl2pq.as_ref(),
+            self.cif_pairs.as_ref(),
+        ) else {
+            return;
+        };
+        let (mut added, mut removed) = (0., 0.);
+        for &(node_id, position) in touched {
+            let Some(pair) = cif_pairs
+                .pairs()
+                .get(&node_id)
+                .and_then(|pairs| pairs.get(position))
+            else {
+                continue;
+            };
+            let previous: Vec<i32> = pair
+                .iter()
+                .map(|&q| match q {
+                    q if q == swap[0] => swap[1],
+                    q if q == swap[1] => swap[0],
+                    q => q,
+                })
+                .collect();
+            let weight = cif_pairs.pair_weight(node_id, position);
+            if is_cross_controller(pair, ctrl2pq) {
+                added += weight;
+            }
+            if is_cross_controller(&previous, ctrl2pq) {
+                removed += weight;
+            }
+        }
+        self.cross_ctrl_count = Some(count + added - removed);
+    }
+
+    /// Use `cost_model` in [DqcMapState::score_swap], e.g. a custom [SwapCostModel].
+    pub fn set_cost_model(&mut self, cost_model: Arc<dyn SwapCostModel>) {
+        self.cost_model = cost_model;
+        self.clear_score_cache();
+    }
+
+    /// The binary checkpoint read by [DqcMapState::from_bytes].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MappingError> {
+        bincode::serialize(&(CHECKPOINT_VERSION, self.get_state()))
+            .map_err(|err| MappingError::Value(err.to_string()))
+    }
+
+    pub fn cost_model(&self) -> &dyn SwapCostModel {
+        self.cost_model.as_ref()
+    }
+
+    /// Score a swap with the cost model of the state, higher is better.
+    pub fn score_swap(&self, swap: Swap, active_nodes: &[usize]) -> Result<f64, MappingError> {
+        let Some(cache) = self.score_cache.as_ref() else {
+            return self.cost_model.score(self, swap, active_nodes);
+        };
+        let key = if self.time_decay > 0. {
+            ScoreCache::ordered_key(swap, active_nodes)
+        } else {
+            ScoreCache::key(swap, active_nodes)
+        };
+        if let Some(score) = cache.get(&key) {
+            return Ok(score);
+        }
+        let score = self.cost_model.score(self, swap, active_nodes)?;
+        cache.insert(key, score);
+        Ok(score)
+    }
+
+    /// The objectives of `swap` for [DqcMapState::pareto_front], see
+    /// [DqcMapState::py_pareto_front].
+    pub fn objectives(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+        distance_delta: f64,
+    ) -> Result<Objectives, MappingError> {
+        let feedback_delta = self
+            .score_weighted(swap, active_nodes)?
+            .map_or(0., |score| -score);
+        let error_cost =
+            self.noise_cost(swap, active_nodes)? + self.crosstalk_cost(swap, active_nodes)?;
+        Ok([distance_delta, feedback_delta, error_cost])
+    }
+
+    /// The index and objectives of the non-dominated swaps of `candidates`, each given with its
+    /// change in distance of the front layer, in input order.
+    pub fn pareto_front(
+        &self,
+        candidates: &[((i32, i32), f64)],
+        active_nodes: &[usize],
+    ) -> Result<Vec<(usize, Objectives)>, MappingError> {
+        let objectives = candidates
+            .iter()
+            .map(|&((q0, q1), distance_delta)| {
+                self.objectives(Swap::new(q0, q1)?, active_nodes, distance_delta)
+            })
+            .collect::<Result<Vec<Objectives>, MappingError>>()?;
+        Ok(pareto::pareto_front(&objectives)
+            .into_iter()
+            .map(|index| (index, objectives[index]))
+            .collect())
+    }
+
+    /// `distance_weight * distance_delta - feedback_weight * score_swap`, lower is better.  The
+    /// feedback term is negated since positive swap scores mean fewer cross-controller feedbacks.
+    pub fn composite_score(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+        distance_delta: f64,
+    ) -> Result<f64, MappingError> {
+        let feedback_delta = -self.score_swap(swap, active_nodes)?;
+        Ok(self.distance_weight * distance_delta + self.feedback_weight * feedback_delta)
     }
 
     /// 0: no additional cross-controller feedback is introduced
     /// -1: one additional cross-controller feedback is introduced
-    /// etc
-    pub fn score(&self, swap: &Vec<i32>, active_nodes: &Vec<usize>) -> Option<i32> {
-        let ctrl2pq = self.ctrl2pq.as_ref()?;
-        let ctrl0 = ctrl2pq.get_controller_by_qubit(swap[0])?;
-        let ctrl1 = ctrl2pq.get_controller_by_qubit(swap[1])?;
+    /// etc, every pair counting as many times as its weight in `cif_pairs`
+    pub fn score(&self, swap: Swap, active_nodes: &[usize]) -> Result<Option<f64>, MappingError> {
+        metrics::record_score_call();
+        let Some(ctrl2pq) = self.ctrl2pq.as_ref() else {
+            return Ok(None);
+        };
+        let (Some(ctrl0), Some(ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(swap[0]),
+            ctrl2pq.get_controller_by_qubit(swap[1]),
+        ) else {
+            return Ok(None);
+        };
         if ctrl0 != ctrl1 {
             // if the swap involves two qubits controlled by different
             // controllers, we count the number of inter-controller feedbacks
             // before and after this swap, then we use the difference as the score
-            let cif_pairs = self.cif_pairs.as_ref()?;
-            let involved_pairs: Vec<Vec<i32>> =
-                cif_pairs.get_swap_involved_pairs(swap, active_nodes);
-            let swapped_pairs: Vec<Vec<i32>> = swap_involved_pairs(&involved_pairs, swap);
-            let count_inv: i32 = count_ctrl_pairs(&involved_pairs, ctrl2pq, ctrl0, ctrl1);
-            let count_swapped: i32 = count_ctrl_pairs(&swapped_pairs, ctrl2pq, ctrl0, ctrl1);
-            Some(count_inv - count_swapped)
+            let Some(cif_pairs) = self.cif_pairs.as_ref() else {
+                return Ok(None);
+            };
+            let mut delta = 0.;
+            for (node_id, position) in cif_pairs.swap_involved_entries(swap, active_nodes)? {
+                let pair = &cif_pairs.pairs()[&node_id][position];
+                if pair.len() < 2 {
+                    continue;
+                }
+                let before = (pair[0], pair[1]);
+                let after = (swap.apply(pair[0]), swap.apply(pair[1]));
+                let change = i32::from(is_between_ctrls(ctrl2pq, before, ctrl0, ctrl1))
+                    - i32::from(is_between_ctrls(ctrl2pq, after, ctrl0, ctrl1));
+                if change != 0 {
+                    delta += f64::from(change) * cif_pairs.pair_weight(node_id, position);
+                }
+            }
+            Ok(Some(delta))
         } else {
-            Some(0)
+            Ok(Some(0.))
         }
     }
 
-    pub fn apply_swap(&mut self, swap: &Vec<i32>, gate_order: &Vec<usize>) {
-        if let Some(cif_pairs) = self.cif_pairs.as_mut() {
-            cif_pairs.apply_swap(swap, gate_order);
+    /// Score of a swap on the active nodes, plus the decayed scores of the first
+    /// `lookahead_window` nodes of `future_nodes`, so that swaps helping upcoming feedbacks are
+    /// preferred among otherwise equal ones.
+    pub fn score_lookahead(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+        future_nodes: &[usize],
+    ) -> Result<Option<f64>, MappingError> {
+        let Some(mut score) = self.score(swap, active_nodes)? else {
+            return Ok(None);
+        };
+        let mut weight = 1.;
+        for node in future_nodes.iter().take(self.lookahead_window) {
+            weight *= self.lookahead_decay;
+            let Some(node_score) = self.score(swap, std::slice::from_ref(node))? else {
+                return Ok(None);
+            };
+            score += weight * node_score;
         }
+        Ok(Some(score))
     }
 
-    pub fn total_cross_ctrl_fb(&self) -> Option<i32> {
-        let mut all_pairs: Vec<Vec<i32>> = Vec::new();
-        let cif_pairs: &CifPairs = self.cif_pairs.as_ref()?;
+    /// [DqcMapState::score] of `swap` plus the best score of the sequences of up to `depth - 1`
+    /// swaps of `candidates` following it, explored depth-first on forks of the state where the
+    /// swaps are applied to all cif pairs.  A continuation with a qubit that is not connected to
+    /// any controller is skipped.
+    pub fn score_dfs(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+        candidates: &[(i32, i32)],
+        depth: usize,
+    ) -> Result<Option<f64>, MappingError> {
+        let Some(score) = self.score(swap, active_nodes)? else {
+            return Ok(None);
+        };
+        if depth <= 1 || candidates.is_empty() {
+            return Ok(Some(score));
+        }
+        let mut next = self.fork();
+        next.enable_score_cache(false);
+        next.apply_swap(swap, &Vec::new())?;
+        let mut best_continuation: f64 = 0.;
+        for &(q0, q1) in candidates {
+            if let Some(continuation) =
+                next.score_dfs(Swap::new(q0, q1)?, active_nodes, candidates, depth - 1)?
+            {
+                best_continuation = best_continuation.max(continuation);
+            }
+        }
+        Ok(Some(score + best_continuation))
+    }
+
+    /// Difference of the latency-weighted cost of the involved cif pairs before and after the
+    /// swap.  Unlike [DqcMapState::score], pairs towards a third controller are not unchanged by
+    /// a swap once links have different latencies, so all involved pairs are accounted for.
+    pub fn score_weighted(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Option<f64>, MappingError> {
+        metrics::record_score_call();
+        let (Some(ctrl2pq), Some(cif_pairs)) = (self.ctrl2pq.as_ref(), self.cif_pairs.as_ref())
+        else {
+            return Ok(None);
+        };
+        let (Some(ctrl0), Some(ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(swap[0]),
+            ctrl2pq.get_controller_by_qubit(swap[1]),
+        ) else {
+            return Ok(None);
+        };
+        if ctrl0 == ctrl1 {
+            return Ok(Some(0.));
+        }
+        let time_weights = self.time_weights(active_nodes);
+        Ok(Some(
+            cif_pairs
+                .swap_involved_entries(swap, active_nodes)?
+                .into_iter()
+                .map(|(node_id, position)| {
+                    let pair = &cif_pairs.pairs()[&node_id][position];
+                    if !is_well_formed(pair) {
+                        return 0.;
+                    }
+                    let after = (swap.apply(pair[0]), swap.apply(pair[1]));
+                    let clbit = pair_clbit(pair);
+                    self.node_weight(node_id)
+                        * time_weights
+                            .as_ref()
+                            .and_then(|weights| weights.get(&node_id))
+                            .copied()
+                            .unwrap_or(1.)
+                        * cif_pairs.pair_weight(node_id, position)
+                        * (self.qubits_cost((pair[0], pair[1]), clbit, ctrl2pq)
+                            - self.qubits_cost(after, clbit, ctrl2pq))
+                })
+                .sum(),
+        ))
+    }
+
+    /// Like [DqcMapState::score_weighted], but the active nodes are a window within which all
+    /// feedbacks between the same two controllers are batched into a single transfer, so that a
+    /// swap gains by consolidating the feedbacks onto fewer controller pairs.  Node weights do not
+    /// apply since repeated feedbacks share their transfer, but a transfer repeats as many times
+    /// as the heaviest pair in `cif_pairs` it carries.
+    pub fn score_batched(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<Option<f64>, MappingError> {
+        metrics::record_score_call();
+        let (Some(ctrl2pq), Some(cif_pairs)) = (self.ctrl2pq.as_ref(), self.cif_pairs.as_ref())
+        else {
+            return Ok(None);
+        };
+        let (Some(ctrl0), Some(ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(swap[0]),
+            ctrl2pq.get_controller_by_qubit(swap[1]),
+        ) else {
+            return Ok(None);
+        };
+        if ctrl0 == ctrl1 {
+            return Ok(Some(0.));
+        }
+        let mut pairs: Vec<(&[i32], f64)> = Vec::new();
+        for &node_id in active_nodes {
+            let Some(node_pairs) = cif_pairs.pairs().get(&node_id) else {
+                continue;
+            };
+            for (position, pair) in node_pairs.iter().enumerate() {
+                if is_well_formed(pair) {
+                    pairs.push((pair, cif_pairs.pair_weight(node_id, position)));
+                }
+            }
+        }
+        let cost = |map: &dyn Fn(i32) -> i32| {
+            let mut transfers: HashMap<(i32, i32), f64> = HashMap::new();
+            for &(pair, weight) in &pairs {
+                self.add_transfers(
+                    (map(pair[0]), map(pair[1])),
+                    pair_clbit(pair),
+                    weight,
+                    ctrl2pq,
+                    &mut transfers,
+                );
+            }
+            transfers
+                .into_iter()
+                .map(|((ctrl0, ctrl1), weight)| weight * self.ctrl_cost(ctrl0, ctrl1))
+                .sum::<f64>()
+        };
+        Ok(Some(
+            cost(&|qubit| qubit) - cost(&|qubit| swap.apply(qubit)),
+        ))
+    }
+
+    /// [DqcMapState::score_weighted] combined with [DqcMapState::noise_cost],
+    /// [DqcMapState::crosstalk_cost] and [DqcMapState::bandwidth_cost], higher is better.
+    pub fn score_noise_aware(
+        &self,
+        swap: Swap,
+        active_nodes: &[usize],
+    ) -> Result<f64, MappingError> {
+        Ok(self.score_weighted(swap, active_nodes)?.unwrap_or(0.)
+            - self.noise_cost(swap, active_nodes)?
+            - self.crosstalk_cost(swap, active_nodes)?
+            - self.bandwidth_cost(swap, active_nodes)?)
+    }
+
+    /// Increase of the number of cross-controller feedback transfers of the active nodes beyond
+    /// the bandwidth of their controllers given by `ctrl_topology`, as the active nodes are
+    /// executed concurrently.
+    pub fn bandwidth_cost(&self, swap: Swap, active_nodes: &[usize]) -> Result<f64, MappingError> {
+        let (Some(topology), Some(ctrl2pq), Some(cif_pairs)) = (
+            self.ctrl_topology.as_ref(),
+            self.ctrl2pq.as_ref(),
+            self.cif_pairs.as_ref(),
+        ) else {
+            return Ok(0.);
+        };
+        if !topology.has_bandwidth() {
+            return Ok(0.);
+        }
+        let pairs = pair_qubits(
+            active_nodes
+                .iter()
+                .filter_map(|node_id| cif_pairs.pairs().get(node_id))
+                .flat_map(|pairs| pairs.iter()),
+        );
+        let excess = |map: &dyn Fn(i32) -> i32| {
+            topology.excess_transfers(pairs.iter().filter_map(|&(q0, q1)| {
+                match (
+                    ctrl2pq.get_controller_by_qubit(map(q1)),
+                    ctrl2pq.get_controller_by_qubit(map(q0)),
+                ) {
+                    (Some(&measured), Some(&conditioned)) => Some((measured, conditioned)),
+                    _ => None,
+                }
+            }))
+        };
+        let before = excess(&|qubit| qubit);
+        let after = excess(&|qubit| swap.apply(qubit));
+        Ok(after as f64 - before as f64)
+    }
 
-        // Collect all pairs from cif_pairs
-        for (_, pairs) in &cif_pairs.pairs {
-            all_pairs.extend(pairs.iter().cloned()); // Collect all pairs
+    /// Weighted probability that the swap itself fails, plus the increase of readout error of the
+    /// measured qubits (the second qubit of a pair) of the involved cif pairs that it moves.
+    pub fn noise_cost(&self, swap: Swap, active_nodes: &[usize]) -> Result<f64, MappingError> {
+        let Some(noise) = self.noise_model.as_ref() else {
+            return Ok(0.);
+        };
+        let mut cost = noise.swap_error(swap[0], swap[1]);
+        if let Some(cif_pairs) = self.cif_pairs.as_ref() {
+            let readout_delta = noise.readout(swap[1]) - noise.readout(swap[0]);
+            for (node_id, pair) in cif_pairs.swap_involved_pair_views(swap, active_nodes)? {
+                match pair.get(1) {
+                    Some(&measured) if measured == swap[0] => {
+                        cost += self.node_weight(node_id) * readout_delta
+                    }
+                    Some(&measured) if measured == swap[1] => {
+                        cost -= self.node_weight(node_id) * readout_delta
+                    }
+                    _ => {}
+                }
+            }
         }
+        Ok(noise.weight * cost)
+    }
 
+    /// Weighted increase of the crosstalk exposure of the qubits of the involved cif pairs moved
+    /// by the swap.
+    pub fn crosstalk_cost(&self, swap: Swap, active_nodes: &[usize]) -> Result<f64, MappingError> {
+        let (Some(crosstalk), Some(cif_pairs)) = (self.crosstalk.as_ref(), self.cif_pairs.as_ref())
+        else {
+            return Ok(0.);
+        };
+        let cost: f64 = cif_pairs
+            .swap_involved_pair_views(swap, active_nodes)?
+            .into_iter()
+            .map(|(node_id, pair)| self.node_weight(node_id) * crosstalk.exposure_delta(pair, swap))
+            .sum();
+        Ok(crosstalk.weight * cost)
+    }
+
+    /// Latency-weighted cost of all the current cif pairs, weighted by the expected number of
+    /// executions of their nodes and by their own weights.
+    pub fn total_cross_ctrl_cost(&self) -> Option<f64> {
+        let cif_pairs = self.cif_pairs.as_ref()?;
         let ctrl2pq = self.ctrl2pq.as_ref()?;
-        let mut total_cross_ctrl_fb = 0;
+        Some(
+            cif_pairs
+                .pairs()
+                .iter()
+                .map(|(&node_id, pairs)| {
+                    let pairs_cost: f64 = pairs
+                        .iter()
+                        .enumerate()
+                        .map(|(position, pair)| {
+                            cif_pairs.pair_weight(node_id, position) * self.pair_cost(pair, ctrl2pq)
+                        })
+                        .sum();
+                    self.node_weight(node_id) * pairs_cost
+                })
+                .sum(),
+        )
+    }
+
+    /// The weight of the cif pairs of node `node_id`: its expected number of executions given by
+    /// the control-flow model times its criticality, each 1 if unset.
+    #[inline]
+    pub fn node_weight(&self, node_id: usize) -> f64 {
+        let multiplicity = self
+            .control_flow
+            .as_ref()
+            .map_or(1., |control_flow| control_flow.multiplicity(node_id));
+        let criticality = self
+            .criticality
+            .as_ref()
+            .and_then(|criticality| criticality.get(&node_id))
+            .copied()
+            .unwrap_or(1.);
+        multiplicity * criticality
+    }
+
+    /// The `exp(-time_decay * k)` weight of every active node from its first position `k`, `None`
+    /// if the time decay is disabled.
+    fn time_weights(&self, active_nodes: &[usize]) -> Option<HashMap<usize, f64>> {
+        if self.time_decay == 0. {
+            return None;
+        }
+        let mut weights = HashMap::with_capacity(active_nodes.len());
+        for (position, &node_id) in active_nodes.iter().enumerate() {
+            weights
+                .entry(node_id)
+                .or_insert_with(|| (-self.time_decay * position as f64).exp());
+        }
+        Some(weights)
+    }
+
+    /// See [DqcMapState::criticality].
+    pub fn set_criticality(
+        &mut self,
+        criticality: Option<HashMap<usize, f64>>,
+    ) -> Result<(), MappingError> {
+        if let Some(value) = criticality
+            .iter()
+            .flat_map(|criticality| criticality.values())
+            .find(|value| !(value.is_finite() && **value >= 0.))
+        {
+            return Err(MappingError::Value(format!(
+                "node criticality must be finite and non-negative, got {value}"
+            )));
+        }
+        self.criticality = criticality;
+        self.clear_score_cache();
+        Ok(())
+    }
+
+    /// The cost of a feedback between two controllers under the feedback model.
+    #[inline]
+    pub fn ctrl_cost(&self, ctrl0: i32, ctrl1: i32) -> f64 {
+        self.feedback_model
+            .latency(self.ctrl_topology.as_ref(), ctrl0, ctrl1)
+    }
+
+    /// The cost of the feedback of a single cif pair, 0 if it is malformed or unmapped.  A pair
+    /// whose clbit is held by a controller in `clbit2ctrl` is routed from the measured qubit
+    /// through that controller to the conditioned qubit.
+    fn pair_cost(&self, pair: &[i32], ctrl2pq: &Ctrl2Pq) -> f64 {
+        if !is_well_formed(pair) {
+            return 0.;
+        }
+        self.qubits_cost((pair[0], pair[1]), pair_clbit(pair), ctrl2pq)
+    }
 
-        // Iterate through all pairs and count the cross-controller feedbacks
-        for pair in &all_pairs {
-            if pair.len() != 2 {
-                continue; // Skip invalid pairs
+    /// [DqcMapState::pair_cost] of a pair given by its qubits and clbit.
+    fn qubits_cost(&self, (q0, q1): (i32, i32), clbit: Option<i32>, ctrl2pq: &Ctrl2Pq) -> f64 {
+        let (Some(&ctrl0), Some(&ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(q0),
+            ctrl2pq.get_controller_by_qubit(q1),
+        ) else {
+            return 0.;
+        };
+        let clbit_ctrl = clbit.and_then(|clbit| {
+            self.clbit2ctrl
+                .as_ref()
+                .and_then(|clbit2ctrl| clbit2ctrl.get_controller_by_clbit(clbit))
+        });
+        match clbit_ctrl {
+            Some(clbit_ctrl) => {
+                self.ctrl_cost(ctrl1, clbit_ctrl) + self.ctrl_cost(clbit_ctrl, ctrl0)
             }
+            None => self.ctrl_cost(ctrl0, ctrl1),
+        }
+    }
 
-            let ctrl0 = ctrl2pq.get_controller_by_qubit(pair[0]);
-            let ctrl1 = ctrl2pq.get_controller_by_qubit(pair[1]);
+    /// Add the (smaller, larger) controllers of the cross-controller transfers of a feedback
+    /// repeating `weight` times to `transfers`, routed as in [DqcMapState::qubits_cost].  Every
+    /// transfer keeps the largest weight of the feedbacks it carries.
+    fn add_transfers(
+        &self,
+        (q0, q1): (i32, i32),
+        clbit: Option<i32>,
+        weight: f64,
+        ctrl2pq: &Ctrl2Pq,
+        transfers: &mut HashMap<(i32, i32), f64>,
+    ) {
+        let (Some(&ctrl0), Some(&ctrl1)) = (
+            ctrl2pq.get_controller_by_qubit(q0),
+            ctrl2pq.get_controller_by_qubit(q1),
+        ) else {
+            return;
+        };
+        let clbit_ctrl = clbit.and_then(|clbit| {
+            self.clbit2ctrl
+                .as_ref()
+                .and_then(|clbit2ctrl| clbit2ctrl.get_controller_by_clbit(clbit))
+        });
+        let hops = match clbit_ctrl {
+            Some(clbit_ctrl) => vec![(ctrl1, clbit_ctrl), (clbit_ctrl, ctrl0)],
+            None => vec![(ctrl0, ctrl1)],
+        };
+        for (ctrl0, ctrl1) in hops {
+            if ctrl0 != ctrl1 {
+                let repeats = transfers
+                    .entry((ctrl0.min(ctrl1), ctrl0.max(ctrl1)))
+                    .or_insert(0.);
+                *repeats = repeats.max(weight);
+            }
+        }
+    }
 
-            // Check if the pair involves different controllers
-            if let (Some(c0), Some(c1)) = (ctrl0, ctrl1) {
-                if c0 != c1 {
-                    total_cross_ctrl_fb += 1; // Increase count for cross-controller feedback
-                }
+    /// Score every swap in `swaps` against `active_nodes`, see [DqcMapState::score].  Batches
+    /// larger than `PARALLEL_SCORE_THRESHOLD` are scored in parallel unless we are already in a
+    /// parallel context.
+    pub fn score_batch(
+        &self,
+        swaps: &[(i32, i32)],
+        active_nodes: &[usize],
+    ) -> Result<Vec<Option<f64>>, MappingError> {
+        let _timer = metrics::time_phase(Phase::ScoreBatch);
+        let parallel = swaps.len() >= PARALLEL_SCORE_THRESHOLD && getenv_use_multiple_threads();
+        self.score_batch_inner(swaps, active_nodes, parallel)
+    }
+
+    /// The [DqcMapState::score_swap] of every swap in `swaps`, in parallel for large batches like
+    /// [DqcMapState::score_batch].
+    fn score_swap_batch(
+        &self,
+        swaps: &[(i32, i32)],
+        active_nodes: &[usize],
+    ) -> Result<Vec<f64>, MappingError> {
+        let _timer = metrics::time_phase(Phase::ScoreBatch);
+        if swaps.len() >= PARALLEL_SCORE_THRESHOLD && getenv_use_multiple_threads() {
+            swaps
+                .par_iter()
+                .map(|&swap| self.score_swap(Swap::try_from(swap)?, active_nodes))
+                .collect()
+        } else {
+            swaps
+                .iter()
+                .map(|&swap| self.score_swap(Swap::try_from(swap)?, active_nodes))
+                .collect()
+        }
+    }
+
+    /// The index in `swaps` and the score of the best swap for [DqcMapState::score_swap], ties
+    /// within `BEST_EPSILON` going to the first one or, given a seed, to a random one.
+    pub fn best_swap(
+        &self,
+        swaps: &[(i32, i32)],
+        active_nodes: &[usize],
+        seed: Option<u64>,
+    ) -> Result<Option<(usize, f64)>, MappingError> {
+        let scores = self.score_swap_batch(swaps, active_nodes)?;
+        let Some(max_score) = scores.iter().copied().reduce(f64::max) else {
+            return Ok(None);
+        };
+        let best: Vec<usize> = (0..scores.len())
+            .filter(|&index| scores[index] > max_score - BEST_EPSILON)
+            .collect();
+        let index = match seed {
+            Some(seed) => *best
+                .choose(&mut Pcg64Mcg::seed_from_u64(seed))
+                .expect("the best score has a swap"),
+            None => best[0],
+        };
+        Ok(Some((index, scores[index])))
+    }
+
+    /// The indices in `swaps` and the scores of the `k` best swaps for
+    /// [DqcMapState::score_swap], best first, equally scored swaps keeping their order.
+    pub fn top_k(
+        &self,
+        swaps: &[(i32, i32)],
+        active_nodes: &[usize],
+        k: usize,
+    ) -> Result<Vec<(usize, f64)>, MappingError> {
+        let mut ranked: Vec<(usize, f64)> = self
+            .score_swap_batch(swaps, active_nodes)?
+            .into_iter()
+            .enumerate()
+            .collect();
+        ranked.sort_by(|(_, score0), (_, score1)| score1.total_cmp(score0));
+        ranked.truncate(k);
+        Ok(ranked)
+    }
+
+    fn score_batch_inner(
+        &self,
+        swaps: &[(i32, i32)],
+        active_nodes: &[usize],
+        parallel: bool,
+    ) -> Result<Vec<Option<f64>>, MappingError> {
+        if parallel {
+            swaps
+                .par_iter()
+                .map(|&swap| self.score(Swap::try_from(swap)?, active_nodes))
+                .collect()
+        } else {
+            swaps
+                .iter()
+                .map(|&swap| self.score(Swap::try_from(swap)?, active_nodes))
+                .collect()
+        }
+    }
+
+    pub fn apply_swap(&mut self, swap: Swap, gate_order: &[usize]) -> Result<(), MappingError> {
+        let touched = match self.cif_pairs.as_mut() {
+            Some(cif_pairs) => cif_pairs.apply_swap_recorded(swap, gate_order)?,
+            None => Vec::new(),
+        };
+        self.update_cross_ctrl_count(swap.qubits(), &touched);
+        self.invalidate_scores(swap.qubits(), &touched);
+        self.undo_log.push((swap.qubits(), touched));
+        metrics::record_swap_applied();
+        Ok(())
+    }
+
+    /// See [DqcMapState::py_with_swap].
+    pub fn with_swap(&self, swap: Swap, gate_order: &[usize]) -> Result<Self, MappingError> {
+        let mut state = self.fork();
+        state.apply_swap(swap, gate_order)?;
+        Ok(state)
+    }
+
+    /// Drop the cached scores of the swaps on a qubit of `swap` or of one of the `touched` pairs,
+    /// whose involved pairs may have changed.
+    fn invalidate_scores(&self, swap: [i32; 2], touched: &[(usize, usize)]) {
+        let Some(cif_pairs) = self.cif_pairs.as_ref() else {
+            return;
+        };
+        let mut qubits: HashSet<i32> = HashSet::from(swap);
+        for &(node_id, position) in touched {
+            if let Some(pair) = cif_pairs
+                .pairs()
+                .get(&node_id)
+                .and_then(|pairs| pairs.get(position))
+            {
+                qubits.extend(pair.iter().take(2));
             }
         }
+        self.invalidate_cached_scores(&qubits);
+    }
+
+    /// Drop the cached scores of the swaps on one of `qubits`, or all of them if the scores of
+    /// the cost model are not local to the pairs of a swap's qubits.  A bandwidth limit makes
+    /// them global as well, since [DqcMapState::bandwidth_cost] counts the transfers of all
+    /// active pairs.
+    fn invalidate_cached_scores(&self, qubits: &HashSet<i32>) {
+        let Some(cache) = self.score_cache.as_ref() else {
+            return;
+        };
+        let limits_bandwidth = self
+            .ctrl_topology
+            .as_ref()
+            .is_some_and(CtrlTopology::has_bandwidth);
+        if self.cost_model.is_local() && !limits_bandwidth {
+            cache.invalidate(qubits);
+        } else {
+            cache.clear();
+        }
+    }
+
+    /// Revert the most recently applied swap and return it, `None` if the undo log is empty.
+    pub fn undo_last(&mut self) -> Result<Option<[i32; 2]>, MappingError> {
+        let Some((swap, touched)) = self.undo_log.pop() else {
+            return Ok(None);
+        };
+        if let Some(cif_pairs) = self.cif_pairs.as_mut() {
+            cif_pairs.revert_swap(Swap::try_from(&swap[..])?, &touched)?;
+        }
+        self.update_cross_ctrl_count(swap, &touched);
+        self.invalidate_scores(swap, &touched);
+        Ok(Some(swap))
+    }
+
+    /// Revert the `k` most recently applied swaps and return them, most recent first.
+    pub fn undo_n(&mut self, k: usize) -> Result<Vec<[i32; 2]>, MappingError> {
+        if k > self.undo_log.len() {
+            return Err(MappingError::UndoOutOfRange {
+                requested: k,
+                available: self.undo_log.len(),
+            });
+        }
+        let mut swaps = Vec::with_capacity(k);
+        for _ in 0..k {
+            swaps.extend(self.undo_last()?);
+        }
+        Ok(swaps)
+    }
+
+    pub fn total_cross_ctrl_fb(&self) -> Option<f64> {
+        let cif_pairs: &CifPairs = self.cif_pairs.as_ref()?;
+        let ctrl2pq = self.ctrl2pq.as_ref()?;
+        Some(cif_pairs.count_cross_controller(ctrl2pq))
+    }
 
-        Some(total_cross_ctrl_fb)
+    /// Net change in the weighted number of cross-controller feedbacks after applying `swaps` in
+    /// order, with the same sign convention as [DqcMapState::score].  The swaps are applied to a
+    /// scratch copy of the cif pairs, so the state itself is left untouched.
+    pub fn score_sequence(
+        &self,
+        swaps: &[(i32, i32)],
+        gate_order: &[usize],
+    ) -> Result<Option<f64>, MappingError> {
+        let (Some(ctrl2pq), Some(cif_pairs)) = (self.ctrl2pq.as_ref(), self.cif_pairs.as_ref())
+        else {
+            return Ok(None);
+        };
+        let mut swapped = cif_pairs.clone();
+        for &(q0, q1) in swaps {
+            swapped.apply_swap(Swap::new(q0, q1)?, gate_order)?;
+        }
+        Ok(Some(
+            cif_pairs.count_cross_controller(ctrl2pq) - swapped.count_cross_controller(ctrl2pq),
+        ))
     }
 }
 
+/// Score every swap in ``swaps`` against the cif pairs of the nodes in ``gate_order`` without
+/// building a ``DqcMapState``, see ``DqcMapState.score_batch``.  The GIL is released while
+/// scoring.
+///
+/// Args:
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     cif_pairs (CifPairs): The cif pairs of the circuit keyed by node id.
+///     swaps (list[tuple[int, int]]): The swaps to score.
+///     gate_order (list[int]): The nodes whose cif pairs are scored.
+///
+/// Returns:
+///     list[int | None]: The score of every swap in the input order, ``None`` for swaps of a
+///     qubit that is not connected to any controller.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (ctrl2pq, cif_pairs, swaps, gate_order))]
+pub fn score_swaps(
+    py: Python,
+    ctrl2pq: &Ctrl2Pq,
+    cif_pairs: &CifPairs,
+    swaps: Vec<(i32, i32)>,
+    gate_order: Vec<usize>,
+) -> PyResult<Vec<Option<f64>>> {
+    let state = DqcMapState::new(Some(ctrl2pq.clone()), Some(cif_pairs.clone()));
+    Ok(py.allow_threads(|| state.score_batch(&swaps, &gate_order))?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test::in_scoped_thread_pool;
     use hashbrown::HashMap;
+    use std::sync::Arc;
 
-    #[test]
-    fn test_dqcmapstate_score() {
+    fn build_state() -> DqcMapState {
         // Set up a Ctrl2Pq instance with mock controller mappings
         let mut ctrl2pq_map: HashMap<i32, Vec<i32>> = HashMap::new();
         let mut reverse_map: HashMap<i32, i32> = HashMap::new();
-        let gate_order: Vec<usize> = vec![1, 2];
 
         // Controller 1 controls qubits 0 and 1
         ctrl2pq_map.insert(1, vec![0, 1]);
@@ -144,31 +2026,1079 @@ mod tests {
         reverse_map.insert(3, 2);
 
         let ctrl2pq: Ctrl2Pq = Ctrl2Pq {
-            map: ctrl2pq_map,
-            reverse_map,
+            map: ctrl2pq_map.into(),
+            reverse_map: reverse_map.into(),
+            capacity: HashMap::new().into(),
         };
 
         // Set up a CifPairs instance with some feedback pairs
         let mut pairs_map: HashMap<usize, Vec<Vec<i32>>> = HashMap::new();
         pairs_map.insert(1, vec![vec![0, 2], vec![1, 3]]); // Feedback pairs between qubits
-        let cif_pairs: CifPairs = CifPairs { pairs: pairs_map };
+        let cif_pairs: CifPairs = CifPairs::from_pairs(pairs_map);
 
         // Create the DqcMapState with the Ctrl2Pq and CifPairs
-        let dqcmap_state: DqcMapState = DqcMapState::new(Some(ctrl2pq), Some(cif_pairs));
+        DqcMapState::new(Some(ctrl2pq), Some(cif_pairs))
+    }
+
+    #[test]
+    fn test_dqcmapstate_score() {
+        let gate_order: Vec<usize> = vec![1, 2];
+        let dqcmap_state: DqcMapState = build_state();
 
         // Test case 1: swap between qubits controlled by different controllers
-        let swap1: Vec<i32> = vec![0, 2]; // Qubit 0 (Controller 1) and qubit 2 (Controller 2)
-        let score1: Option<i32> = dqcmap_state.score(&swap1, &gate_order);
-        assert_eq!(score1, Some(0)); // Cross-controller feedback reduced
+        let swap1 = Swap::new(0, 2).unwrap(); // Qubit 0 (Controller 1) and qubit 2 (Controller 2)
+        let score1: Option<f64> = dqcmap_state.score(swap1, &gate_order).unwrap();
+        assert_eq!(score1, Some(0.)); // Cross-controller feedback reduced
 
         // Test case 2: swap between qubits controlled by the same controller
-        let swap2: Vec<i32> = vec![0, 1]; // Qubit 0 and qubit 1 both controlled by Controller 1
-        let score2: Option<i32> = dqcmap_state.score(&swap2, &gate_order);
-        assert_eq!(score2, Some(0)); // No cross-controller feedback is introduced
+        let swap2 = Swap::new(0, 1).unwrap(); // Qubit 0 and qubit 1 both controlled by Controller 1
+        let score2: Option<f64> = dqcmap_state.score(swap2, &gate_order).unwrap();
+        assert_eq!(score2, Some(0.)); // No cross-controller feedback is introduced
 
         // Test case 3: swap with no involved pairs (no feedback)
-        let swap3: Vec<i32> = vec![1, 2]; // Qubit 1 (Controller 1) and qubit 2 (Controller 2)
-        let score3: Option<i32> = dqcmap_state.score(&swap3, &gate_order);
-        assert_eq!(score3, Some(2)); // No change in feedback count
+        let swap3 = Swap::new(1, 2).unwrap(); // Qubit 1 (Controller 1) and qubit 2 (Controller 2)
+        let score3: Option<f64> = dqcmap_state.score(swap3, &gate_order).unwrap();
+        assert_eq!(score3, Some(2.)); // No change in feedback count
+
+        // Test case 4: malformed swaps cannot be built
+        assert_eq!(
+            Swap::try_from(&[0, 1, 2][..]),
+            Err(MappingError::InvalidSwap(vec![0, 1, 2]))
+        );
+        assert!(Swap::try_from(&[0][..]).is_err());
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_batch() {
+        let dqcmap_state: DqcMapState = build_state();
+        let scores = dqcmap_state
+            .score_batch(&[(0, 2), (0, 1), (1, 2), (0, 9)], &[1, 2])
+            .unwrap();
+        assert_eq!(scores, vec![Some(0.), Some(0.), Some(2.), None]);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_score_swaps() {
+        let dqcmap_state: DqcMapState = build_state();
+        let swaps = vec![(0, 2), (1, 2), (0, 9)];
+        pyo3::prepare_freethreaded_python();
+        let scores = Python::with_gil(|py| {
+            score_swaps(
+                py,
+                dqcmap_state.ctrl2pq.as_ref().unwrap(),
+                dqcmap_state.cif_pairs.as_ref().unwrap(),
+                swaps.clone(),
+                vec![1, 2],
+            )
+        })
+        .unwrap();
+        assert_eq!(scores, dqcmap_state.score_batch(&swaps, &[1, 2]).unwrap());
+        assert_eq!(scores, vec![Some(0.), Some(2.), None]);
+    }
+
+    #[test]
+    fn score_batch_threaded_and_serial_equal() {
+        let dqcmap_state: DqcMapState = build_state();
+        let swaps: Vec<(i32, i32)> = (0..4)
+            .flat_map(|q0| (0..4).map(move |q1| (q0, q1)))
+            .filter(|(q0, q1)| q0 != q1)
+            .cycle()
+            .take(4 * PARALLEL_SCORE_THRESHOLD)
+            .collect();
+        let parallel =
+            in_scoped_thread_pool(|| dqcmap_state.score_batch_inner(&swaps, &[1], true).unwrap())
+                .unwrap();
+        let serial = dqcmap_state.score_batch_inner(&swaps, &[1], false).unwrap();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_sequence() {
+        let dqcmap_state: DqcMapState = build_state();
+        // a single swap matches `score` over all nodes
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2)], &[]).unwrap(),
+            dqcmap_state.score(Swap::new(1, 2).unwrap(), &[1]).unwrap()
+        );
+        // swapping back and forth is neutral
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2), (1, 2)], &[]).unwrap(),
+            Some(0.)
+        );
+        // a following swap within controller 2 keeps the gain of the first one
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2), (2, 3)], &[]).unwrap(),
+            Some(2.)
+        );
+        // the second swap splits both pairs across the controllers again
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2), (0, 3)], &[]).unwrap(),
+            Some(0.)
+        );
+        // routed nodes do not follow the swaps
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2)], &[1]).unwrap(),
+            Some(0.)
+        );
+        assert_eq!(dqcmap_state.total_cross_ctrl_fb(), Some(2.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_undo() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let initial = dqcmap_state.cif_pairs.as_ref().unwrap().pairs().clone();
+
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+        dqcmap_state
+            .apply_swap(Swap::new(2, 3).unwrap(), &[])
+            .unwrap();
+        dqcmap_state
+            .apply_swap(Swap::new(0, 1).unwrap(), &[1])
+            .unwrap();
+        assert_eq!(dqcmap_state.undo_depth(), 3);
+        assert_eq!(dqcmap_state.total_cross_ctrl_fb(), Some(0.));
+
+        assert_eq!(dqcmap_state.undo_last().unwrap(), Some([0, 1]));
+        assert_eq!(
+            dqcmap_state.cif_pairs.as_ref().unwrap().pairs()[&1].to_vec(),
+            vec![vec![0, 1], vec![3, 2]]
+        );
+        assert!(dqcmap_state.undo_n(3).is_err());
+        assert_eq!(dqcmap_state.undo_n(2).unwrap(), vec![[2, 3], [1, 2]]);
+        assert_eq!(dqcmap_state.cif_pairs.as_ref().unwrap().pairs(), &initial);
+        // the qubit index follows the pairs back
+        assert_eq!(
+            dqcmap_state.score(Swap::new(1, 2).unwrap(), &[1]).unwrap(),
+            Some(2.)
+        );
+        assert_eq!(dqcmap_state.undo_last().unwrap(), None);
+    }
+
+    #[test]
+    fn test_dqcmapstate_best_swap_top_k() {
+        let dqcmap_state: DqcMapState = build_state();
+        let swaps = [(1, 2), (0, 2), (0, 1), (1, 3)];
+        let scores: Vec<f64> = swaps
+            .iter()
+            .map(|&(q0, q1)| {
+                dqcmap_state
+                    .score_swap(Swap::new(q0, q1).unwrap(), &[1])
+                    .unwrap()
+            })
+            .collect();
+        let (index, score) = dqcmap_state.best_swap(&swaps, &[1], None).unwrap().unwrap();
+        assert_eq!(score, scores.iter().copied().fold(f64::MIN, f64::max));
+        assert_eq!(
+            index,
+            scores.iter().position(|&other| other == score).unwrap()
+        );
+        assert_eq!(
+            dqcmap_state.best_swap(&swaps, &[1], Some(3)).unwrap(),
+            dqcmap_state.best_swap(&swaps, &[1], Some(3)).unwrap()
+        );
+        assert_eq!(dqcmap_state.best_swap(&[], &[1], None).unwrap(), None);
+
+        let ranked = dqcmap_state.top_k(&swaps, &[1], 3).unwrap();
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0], (index, score));
+        assert!(ranked.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert_eq!(dqcmap_state.top_k(&swaps, &[1], 10).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_dfs() {
+        // a single pair of node 1 across controllers 1 and 2, two swaps away from being local
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.cif_pairs = Some(CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 3]])])));
+        dqcmap_state.recount_cross_ctrl();
+        let candidates = [(0, 1), (1, 2), (2, 3)];
+
+        // moving qubit 0 to qubit 1 does not help on its own
+        assert_eq!(
+            dqcmap_state.score(Swap::new(0, 1).unwrap(), &[1]).unwrap(),
+            Some(0.)
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_dfs(Swap::new(0, 1).unwrap(), &[1], &candidates, 1)
+                .unwrap(),
+            Some(0.)
+        );
+        // but it does when followed by (1, 2)
+        assert_eq!(
+            dqcmap_state
+                .score_dfs(Swap::new(0, 1).unwrap(), &[1], &candidates, 2)
+                .unwrap(),
+            Some(1.)
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_dfs(Swap::new(0, 1).unwrap(), &[1], &[], 3)
+                .unwrap(),
+            Some(0.)
+        );
+        // the state itself is left untouched
+        assert_eq!(dqcmap_state.undo_depth(), 0);
+        assert_eq!(dqcmap_state.current_cost(), Some(1.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_retire_nodes() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state
+            .cif_pairs
+            .as_mut()
+            .unwrap()
+            .insert_node(2, vec![vec![0, 1]]);
+        dqcmap_state.recount_cross_ctrl();
+        dqcmap_state.enable_score_cache(true);
+        assert_eq!(
+            dqcmap_state
+                .score_swap(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            2.
+        );
+        assert_eq!(dqcmap_state.current_cost(), Some(2.));
+
+        assert_eq!(dqcmap_state.retire_nodes(&[1, 7]), 2);
+        assert_eq!(dqcmap_state.current_cost(), Some(0.));
+        assert_eq!(
+            dqcmap_state.current_cost(),
+            dqcmap_state.total_cross_ctrl_fb()
+        );
+        // the cached score of the retired pairs is dropped
+        assert_eq!(
+            dqcmap_state
+                .score_swap(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        assert_eq!(dqcmap_state.retire_nodes(&[1]), 0);
+
+        let cif_pairs = dqcmap_state.cif_pairs.as_mut().unwrap();
+        assert_eq!(cif_pairs.node_ids(), vec![2]);
+        assert_eq!(cif_pairs.memory_stats().index_entries, 2);
+        assert_eq!(
+            cif_pairs.retire_node(2).map(|pairs| pairs.to_vec()),
+            Some(vec![vec![0, 1]])
+        );
+        assert_eq!(cif_pairs.memory_stats().index_entries, 0);
+    }
+
+    #[test]
+    fn test_dqcmapstate_retire_nodes_then_undo() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state
+            .cif_pairs
+            .as_mut()
+            .unwrap()
+            .insert_node(2, vec![vec![0, 1]]);
+        dqcmap_state.recount_cross_ctrl();
+        dqcmap_state.enable_score_cache(true);
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+        assert_eq!(dqcmap_state.current_cost(), Some(1.));
+
+        // the undo log forgets the retired pairs, the others are reverted as usual
+        assert_eq!(dqcmap_state.retire_nodes(&[1]), 2);
+        assert_eq!(dqcmap_state.undo_last().unwrap(), Some([1, 2]));
+        let cif_pairs = dqcmap_state.cif_pairs.as_ref().unwrap();
+        assert_eq!(cif_pairs.node_ids(), vec![2]);
+        assert_eq!(cif_pairs.pairs()[&2].to_vec(), vec![vec![0, 1]]);
+        assert_eq!(dqcmap_state.current_cost(), Some(0.));
+        assert_eq!(
+            dqcmap_state.current_cost(),
+            dqcmap_state.total_cross_ctrl_fb()
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_memory_stats() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let stats = dqcmap_state.memory_stats();
+        assert_eq!((stats.num_nodes, stats.num_pairs), (1, 2));
+        // one index entry per qubit of every pair
+        assert_eq!(stats.index_entries, 4);
+        assert!(stats.pair_bytes > 0 && stats.index_bytes > 0 && stats.ctrl2pq_bytes > 0);
+        assert_eq!((stats.undo_log_entries, stats.undo_log_bytes), (0, 0));
+
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[1])
+            .unwrap();
+        let stats = dqcmap_state.memory_stats();
+        assert_eq!(stats.undo_log_entries, 1);
+        assert!(stats.undo_log_bytes > 0);
+        assert_eq!(
+            stats.total_bytes(),
+            stats.pair_bytes + stats.index_bytes + stats.ctrl2pq_bytes + stats.undo_log_bytes
+        );
+        assert_eq!(DqcMapState::new(None, None).memory_stats().total_bytes(), 0);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_dqcmapstate_candidate_swaps() {
+        let q = PhysicalQubit::new;
+        let edges = (0..4).map(|i| [q(i), q(i + 1)]).collect();
+        let coupling = CouplingMap::new(edges, None).unwrap();
+        let dqcmap_state: DqcMapState = build_state();
+
+        let swaps = dqcmap_state
+            .py_candidate_swaps(&coupling, vec![vec![q(3), q(1)], vec![q(2), q(4)]])
+            .unwrap();
+        assert_eq!(
+            swaps,
+            vec![(q(0), q(1)), (q(1), q(2)), (q(2), q(3)), (q(3), q(4))]
+        );
+        assert!(dqcmap_state
+            .py_candidate_swaps(&coupling, vec![vec![q(0), q(5)]])
+            .is_err());
+    }
+
+    #[test]
+    fn test_dqcmapstate_current_cost() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        assert_eq!(dqcmap_state.current_cost(), Some(2.));
+
+        for ((q0, q1), gate_order) in [
+            ((1, 2), vec![]),
+            ((0, 3), vec![]),
+            ((2, 3), vec![1]),
+            ((0, 2), vec![]),
+        ] {
+            dqcmap_state
+                .apply_swap(Swap::new(q0, q1).unwrap(), &gate_order)
+                .unwrap();
+            assert_eq!(
+                dqcmap_state.current_cost(),
+                dqcmap_state.total_cross_ctrl_fb()
+            );
+        }
+        while dqcmap_state.undo_last().unwrap().is_some() {
+            assert_eq!(
+                dqcmap_state.current_cost(),
+                dqcmap_state.total_cross_ctrl_fb()
+            );
+        }
+        assert_eq!(dqcmap_state.current_cost(), Some(2.));
+        assert_eq!(DqcMapState::new(None, None).current_cost(), None);
+    }
+
+    #[test]
+    fn test_dqcmapstate_fork() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state
+            .apply_swap(Swap::new(0, 1).unwrap(), &[])
+            .unwrap();
+        let mut fork = dqcmap_state.fork();
+        assert_eq!(fork.undo_depth(), 0);
+
+        let shared = |a: &DqcMapState, b: &DqcMapState| {
+            Arc::ptr_eq(
+                &a.cif_pairs.as_ref().unwrap().pairs()[&1],
+                &b.cif_pairs.as_ref().unwrap().pairs()[&1],
+            )
+        };
+        assert!(shared(&dqcmap_state, &fork));
+        // a swap on qubits without pairs leaves the pairs shared
+        fork.apply_swap(Swap::new(5, 6).unwrap(), &[]).unwrap();
+        assert!(shared(&dqcmap_state, &fork));
+
+        fork.apply_swap(Swap::new(0, 2).unwrap(), &[]).unwrap();
+        assert!(!shared(&dqcmap_state, &fork));
+        assert_eq!(fork.total_cross_ctrl_fb(), Some(0.));
+        assert_eq!(dqcmap_state.total_cross_ctrl_fb(), Some(2.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_with_swap() {
+        let dqcmap_state: DqcMapState = build_state();
+        let swapped = dqcmap_state
+            .with_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+        assert_eq!(swapped.total_cross_ctrl_fb(), Some(0.));
+        assert_eq!(swapped.current_cost(), Some(0.));
+        assert_eq!(swapped.undo_depth(), 1);
+        assert_eq!(dqcmap_state.total_cross_ctrl_fb(), Some(2.));
+        assert_eq!(dqcmap_state.undo_depth(), 0);
+        assert!(
+            dqcmap_state
+                .with_swap(Swap::new(1, 2).unwrap(), &[1, 2])
+                .unwrap()
+                .total_cross_ctrl_fb()
+                == Some(2.)
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_pickle_state() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.set_lookahead(2, 0.25).unwrap();
+        dqcmap_state.set_objective_weights(2., 0.5).unwrap();
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_count").unwrap());
+        dqcmap_state.set_feedback_model("broadcast").unwrap();
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+
+        let mut restored = DqcMapState::new(None, None);
+        restored.set_state(dqcmap_state.get_state()).unwrap();
+        assert_eq!(restored.feedback_model, FeedbackModel::Broadcast);
+        assert_eq!(restored.lookahead_window, 2);
+        assert_eq!(restored.lookahead_decay, 0.25);
+        assert_eq!(restored.feedback_weight, 0.5);
+        assert_eq!(restored.cost_model().name(), "feedback_count");
+        assert_eq!(restored.total_cross_ctrl_fb(), Some(0.));
+        // the undo log survives, so the restored state can still backtrack
+        assert_eq!(restored.undo_last().unwrap(), Some([1, 2]));
+        assert_eq!(restored.total_cross_ctrl_fb(), Some(2.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_checkpoint() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.ctrl_topology = Some(
+            CtrlTopology::new(HashMap::from([((1, 2), 4.)]), 1., HashMap::new(), None).unwrap(),
+        );
+        dqcmap_state.set_objective_weights(2., 0.5).unwrap();
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+
+        let bytes = dqcmap_state.to_bytes().unwrap();
+        let mut restored = DqcMapState::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.distance_weight, 2.);
+        assert_eq!(restored.ctrl_topology.as_ref().unwrap().default_latency, 1.);
+        assert_eq!(restored.current_cost(), dqcmap_state.current_cost());
+        assert_eq!(restored.undo_last().unwrap(), Some([1, 2]));
+        assert_eq!(restored.total_cross_ctrl_fb(), Some(2.));
+
+        assert!(DqcMapState::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+        // well-formed checkpoints with invalid contents are rejected as well
+        let corrupted = |corrupt: fn(&mut PickleState)| {
+            let mut state = dqcmap_state.get_state();
+            corrupt(&mut state);
+            DqcMapState::from_bytes(&bincode::serialize(&(CHECKPOINT_VERSION, state)).unwrap())
+        };
+        assert_eq!(
+            corrupted(|state| state.11[0].1 = vec![(1, 0), (7, 0)]).unwrap_err(),
+            MappingError::Value(
+                "invalid undo log: swap [1, 2] touched pair 0 of node 7, which does not exist"
+                    .to_string()
+            )
+        );
+        assert!(corrupted(|state| state.11[0].1 = vec![(1, 1), (1, 0)]).is_err());
+        assert!(corrupted(|state| state.11[0].0 = [2, 2]).is_err());
+        assert!(corrupted(|state| state.2 .2 = -1.).is_err());
+        assert!(corrupted(|state| state.2 .1 = f64::NAN).is_err());
+        assert!(corrupted(|state| state.7 = Some(HashMap::from([(1, -1.)]))).is_err());
+        let mut newer = bytes.clone();
+        newer[0] = CHECKPOINT_VERSION as u8 + 1;
+        assert!(DqcMapState::from_bytes(&newer).is_err());
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_lookahead() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state
+            .cif_pairs
+            .as_mut()
+            .unwrap()
+            .insert_node(2, vec![vec![1, 3]]);
+        let swap = Swap::new(1, 2).unwrap();
+
+        // without a window only the active nodes count
+        assert_eq!(
+            dqcmap_state.score_lookahead(swap, &[1], &[2]).unwrap(),
+            Some(2.)
+        );
+
+        dqcmap_state.set_lookahead(1, 0.5).unwrap();
+        assert_eq!(
+            dqcmap_state.score_lookahead(swap, &[1], &[2]).unwrap(),
+            Some(2.5)
+        );
+        assert_eq!(
+            dqcmap_state.score_lookahead(swap, &[2], &[1]).unwrap(),
+            Some(2.)
+        );
+        assert!(dqcmap_state.set_lookahead(1, 2.).is_err());
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_weighted() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        // without a topology the weighted score matches the count
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            Some(2.)
+        );
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(2.));
+
+        dqcmap_state.ctrl_topology = Some(
+            CtrlTopology::new(HashMap::from([((1, 2), 4.)]), 1., HashMap::new(), None).unwrap(),
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            Some(8.)
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(0, 1).unwrap(), &[1])
+                .unwrap(),
+            Some(0.)
+        );
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(8.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_clbit_controller() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(1, vec![0, 1]), (2, vec![2, 3])]));
+        // qubit 1 is conditioned on a measurement of qubit 0 stored in clbit 2
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![1, 0, 2]])]));
+        let mut dqcmap_state = DqcMapState::new(Some(ctrl2pq), Some(cif_pairs));
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(0.));
+
+        // the clbit lives on a third controller, so the feedback crosses controllers twice
+        dqcmap_state.clbit2ctrl = Some(Clbit2Ctrl::new(HashMap::from([(3, vec![2])])).unwrap());
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(2.));
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            Some(0.)
+        );
+
+        // on the controller of qubits 2 and 3, moving the conditioned qubit there saves a hop
+        dqcmap_state.clbit2ctrl = Some(Clbit2Ctrl::new(HashMap::from([(2, vec![2])])).unwrap());
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            Some(1.)
+        );
+        dqcmap_state
+            .apply_swap(Swap::new(1, 2).unwrap(), &[])
+            .unwrap();
+        // the clbit is not moved by the swap
+        assert_eq!(
+            dqcmap_state.cif_pairs.as_ref().unwrap().pairs()[&1][0],
+            vec![2, 0, 2]
+        );
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(1.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_cache() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let uncached = |state: &DqcMapState, swap: Swap| {
+            let mut state = state.clone();
+            state.enable_score_cache(false);
+            state.score_swap(swap, &[1]).unwrap()
+        };
+        assert_eq!(dqcmap_state.score_cache_size(), None);
+        dqcmap_state.enable_score_cache(true);
+
+        let swaps = [(1, 2), (1, 3), (3, 1)].map(|swap| Swap::try_from(swap).unwrap());
+        for &swap in &swaps {
+            let expected = uncached(&dqcmap_state, swap);
+            assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), expected);
+        }
+        // the reversed swap shares its entry
+        assert_eq!(dqcmap_state.score_cache_size(), Some(2));
+
+        // only the entries of swaps on the qubits of the pair [0, 2] are dropped
+        dqcmap_state
+            .apply_swap(Swap::new(0, 2).unwrap(), &[])
+            .unwrap();
+        assert_eq!(dqcmap_state.score_cache_size(), Some(1));
+        for &swap in &swaps {
+            let expected = uncached(&dqcmap_state, swap);
+            assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), expected);
+        }
+        dqcmap_state.undo_last().unwrap();
+        assert_eq!(dqcmap_state.score_cache_size(), Some(1));
+
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_count").unwrap());
+        assert_eq!(dqcmap_state.score_cache_size(), Some(0));
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_cache_batched() {
+        let mut dqcmap_state = DqcMapState::new(
+            Some(Ctrl2Pq::from_map(HashMap::from([
+                (0, vec![0, 1]),
+                (1, vec![2, 3]),
+                (2, vec![4, 5]),
+            ]))),
+            Some(CifPairs::from_pairs(HashMap::from([
+                (0, vec![vec![0, 2]]),
+                (1, vec![vec![1, 4]]),
+            ]))),
+        );
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_batched").unwrap());
+        dqcmap_state.enable_score_cache(true);
+        let swap = Swap::new(3, 4).unwrap();
+        // moving qubit 4 next to 2 batches both feedbacks into one transfer
+        assert_eq!(dqcmap_state.score_swap(swap, &[0, 1]).unwrap(), 1.);
+
+        // the swap does not touch qubit 3 or 4 but splits the transfers again
+        dqcmap_state
+            .apply_swap(Swap::new(0, 5).unwrap(), &[])
+            .unwrap();
+        assert_eq!(dqcmap_state.score_cache_size(), Some(0));
+        assert_eq!(dqcmap_state.score_swap(swap, &[0, 1]).unwrap(), 0.);
+        dqcmap_state.undo_last().unwrap();
+        assert_eq!(dqcmap_state.score_swap(swap, &[0, 1]).unwrap(), 1.);
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_cache_bandwidth() {
+        let mut dqcmap_state = DqcMapState::new(
+            Some(Ctrl2Pq::from_map(HashMap::from([
+                (0, vec![0, 1]),
+                (1, vec![2, 3]),
+                (2, vec![4, 5]),
+            ]))),
+            Some(CifPairs::from_pairs(HashMap::from([
+                (0, vec![vec![0, 2]]),
+                (1, vec![vec![1, 4]]),
+            ]))),
+        );
+        dqcmap_state.ctrl_topology =
+            Some(CtrlTopology::new(HashMap::new(), 1., HashMap::new(), Some(1)).unwrap());
+        // cached as while routing
+        dqcmap_state.enable_score_cache(true);
+        let swap = Swap::new(3, 4).unwrap();
+        // both feedbacks would cross between controllers 0 and 1
+        assert_eq!(dqcmap_state.score_swap(swap, &[0, 1]).unwrap(), -1.);
+
+        dqcmap_state
+            .apply_swap(Swap::new(0, 5).unwrap(), &[])
+            .unwrap();
+        assert_eq!(dqcmap_state.score_cache_size(), Some(0));
+        assert_eq!(dqcmap_state.score_swap(swap, &[0, 1]).unwrap(), 0.);
+    }
+
+    #[test]
+    fn test_dqcmapstate_control_flow_weights() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let mut control_flow = ControlFlowModel::new();
+        let body = control_flow.add_while(3., None).unwrap();
+        control_flow.assign(1, Some(body)).unwrap();
+        dqcmap_state.control_flow = Some(control_flow);
+
+        // the pairs of node 1 are executed three times per run of the circuit
+        assert_eq!(
+            dqcmap_state
+                .score_weighted(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            Some(6.)
+        );
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(6.));
+        // the plain count ignores how often the node executes
+        assert_eq!(
+            dqcmap_state.score(Swap::new(1, 2).unwrap(), &[1]).unwrap(),
+            Some(2.)
+        );
+
+        let mut restored = DqcMapState::new(None, None);
+        restored.set_state(dqcmap_state.get_state()).unwrap();
+        assert_eq!(restored.node_weight(1), 3.);
+    }
+
+    #[test]
+    fn test_dqcmapstate_time_decay() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.enable_score_cache(true);
+        let swap = Swap::new(1, 2).unwrap();
+        let before = dqcmap_state.score_swap(swap, &[2, 1]).unwrap();
+        assert_eq!(before, 2.);
+
+        dqcmap_state.set_time_decay(2f64.ln()).unwrap();
+        // node 1 comes second, so its pairs count half
+        assert!((dqcmap_state.score_swap(swap, &[2, 1]).unwrap() - 1.).abs() < 1e-12);
+        assert_eq!(dqcmap_state.score_swap(swap, &[1, 2]).unwrap(), 2.);
+        assert!(dqcmap_state.set_time_decay(-1.).is_err());
+        assert!(dqcmap_state.set_time_decay(f64::INFINITY).is_err());
+
+        let restored = DqcMapState::from_bytes(&dqcmap_state.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.time_decay, 2f64.ln());
+    }
+
+    #[test]
+    fn test_dqcmapstate_criticality() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.enable_score_cache(true);
+        let swap = Swap::new(1, 2).unwrap();
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 2.);
+
+        // node 1 is on the critical path, node 5 has no pairs
+        dqcmap_state
+            .set_criticality(Some(HashMap::from([(1, 2.5), (5, 0.)])))
+            .unwrap();
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 5.);
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(5.));
+        assert!(dqcmap_state
+            .set_criticality(Some(HashMap::from([(1, -1.)])))
+            .is_err());
+
+        let restored = DqcMapState::from_bytes(&dqcmap_state.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.node_weight(1), 2.5);
+        assert_eq!(restored.node_weight(2), 1.);
+    }
+
+    #[test]
+    fn test_dqcmapstate_pair_weights() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let cif_pairs = dqcmap_state.cif_pairs.as_mut().unwrap();
+        // the feedback of the pair [0, 2] repeats 4 times
+        cif_pairs.set_weights(1, vec![4., 1.]).unwrap();
+        dqcmap_state.recount_cross_ctrl();
+        let swap = Swap::new(1, 2).unwrap();
+        assert_eq!(dqcmap_state.score_weighted(swap, &[1]).unwrap(), Some(5.));
+        assert_eq!(dqcmap_state.total_cross_ctrl_cost(), Some(5.));
+        assert_eq!(dqcmap_state.score(swap, &[1]).unwrap(), Some(5.));
+        assert_eq!(
+            dqcmap_state.score_sequence(&[(1, 2)], &[]).unwrap(),
+            Some(5.)
+        );
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_count").unwrap());
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 5.);
+        // a transfer batching both feedbacks repeats as often as the heaviest one
+        assert_eq!(dqcmap_state.score_batched(swap, &[1]).unwrap(), Some(4.));
+
+        // the weighted count follows swaps, undos and retired nodes
+        assert_eq!(dqcmap_state.current_cost(), Some(5.));
+        dqcmap_state.apply_swap(swap, &[]).unwrap();
+        assert_eq!(dqcmap_state.current_cost(), Some(0.));
+        assert_eq!(dqcmap_state.total_cross_ctrl_fb(), Some(0.));
+        dqcmap_state.undo_last().unwrap();
+        assert_eq!(dqcmap_state.current_cost(), Some(5.));
+        let mut retired = dqcmap_state.fork();
+        retired.retire_nodes(&[1, 1]);
+        assert_eq!(retired.current_cost(), Some(0.));
+
+        // weights stack with the expected executions of the node and survive checkpoints
+        let mut control_flow = ControlFlowModel::new();
+        let body = control_flow.add_while(2., None).unwrap();
+        control_flow.assign(1, Some(body)).unwrap();
+        dqcmap_state.control_flow = Some(control_flow);
+        let restored = DqcMapState::from_bytes(&dqcmap_state.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.score_weighted(swap, &[1]).unwrap(), Some(10.));
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_noise_aware() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        // without a noise model only the feedbacks count
+        assert_eq!(
+            dqcmap_state
+                .noise_cost(Swap::new(2, 3).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_noise_aware(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            2.
+        );
+
+        dqcmap_state.noise_model = Some(
+            NoiseModel::new(
+                HashMap::from([((2, 3), 0.5)]),
+                HashMap::from([(1, 0.25)]),
+                2.,
+            )
+            .unwrap(),
+        );
+        // feedback-neutral swap over a bad link
+        assert_eq!(
+            dqcmap_state
+                .score_noise_aware(Swap::new(2, 3).unwrap(), &[1])
+                .unwrap(),
+            -1.75
+        );
+        // moves the measured qubit 2 onto qubit 1, which has a worse readout
+        assert_eq!(
+            dqcmap_state
+                .score_noise_aware(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            1.5
+        );
+        // the readout term only applies to the pairs of the active nodes
+        assert_eq!(
+            dqcmap_state
+                .noise_cost(Swap::new(1, 2).unwrap(), &[])
+                .unwrap(),
+            0.
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_pareto_front() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.noise_model =
+            Some(NoiseModel::new(HashMap::from([((1, 2), 0.5)]), HashMap::new(), 1.).unwrap());
+        let candidates = [((1, 2), 0.), ((0, 1), 0.), ((2, 3), -1.), ((0, 3), 1.)];
+        let front = dqcmap_state.pareto_front(&candidates, &[1]).unwrap();
+        // (1, 2) removes two feedbacks over a noisy link, (2, 3) shortens the front layer and
+        // (0, 3) removes two feedbacks at the cost of distance, while (0, 1) is dominated by (2, 3)
+        assert_eq!(
+            front,
+            vec![
+                (0, [0., -2., 0.875]),
+                (2, [-1., 0., 0.]),
+                (3, [1., -2., 0.])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_crosstalk_cost() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        assert_eq!(
+            dqcmap_state
+                .crosstalk_cost(Swap::new(3, 4).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        dqcmap_state.crosstalk = Some(CrosstalkMap::new(vec![((4, 5), (5, 6))], 2.).unwrap());
+        // moves the measured qubit 3 next to the crosstalk-prone edge (4, 5)
+        assert_eq!(
+            dqcmap_state
+                .crosstalk_cost(Swap::new(3, 4).unwrap(), &[1])
+                .unwrap(),
+            2.
+        );
+        assert_eq!(
+            dqcmap_state
+                .score_noise_aware(Swap::new(3, 4).unwrap(), &[1])
+                .unwrap(),
+            -2.
+        );
+        // exchanging two qubits of cif pairs keeps their total exposure
+        assert_eq!(
+            dqcmap_state
+                .crosstalk_cost(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        assert_eq!(
+            dqcmap_state
+                .crosstalk_cost(Swap::new(3, 4).unwrap(), &[])
+                .unwrap(),
+            0.
+        );
+
+        let mut restored = DqcMapState::new(None, None);
+        restored.set_state(dqcmap_state.get_state()).unwrap();
+        assert_eq!(
+            restored
+                .crosstalk_cost(Swap::new(3, 4).unwrap(), &[1])
+                .unwrap(),
+            2.
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_bandwidth_cost() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.ctrl_topology =
+            Some(CtrlTopology::new(HashMap::new(), 1., HashMap::new(), None).unwrap());
+        assert_eq!(
+            dqcmap_state
+                .bandwidth_cost(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        // both pairs of node 1 cross from controller 1 to controller 2 at once
+        dqcmap_state.ctrl_topology =
+            Some(CtrlTopology::new(HashMap::new(), 1., HashMap::new(), Some(1)).unwrap());
+        assert_eq!(
+            dqcmap_state
+                .bandwidth_cost(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            -2.
+        );
+        assert_eq!(
+            dqcmap_state
+                .bandwidth_cost(Swap::new(0, 1).unwrap(), &[1])
+                .unwrap(),
+            0.
+        );
+        assert_eq!(
+            dqcmap_state
+                .bandwidth_cost(Swap::new(1, 2).unwrap(), &[])
+                .unwrap(),
+            0.
+        );
+        // the bandwidth gain adds to the latency-weighted gain of 2
+        assert_eq!(
+            dqcmap_state
+                .score_noise_aware(Swap::new(1, 2).unwrap(), &[1])
+                .unwrap(),
+            4.
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_cost_model() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.ctrl_topology = Some(
+            CtrlTopology::new(HashMap::from([((1, 2), 4.)]), 1., HashMap::new(), None).unwrap(),
+        );
+        dqcmap_state.noise_model =
+            Some(NoiseModel::new(HashMap::from([((1, 2), 0.5)]), HashMap::new(), 1.).unwrap());
+        let swap = Swap::new(1, 2).unwrap();
+
+        assert_eq!(dqcmap_state.cost_model().name(), "fidelity_weighted");
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 7.125);
+        dqcmap_state.set_cost_model(cost_model_by_name("latency_weighted").unwrap());
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 8.);
+        // broadcasts cost the default latency of the topology whatever the link
+        dqcmap_state.set_feedback_model("broadcast").unwrap();
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 2.);
+        assert!(dqcmap_state.set_feedback_model("multicast").is_err());
+        dqcmap_state.set_feedback_model("point_to_point").unwrap();
+        // both feedbacks go between controllers 1 and 2 in a single transfer
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_batched").unwrap());
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 4.);
+        dqcmap_state.set_cost_model(cost_model_by_name("feedback_count").unwrap());
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 2.);
+        // forks keep the model
+        assert_eq!(dqcmap_state.fork().cost_model().name(), "feedback_count");
+        #[cfg(feature = "python")]
+        assert!(DqcMapState::py_new(None, None, Some("distance"), None).is_err());
+    }
+
+    #[test]
+    fn test_dqcmapstate_set_ctrl2pq() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        dqcmap_state.enable_score_cache(true);
+        let swap = Swap::new(1, 2).unwrap();
+        assert_eq!(dqcmap_state.current_cost(), Some(2.));
+        dqcmap_state.apply_swap(swap, &[]).unwrap();
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), -2.);
+
+        // the pairs (0, 1) and (2, 3) are now split across controllers 1 and 2
+        dqcmap_state
+            .set_ctrl2pq(Ctrl2Pq::from_map(HashMap::from([
+                (1, vec![0, 2]),
+                (2, vec![1, 3]),
+            ])))
+            .unwrap();
+        assert_eq!(dqcmap_state.current_cost(), Some(2.));
+        assert_eq!(
+            dqcmap_state.current_cost(),
+            dqcmap_state.total_cross_ctrl_fb()
+        );
+        // cached scores of the previous mapping are dropped
+        assert_eq!(dqcmap_state.score_swap(swap, &[1]).unwrap(), 2.);
+        // the undo log survives the new mapping
+        assert_eq!(dqcmap_state.undo_last().unwrap(), Some([1, 2]));
+        assert_eq!(dqcmap_state.current_cost(), Some(0.));
+
+        // a mapping leaving qubit 3 of the pair (2, 3) without controller is rejected
+        let before = dqcmap_state.ctrl2pq.clone();
+        assert!(dqcmap_state
+            .set_ctrl2pq(Ctrl2Pq::from_map(HashMap::from([(1, vec![0, 1, 2])])))
+            .is_err());
+        assert_eq!(dqcmap_state.ctrl2pq, before);
+    }
+
+    #[test]
+    fn test_dqcmapstate_score_batched() {
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([
+            (0, vec![0, 1]),
+            (1, vec![2, 3]),
+            (2, vec![4, 5]),
+        ]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(0, vec![vec![0, 2], vec![1, 4]])]));
+        let dqcmap_state = DqcMapState::new(Some(ctrl2pq), Some(cif_pairs));
+        let swap = Swap::new(3, 4).unwrap();
+
+        // moving qubit 4 to controller 1 keeps a cross-controller feedback, only batching gains
+        assert_eq!(dqcmap_state.score_weighted(swap, &[0]).unwrap(), Some(0.));
+        assert_eq!(dqcmap_state.score_batched(swap, &[0]).unwrap(), Some(1.));
+        assert_eq!(dqcmap_state.score_batched(swap, &[]).unwrap(), Some(0.));
+        // or onto controllers 0 and 2
+        assert_eq!(
+            dqcmap_state
+                .score_batched(Swap::new(2, 5).unwrap(), &[0])
+                .unwrap(),
+            Some(1.)
+        );
+        // splitting batched feedbacks costs a transfer
+        let forked = dqcmap_state.with_swap(swap, &[]).unwrap();
+        assert_eq!(forked.score_batched(swap, &[0]).unwrap(), Some(-1.));
+        assert_eq!(
+            dqcmap_state
+                .score_batched(Swap::new(0, 1).unwrap(), &[0])
+                .unwrap(),
+            Some(0.)
+        );
+    }
+
+    #[test]
+    fn test_dqcmapstate_composite_score() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let swap = Swap::new(1, 2).unwrap();
+        assert_eq!(dqcmap_state.composite_score(swap, &[1], 1.).unwrap(), 0.8);
+
+        dqcmap_state.set_objective_weights(0.5, 2.).unwrap();
+        assert_eq!(dqcmap_state.composite_score(swap, &[1], 1.).unwrap(), -3.5);
+        assert_eq!(
+            dqcmap_state
+                .composite_score(Swap::new(2, 3).unwrap(), &[1], -2.)
+                .unwrap(),
+            -1.
+        );
+        assert!(dqcmap_state.set_objective_weights(-1., 1.).is_err());
+        assert!(dqcmap_state.set_objective_weights(1., f64::NAN).is_err());
+        assert_eq!(dqcmap_state.distance_weight, 0.5);
+    }
+
+    #[test]
+    fn test_dqcmapstate_apply_config() {
+        let mut dqcmap_state: DqcMapState = build_state();
+        let config = DqcMapConfig::new(
+            2.,
+            0.5,
+            3,
+            0.25,
+            "feedback_count",
+            "first",
+            None,
+            1,
+            1,
+            1.,
+            0.5,
+            "broadcast",
+        )
+        .unwrap();
+        dqcmap_state.apply_config(&config).unwrap();
+        assert_eq!(dqcmap_state.feedback_model, FeedbackModel::Broadcast);
+        assert_eq!(dqcmap_state.cost_model().name(), "feedback_count");
+        assert_eq!(
+            (dqcmap_state.lookahead_window, dqcmap_state.lookahead_decay),
+            (3, 0.25)
+        );
+        assert_eq!(dqcmap_state.time_decay, 0.5);
+        assert_eq!(
+            dqcmap_state
+                .composite_score(Swap::new(1, 2).unwrap(), &[1], 1.)
+                .unwrap(),
+            -3.5
+        );
     }
 }
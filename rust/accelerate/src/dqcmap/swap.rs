@@ -0,0 +1,159 @@
+use std::fmt;
+use std::ops::Deref;
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::error::MappingError;
+
+/// A swap of two distinct, non-negative physical qubits.  Constructing one is the only place
+/// swaps are validated, so everything taking a [Swap] can index both qubits without checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Swap([i32; 2]);
+
+impl Swap {
+    pub fn new(q0: i32, q1: i32) -> Result<Self, MappingError> {
+        if q0 < 0 || q1 < 0 || q0 == q1 {
+            return Err(MappingError::InvalidSwap(vec![q0, q1]));
+        }
+        Ok(Swap([q0, q1]))
+    }
+
+    /// The swap of the ends of a coupling edge.  Swaps are always between physical qubits, so
+    /// routing code builds them from [PhysicalQubit] rather than from raw indices.
+    ///
+    /// # Panics
+    ///
+    /// If the qubits are equal or do not fit an `i32`.
+    pub fn from_physical([q0, q1]: [PhysicalQubit; 2]) -> Self {
+        assert_ne!(q0, q1, "a swap needs two distinct qubits");
+        Swap([
+            i32::try_from(q0.index()).expect("qubit out of range"),
+            i32::try_from(q1.index()).expect("qubit out of range"),
+        ])
+    }
+
+    /// The qubits as physical qubits.
+    #[inline]
+    pub fn physical(self) -> [PhysicalQubit; 2] {
+        self.0.map(|qubit| PhysicalQubit::new(qubit as u32))
+    }
+
+    #[inline]
+    pub fn qubits(self) -> [i32; 2] {
+        self.0
+    }
+
+    /// The qubits with the smaller one first, so that both orders of a swap compare equal.
+    #[inline]
+    pub fn sorted(self) -> [i32; 2] {
+        [self.0[0].min(self.0[1]), self.0[0].max(self.0[1])]
+    }
+
+    /// Whether `qubit` is one of the two qubits of the swap.
+    #[inline]
+    pub fn applies_to(self, qubit: i32) -> bool {
+        qubit == self.0[0] || qubit == self.0[1]
+    }
+
+    /// The qubit `qubit` is exchanged with, `None` if the swap does not apply to it.
+    #[inline]
+    pub fn other(self, qubit: i32) -> Option<i32> {
+        if qubit == self.0[0] {
+            Some(self.0[1])
+        } else if qubit == self.0[1] {
+            Some(self.0[0])
+        } else {
+            None
+        }
+    }
+
+    /// Where `qubit` ends up once the swap is applied.
+    #[inline]
+    pub fn apply(self, qubit: i32) -> i32 {
+        self.other(qubit).unwrap_or(qubit)
+    }
+}
+
+impl Deref for Swap {
+    type Target = [i32; 2];
+
+    #[inline]
+    fn deref(&self) -> &[i32; 2] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[i32]> for Swap {
+    type Error = MappingError;
+
+    fn try_from(qubits: &[i32]) -> Result<Self, MappingError> {
+        match *qubits {
+            [q0, q1] => Swap::new(q0, q1),
+            _ => Err(MappingError::InvalidSwap(qubits.to_vec())),
+        }
+    }
+}
+
+impl TryFrom<(i32, i32)> for Swap {
+    type Error = MappingError;
+
+    fn try_from((q0, q1): (i32, i32)) -> Result<Self, MappingError> {
+        Swap::new(q0, q1)
+    }
+}
+
+impl fmt::Display for Swap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.0[0], self.0[1])
+    }
+}
+
+/// Swaps are passed from Python as any sequence of two qubits, malformed ones raise
+/// ``DqcMapError``.
+#[cfg(feature = "python")]
+impl<'py> FromPyObject<'py> for Swap {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let qubits: Vec<i32> = ob.extract()?;
+        Ok(Swap::try_from(qubits.as_slice())?)
+    }
+}
+
+#[cfg(feature = "python")]
+impl IntoPy<PyObject> for Swap {
+    fn into_py(self, py: Python) -> PyObject {
+        (self.0[0], self.0[1]).into_py(py)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap() {
+        let swap = Swap::new(3, 1).unwrap();
+        assert_eq!(swap.qubits(), [3, 1]);
+        assert_eq!(swap.sorted(), [1, 3]);
+        assert!(swap.applies_to(1) && !swap.applies_to(2));
+        assert_eq!(swap.other(3), Some(1));
+        assert_eq!(swap.other(2), None);
+        assert_eq!((swap.apply(1), swap.apply(2)), (3, 2));
+        assert_eq!(swap.to_string(), "(3, 1)");
+
+        assert_eq!(Swap::new(1, 1), Err(MappingError::InvalidSwap(vec![1, 1])));
+        assert_eq!(
+            Swap::new(-1, 2),
+            Err(MappingError::InvalidSwap(vec![-1, 2]))
+        );
+        assert_eq!(
+            Swap::try_from(&[0, 1, 2][..]),
+            Err(MappingError::InvalidSwap(vec![0, 1, 2]))
+        );
+        let physical = [PhysicalQubit::new(0), PhysicalQubit::new(1)];
+        assert_eq!(Swap::try_from((0, 1)), Ok(Swap::from_physical(physical)));
+        assert_eq!(Swap::from_physical(physical).physical(), physical);
+    }
+}
@@ -0,0 +1,394 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rustworkx_core::petgraph::graph::NodeIndex;
+
+use crate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+use super::{
+    config::DqcMapConfig,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    dag::{CircuitDag, Instruction},
+    front_layer::FrontLayer,
+    router::{route_instruction_list, RouterOutput},
+};
+
+/// The number of swaps removed by [cancel_swaps].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapCancellation {
+    /// Swaps removed together with an inverse swap right before them.
+    pub num_cancelled: usize,
+    /// Swaps removed by exchanging the qubits of the single-qubit instructions after them.
+    pub num_absorbed: usize,
+}
+
+#[inline]
+fn is_swap(instruction: &Instruction) -> bool {
+    instruction.0 == "swap" && instruction.1.len() == 2
+}
+
+/// Remove the redundant swaps of a routed instruction list on physical qubits, updating
+/// `permutation`, the physical qubit of every virtual qubit at the end of the circuit.
+///
+/// A swap directly following a swap on the same qubits, with no instruction on either qubit in
+/// between, cancels it and both are removed.  A swap followed only by single-qubit instructions
+/// on both of its qubits is absorbed into them by exchanging their qubits, which only moves
+/// where the qubits end up.  With `ctrl2pq`, only swaps within a controller are absorbed, so
+/// that no feedback moves to another controller.
+pub fn cancel_swaps(
+    instructions: &mut Vec<Instruction>,
+    permutation: &mut [PhysicalQubit],
+    ctrl2pq: Option<&Ctrl2Pq>,
+) -> SwapCancellation {
+    let mut stats = SwapCancellation::default();
+    let num_qubits = instructions
+        .iter()
+        .flat_map(|(_, qubits, _, _)| qubits.iter())
+        .max()
+        .map_or(0, |qubit| qubit + 1);
+    let mut removed = vec![false; instructions.len()];
+
+    // the instructions kept so far on every qubit
+    let mut on_qubit: Vec<Vec<usize>> = vec![Vec::new(); num_qubits];
+    for index in 0..instructions.len() {
+        let qubits = &instructions[index].1;
+        if is_swap(&instructions[index]) {
+            let [a, b] = [qubits[0], qubits[1]];
+            if let (Some(&last_a), Some(&last_b)) = (on_qubit[a].last(), on_qubit[b].last()) {
+                if last_a == last_b && is_swap(&instructions[last_a]) {
+                    removed[last_a] = true;
+                    removed[index] = true;
+                    on_qubit[a].pop();
+                    on_qubit[b].pop();
+                    stats.num_cancelled += 2;
+                    continue;
+                }
+            }
+        }
+        for &qubit in qubits {
+            on_qubit[qubit].push(index);
+        }
+    }
+
+    // the kept instructions after the current one on every qubit, and whether one of them acts
+    // on several qubits
+    let mut after: Vec<Vec<usize>> = vec![Vec::new(); num_qubits];
+    let mut multi_qubit_after = vec![false; num_qubits];
+    for index in (0..instructions.len()).rev() {
+        if removed[index] {
+            continue;
+        }
+        let qubits = instructions[index].1.clone();
+        if is_swap(&instructions[index]) {
+            let [a, b] = [qubits[0], qubits[1]];
+            let same_controller = ctrl2pq.map_or(true, |ctrl2pq| {
+                ctrl2pq.controller_of(PhysicalQubit::new(a as u32))
+                    == ctrl2pq.controller_of(PhysicalQubit::new(b as u32))
+            });
+            if same_controller && !multi_qubit_after[a] && !multi_qubit_after[b] {
+                for &later in &after[a] {
+                    instructions[later].1[0] = b;
+                }
+                for &later in &after[b] {
+                    instructions[later].1[0] = a;
+                }
+                after.swap(a, b);
+                for physical in permutation.iter_mut() {
+                    if physical.index() == a {
+                        *physical = PhysicalQubit::new(b as u32);
+                    } else if physical.index() == b {
+                        *physical = PhysicalQubit::new(a as u32);
+                    }
+                }
+                removed[index] = true;
+                stats.num_absorbed += 1;
+                continue;
+            }
+        }
+        for &qubit in &qubits {
+            after[qubit].push(index);
+            multi_qubit_after[qubit] |= qubits.len() > 1;
+        }
+    }
+
+    let mut index = 0;
+    instructions.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+    stats
+}
+
+/// The instructions of `dag` on physical qubits, in the order they were routed in `output`, with
+/// the swaps of `output` inserted before the gates they precede.  Instructions that are not
+/// routed, such as directives, are placed as soon as all their predecessors are.
+pub fn routed_instructions(
+    dag: &CircuitDag,
+    initial_layout: &NLayout,
+    output: &RouterOutput,
+) -> Vec<Instruction> {
+    let mut front_layer = FrontLayer::from_dag(dag);
+    let mut layout = initial_layout.clone();
+    let mut instructions = Vec::with_capacity(dag.dag.node_count() + output.swaps.len());
+    let mut swaps = output.swaps.iter().peekable();
+    let emit = |instructions: &mut Vec<Instruction>, layout: &NLayout, node: usize| {
+        let node = &dag.dag[NodeIndex::new(node)];
+        let qubits = node
+            .qubits
+            .iter()
+            .map(|&qubit| VirtualQubit::new(qubit as u32).to_phys(layout).index())
+            .collect();
+        instructions.push((
+            node.name.clone(),
+            qubits,
+            node.clbits.clone(),
+            node.condition.clone(),
+        ));
+    };
+    let retire_unrouted =
+        |front_layer: &mut FrontLayer, instructions: &mut Vec<Instruction>, layout: &NLayout| {
+            while let Some(&node) = front_layer
+                .nodes()
+                .iter()
+                .find(|&&node| !dag.is_router_gate(NodeIndex::new(node)))
+            {
+                emit(instructions, layout, node);
+                front_layer.retire(node);
+            }
+        };
+    for &node in &output.gate_order {
+        retire_unrouted(&mut front_layer, &mut instructions, &layout);
+        while let Some((_, [p0, p1])) = swaps.next_if(|(swap_node, _)| *swap_node == node) {
+            layout.swap_physical(*p0, *p1);
+            instructions.push((
+                "swap".to_string(),
+                vec![p0.index(), p1.index()],
+                Vec::new(),
+                None,
+            ));
+        }
+        emit(&mut instructions, &layout, node);
+        front_layer.retire(node);
+    }
+    retire_unrouted(&mut front_layer, &mut instructions, &layout);
+    instructions
+}
+
+/// Remove the redundant swaps of a routed circuit: pairs of back-to-back swaps on the same
+/// qubits cancel, and swaps followed only by single-qubit instructions on both of their qubits
+/// are absorbed into these instructions by exchanging their qubits.
+///
+/// Args:
+///     instructions (list[tuple[str, list[int], list[int], list[int] | None]]): The routed
+///         instructions on physical qubits, swaps are named ``"swap"``.
+///     permutation (list[int]): The physical qubit of every virtual qubit at the end of the
+///         circuit.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits, restricting
+///         the absorbed swaps to the ones within a controller if given.
+///
+/// Returns:
+///     (instructions, permutation, num_cancelled, num_absorbed): The instructions without the
+///     redundant swaps, the updated permutation, and the numbers of cancelled and absorbed
+///     swaps.
+#[pyfunction]
+#[pyo3(name = "cancel_swaps", signature = (instructions, permutation, ctrl2pq=None))]
+pub fn py_cancel_swaps(
+    py: Python,
+    mut instructions: Vec<Instruction>,
+    mut permutation: Vec<PhysicalQubit>,
+    ctrl2pq: Option<Ctrl2Pq>,
+) -> PyResult<(Vec<Instruction>, Vec<PhysicalQubit>, usize, usize)> {
+    if let Some(instruction) = instructions
+        .iter()
+        .find(|instruction| instruction.0 == "swap" && instruction.1.len() != 2)
+    {
+        return Err(PyValueError::new_err(format!(
+            "a swap acts on two qubits, got {:?}",
+            instruction.1
+        )));
+    }
+    let stats =
+        py.allow_threads(|| cancel_swaps(&mut instructions, &mut permutation, ctrl2pq.as_ref()));
+    Ok((
+        instructions,
+        permutation,
+        stats.num_cancelled,
+        stats.num_absorbed,
+    ))
+}
+
+/// Route the instructions of a circuit like ``route``, but return the routed instruction list on
+/// physical qubits with the swaps inserted, after removing the redundant swaps with
+/// ``cancel_swaps``, where only swaps within a controller are absorbed.  The GIL is released
+/// while routing.
+///
+/// Args:
+///     dag_instructions (list[tuple[str, list[int], list[int], list[int] | None]]): The name,
+///         virtual qubits, clbits and condition clbits of every instruction in topological
+///         order.
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     initial_layout (NLayout): The initial virtual to physical qubit layout.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     seed (int): The seed of the RNG breaking ties between equally scored swaps.
+///     config (DqcMapConfig): The router configuration, replacing ``seed`` if given.
+///     commutation (bool): Whether commuting gates can be routed in any order.
+///     cancel (bool): Whether to remove the redundant swaps.
+///
+/// Returns:
+///     (instructions, permutation, stats): The routed instructions, the physical qubit of every
+///     virtual qubit at the end of the circuit, and a dict of ``num_swaps``,
+///     ``num_cancelled_swaps``, ``num_absorbed_swaps``, ``initial_cross_controller_feedbacks``
+///     and ``cross_controller_feedbacks``, where ``num_swaps`` counts the remaining swaps.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (dag_instructions, coupling_map, initial_layout, ctrl2pq, seed=None, config=None, commutation=false, cancel=true))]
+pub fn route_instructions(
+    py: Python,
+    dag_instructions: Vec<Instruction>,
+    coupling_map: &CouplingMap,
+    initial_layout: &NLayout,
+    ctrl2pq: &Ctrl2Pq,
+    seed: Option<u64>,
+    config: Option<DqcMapConfig>,
+    commutation: bool,
+    cancel: bool,
+) -> PyResult<(Vec<Instruction>, Vec<PhysicalQubit>, Py<PyDict>)> {
+    let (dag, output, initial_cost) = route_instruction_list(
+        py,
+        dag_instructions,
+        coupling_map,
+        initial_layout,
+        ctrl2pq,
+        seed,
+        config,
+        None,
+        commutation,
+    )?;
+    let mut permutation: Vec<PhysicalQubit> = output
+        .final_layout
+        .iter_virtual()
+        .map(|(_, physical)| physical)
+        .collect();
+    let (instructions, cancellation) = py.allow_threads(|| {
+        let mut instructions = routed_instructions(&dag, initial_layout, &output);
+        let cancellation = if cancel {
+            cancel_swaps(&mut instructions, &mut permutation, Some(ctrl2pq))
+        } else {
+            SwapCancellation::default()
+        };
+        (instructions, cancellation)
+    });
+    let stats = PyDict::new_bound(py);
+    stats.set_item(
+        "num_swaps",
+        output.swaps.len() - cancellation.num_cancelled - cancellation.num_absorbed,
+    )?;
+    stats.set_item("num_cancelled_swaps", cancellation.num_cancelled)?;
+    stats.set_item("num_absorbed_swaps", cancellation.num_absorbed)?;
+    stats.set_item("initial_cross_controller_feedbacks", initial_cost)?;
+    stats.set_item("cross_controller_feedbacks", output.cross_ctrl_feedbacks)?;
+    Ok((instructions, permutation, stats.unbind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dqcmap::router::{route_dag, RouteOptions};
+    use crate::dqcmap::state::DqcMapState;
+
+    fn instruction(name: &str, qubits: &[usize], clbits: &[usize]) -> Instruction {
+        (name.to_string(), qubits.to_vec(), clbits.to_vec(), None)
+    }
+
+    #[test]
+    fn test_cancel_swaps() {
+        let q = PhysicalQubit::new;
+        let mut instructions = vec![
+            instruction("swap", &[0, 1], &[]),
+            instruction("swap", &[1, 0], &[]),
+            instruction("cx", &[1, 2], &[]),
+            instruction("swap", &[1, 2], &[]),
+            instruction("h", &[2], &[]),
+            instruction("measure", &[1], &[0]),
+            instruction("swap", &[0, 3], &[]),
+            instruction("cx", &[0, 3], &[]),
+        ];
+        let mut permutation = vec![q(0), q(2), q(1), q(3)];
+        let stats = cancel_swaps(&mut instructions, &mut permutation, None);
+        assert_eq!(
+            stats,
+            SwapCancellation {
+                num_cancelled: 2,
+                num_absorbed: 1
+            }
+        );
+        // the swap on 1 and 2 is followed by single-qubit instructions only, the last one by a cx
+        assert_eq!(
+            instructions,
+            vec![
+                instruction("cx", &[1, 2], &[]),
+                instruction("h", &[1], &[]),
+                instruction("measure", &[2], &[0]),
+                instruction("swap", &[0, 3], &[]),
+                instruction("cx", &[0, 3], &[]),
+            ]
+        );
+        assert_eq!(permutation, vec![q(0), q(1), q(2), q(3)]);
+
+        // 1 and 2 are on different controllers
+        let ctrl2pq = Ctrl2Pq::from_map(hashbrown::HashMap::from([(0, vec![0, 1]), (1, vec![2])]));
+        let mut instructions = vec![
+            instruction("swap", &[1, 2], &[]),
+            instruction("measure", &[1], &[0]),
+        ];
+        let stats = cancel_swaps(&mut instructions, &mut permutation, Some(&ctrl2pq));
+        assert_eq!(stats, SwapCancellation::default());
+        assert_eq!(instructions.len(), 2);
+    }
+
+    #[test]
+    fn test_routed_instructions() {
+        let dag = CircuitDag::new(
+            3,
+            0,
+            vec![
+                instruction("h", &[0], &[]),
+                instruction("barrier", &[0, 1, 2], &[]),
+                instruction("cx", &[0, 2], &[]),
+            ],
+            false,
+        )
+        .unwrap();
+        let coupling = CouplingMap::new(
+            vec![
+                [PhysicalQubit::new(0), PhysicalQubit::new(1)],
+                [PhysicalQubit::new(1), PhysicalQubit::new(2)],
+            ],
+            None,
+        )
+        .unwrap();
+        let layout = NLayout::generate_trivial_layout(3);
+        let output = route_dag(
+            &coupling,
+            &layout,
+            &dag,
+            DqcMapState::new(None, None),
+            RouteOptions::default(),
+        )
+        .unwrap();
+        let instructions = routed_instructions(&dag, &layout, &output);
+        assert_eq!(instructions.len(), 4);
+        assert_eq!(instructions[0], instruction("h", &[0], &[]));
+        assert_eq!(instructions[1], instruction("barrier", &[0, 1, 2], &[]));
+        assert_eq!(instructions[2].0, "swap");
+        let (_, qubits, _, _) = &instructions[3];
+        assert_eq!(
+            coupling.distance(
+                PhysicalQubit::new(qubits[0] as u32),
+                PhysicalQubit::new(qubits[1] as u32)
+            ),
+            1
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::nlayout::{NLayout, PhysicalQubit};
+
+use super::{coupling_map::CouplingMap, ctrl_to_pq::Ctrl2Pq};
+
+/// The approximate token swapping of Miltzow et al., "Approximation and hardness for token
+/// swapping" (2016), which uses at most four times the minimum number of swaps.
+///
+/// Every physical qubit holds a token that must reach its destination.  Swaps that bring both
+/// tokens closer to their destinations are taken first, then rotations along cycles of tokens
+/// that all want to move one step forward, and otherwise a swap moving a token that is already
+/// home out of the way.  Among equally good swaps, those between qubits of the same controller
+/// are preferred.
+struct TokenSwapper<'a> {
+    coupling: &'a CouplingMap,
+    ctrl2pq: Option<&'a Ctrl2Pq>,
+    /// Destination of the token currently on every physical qubit.
+    destination: Vec<PhysicalQubit>,
+    swaps: Vec<[PhysicalQubit; 2]>,
+}
+
+impl<'a> TokenSwapper<'a> {
+    #[inline]
+    fn is_home(&self, qubit: PhysicalQubit) -> bool {
+        self.destination[qubit.index()] == qubit
+    }
+
+    #[inline]
+    fn is_intra_controller(&self, a: PhysicalQubit, b: PhysicalQubit) -> bool {
+        self.ctrl2pq.is_some_and(|ctrl2pq| {
+            let ctrl = ctrl2pq.controller_of(a);
+            ctrl.is_some() && ctrl == ctrl2pq.controller_of(b)
+        })
+    }
+
+    /// Whether the token on `from` gets closer to its destination by moving to `to`.
+    #[inline]
+    fn wants(&self, from: PhysicalQubit, to: PhysicalQubit) -> bool {
+        let destination = self.destination[from.index()];
+        self.coupling.distance(to, destination) < self.coupling.distance(from, destination)
+    }
+
+    /// The neighbor the token on `qubit` wants to move to, preferably on the same controller,
+    /// `None` if the token is home.
+    fn desired_move(&self, qubit: PhysicalQubit) -> Option<PhysicalQubit> {
+        let mut desired = self
+            .coupling
+            .neighbors(qubit)
+            .iter()
+            .copied()
+            .filter(|&neighbor| self.wants(qubit, neighbor));
+        let first = desired.next()?;
+        Some(
+            std::iter::once(first)
+                .chain(desired)
+                .find(|&neighbor| self.is_intra_controller(qubit, neighbor))
+                .unwrap_or(first),
+        )
+    }
+
+    fn swap(&mut self, a: PhysicalQubit, b: PhysicalQubit) {
+        self.destination.swap(a.index(), b.index());
+        self.swaps.push(if a < b { [a, b] } else { [b, a] });
+    }
+
+    /// A coupling edge whose tokens both want to cross it, preferably within a controller.
+    fn happy_swap(&self) -> Option<[PhysicalQubit; 2]> {
+        let mut happy = self
+            .coupling
+            .edges()
+            .iter()
+            .copied()
+            .filter(|&[a, b]| self.wants(a, b) && self.wants(b, a));
+        let first = happy.next()?;
+        Some(
+            std::iter::once(first)
+                .chain(happy)
+                .find(|&[a, b]| self.is_intra_controller(a, b))
+                .unwrap_or(first),
+        )
+    }
+
+    /// Follow the desired moves from the token on `start`, which is not home, until they close a
+    /// cycle, which is rotated, or reach a token that is home, which is swapped backwards.
+    fn advance_from(&mut self, start: PhysicalQubit) {
+        let mut path = Vec::new();
+        let mut position = vec![None; self.destination.len()];
+        let mut current = start;
+        loop {
+            if let Some(index) = position[current.index()] {
+                let cycle: Vec<PhysicalQubit> = path[index..].to_vec();
+                for k in (0..cycle.len() - 1).rev() {
+                    self.swap(cycle[k], cycle[k + 1]);
+                }
+                return;
+            }
+            position[current.index()] = Some(path.len());
+            path.push(current);
+            match self.desired_move(current) {
+                Some(next) => current = next,
+                None => {
+                    // `start` is not home, so the path has at least two qubits
+                    let [.., last_moving, home] = path[..] else {
+                        unreachable!()
+                    };
+                    self.swap(last_moving, home);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        loop {
+            if let Some([a, b]) = self.happy_swap() {
+                self.swap(a, b);
+                continue;
+            }
+            let misplaced = (0..self.destination.len())
+                .map(|q| PhysicalQubit::new(q as u32))
+                .find(|&q| !self.is_home(q));
+            match misplaced {
+                Some(start) => self.advance_from(start),
+                None => return,
+            }
+        }
+    }
+}
+
+/// Swaps on coupling edges moving the state of every physical qubit `p` to `permutation[p]`,
+/// close to the fewest possible and preferably within the controllers of `ctrl2pq`.
+pub fn token_swap(
+    coupling: &CouplingMap,
+    permutation: &[PhysicalQubit],
+    ctrl2pq: Option<&Ctrl2Pq>,
+) -> PyResult<Vec<[PhysicalQubit; 2]>> {
+    let num_qubits = coupling.num_qubits();
+    if permutation.len() != num_qubits {
+        return Err(PyValueError::new_err(format!(
+            "the permutation has {} qubits but the coupling map has {}",
+            permutation.len(),
+            num_qubits
+        )));
+    }
+    let mut is_target = vec![false; num_qubits];
+    for &target in permutation {
+        if target.index() >= num_qubits || is_target[target.index()] {
+            return Err(PyValueError::new_err(format!(
+                "{:?} is not a permutation of {} qubits",
+                permutation, num_qubits
+            )));
+        }
+        is_target[target.index()] = true;
+    }
+    for (qubit, &target) in permutation.iter().enumerate() {
+        if coupling.distance(PhysicalQubit::new(qubit as u32), target) == usize::MAX {
+            return Err(PyValueError::new_err(format!(
+                "qubit {} cannot reach qubit {} in the coupling map",
+                qubit,
+                target.index()
+            )));
+        }
+    }
+    let mut swapper = TokenSwapper {
+        coupling,
+        ctrl2pq,
+        destination: permutation.to_vec(),
+        swaps: Vec::new(),
+    };
+    swapper.run();
+    Ok(swapper.swaps)
+}
+
+/// The permutation of physical qubits taking every virtual qubit from its physical qubit in
+/// `current` to the one in `target`.
+pub fn layout_permutation(current: &NLayout, target: &NLayout) -> Vec<PhysicalQubit> {
+    current
+        .iter_physical()
+        .map(|(_, virt)| virt.to_phys(target))
+        .collect()
+}
+
+/// Compute a swap network realizing a permutation of the physical qubits, with approximate token
+/// swapping.  The number of swaps is at most four times the minimum, and swaps within a
+/// controller are preferred among equally good ones.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     permutation (list[int]): The physical qubit every physical qubit's state must move to.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.  No swap is
+///         preferred if not given.
+///
+/// Returns:
+///     list[tuple[int, int]]: The swaps, in order.
+///
+/// Raises:
+///     ValueError: if ``permutation`` is not a permutation of the qubits of the coupling map or
+///         moves a state between disconnected qubits.
+#[pyfunction]
+#[pyo3(name = "token_swap", signature = (coupling_map, permutation, ctrl2pq=None))]
+pub fn py_token_swap(
+    py: Python,
+    coupling_map: &CouplingMap,
+    permutation: Vec<PhysicalQubit>,
+    ctrl2pq: Option<Ctrl2Pq>,
+) -> PyResult<Vec<[PhysicalQubit; 2]>> {
+    py.allow_threads(|| token_swap(coupling_map, &permutation, ctrl2pq.as_ref()))
+}
+
+/// Compute the swaps moving every virtual qubit from its physical qubit in ``current_layout``
+/// to the one in ``target_layout``, for example to undo the permutation introduced by routing,
+/// see ``token_swap``.
+///
+/// Args:
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     current_layout (NLayout): The layout after routing.
+///     target_layout (NLayout): The layout to reach, usually the initial one.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.  No swap is
+///         preferred if not given.
+///
+/// Returns:
+///     list[tuple[int, int]]: The swaps, in order.
+#[pyfunction]
+#[pyo3(signature = (coupling_map, current_layout, target_layout, ctrl2pq=None))]
+pub fn restore_layout(
+    py: Python,
+    coupling_map: &CouplingMap,
+    current_layout: &NLayout,
+    target_layout: &NLayout,
+    ctrl2pq: Option<Ctrl2Pq>,
+) -> PyResult<Vec<[PhysicalQubit; 2]>> {
+    let permutation = layout_permutation(current_layout, target_layout);
+    py.allow_threads(|| token_swap(coupling_map, &permutation, ctrl2pq.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashbrown::HashMap;
+    use rand::prelude::*;
+    use rand_pcg::Pcg64Mcg;
+
+    fn grid(rows: u32, cols: u32) -> CouplingMap {
+        let q = |r: u32, c: u32| PhysicalQubit::new(r * cols + c);
+        let mut edges = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                if c + 1 < cols {
+                    edges.push([q(r, c), q(r, c + 1)]);
+                }
+                if r + 1 < rows {
+                    edges.push([q(r, c), q(r + 1, c)]);
+                }
+            }
+        }
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    fn apply(permutation: &[PhysicalQubit], swaps: &[[PhysicalQubit; 2]]) -> Vec<PhysicalQubit> {
+        // the state on every qubit, which must end up on its own qubit
+        let mut state: Vec<PhysicalQubit> = (0..permutation.len() as u32)
+            .map(PhysicalQubit::new)
+            .collect();
+        for &[a, b] in swaps {
+            state.swap(a.index(), b.index());
+        }
+        let mut reached = vec![PhysicalQubit::new(0); permutation.len()];
+        for (qubit, origin) in state.iter().enumerate() {
+            reached[origin.index()] = PhysicalQubit::new(qubit as u32);
+        }
+        reached
+    }
+
+    #[test]
+    fn test_token_swap_random_permutations() {
+        let mut rng = Pcg64Mcg::seed_from_u64(7);
+        for (rows, cols) in [(1, 6), (3, 3), (3, 4)] {
+            let coupling = grid(rows, cols);
+            for _ in 0..20 {
+                let mut permutation: Vec<PhysicalQubit> =
+                    (0..rows * cols).map(PhysicalQubit::new).collect();
+                permutation.shuffle(&mut rng);
+                let swaps = token_swap(&coupling, &permutation, None).unwrap();
+                assert!(swaps.iter().all(|&[a, b]| coupling.is_adjacent(a, b)));
+                assert_eq!(apply(&permutation, &swaps), permutation);
+                // every swap moves two tokens by one step
+                let total_distance: usize = permutation
+                    .iter()
+                    .enumerate()
+                    .map(|(q, &t)| coupling.distance(PhysicalQubit::new(q as u32), t))
+                    .sum();
+                assert!(swaps.len() * 2 >= total_distance);
+                assert!(swaps.len() <= 2 * total_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_token_swap_prefers_intra_controller() {
+        // a 4-cycle where the states of 0 and 2 are exchanged, through 1 or through 3
+        let coupling = CouplingMap::new(
+            [[0, 1], [1, 2], [2, 3], [3, 0]]
+                .iter()
+                .map(|&[a, b]| [PhysicalQubit::new(a), PhysicalQubit::new(b)])
+                .collect(),
+            None,
+        )
+        .unwrap();
+        let permutation: Vec<PhysicalQubit> =
+            [2, 1, 0, 3].into_iter().map(PhysicalQubit::new).collect();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 3, 2]), (1, vec![1])]));
+        let swaps = token_swap(&coupling, &permutation, Some(&ctrl2pq)).unwrap();
+        assert_eq!(apply(&permutation, &swaps), permutation);
+        assert_eq!(swaps.len(), 3);
+        assert!(swaps.iter().all(|&[a, b]| a.index() != 1 && b.index() != 1));
+
+        assert!(token_swap(&coupling, &permutation[..3], None).is_err());
+        let not_permutation: Vec<PhysicalQubit> =
+            [0, 0, 1, 2].into_iter().map(PhysicalQubit::new).collect();
+        assert!(token_swap(&coupling, &not_permutation, None).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::{PyDict, PyList};
+use serde_json::{json, Value};
+
+use crate::nlayout::PhysicalQubit;
+
+use super::error::MappingError;
+
+/// A candidate swap considered by the router and the terms of its score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandidateTrace {
+    pub swap: [PhysicalQubit; 2],
+    /// Change in (decayed) distance of the front layer gates.
+    pub distance_delta: f64,
+    /// Controller locality of the swap as given by `DqcMapState::score_swap`, higher is better.
+    pub feedback_score: f64,
+    /// The composite score minimized by the router.
+    pub score: f64,
+}
+
+/// A single swap selection of the router.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceStep {
+    /// Node ids of the gates of the front layer.
+    pub front_layer: Vec<usize>,
+    pub candidates: Vec<CandidateTrace>,
+    /// The selected swap, `None` if there was no candidate.
+    pub chosen: Option<[PhysicalQubit; 2]>,
+}
+
+/// The swap selections of a routing run, recorded when a ``RoutingTrace`` is passed as ``trace``
+/// to ``dqcmap_routing`` or ``route``, so that the reasons behind a bad swap can be inspected.
+///
+/// Every step lists the node ids of the front layer, every candidate swap with its change in
+/// distance of the front layer (``distance_delta``), its controller locality score
+/// (``feedback_score``, higher is better) and the composite ``score`` minimized by the router,
+/// and the ``chosen`` swap.  Swaps forced along a shortest path when the search gets stuck are
+/// not recorded.
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.dqcmap"))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoutingTrace {
+    pub steps: Vec<TraceStep>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RoutingTrace {
+    #[new]
+    fn py_new() -> Self {
+        RoutingTrace::new()
+    }
+
+    /// Remove all recorded steps.
+    #[pyo3(name = "clear", text_signature = "(self, /)")]
+    fn py_clear(&mut self) {
+        self.clear();
+    }
+
+    /// The steps as a list of dicts with the keys ``front_layer``, ``candidates`` and
+    /// ``chosen``, every candidate being a dict with the keys ``swap``, ``distance_delta``,
+    /// ``feedback_score`` and ``score``.
+    #[pyo3(text_signature = "(self, /)")]
+    fn to_list<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let steps = PyList::empty_bound(py);
+        for step in &self.steps {
+            let candidates = PyList::empty_bound(py);
+            for candidate in &step.candidates {
+                let item = PyDict::new_bound(py);
+                let [a, b] = candidate.swap;
+                item.set_item("swap", (a, b))?;
+                item.set_item("distance_delta", candidate.distance_delta)?;
+                item.set_item("feedback_score", candidate.feedback_score)?;
+                item.set_item("score", candidate.score)?;
+                candidates.append(item)?;
+            }
+            let item = PyDict::new_bound(py);
+            item.set_item("front_layer", step.front_layer.clone())?;
+            item.set_item("candidates", candidates)?;
+            item.set_item("chosen", step.chosen.map(|[a, b]| (a, b)))?;
+            steps.append(item)?;
+        }
+        Ok(steps)
+    }
+
+    /// Serialize the steps to a JSON array with the layout of ``to_list``.
+    #[pyo3(name = "to_json", text_signature = "(self, /)")]
+    fn py_to_json(&self) -> PyResult<String> {
+        Ok(self.to_json()?)
+    }
+
+    fn __len__(&self) -> usize {
+        self.steps.len()
+    }
+}
+
+impl RoutingTrace {
+    pub fn new() -> Self {
+        RoutingTrace::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    pub fn to_json(&self) -> Result<String, MappingError> {
+        serde_json::to_string(&self.to_value()).map_err(|err| MappingError::Value(err.to_string()))
+    }
+
+    pub fn to_value(&self) -> Value {
+        let swap = |[a, b]: [PhysicalQubit; 2]| json!([a.index(), b.index()]);
+        Value::Array(
+            self.steps
+                .iter()
+                .map(|step| {
+                    let candidates: Vec<Value> = step
+                        .candidates
+                        .iter()
+                        .map(|candidate| {
+                            json!({
+                                "swap": swap(candidate.swap),
+                                "distance_delta": candidate.distance_delta,
+                                "feedback_score": candidate.feedback_score,
+                                "score": candidate.score,
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "front_layer": step.front_layer,
+                        "candidates": candidates,
+                        "chosen": step.chosen.map(swap),
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routing_trace_to_json() {
+        let q = PhysicalQubit::new;
+        let trace = RoutingTrace {
+            steps: vec![TraceStep {
+                front_layer: vec![3],
+                candidates: vec![CandidateTrace {
+                    swap: [q(0), q(1)],
+                    distance_delta: -1.,
+                    feedback_score: 0.5,
+                    score: -1.05,
+                }],
+                chosen: Some([q(0), q(1)]),
+            }],
+        };
+        let value: Value = serde_json::from_str(&trace.to_json().unwrap()).unwrap();
+        assert_eq!(value[0]["front_layer"], json!([3]));
+        assert_eq!(value[0]["candidates"][0]["swap"], json!([0, 1]));
+        assert_eq!(value[0]["candidates"][0]["score"], json!(-1.05));
+        assert_eq!(value[0]["chosen"], json!([0, 1]));
+    }
+}
@@ -0,0 +1,351 @@
+use hashbrown::HashMap;
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+
+use crate::nlayout::PhysicalQubit;
+
+use super::{
+    cif_pairs::CifPairs,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    layout::{controller_aware_layout, feedback_weights},
+    metrics::{self, Phase},
+};
+
+/// Default number of search states explored before giving up on finding a better embedding.
+const DEFAULT_CALL_LIMIT: usize = 100_000;
+
+/// State of the backtracking search for an embedding of the interaction graph.
+struct EmbeddingSearch<'a> {
+    coupling: &'a CouplingMap,
+    ctrl2pq: &'a Ctrl2Pq,
+    /// Logical qubits every logical qubit interacts with.
+    interactions: Vec<Vec<usize>>,
+    /// Number of feedbacks between every two logical qubits.
+    weights: Vec<HashMap<usize, usize>>,
+    /// Order in which the logical qubits are placed.
+    order: Vec<usize>,
+    /// Physical qubits connected to a controller, in ascending order.
+    usable: Vec<i32>,
+    /// Number of qubits that can still be placed on every controller.
+    free: HashMap<i32, usize>,
+    mapping: Vec<Option<i32>>,
+    used: Vec<bool>,
+    calls: usize,
+    call_limit: usize,
+    /// Cross-controller feedbacks and mapping of the best complete embedding so far.
+    best: Option<(usize, Vec<i32>)>,
+}
+
+impl<'a> EmbeddingSearch<'a> {
+    /// Logical qubits in the order they are placed: the busiest qubit first, then always the one
+    /// with most interactions towards the already ordered ones, so that the candidates of every
+    /// qubit after the first are restricted to the neighbors of a placed qubit.
+    fn placement_order(
+        interactions: &[Vec<usize>],
+        weights: &[HashMap<usize, usize>],
+    ) -> Vec<usize> {
+        let num_logical = interactions.len();
+        let mut ordered = vec![false; num_logical];
+        let mut order = Vec::with_capacity(num_logical);
+        while order.len() < num_logical {
+            let next = (0..num_logical)
+                .filter(|&q| !ordered[q])
+                .max_by_key(|&q| {
+                    (
+                        interactions[q].iter().filter(|&&n| ordered[n]).count(),
+                        interactions[q].len(),
+                        weights[q].values().sum::<usize>(),
+                        usize::MAX - q,
+                    )
+                })
+                .unwrap();
+            ordered[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    /// Number of feedbacks of `logical` towards placed qubits on another controller than `phys`.
+    fn cost_of(&self, logical: usize, phys: i32) -> usize {
+        let ctrl = self.ctrl2pq.get_controller_by_qubit(phys);
+        self.weights[logical]
+            .iter()
+            .filter_map(|(&partner, &weight)| {
+                let partner_phys = self.mapping[partner]?;
+                (self.ctrl2pq.get_controller_by_qubit(partner_phys) != ctrl).then_some(weight)
+            })
+            .sum()
+    }
+
+    fn search(&mut self, depth: usize, cost: usize) {
+        if self.calls >= self.call_limit {
+            return;
+        }
+        self.calls += 1;
+        if matches!(self.best, Some((best_cost, _)) if cost >= best_cost) {
+            return;
+        }
+        if depth == self.order.len() {
+            self.best = Some((cost, self.mapping.iter().flatten().copied().collect()));
+            return;
+        }
+
+        let logical = self.order[depth];
+        let placed_neighbors: Vec<PhysicalQubit> = self.interactions[logical]
+            .iter()
+            .filter_map(|&n| self.mapping[n])
+            .map(|phys| PhysicalQubit::new(phys as u32))
+            .collect();
+        let candidates: Vec<i32> = match placed_neighbors.first() {
+            Some(&anchor) => self
+                .coupling
+                .neighbors(anchor)
+                .iter()
+                .map(|phys| phys.index() as i32)
+                .collect(),
+            None => self.usable.clone(),
+        };
+        let mut scored: Vec<(usize, i32)> = candidates
+            .into_iter()
+            .filter(|&phys| {
+                let qubit = PhysicalQubit::new(phys as u32);
+                !self.used[phys as usize]
+                    && self
+                        .ctrl2pq
+                        .get_controller_by_qubit(phys)
+                        .is_some_and(|ctrl| self.free[ctrl] > 0)
+                    && self.coupling.neighbors(qubit).len() >= self.interactions[logical].len()
+                    && placed_neighbors
+                        .iter()
+                        .all(|&neighbor| self.coupling.is_adjacent(qubit, neighbor))
+            })
+            .map(|phys| (self.cost_of(logical, phys), phys))
+            .collect();
+        scored.sort_unstable();
+
+        for (delta, phys) in scored {
+            let ctrl = *self.ctrl2pq.get_controller_by_qubit(phys).unwrap();
+            self.mapping[logical] = Some(phys);
+            self.used[phys as usize] = true;
+            *self.free.get_mut(&ctrl).unwrap() -= 1;
+            self.search(depth + 1, cost + delta);
+            *self.free.get_mut(&ctrl).unwrap() += 1;
+            self.used[phys as usize] = false;
+            self.mapping[logical] = None;
+            if self.calls >= self.call_limit || matches!(self.best, Some((0, _))) {
+                return;
+            }
+        }
+    }
+}
+
+/// Embed the interaction graph of `num_logical` logical qubits into `coupling` so that every
+/// two-qubit interaction lands on a coupling edge, preferring embeddings where the qubits of the
+/// cif pairs share a controller.
+///
+/// The search is a VF2-style backtracking over the logical qubits in order of connectivity,
+/// placing each qubit next to an already placed partner and trying the candidates with the fewest
+/// new cross-controller feedbacks first.  Complete embeddings are compared by their number of
+/// cross-controller feedbacks, and branches that cannot improve on the best one are pruned.  The
+/// search stops after `call_limit` states or once an embedding without cross-controller feedback
+/// is found.  Only the physical qubits of `ctrl2pq` are used, within the capacity of their
+/// controller.
+///
+/// Returns `None` if no embedding was found within the limit.
+pub fn find_embedding(
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    num_logical: usize,
+    coupling: &CouplingMap,
+    interactions: &[[usize; 2]],
+    call_limit: usize,
+) -> PyResult<Option<(usize, HashMap<usize, i32>)>> {
+    let weights = feedback_weights(cif_pairs, num_logical)?;
+    let mut adjacency = vec![Vec::new(); num_logical];
+    for &[q0, q1] in interactions {
+        if q0 >= num_logical || q1 >= num_logical {
+            return Err(PyIndexError::new_err(format!(
+                "interaction ({}, {}) is out of range for {} logical qubits",
+                q0, q1, num_logical
+            )));
+        }
+        if q0 != q1 && !adjacency[q0].contains(&q1) {
+            adjacency[q0].push(q1);
+            adjacency[q1].push(q0);
+        }
+    }
+
+    let mut usable: Vec<i32> = ctrl2pq.map.values().flatten().copied().collect();
+    if let Some(phys) = usable
+        .iter()
+        .find(|&&phys| phys < 0 || phys as usize >= coupling.num_qubits())
+    {
+        return Err(PyIndexError::new_err(format!(
+            "qubit index {} is out of range for {} physical qubits",
+            phys,
+            coupling.num_qubits()
+        )));
+    }
+    usable.sort_unstable();
+    let free = ctrl2pq
+        .map
+        .iter()
+        .map(|(&ctrl, qubits)| {
+            let capacity = ctrl2pq.get_capacity(ctrl).unwrap_or(usize::MAX);
+            (ctrl, qubits.len().min(capacity))
+        })
+        .collect();
+
+    let order = EmbeddingSearch::placement_order(&adjacency, &weights);
+    let mut search = EmbeddingSearch {
+        coupling,
+        ctrl2pq,
+        interactions: adjacency,
+        weights,
+        order,
+        usable,
+        free,
+        mapping: vec![None; num_logical],
+        used: vec![false; coupling.num_qubits()],
+        calls: 0,
+        call_limit,
+        best: None,
+    };
+    search.search(0, 0);
+    Ok(search
+        .best
+        .map(|(cost, mapping)| (cost, mapping.into_iter().enumerate().collect())))
+}
+
+/// Find a controller-aware initial layout that embeds the interaction graph of the circuit into
+/// the coupling map, so that no swap is needed before the first feedback, see
+/// [find_embedding].  Falls back to [controller_aware_layout] when no exact embedding is found.
+pub fn vf2_controller_layout(
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    num_logical: usize,
+    coupling: &CouplingMap,
+    interactions: &[[usize; 2]],
+    call_limit: usize,
+) -> PyResult<HashMap<usize, i32>> {
+    let embedding = {
+        let _timer = metrics::time_phase(Phase::Layout);
+        find_embedding(
+            cif_pairs,
+            ctrl2pq,
+            num_logical,
+            coupling,
+            interactions,
+            call_limit,
+        )?
+    };
+    match embedding {
+        Some((_, layout)) => Ok(layout),
+        None => controller_aware_layout(cif_pairs, ctrl2pq, num_logical, Some(coupling)),
+    }
+}
+
+/// Compute an initial layout embedding the two-qubit interactions of the circuit into the
+/// coupling map while keeping the qubits of cif pairs on the same controller where possible.
+/// The GIL is released while searching.
+///
+/// Args:
+///     cif_pairs (CifPairs): The cif pairs of the circuit on logical qubits.
+///     ctrl2pq (Ctrl2Pq): The mapping between controllers and physical qubits.
+///     num_qubits (int): The number of logical qubits to place.
+///     coupling_map (CouplingMap): The coupling map of the device.
+///     interactions (list[tuple[int, int]]): The pairs of logical qubits of the two-qubit gates.
+///     call_limit (int): The number of search states explored before settling for the best
+///         embedding found so far.
+///
+/// Returns:
+///     dict[int, int]: The physical qubit of every logical qubit.  If the interaction graph
+///     cannot be embedded within the call limit, the layout of ``dqcmap_layout`` is returned.
+#[pyfunction]
+#[pyo3(signature = (cif_pairs, ctrl2pq, num_qubits, coupling_map, interactions, call_limit=DEFAULT_CALL_LIMIT))]
+pub fn dqcmap_vf2_layout(
+    py: Python,
+    cif_pairs: &CifPairs,
+    ctrl2pq: &Ctrl2Pq,
+    num_qubits: usize,
+    coupling_map: &CouplingMap,
+    interactions: Vec<[usize; 2]>,
+    call_limit: usize,
+) -> PyResult<HashMap<usize, i32>> {
+    py.allow_threads(|| {
+        vf2_controller_layout(
+            cif_pairs,
+            ctrl2pq,
+            num_qubits,
+            coupling_map,
+            &interactions,
+            call_limit,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(num_qubits: u32) -> CouplingMap {
+        let edges = (0..num_qubits)
+            .map(|q| {
+                [
+                    PhysicalQubit::new(q),
+                    PhysicalQubit::new((q + 1) % num_qubits),
+                ]
+            })
+            .collect();
+        CouplingMap::new(edges, None).unwrap()
+    }
+
+    #[test]
+    fn test_vf2_layout_embeds_and_groups_feedback() {
+        let coupling = ring(6);
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1, 2]), (1, vec![3, 4, 5])]));
+        // a path 0 - 1 - 2 - 3 with a feedback between 0 and 2
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(5, vec![vec![2, 0]])]));
+        let interactions = [[0, 1], [1, 2], [2, 3]];
+
+        let (cost, layout) =
+            find_embedding(&cif_pairs, &ctrl2pq, 4, &coupling, &interactions, 1000)
+                .unwrap()
+                .unwrap();
+        assert_eq!(cost, 0);
+        let phys = |q: usize| PhysicalQubit::new(layout[&q] as u32);
+        for [q0, q1] in interactions {
+            assert!(coupling.is_adjacent(phys(q0), phys(q1)));
+        }
+        assert_eq!(
+            ctrl2pq.get_controller_by_qubit(layout[&0]),
+            ctrl2pq.get_controller_by_qubit(layout[&2])
+        );
+    }
+
+    #[test]
+    fn test_vf2_layout_fallback() {
+        let line = CouplingMap::new(
+            (0..3)
+                .map(|q| [PhysicalQubit::new(q), PhysicalQubit::new(q + 1)])
+                .collect(),
+            None,
+        )
+        .unwrap();
+        let ctrl2pq = Ctrl2Pq::from_map(HashMap::from([(0, vec![0, 1]), (1, vec![2, 3])]));
+        let cif_pairs = CifPairs::from_pairs(HashMap::from([(1, vec![vec![0, 2]])]));
+        // a triangle cannot be embedded into a line
+        let interactions = [[0, 1], [1, 2], [0, 2]];
+        assert!(
+            find_embedding(&cif_pairs, &ctrl2pq, 3, &line, &interactions, 1000)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            vf2_controller_layout(&cif_pairs, &ctrl2pq, 3, &line, &interactions, 1000).unwrap(),
+            controller_aware_layout(&cif_pairs, &ctrl2pq, 3, Some(&line)).unwrap()
+        );
+        assert!(find_embedding(&cif_pairs, &ctrl2pq, 3, &line, &[[0, 3]], 1000).is_err());
+    }
+}
@@ -12,28 +12,47 @@
 
 use std::env;
 
+#[cfg(feature = "python")]
 use pyo3::import_exception;
 
+#[cfg(feature = "python")]
 pub mod convert_2q_block_matrix;
+#[cfg(feature = "python")]
 pub mod dense_layout;
 pub mod dqcmap;
+#[cfg(feature = "python")]
 pub mod edge_collections;
+#[cfg(feature = "python")]
 pub mod error_map;
+#[cfg(feature = "python")]
 pub mod euler_one_qubit_decomposer;
+#[cfg(feature = "python")]
 pub mod isometry;
 pub mod nlayout;
+#[cfg(feature = "python")]
 pub mod optimize_1q_gates;
+#[cfg(feature = "python")]
 pub mod pauli_exp_val;
+#[cfg(feature = "python")]
 pub mod results;
+#[cfg(feature = "python")]
 pub mod sabre;
+#[cfg(feature = "python")]
 pub mod sampled_exp_val;
+#[cfg(feature = "python")]
 pub mod sparse_pauli_op;
+#[cfg(feature = "python")]
 pub mod stochastic_swap;
+#[cfg(feature = "python")]
 pub mod two_qubit_decompose;
+#[cfg(feature = "python")]
 pub mod uc_gate;
+#[cfg(feature = "python")]
 pub mod utils;
+#[cfg(feature = "python")]
 pub mod vf2_layout;
 
+#[cfg(feature = "python")]
 mod rayon_ext;
 #[cfg(test)]
 mod test;
@@ -51,4 +70,5 @@ pub fn getenv_use_multiple_threads() -> bool {
     !parallel_context || force_threads
 }
 
+#[cfg(feature = "python")]
 import_exception!(qiskit.exceptions, QiskitError);
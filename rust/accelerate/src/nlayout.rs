@@ -10,9 +10,14 @@
 // copyright notice, and modified files need to carry a notice indicating
 // that they have been altered from the originals.
 
+use std::num::TryFromIntError;
+
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use pyo3::types::PyList;
 
+#[cfg(feature = "python")]
 use hashbrown::HashMap;
 
 /// A newtype for the different categories of qubits used within layouts.  This is to enforce
@@ -37,23 +42,27 @@ macro_rules! qubit_newtype {
             }
         }
 
+        #[cfg(feature = "python")]
         impl pyo3::IntoPy<PyObject> for $id {
             fn into_py(self, py: Python<'_>) -> PyObject {
                 self.0.into_py(py)
             }
         }
+        #[cfg(feature = "python")]
         impl pyo3::ToPyObject for $id {
             fn to_object(&self, py: Python<'_>) -> PyObject {
                 self.0.to_object(py)
             }
         }
 
+        #[cfg(feature = "python")]
         impl pyo3::FromPyObject<'_> for $id {
             fn extract(ob: &PyAny) -> PyResult<Self> {
                 Ok(Self(ob.extract()?))
             }
         }
 
+        #[cfg(feature = "python")]
         unsafe impl numpy::Element for $id {
             const IS_COPY: bool = true;
 
@@ -91,13 +100,14 @@ impl VirtualQubit {
 ///         physical qubit index on the coupling graph.
 ///     logical_qubits (int): The number of logical qubits in the layout
 ///     physical_qubits (int): The number of physical qubits in the layout
-#[pyclass(module = "dqcmap._accelerate.nlayout")]
+#[cfg_attr(feature = "python", pyclass(module = "dqcmap._accelerate.nlayout"))]
 #[derive(Clone, Debug)]
 pub struct NLayout {
     virt_to_phys: Vec<PhysicalQubit>,
     phys_to_virt: Vec<VirtualQubit>,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl NLayout {
     #[new]
@@ -143,19 +153,59 @@ impl NLayout {
     }
 
     /// Get physical bit from virtual bit
-    #[pyo3(text_signature = "(self, virtual, /)")]
+    #[pyo3(name = "virtual_to_physical", text_signature = "(self, virtual, /)")]
+    fn py_virtual_to_physical(&self, r#virtual: VirtualQubit) -> PhysicalQubit {
+        self.virtual_to_physical(r#virtual)
+    }
+
+    /// Get virtual bit from physical bit
+    #[pyo3(name = "physical_to_virtual", text_signature = "(self, physical, /)")]
+    fn py_physical_to_virtual(&self, physical: PhysicalQubit) -> VirtualQubit {
+        self.physical_to_virtual(physical)
+    }
+
+    /// Swap the specified virtual qubits
+    #[pyo3(name = "swap_virtual", text_signature = "(self, bit_a, bit_b, /)")]
+    fn py_swap_virtual(&mut self, bit_a: VirtualQubit, bit_b: VirtualQubit) {
+        self.swap_virtual(bit_a, bit_b)
+    }
+
+    /// Swap the specified physical qubits
+    #[pyo3(name = "swap_physical", text_signature = "(self, bit_a, bit_b, /)")]
+    fn py_swap_physical(&mut self, bit_a: PhysicalQubit, bit_b: PhysicalQubit) {
+        self.swap_physical(bit_a, bit_b)
+    }
+
+    #[pyo3(name = "copy")]
+    fn py_copy(&self) -> NLayout {
+        self.copy()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "generate_trivial_layout")]
+    fn py_generate_trivial_layout(num_qubits: u32) -> Self {
+        NLayout::generate_trivial_layout(num_qubits)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_virtual_to_physical")]
+    fn py_from_virtual_to_physical(virt_to_phys: Vec<PhysicalQubit>) -> PyResult<Self> {
+        Ok(NLayout::from_virtual_to_physical(virt_to_phys)?)
+    }
+}
+
+impl NLayout {
+    /// Get physical bit from virtual bit
     pub fn virtual_to_physical(&self, r#virtual: VirtualQubit) -> PhysicalQubit {
         self.virt_to_phys[r#virtual.index()]
     }
 
     /// Get virtual bit from physical bit
-    #[pyo3(text_signature = "(self, physical, /)")]
     pub fn physical_to_virtual(&self, physical: PhysicalQubit) -> VirtualQubit {
         self.phys_to_virt[physical.index()]
     }
 
     /// Swap the specified virtual qubits
-    #[pyo3(text_signature = "(self, bit_a, bit_b, /)")]
     pub fn swap_virtual(&mut self, bit_a: VirtualQubit, bit_b: VirtualQubit) {
         self.virt_to_phys.swap(bit_a.index(), bit_b.index());
         self.phys_to_virt[self.virt_to_phys[bit_a.index()].index()] = bit_a;
@@ -163,7 +213,6 @@ impl NLayout {
     }
 
     /// Swap the specified physical qubits
-    #[pyo3(text_signature = "(self, bit_a, bit_b, /)")]
     pub fn swap_physical(&mut self, bit_a: PhysicalQubit, bit_b: PhysicalQubit) {
         self.phys_to_virt.swap(bit_a.index(), bit_b.index());
         self.virt_to_phys[self.phys_to_virt[bit_a.index()].index()] = bit_a;
@@ -174,7 +223,6 @@ impl NLayout {
         self.clone()
     }
 
-    #[staticmethod]
     pub fn generate_trivial_layout(num_qubits: u32) -> Self {
         NLayout {
             virt_to_phys: (0..num_qubits).map(PhysicalQubit).collect(),
@@ -182,8 +230,9 @@ impl NLayout {
         }
     }
 
-    #[staticmethod]
-    pub fn from_virtual_to_physical(virt_to_phys: Vec<PhysicalQubit>) -> PyResult<Self> {
+    pub fn from_virtual_to_physical(
+        virt_to_phys: Vec<PhysicalQubit>,
+    ) -> Result<Self, TryFromIntError> {
         let mut phys_to_virt = vec![VirtualQubit(std::u32::MAX); virt_to_phys.len()];
         for (virt, phys) in virt_to_phys.iter().enumerate() {
             phys_to_virt[phys.index()] = VirtualQubit(virt.try_into()?);
@@ -193,9 +242,7 @@ impl NLayout {
             phys_to_virt,
         })
     }
-}
 
-impl NLayout {
     /// Iterator of `(VirtualQubit, PhysicalQubit)` pairs, in order of the `VirtualQubit` indices.
     pub fn iter_virtual(
         &'_ self,
@@ -216,6 +263,7 @@ impl NLayout {
     }
 }
 
+#[cfg(feature = "python")]
 #[pymodule]
 pub fn nlayout(m: &Bound<PyModule>) -> PyResult<()> {
     m.add_class::<NLayout>()?;
@@ -33,6 +33,7 @@ use rustworkx_core::token_swapper::token_swapper;
 use crate::dqcmap::cif_pairs::CifPairs;
 use crate::dqcmap::ctrl_to_pq::Ctrl2Pq;
 use crate::dqcmap::state::DqcMapState;
+use crate::dqcmap::swap::Swap;
 use crate::getenv_use_multiple_threads;
 use crate::nlayout::{NLayout, PhysicalQubit};
 
@@ -55,7 +56,7 @@ const EXTENDED_SET_WEIGHT: f64 = 0.5;
 /// Number of trials for control flow block swap epilogues.
 const SWAP_EPILOGUE_TRIALS: usize = 4;
 /// Delta for selecting a swap for less inter-controller feedback
-const DQC_SWAP_DELTA: i32 = 0;
+const DQC_SWAP_DELTA: f64 = 0.;
 
 /// A view object onto a full routing target.  This is cheap to clone and to replace components
 /// within it; cloning only duplicates the inner references and not the data objects beneath.  This
@@ -104,7 +105,7 @@ struct RoutingState<'a, 'b> {
 impl<'a, 'b> RoutingState<'a, 'b> {
     /// Get current total dqcmap cross-controller feedback
     #[inline]
-    pub fn get_total_cross_ctrl_fb(&self) -> Option<i32> {
+    pub fn get_total_cross_ctrl_fb(&self) -> Option<f64> {
         self.dqcmap_state.total_cross_ctrl_fb()
     }
 
@@ -117,15 +118,13 @@ impl<'a, 'b> RoutingState<'a, 'b> {
         self.layout.swap_physical(swap[0], swap[1]);
 
         // Update dqcmap state
-        // FIXME: current impl is ugly
-        let swap_vec = [swap[0].index(), swap[1].index()]
-            .iter()
-            .map(|&x| x as i32)
-            .collect();
-        debug!("applying swap: {:?}", swap_vec);
-        self.dqcmap_state.apply_swap(&swap_vec, &self.gate_order);
+        let dqcmap_swap = Swap::from_physical(swap);
+        debug!("applying swap: {}", dqcmap_swap);
+        if let Err(err) = self.dqcmap_state.apply_swap(dqcmap_swap, &self.gate_order) {
+            warn!("Failed to apply swap to dqcmap state: {}", err);
+        }
         if let Some(pairs) = self.dqcmap_state.cif_pairs.as_ref() {
-            debug!("Current cif_pairs are: {:?}", pairs.pairs);
+            debug!("Current cif_pairs are: {:?}", pairs.pairs());
         }
     }
 
@@ -527,12 +526,12 @@ impl<'a, 'b> RoutingState<'a, 'b> {
                     debug!("Heuristic DM1 -> sabre_score::{:?}", sabre_score);
                     // calculate dqcmap score
                     self.get_dqcmap_active_nodes(swap);
-                    if let Some(dqcmap_score) = self.dqcmap_state.score(
-                        &vec![swap[0].index() as i32, swap[1].index() as i32],
-                        &self.dqcmap_active_nodes,
-                    ) {
+                    if let Ok(Some(dqcmap_score)) = self
+                        .dqcmap_state
+                        .score(Swap::from_physical(swap), &self.dqcmap_active_nodes)
+                    {
                         debug!("Heuristic DM1 -> dqcmap_score::{:?}", dqcmap_score);
-                        sabre_score - 0.1 * (dqcmap_score as f64)
+                        sabre_score - 0.1 * dqcmap_score
                     } else {
                         warn!("DqcMap score is None, ideally we should not go here.");
                         0.
@@ -556,7 +555,7 @@ impl<'a, 'b> RoutingState<'a, 'b> {
                 "Checking dqcmap scores for {} swaps",
                 self.swap_scratch.len()
             );
-            let mut max_dqcmap_score = i32::MIN;
+            let mut max_dqcmap_score = f64::NEG_INFINITY;
             let mut best_swaps: Vec<[PhysicalQubit; 2]> = Vec::new();
 
             for &swap in self.swap_scratch.clone().iter() {
@@ -565,10 +564,10 @@ impl<'a, 'b> RoutingState<'a, 'b> {
                     "Current dqcmap active nodes are: {:?}",
                     self.dqcmap_active_nodes
                 );
-                if let Some(score) = self.dqcmap_state.score(
-                    &vec![swap[0].index() as i32, swap[1].index() as i32],
-                    &self.dqcmap_active_nodes,
-                ) {
+                if let Ok(Some(score)) = self
+                    .dqcmap_state
+                    .score(Swap::from_physical(swap), &self.dqcmap_active_nodes)
+                {
                     debug!("Score of swap: {:?} is: {}", swap, score);
                     if score > max_dqcmap_score + DQC_SWAP_DELTA {
                         max_dqcmap_score = score;
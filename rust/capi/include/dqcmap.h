@@ -0,0 +1,149 @@
+#ifndef DQCMAP_H
+#define DQCMAP_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The outcome of a C API call.
+ */
+typedef enum DqcMapStatus {
+  DqcMapStatus_Ok = 0,
+  /**
+   * A required pointer is null.
+   */
+  DqcMapStatus_NullPointer = 1,
+  /**
+   * An argument is out of range or inconsistent, e.g. a malformed layout or coupling map.
+   */
+  DqcMapStatus_InvalidArgument = 2,
+  /**
+   * The output buffer is too small, the required length has been written.
+   */
+  DqcMapStatus_BufferTooSmall = 3,
+  /**
+   * An internal error, the call had no effect.
+   */
+  DqcMapStatus_Internal = 4,
+} DqcMapStatus;
+
+/**
+ * The cif pairs of a circuit scored against a controller mapping, created by
+ * [dqcmap_context_new].
+ */
+typedef struct DqcMapContext DqcMapContext;
+
+/**
+ * A controller mapping, created by [dqcmap_mapping_new].
+ */
+typedef struct DqcMapMapping DqcMapMapping;
+
+/**
+ * A swap inserted by [dqcmap_route] before the gate of node `node_id`.
+ */
+typedef struct DqcMapSwap {
+  size_t node_id;
+  uint32_t q0;
+  uint32_t q1;
+} DqcMapSwap;
+
+/**
+ * Create the mapping connecting physical qubit `qubits[i]` to controller `ctrl_ids[i]`, or
+ * null if an array is null or a qubit is listed twice.
+ *
+ * # Safety
+ *
+ * `qubits` and `ctrl_ids` must point to `len` readable values each.
+ */
+struct DqcMapMapping *dqcmap_mapping_new(const int32_t *qubits,
+                                         const int32_t *ctrl_ids,
+                                         size_t len);
+
+/**
+ * Release a mapping created by [dqcmap_mapping_new], null is ignored.
+ *
+ * # Safety
+ *
+ * `mapping` must be null or a pointer returned by [dqcmap_mapping_new] that was not freed yet.
+ */
+void dqcmap_mapping_free(struct DqcMapMapping *mapping);
+
+/**
+ * Create a context scoring swaps against `mapping`, with the cif pair
+ * `(q0s[i], q1s[i])` of node `node_ids[i]`, or null if a pointer is null.  The mapping is
+ * copied, so it can be freed afterwards.
+ *
+ * # Safety
+ *
+ * `mapping` must be a live mapping, and `node_ids`, `q0s` and `q1s` must point to
+ * `num_pairs` readable values each.
+ */
+struct DqcMapContext *dqcmap_context_new(const struct DqcMapMapping *mapping,
+                                         const size_t *node_ids,
+                                         const int32_t *q0s,
+                                         const int32_t *q1s,
+                                         size_t num_pairs);
+
+/**
+ * Release a context created by [dqcmap_context_new], null is ignored.
+ *
+ * # Safety
+ *
+ * `context` must be null or a pointer returned by [dqcmap_context_new] that was not freed yet.
+ */
+void dqcmap_context_free(struct DqcMapContext *context);
+
+/**
+ * Score the swap of physical qubits `q0` and `q1` for the nodes `active_nodes` with the cost
+ * model of `context` into `score`, higher is better.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, `active_nodes` must point to `num_active` readable values and
+ * `score` must be writable.
+ */
+enum DqcMapStatus dqcmap_score_swap(const struct DqcMapContext *context,
+                                    int32_t q0,
+                                    int32_t q1,
+                                    const size_t *active_nodes,
+                                    size_t num_active,
+                                    double *score);
+
+/**
+ * Route two-qubit gates on a coupling map with the swap selection of `context`.
+ *
+ * The coupling map has `num_qubits` physical qubits and the `num_edges` edges
+ * `(edges[2 * i], edges[2 * i + 1])`.  `initial_layout[v]` is the physical qubit of virtual
+ * qubit `v`, for all `num_qubits` virtual qubits.  Gate `i` is node `gate_node_ids[i]` acting on
+ * the virtual qubits `gate_qubits[2 * i]` and `gate_qubits[2 * i + 1]`, in a valid topological
+ * order.  `seed` breaks ties between equally scored swaps at random if not null.
+ *
+ * The inserted swaps are written to `swaps` and their number to `num_swaps`.  If they do not
+ * fit in `swaps_capacity`, nothing is written to `swaps`, `num_swaps` receives the required
+ * capacity and [DqcMapStatus::BufferTooSmall] is returned.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, every array must point to as many readable values as described
+ * above, `seed` must be null or readable, `swaps` must point to `swaps_capacity` writable values
+ * and `num_swaps` must be writable.
+ */
+enum DqcMapStatus dqcmap_route(const struct DqcMapContext *context,
+                               uint32_t num_qubits,
+                               const uint32_t *edges,
+                               size_t num_edges,
+                               const uint32_t *initial_layout,
+                               const size_t *gate_node_ids,
+                               const uint32_t *gate_qubits,
+                               size_t num_gates,
+                               const uint64_t *seed,
+                               struct DqcMapSwap *swaps,
+                               size_t swaps_capacity,
+                               size_t *num_swaps);
+
+#endif /* DQCMAP_H */
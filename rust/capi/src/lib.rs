@@ -0,0 +1,396 @@
+//! A minimal C API over the mapping logic, so that non-Python schedulers can create a mapping,
+//! score swaps and route with the same code as the Python stack.  It is built as the static
+//! library `libdqcmap.a` on the pure-Rust core of `dqcmap-accelerate`, without its `python`
+//! feature, so it does not depend on `libpython`.
+//!
+//! The header `include/dqcmap.h` is generated from this crate with `cbindgen --config
+//! cbindgen.toml --output include/dqcmap.h` from `rust/capi`.  Objects created by the API
+//! are opaque and must be released with the matching `*_free` function.  No function unwinds
+//! into C: invalid input and internal failures are reported through [DqcMapStatus].
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+use hashbrown::HashMap;
+
+use dqcmap_accelerate::dqcmap::{
+    cif_pairs::CifPairs,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    pair_list::PairList,
+    router::{route, RouteOptions, RouterGate},
+    state::DqcMapState,
+    swap::Swap,
+};
+use dqcmap_accelerate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+/// The outcome of a C API call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DqcMapStatus {
+    Ok = 0,
+    /// A required pointer is null.
+    NullPointer = 1,
+    /// An argument is out of range or inconsistent, e.g. a malformed layout or coupling map.
+    InvalidArgument = 2,
+    /// The output buffer is too small, the required length has been written.
+    BufferTooSmall = 3,
+    /// An internal error, the call had no effect.
+    Internal = 4,
+}
+
+/// A swap inserted by [dqcmap_route] before the gate of node `node_id`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DqcMapSwap {
+    pub node_id: usize,
+    pub q0: u32,
+    pub q1: u32,
+}
+
+/// A controller mapping, created by [dqcmap_mapping_new].
+pub struct DqcMapMapping {
+    ctrl2pq: Ctrl2Pq,
+}
+
+/// The cif pairs of a circuit scored against a controller mapping, created by
+/// [dqcmap_context_new].
+pub struct DqcMapContext {
+    state: DqcMapState,
+}
+
+/// A slice from a pointer and a length, empty for a zero length even if the pointer is null.
+unsafe fn input<'a, T>(data: *const T, len: usize) -> Option<&'a [T]> {
+    if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+/// Create the mapping connecting physical qubit `qubits[i]` to controller `ctrl_ids[i]`, or
+/// null if an array is null or a qubit is listed twice.
+///
+/// # Safety
+///
+/// `qubits` and `ctrl_ids` must point to `len` readable values each.
+#[no_mangle]
+pub unsafe extern "C" fn dqcmap_mapping_new(
+    qubits: *const i32,
+    ctrl_ids: *const i32,
+    len: usize,
+) -> *mut DqcMapMapping {
+    let (Some(qubits), Some(ctrl_ids)) = (input(qubits, len), input(ctrl_ids, len)) else {
+        return std::ptr::null_mut();
+    };
+    let mut ctrl2pq = Ctrl2Pq::from_map(HashMap::new());
+    for (&qubit, &ctrl_id) in qubits.iter().zip(ctrl_ids) {
+        if ctrl2pq.assign_qubit(qubit, ctrl_id).is_err() {
+            return std::ptr::null_mut();
+        }
+    }
+    Box::into_raw(Box::new(DqcMapMapping { ctrl2pq }))
+}
+
+/// Release a mapping created by [dqcmap_mapping_new], null is ignored.
+///
+/// # Safety
+///
+/// `mapping` must be null or a pointer returned by [dqcmap_mapping_new] that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn dqcmap_mapping_free(mapping: *mut DqcMapMapping) {
+    if !mapping.is_null() {
+        drop(Box::from_raw(mapping));
+    }
+}
+
+/// Create a context scoring swaps against `mapping`, with the cif pair
+/// `(q0s[i], q1s[i])` of node `node_ids[i]`, or null if a pointer is null.  The mapping is
+/// copied, so it can be freed afterwards.
+///
+/// # Safety
+///
+/// `mapping` must be a live mapping, and `node_ids`, `q0s` and `q1s` must point to
+/// `num_pairs` readable values each.
+#[no_mangle]
+pub unsafe extern "C" fn dqcmap_context_new(
+    mapping: *const DqcMapMapping,
+    node_ids: *const usize,
+    q0s: *const i32,
+    q1s: *const i32,
+    num_pairs: usize,
+) -> *mut DqcMapContext {
+    let (Some(mapping), Some(node_ids), Some(q0s), Some(q1s)) = (
+        mapping.as_ref(),
+        input(node_ids, num_pairs),
+        input(q0s, num_pairs),
+        input(q1s, num_pairs),
+    ) else {
+        return std::ptr::null_mut();
+    };
+    let mut pairs: HashMap<usize, PairList> = HashMap::new();
+    for ((&node_id, &q0), &q1) in node_ids.iter().zip(q0s).zip(q1s) {
+        pairs.entry(node_id).or_default().push(&[q0, q1]);
+    }
+    let state = DqcMapState::new(
+        Some(mapping.ctrl2pq.clone()),
+        Some(CifPairs::from_lists(pairs)),
+    );
+    Box::into_raw(Box::new(DqcMapContext { state }))
+}
+
+/// Release a context created by [dqcmap_context_new], null is ignored.
+///
+/// # Safety
+///
+/// `context` must be null or a pointer returned by [dqcmap_context_new] that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn dqcmap_context_free(context: *mut DqcMapContext) {
+    if !context.is_null() {
+        drop(Box::from_raw(context));
+    }
+}
+
+/// Score the swap of physical qubits `q0` and `q1` for the nodes `active_nodes` with the cost
+/// model of `context` into `score`, higher is better.
+///
+/// # Safety
+///
+/// `context` must be a live context, `active_nodes` must point to `num_active` readable values and
+/// `score` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn dqcmap_score_swap(
+    context: *const DqcMapContext,
+    q0: i32,
+    q1: i32,
+    active_nodes: *const usize,
+    num_active: usize,
+    score: *mut f64,
+) -> DqcMapStatus {
+    let (Some(state), Some(active_nodes), Some(score)) = (
+        context.as_ref().map(|context| &context.state),
+        input(active_nodes, num_active),
+        score.as_mut(),
+    ) else {
+        return DqcMapStatus::NullPointer;
+    };
+    match catch_unwind(AssertUnwindSafe(|| {
+        Swap::new(q0, q1).and_then(|swap| state.score_swap(swap, active_nodes))
+    })) {
+        Ok(Ok(value)) => {
+            *score = value;
+            DqcMapStatus::Ok
+        }
+        Ok(Err(_)) => DqcMapStatus::InvalidArgument,
+        Err(_) => DqcMapStatus::Internal,
+    }
+}
+
+/// Route two-qubit gates on a coupling map with the swap selection of `context`.
+///
+/// The coupling map has `num_qubits` physical qubits and the `num_edges` edges
+/// `(edges[2 * i], edges[2 * i + 1])`.  `initial_layout[v]` is the physical qubit of virtual
+/// qubit `v`, for all `num_qubits` virtual qubits.  Gate `i` is node `gate_node_ids[i]` acting on
+/// the virtual qubits `gate_qubits[2 * i]` and `gate_qubits[2 * i + 1]`, in a valid topological
+/// order.  `seed` breaks ties between equally scored swaps at random if not null.
+///
+/// The inserted swaps are written to `swaps` and their number to `num_swaps`.  If they do not
+/// fit in `swaps_capacity`, nothing is written to `swaps`, `num_swaps` receives the required
+/// capacity and [DqcMapStatus::BufferTooSmall] is returned.
+///
+/// # Safety
+///
+/// `context` must be a live context, every array must point to as many readable values as described
+/// above, `seed` must be null or readable, `swaps` must point to `swaps_capacity` writable values
+/// and `num_swaps` must be writable.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn dqcmap_route(
+    context: *const DqcMapContext,
+    num_qubits: u32,
+    edges: *const u32,
+    num_edges: usize,
+    initial_layout: *const u32,
+    gate_node_ids: *const usize,
+    gate_qubits: *const u32,
+    num_gates: usize,
+    seed: *const u64,
+    swaps: *mut DqcMapSwap,
+    swaps_capacity: usize,
+    num_swaps: *mut usize,
+) -> DqcMapStatus {
+    let (
+        Some(state),
+        Some(edges),
+        Some(initial_layout),
+        Some(gate_node_ids),
+        Some(gate_qubits),
+        Some(num_swaps),
+    ) = (
+        context.as_ref().map(|context| &context.state),
+        input(edges, 2 * num_edges),
+        input(initial_layout, num_qubits as usize),
+        input(gate_node_ids, num_gates),
+        input(gate_qubits, 2 * num_gates),
+        num_swaps.as_mut(),
+    )
+    else {
+        return DqcMapStatus::NullPointer;
+    };
+    if swaps.is_null() && swaps_capacity > 0 {
+        return DqcMapStatus::NullPointer;
+    }
+    let seed = seed.as_ref().copied();
+
+    let routed = catch_unwind(AssertUnwindSafe(|| {
+        let in_range = |qubit: u32| qubit < num_qubits;
+        if !edges
+            .iter()
+            .chain(gate_qubits)
+            .all(|&qubit| in_range(qubit))
+        {
+            return None;
+        }
+        let mut seen = vec![false; num_qubits as usize];
+        for &physical in initial_layout {
+            if !in_range(physical) || std::mem::replace(&mut seen[physical as usize], true) {
+                return None;
+            }
+        }
+        let coupling = CouplingMap::new(
+            edges
+                .chunks_exact(2)
+                .map(|edge| [PhysicalQubit::new(edge[0]), PhysicalQubit::new(edge[1])])
+                .collect(),
+            Some(num_qubits as usize),
+        )
+        .ok()?;
+        let layout = NLayout::from_virtual_to_physical(
+            initial_layout
+                .iter()
+                .map(|&q| PhysicalQubit::new(q))
+                .collect(),
+        )
+        .ok()?;
+        let gates: Vec<RouterGate> = gate_node_ids
+            .iter()
+            .zip(gate_qubits.chunks_exact(2))
+            .map(|(&node_id, qubits)| RouterGate {
+                node_id,
+                qubits: qubits.iter().map(|&q| VirtualQubit::new(q)).collect(),
+            })
+            .collect();
+        route(
+            &coupling,
+            &layout,
+            &gates,
+            state.clone(),
+            RouteOptions {
+                seed,
+                ..RouteOptions::default()
+            },
+        )
+        .ok()
+    }));
+    let output = match routed {
+        Ok(Some(output)) => output,
+        Ok(None) => return DqcMapStatus::InvalidArgument,
+        Err(_) => return DqcMapStatus::Internal,
+    };
+
+    *num_swaps = output.swaps.len();
+    if output.swaps.len() > swaps_capacity {
+        return DqcMapStatus::BufferTooSmall;
+    }
+    for (index, (node_id, [q0, q1])) in output.swaps.into_iter().enumerate() {
+        *swaps.add(index) = DqcMapSwap {
+            node_id,
+            q0: q0.index() as u32,
+            q1: q1.index() as u32,
+        };
+    }
+    DqcMapStatus::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_ffi_route() {
+        unsafe {
+            // line 0 - 1 - 2 - 3, controller 0 owns {0, 1} and controller 1 owns {2, 3}
+            let mapping = dqcmap_mapping_new([0, 1, 2, 3].as_ptr(), [0, 0, 1, 1].as_ptr(), 4);
+            assert!(!mapping.is_null());
+            assert!(dqcmap_mapping_new([0, 0].as_ptr(), [0, 1].as_ptr(), 2).is_null());
+            // node 1 conditions qubit 2 on a measurement of qubit 0
+            let context = dqcmap_context_new(mapping, [1].as_ptr(), [0].as_ptr(), [2].as_ptr(), 1);
+            dqcmap_mapping_free(mapping);
+            assert!(!context.is_null());
+
+            let mut score = 0.;
+            assert_eq!(
+                dqcmap_score_swap(context, 1, 2, [1].as_ptr(), 1, &mut score),
+                DqcMapStatus::Ok
+            );
+            let mut other = 0.;
+            dqcmap_score_swap(context, 0, 1, [1].as_ptr(), 1, &mut other);
+            assert!(score > other);
+            assert_eq!(
+                dqcmap_score_swap(context, 1, 2, ptr::null(), 1, &mut score),
+                DqcMapStatus::NullPointer
+            );
+
+            let edges = [0, 1, 1, 2, 2, 3];
+            let layout = [0, 1, 2, 3];
+            let mut swaps = [DqcMapSwap {
+                node_id: 0,
+                q0: 0,
+                q1: 0,
+            }; 4];
+            let mut num_swaps = 0;
+            let route_with =
+                |layout: &[u32], capacity: usize, swaps: *mut DqcMapSwap, num_swaps| {
+                    dqcmap_route(
+                        context,
+                        4,
+                        edges.as_ptr(),
+                        3,
+                        layout.as_ptr(),
+                        [1].as_ptr(),
+                        [0, 2].as_ptr(),
+                        1,
+                        ptr::null(),
+                        swaps,
+                        capacity,
+                        num_swaps,
+                    )
+                };
+            assert_eq!(
+                route_with(&layout, 0, ptr::null_mut(), &mut num_swaps),
+                DqcMapStatus::BufferTooSmall
+            );
+            assert_eq!(num_swaps, 1);
+            assert_eq!(
+                route_with(&layout, 4, swaps.as_mut_ptr(), &mut num_swaps),
+                DqcMapStatus::Ok
+            );
+            assert_eq!(
+                &swaps[..num_swaps],
+                &[DqcMapSwap {
+                    node_id: 1,
+                    q0: 1,
+                    q1: 2
+                }]
+            );
+            assert_eq!(
+                route_with(&[0, 1, 1, 3], 4, swaps.as_mut_ptr(), &mut num_swaps),
+                DqcMapStatus::InvalidArgument
+            );
+            dqcmap_context_free(context);
+        }
+    }
+}
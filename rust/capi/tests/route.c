@@ -0,0 +1,50 @@
+/* Link against libdqcmap.a from C and route a gate, see .github/workflows/capi.yml. */
+
+#include <stdio.h>
+
+#include "dqcmap.h"
+
+#define CHECK(cond)                                                                  \
+    do {                                                                             \
+        if (!(cond)) {                                                               \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                                \
+        }                                                                            \
+    } while (0)
+
+int main(void) {
+    /* line 0 - 1 - 2 - 3, controller 0 owns {0, 1} and controller 1 owns {2, 3} */
+    const int32_t qubits[] = {0, 1, 2, 3};
+    const int32_t ctrl_ids[] = {0, 0, 1, 1};
+    DqcMapMapping *mapping = dqcmap_mapping_new(qubits, ctrl_ids, 4);
+    CHECK(mapping != NULL);
+
+    /* node 1 conditions qubit 2 on a measurement of qubit 0 */
+    const size_t node_ids[] = {1};
+    const int32_t q0s[] = {0};
+    const int32_t q1s[] = {2};
+    DqcMapContext *context = dqcmap_context_new(mapping, node_ids, q0s, q1s, 1);
+    dqcmap_mapping_free(mapping);
+    CHECK(context != NULL);
+
+    double score = 0.;
+    CHECK(dqcmap_score_swap(context, 1, 2, node_ids, 1, &score) == DqcMapStatus_Ok);
+    CHECK(dqcmap_score_swap(context, 1, 2, NULL, 1, &score) == DqcMapStatus_NullPointer);
+
+    const uint32_t edges[] = {0, 1, 1, 2, 2, 3};
+    const uint32_t layout[] = {0, 1, 2, 3};
+    const uint32_t gate_qubits[] = {0, 2};
+    DqcMapSwap swaps[4];
+    size_t num_swaps = 0;
+    CHECK(dqcmap_route(context, 4, edges, 3, layout, node_ids, gate_qubits, 1, NULL, swaps, 4,
+                       &num_swaps) == DqcMapStatus_Ok);
+    CHECK(num_swaps == 1);
+    CHECK(swaps[0].node_id == 1 && swaps[0].q0 == 1 && swaps[0].q1 == 2);
+
+    const uint32_t bad_layout[] = {0, 1, 1, 3};
+    CHECK(dqcmap_route(context, 4, edges, 3, bad_layout, node_ids, gate_qubits, 1, NULL, swaps, 4,
+                       &num_swaps) == DqcMapStatus_InvalidArgument);
+
+    dqcmap_context_free(context);
+    return 0;
+}
@@ -0,0 +1,296 @@
+//! `dqcmap-cli`: route a circuit described in JSON with the dqcmap router, for CI pipelines and
+//! users without a Python stack.
+//!
+//! Usage: `dqcmap-cli [INPUT] [-o OUTPUT]`, reading from stdin if `INPUT` is missing or `-` and
+//! writing to stdout if no output is given.  The input is a JSON object with the keys
+//!
+//! * `coupling_map`: the edges `[p0, p1]` of the device,
+//! * `num_qubits` (optional): the number of physical qubits, one more than the largest qubit of
+//!   `coupling_map` by default,
+//! * `initial_layout` (optional): the physical qubit of every virtual qubit, with as many virtual
+//!   qubits as physical ones, trivial by default,
+//! * `ctrl2pq`: the physical qubits of every controller, keyed by controller id, every qubit
+//!   connected to one controller at most,
+//! * `cif_pairs` (optional): the `[q0, q1]` or `[q0, q1, clbit]` cif pairs of every node, keyed by
+//!   node id, on qubits connected to a controller,
+//! * `gates`: the `[node_id, [v0, v1]]` gates on virtual qubits in a valid topological order,
+//! * `cost_model`, `alpha`, `beta` and `seed` (optional): see `dqcmap_routing`.
+//!
+//! The output is a JSON object with the chosen `swaps` as `[node_id, [p0, p1]]`, the
+//! `gate_order`, the `final_layout` and `stats`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use serde::{Deserialize, Serialize};
+
+use dqcmap_accelerate::dqcmap::{
+    builder::DqcMapStateBuilder,
+    cif_pairs::{is_well_formed, CifPairs},
+    cost_model::cost_model_by_name,
+    coupling_map::CouplingMap,
+    ctrl_to_pq::Ctrl2Pq,
+    router::{route, RouteOptions, RouterGate},
+    state::{DEFAULT_DISTANCE_WEIGHT, DEFAULT_FEEDBACK_WEIGHT},
+};
+use dqcmap_accelerate::nlayout::{NLayout, PhysicalQubit, VirtualQubit};
+
+const USAGE: &str = "usage: dqcmap-cli [INPUT] [-o OUTPUT]";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Input {
+    coupling_map: Vec<[u32; 2]>,
+    #[serde(default)]
+    num_qubits: Option<usize>,
+    #[serde(default)]
+    initial_layout: Option<Vec<u32>>,
+    ctrl2pq: BTreeMap<i32, Vec<i32>>,
+    #[serde(default)]
+    cif_pairs: BTreeMap<usize, Vec<Vec<i32>>>,
+    gates: Vec<(usize, Vec<u32>)>,
+    #[serde(default)]
+    cost_model: Option<String>,
+    #[serde(default = "default_alpha")]
+    alpha: f64,
+    #[serde(default = "default_beta")]
+    beta: f64,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+fn default_alpha() -> f64 {
+    DEFAULT_DISTANCE_WEIGHT
+}
+
+fn default_beta() -> f64 {
+    DEFAULT_FEEDBACK_WEIGHT
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Stats {
+    num_swaps: usize,
+    num_gates: usize,
+    cross_controller_feedbacks: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Output {
+    swaps: Vec<(usize, [u32; 2])>,
+    gate_order: Vec<usize>,
+    final_layout: Vec<u32>,
+    stats: Stats,
+}
+
+fn run(input: Input) -> Result<Output, String> {
+    let coupling = CouplingMap::new(
+        input
+            .coupling_map
+            .iter()
+            .map(|&[p0, p1]| [PhysicalQubit::new(p0), PhysicalQubit::new(p1)])
+            .collect(),
+        input.num_qubits,
+    )
+    .map_err(|err| err.to_string())?;
+    let num_qubits = coupling.num_qubits();
+    let initial_layout = input
+        .initial_layout
+        .unwrap_or_else(|| (0..num_qubits as u32).collect());
+    if initial_layout.len() != num_qubits {
+        return Err(format!(
+            "initial layout has {} qubits, expected {num_qubits}",
+            initial_layout.len()
+        ));
+    }
+    let mut seen = vec![false; num_qubits];
+    for &physical in &initial_layout {
+        match seen.get_mut(physical as usize) {
+            Some(seen) if !*seen => *seen = true,
+            _ => return Err(format!("invalid initial layout {initial_layout:?}")),
+        }
+    }
+    let layout = NLayout::from_virtual_to_physical(
+        initial_layout
+            .iter()
+            .map(|&physical| PhysicalQubit::new(physical))
+            .collect(),
+    )
+    .map_err(|err| err.to_string())?;
+
+    let mut ctrl2pq = Ctrl2Pq::empty();
+    for (&ctrl_id, qubits) in &input.ctrl2pq {
+        for &qubit in qubits {
+            ctrl2pq
+                .assign_qubit(qubit, ctrl_id)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    let mut cif_pairs = CifPairs::empty();
+    for (&node_id, pairs) in &input.cif_pairs {
+        for pair in pairs {
+            if !is_well_formed(pair) {
+                return Err(format!(
+                    "cif pair {pair:?} of node {node_id} must be [q0, q1] or [q0, q1, clbit]"
+                ));
+            }
+            cif_pairs.add_pair(node_id, pair);
+        }
+    }
+    let mut state = DqcMapStateBuilder::new()
+        .ctrl2pq(ctrl2pq)
+        .cif_pairs(cif_pairs)
+        .num_qubits(num_qubits)
+        .build()
+        .map_err(|err| err.to_string())?;
+    if let Some(name) = &input.cost_model {
+        state.set_cost_model(cost_model_by_name(name).map_err(|err| err.to_string())?);
+    }
+    state
+        .set_objective_weights(input.alpha, input.beta)
+        .map_err(|err| err.to_string())?;
+    let gates: Vec<RouterGate> = input
+        .gates
+        .into_iter()
+        .map(|(node_id, qubits)| RouterGate {
+            node_id,
+            qubits: qubits.into_iter().map(VirtualQubit::new).collect(),
+        })
+        .collect();
+
+    let options = RouteOptions {
+        seed: input.seed,
+        ..RouteOptions::default()
+    };
+    let output =
+        route(&coupling, &layout, &gates, state, options).map_err(|err| err.to_string())?;
+    Ok(Output {
+        stats: Stats {
+            num_swaps: output.swaps.len(),
+            num_gates: output.gate_order.len(),
+            cross_controller_feedbacks: output.cross_ctrl_feedbacks,
+        },
+        swaps: output
+            .swaps
+            .iter()
+            .map(|&(node_id, [p0, p1])| (node_id, [p0.index() as u32, p1.index() as u32]))
+            .collect(),
+        gate_order: output.gate_order,
+        final_layout: (0..initial_layout.len() as u32)
+            .map(|virt| {
+                output
+                    .final_layout
+                    .virtual_to_physical(VirtualQubit::new(virt))
+                    .index() as u32
+            })
+            .collect(),
+    })
+}
+
+fn main_with_args(args: &[String]) -> Result<(), String> {
+    let mut input_path = None;
+    let mut output_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            "-o" | "--output" => {
+                output_path = Some(args.next().ok_or(USAGE)?.clone());
+            }
+            _ if input_path.is_none() => input_path = Some(arg.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let mut json = String::new();
+    match input_path.as_deref() {
+        None | Some("-") => io::stdin()
+            .read_to_string(&mut json)
+            .map_err(|err| format!("cannot read stdin: {err}"))
+            .map(|_| ())?,
+        Some(path) => {
+            json = fs::read_to_string(path).map_err(|err| format!("cannot read {path}: {err}"))?
+        }
+    }
+    let input: Input =
+        serde_json::from_str(&json).map_err(|err| format!("invalid input JSON: {err}"))?;
+    let output = serde_json::to_string(&run(input)?).map_err(|err| err.to_string())?;
+    match output_path {
+        Some(path) => fs::write(&path, output).map_err(|err| format!("cannot write {path}: {err}")),
+        None => writeln!(io::stdout(), "{output}").map_err(|err| err.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match main_with_args(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("dqcmap-cli: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        // line 0 - 1 - 2 - 3, node 1 conditions qubit 2 on a measurement of qubit 0
+        let input: Input = serde_json::from_str(
+            r#"{
+                "coupling_map": [[0, 1], [1, 2], [2, 3]],
+                "ctrl2pq": {"0": [0, 1], "1": [2, 3]},
+                "cif_pairs": {"1": [[0, 2]]},
+                "gates": [[1, [0, 2]]]
+            }"#,
+        )
+        .unwrap();
+        let output = run(input).unwrap();
+        assert_eq!(output.swaps, vec![(1, [1, 2])]);
+        assert_eq!(output.gate_order, vec![1]);
+        assert_eq!(output.final_layout, vec![0, 2, 1, 3]);
+        assert_eq!(output.stats.num_swaps, 1);
+
+        let input: Input = serde_json::from_str(
+            r#"{"coupling_map": [[0, 1]], "ctrl2pq": {}, "gates": [], "initial_layout": [0, 0]}"#,
+        )
+        .unwrap();
+        assert!(run(input).is_err());
+        assert!(serde_json::from_str::<Input>(r#"{"coupling": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        // truncated, missing the gates, a negative qubit and an unknown key
+        for json in [
+            r#"{"coupling_map": [[0, 1]]"#,
+            r#"{"coupling_map": [[0, 1]], "ctrl2pq": {}}"#,
+            r#"{"coupling_map": [[0, -1]], "ctrl2pq": {}, "gates": []}"#,
+            r#"{"coupling_map": [], "ctrl2pq": {}, "gates": [], "layout": []}"#,
+        ] {
+            assert!(serde_json::from_str::<Input>(json).is_err(), "{json}");
+        }
+
+        // well-formed descriptions rejected by the router are reported with its message
+        let run_json = |json: &str| run(serde_json::from_str(json).unwrap()).unwrap_err();
+        assert_eq!(
+            run_json(r#"{"coupling_map": [[0, 1]], "ctrl2pq": {}, "gates": [[0, [1, 1]]]}"#),
+            "node 0 acts twice on qubit 1"
+        );
+        assert_eq!(
+            run_json(r#"{"coupling_map": [[0, 1]], "ctrl2pq": {}, "gates": [], "alpha": -1}"#),
+            "objective weights must be non-negative finite numbers, got -1"
+        );
+        assert_eq!(
+            run_json(r#"{"coupling_map": [], "ctrl2pq": {}, "gates": [], "cost_model": "x"}"#),
+            cost_model_by_name("x").unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Run the `dqcmap-cli` binary on files and check its output and exit status.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn dqcmap_cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dqcmap-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn write_input(name: &str, json: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("dqcmap-cli-{}-{name}", std::process::id()));
+    fs::write(&path, json).unwrap();
+    path
+}
+
+#[test]
+fn test_cli_route() {
+    // line 0 - 1 - 2 - 3, node 1 conditions qubit 2 on a measurement of qubit 0
+    let input = write_input(
+        "route.json",
+        r#"{
+            "coupling_map": [[0, 1], [1, 2], [2, 3]],
+            "ctrl2pq": {"0": [0, 1], "1": [2, 3]},
+            "cif_pairs": {"1": [[0, 2]]},
+            "gates": [[1, [0, 2]]]
+        }"#,
+    );
+    let output_path = input.with_extension("out.json");
+    let output = dqcmap_cli(&[input.to_str().unwrap(), "-o", output_path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let routed = fs::read_to_string(&output_path).unwrap();
+    assert!(routed.starts_with(r#"{"swaps":[[1,[1,2]]],"gate_order":[1]"#));
+    fs::remove_file(input).unwrap();
+    fs::remove_file(output_path).unwrap();
+}
+
+#[test]
+fn test_cli_errors() {
+    let stderr = |output: Output| {
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let malformed = write_input("malformed.json", r#"{"coupling_map": [[0, 1]"#);
+    assert!(stderr(dqcmap_cli(&[malformed.to_str().unwrap()]))
+        .starts_with("dqcmap-cli: invalid input JSON: "));
+    fs::remove_file(malformed).unwrap();
+
+    let invalid = write_input(
+        "invalid.json",
+        r#"{"coupling_map": [[0, 1]], "ctrl2pq": {}, "gates": [[0, [1, 1]]]}"#,
+    );
+    assert_eq!(
+        stderr(dqcmap_cli(&[invalid.to_str().unwrap()])),
+        "dqcmap-cli: node 0 acts twice on qubit 1\n"
+    );
+    fs::remove_file(invalid).unwrap();
+
+    assert!(
+        stderr(dqcmap_cli(&["missing.json"])).starts_with("dqcmap-cli: cannot read missing.json")
+    );
+    assert_eq!(
+        stderr(dqcmap_cli(&["a.json", "b.json"])),
+        "dqcmap-cli: usage: dqcmap-cli [INPUT] [-o OUTPUT]\n"
+    );
+}
+
+#[test]
+fn test_cli_inconsistent_input() {
+    // line 0 - 1 - 2
+    for (json, message) in [
+        (
+            r#""initial_layout": [2, 0], "ctrl2pq": {"0": [0, 1, 2]}"#,
+            "initial layout has 2 qubits, expected 3",
+        ),
+        (
+            r#""ctrl2pq": {"0": [0, 1], "1": [1, 2]}"#,
+            "qubit 1 is already connected to controller 0",
+        ),
+        (
+            r#""ctrl2pq": {"0": [0, 1, 2]}, "cif_pairs": {"0": [[0]]}"#,
+            "cif pair [0] of node 0 must be [q0, q1] or [q0, q1, clbit]",
+        ),
+        (
+            r#""ctrl2pq": {"0": [-1, 0, 1, 2]}"#,
+            "qubit -1 is out of range for a device of 3 qubits",
+        ),
+    ] {
+        let input = write_input(
+            "inconsistent.json",
+            &format!(r#"{{"coupling_map": [[0, 1], [1, 2]], "gates": [], {json}}}"#),
+        );
+        let output = dqcmap_cli(&[input.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(1), "{json}");
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            format!("dqcmap-cli: {message}\n")
+        );
+        fs::remove_file(input).unwrap();
+    }
+}